@@ -0,0 +1,706 @@
+use std::ops::RangeInclusive;
+
+use crate::memory_map::SOUND_REGISTER_RANGE;
+use crate::state::{StateError, StateReader, StateWriter};
+
+pub const SAMPLE_RATE: u32 = 48_000;
+/// Interleaved stereo samples kept when nobody takes them, one second's worth. Older samples
+/// are dropped so that headless runs do not grow without bound.
+pub const MAX_PENDING_SAMPLES: usize = 2 * SAMPLE_RATE as usize;
+
+const CYCLES_PER_SECOND: u32 = 4_194_304;
+const FRAME_SEQUENCER_PERIOD: u32 = 8192;
+const WAVE_RAM_RANGE: RangeInclusive<u16> = 0xFF30..=0xFF3F;
+const POWER_REGISTER_INDEX: u16 = 0xFF26;
+
+const READ_MASKS: [u8; 0x17] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, 0xFF, 0x3F, 0x00, 0xFF, 0xBF, 0x7F, 0xFF, 0x9F, 0xFF, 0xBF, 0xFF,
+    0xFF, 0x00, 0x00, 0xBF, 0x00, 0x00, 0x70,
+];
+
+const DUTY_PATTERNS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+#[derive(Debug, Clone, Default)]
+struct Envelope {
+    initial_volume: u8,
+    increase: bool,
+    period: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.initial_volume);
+        state.bool(self.increase);
+        state.u8(self.period);
+        state.u8(self.volume);
+        state.u8(self.timer);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.initial_volume = state.u8()? & 0x0F;
+        self.increase = state.bool()?;
+        self.period = state.u8()? & 0x07;
+        self.volume = state.u8()? & 0x0F;
+        self.timer = state.u8()?;
+        Ok(())
+    }
+
+    const fn configure(&mut self, value: u8) {
+        self.initial_volume = value >> 4;
+        self.increase = value & 0x08 != 0;
+        self.period = value & 0x07;
+    }
+
+    const fn dac_enabled(&self) -> bool {
+        self.initial_volume != 0 || self.increase
+    }
+
+    const fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.period;
+    }
+
+    const fn step(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Length {
+    counter: u16,
+    enabled: bool,
+}
+
+impl Length {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.counter);
+        state.bool(self.enabled);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.counter = state.u16()?;
+        self.enabled = state.bool()?;
+        Ok(())
+    }
+
+    const fn step(&mut self, active: &mut bool) {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            if self.counter == 0 {
+                *active = false;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct SquareChannel {
+    active: bool,
+    duty: u8,
+    duty_step: u8,
+    frequency: u16,
+    timer: i32,
+    length: Length,
+    envelope: Envelope,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_timer: u8,
+    sweep_enabled: bool,
+    shadow_frequency: u16,
+}
+
+impl SquareChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.active);
+        state.u8(self.duty);
+        state.u8(self.duty_step);
+        state.u16(self.frequency);
+        state.i32(self.timer);
+        self.length.save_state(state);
+        self.envelope.save_state(state);
+        state.u8(self.sweep_period);
+        state.bool(self.sweep_negate);
+        state.u8(self.sweep_shift);
+        state.u8(self.sweep_timer);
+        state.bool(self.sweep_enabled);
+        state.u16(self.shadow_frequency);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.active = state.bool()?;
+        self.duty = state.u8()? & 0x03;
+        self.duty_step = state.u8()? & 0x07;
+        self.frequency = state.u16()? & 0x07FF;
+        self.timer = state.i32()?;
+        self.length.load_state(state)?;
+        self.envelope.load_state(state)?;
+        self.sweep_period = state.u8()? & 0x07;
+        self.sweep_negate = state.bool()?;
+        self.sweep_shift = state.u8()? & 0x07;
+        self.sweep_timer = state.u8()?;
+        self.sweep_enabled = state.bool()?;
+        self.shadow_frequency = state.u16()? & 0x07FF;
+        Ok(())
+    }
+
+    const fn period(&self) -> i32 {
+        (2048 - self.frequency as i32) * 4
+    }
+
+    const fn tick(&mut self, cycles: i32) {
+        self.timer -= cycles;
+        while self.timer <= 0 {
+            self.timer += self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+    }
+
+    const fn output(&self) -> u8 {
+        if self.active && DUTY_PATTERNS[self.duty as usize] & (1 << self.duty_step) != 0 {
+            self.envelope.volume
+        } else {
+            0
+        }
+    }
+
+    const fn trigger(&mut self) {
+        self.active = self.envelope.dac_enabled();
+        if self.length.counter == 0 {
+            self.length.counter = 64;
+        }
+        self.timer = self.period();
+        self.envelope.trigger();
+        self.shadow_frequency = self.frequency;
+        self.sweep_timer = if self.sweep_period == 0 {
+            8
+        } else {
+            self.sweep_period
+        };
+        self.sweep_enabled = self.sweep_period != 0 || self.sweep_shift != 0;
+        if self.sweep_shift != 0 {
+            self.sweep_frequency();
+        }
+    }
+
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            1 => {
+                self.duty = value >> 6;
+                self.length.counter = 64 - u16::from(value & 0x3F);
+            }
+            2 => {
+                self.envelope.configure(value);
+                if !self.envelope.dac_enabled() {
+                    self.active = false;
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x700) | u16::from(value),
+            _ => {
+                self.frequency = (self.frequency & 0xFF) | (u16::from(value & 0x07) << 8);
+                self.length.enabled = value & 0x40 != 0;
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+        }
+    }
+
+    const fn sweep_frequency(&mut self) -> u16 {
+        let delta = self.shadow_frequency >> self.sweep_shift;
+        let frequency = if self.sweep_negate {
+            self.shadow_frequency.wrapping_sub(delta)
+        } else {
+            self.shadow_frequency + delta
+        };
+        if frequency > 2047 {
+            self.active = false;
+        }
+        frequency
+    }
+
+    const fn step_sweep(&mut self) {
+        self.sweep_timer = self.sweep_timer.saturating_sub(1);
+        if self.sweep_timer != 0 {
+            return;
+        }
+        self.sweep_timer = if self.sweep_period == 0 {
+            8
+        } else {
+            self.sweep_period
+        };
+        if self.sweep_enabled && self.sweep_period != 0 {
+            let frequency = self.sweep_frequency();
+            if frequency <= 2047 && self.sweep_shift != 0 {
+                self.shadow_frequency = frequency;
+                self.frequency = frequency;
+                self.sweep_frequency();
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct WaveChannel {
+    active: bool,
+    dac_enabled: bool,
+    volume_shift: u8,
+    frequency: u16,
+    timer: i32,
+    position: u8,
+    length: Length,
+    ram: [u8; 16],
+}
+
+impl WaveChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.active);
+        state.bool(self.dac_enabled);
+        state.u8(self.volume_shift);
+        state.u16(self.frequency);
+        state.i32(self.timer);
+        state.u8(self.position);
+        self.length.save_state(state);
+        state.bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.active = state.bool()?;
+        self.dac_enabled = state.bool()?;
+        self.volume_shift = state.u8()? & 0x03;
+        self.frequency = state.u16()? & 0x07FF;
+        self.timer = state.i32()?;
+        self.position = state.u8()? % 32;
+        self.length.load_state(state)?;
+        state.bytes_into(&mut self.ram, "wave RAM size")
+    }
+
+    const fn period(&self) -> i32 {
+        (2048 - self.frequency as i32) * 2
+    }
+
+    const fn tick(&mut self, cycles: i32) {
+        self.timer -= cycles;
+        while self.timer <= 0 {
+            self.timer += self.period();
+            self.position = (self.position + 1) % 32;
+        }
+    }
+
+    const fn output(&self) -> u8 {
+        if !self.active || self.volume_shift == 0 {
+            return 0;
+        }
+        let byte = self.ram[(self.position / 2) as usize];
+        let sample = if self.position.is_multiple_of(2) {
+            byte >> 4
+        } else {
+            byte & 0x0F
+        };
+        sample >> (self.volume_shift - 1)
+    }
+
+    const fn trigger(&mut self) {
+        self.active = self.dac_enabled;
+        if self.length.counter == 0 {
+            self.length.counter = 256;
+        }
+        self.timer = self.period();
+        self.position = 0;
+    }
+
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.dac_enabled = value & 0x80 != 0;
+                if !self.dac_enabled {
+                    self.active = false;
+                }
+            }
+            1 => self.length.counter = 256 - u16::from(value),
+            2 => self.volume_shift = (value >> 5) & 0x03,
+            3 => self.frequency = (self.frequency & 0x700) | u16::from(value),
+            _ => {
+                self.frequency = (self.frequency & 0xFF) | (u16::from(value & 0x07) << 8);
+                self.length.enabled = value & 0x40 != 0;
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct NoiseChannel {
+    active: bool,
+    length: Length,
+    envelope: Envelope,
+    shift: u8,
+    narrow: bool,
+    divisor_code: u8,
+    timer: i32,
+    lfsr: u16,
+}
+
+impl NoiseChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.active);
+        self.length.save_state(state);
+        self.envelope.save_state(state);
+        state.u8(self.shift);
+        state.bool(self.narrow);
+        state.u8(self.divisor_code);
+        state.i32(self.timer);
+        state.u16(self.lfsr);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.active = state.bool()?;
+        self.length.load_state(state)?;
+        self.envelope.load_state(state)?;
+        self.shift = state.u8()? & 0x0F;
+        self.narrow = state.bool()?;
+        self.divisor_code = state.u8()? & 0x07;
+        self.timer = state.i32()?;
+        self.lfsr = state.u16()?;
+        Ok(())
+    }
+
+    const fn period(&self) -> i32 {
+        let divisor = if self.divisor_code == 0 {
+            8
+        } else {
+            self.divisor_code as i32 * 16
+        };
+        divisor << self.shift
+    }
+
+    const fn tick(&mut self, cycles: i32) {
+        self.timer -= cycles;
+        while self.timer <= 0 {
+            self.timer += self.period();
+            let bit = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (bit << 14);
+            if self.narrow {
+                self.lfsr = (self.lfsr & !0x40) | (bit << 6);
+            }
+        }
+    }
+
+    const fn output(&self) -> u8 {
+        if self.active && self.lfsr & 1 == 0 {
+            self.envelope.volume
+        } else {
+            0
+        }
+    }
+
+    const fn trigger(&mut self) {
+        self.active = self.envelope.dac_enabled();
+        if self.length.counter == 0 {
+            self.length.counter = 64;
+        }
+        self.timer = self.period();
+        self.envelope.trigger();
+        self.lfsr = 0x7FFF;
+    }
+
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            1 => self.length.counter = 64 - u16::from(value & 0x3F),
+            2 => {
+                self.envelope.configure(value);
+                if !self.envelope.dac_enabled() {
+                    self.active = false;
+                }
+            }
+            3 => {
+                self.shift = value >> 4;
+                self.narrow = value & 0x08 != 0;
+                self.divisor_code = value & 0x07;
+            }
+            _ => {
+                self.length.enabled = value & 0x40 != 0;
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Apu {
+    registers: [u8; 0x17],
+    enabled: bool,
+    square1: SquareChannel,
+    square2: SquareChannel,
+    wave: WaveChannel,
+    noise: NoiseChannel,
+    sequencer_cycles: u32,
+    sequencer_step: u8,
+    sample_cycles: u64,
+    samples: Vec<f32>,
+}
+
+impl Apu {
+    pub fn reset_post_boot(&mut self) {
+        *self = Self::default();
+        self.write(POWER_REGISTER_INDEX, 0x80);
+        self.write(0xFF24, 0x77);
+        self.write(0xFF25, 0xF3);
+    }
+
+    /// Interleaved stereo samples produced since they were last taken, at most
+    /// [`MAX_PENDING_SAMPLES`].
+    #[must_use]
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    /// Saves the registers and channel state; pending output samples are not included.
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.registers);
+        state.bool(self.enabled);
+        self.square1.save_state(state);
+        self.square2.save_state(state);
+        self.wave.save_state(state);
+        self.noise.save_state(state);
+        state.u32(self.sequencer_cycles);
+        state.u8(self.sequencer_step);
+        state.u64(self.sample_cycles);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.bytes_into(&mut self.registers, "sound register count")?;
+        self.enabled = state.bool()?;
+        self.square1.load_state(state)?;
+        self.square2.load_state(state)?;
+        self.wave.load_state(state)?;
+        self.noise.load_state(state)?;
+        self.sequencer_cycles = state.u32()?;
+        self.sequencer_step = state.u8()? % 8;
+        self.sample_cycles = state.u64()?;
+        self.samples.clear();
+        Ok(())
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        if self.enabled {
+            let step = i32::try_from(cycles).unwrap_or(i32::MAX);
+            self.square1.tick(step);
+            self.square2.tick(step);
+            self.wave.tick(step);
+            self.noise.tick(step);
+
+            self.sequencer_cycles += cycles;
+            while self.sequencer_cycles >= FRAME_SEQUENCER_PERIOD {
+                self.sequencer_cycles -= FRAME_SEQUENCER_PERIOD;
+                self.step_sequencer();
+            }
+        }
+
+        self.sample_cycles += u64::from(cycles) * u64::from(SAMPLE_RATE);
+        while self.sample_cycles >= u64::from(CYCLES_PER_SECOND) {
+            self.sample_cycles -= u64::from(CYCLES_PER_SECOND);
+            self.push_sample();
+        }
+    }
+
+    const fn step_sequencer(&mut self) {
+        if self.sequencer_step.is_multiple_of(2) {
+            self.square1.length.step(&mut self.square1.active);
+            self.square2.length.step(&mut self.square2.active);
+            self.wave.length.step(&mut self.wave.active);
+            self.noise.length.step(&mut self.noise.active);
+        }
+        if self.sequencer_step == 2 || self.sequencer_step == 6 {
+            self.square1.step_sweep();
+        }
+        if self.sequencer_step == 7 {
+            self.square1.envelope.step();
+            self.square2.envelope.step();
+            self.noise.envelope.step();
+        }
+        self.sequencer_step = (self.sequencer_step + 1) % 8;
+    }
+
+    fn push_sample(&mut self) {
+        let outputs = [
+            (self.square1.output(), self.square1.envelope.dac_enabled()),
+            (self.square2.output(), self.square2.envelope.dac_enabled()),
+            (self.wave.output(), self.wave.dac_enabled),
+            (self.noise.output(), self.noise.envelope.dac_enabled()),
+        ];
+        let panning = self.registers[0x15];
+        let volume = self.registers[0x14];
+        let mut left = 0.0;
+        let mut right = 0.0;
+
+        if self.enabled {
+            for (channel, (output, dac_enabled)) in outputs.into_iter().enumerate() {
+                if !dac_enabled {
+                    continue;
+                }
+                let analog = 1.0 - f32::from(output) / 7.5;
+                if panning & (0x10 << channel) != 0 {
+                    left += analog;
+                }
+                if panning & (0x01 << channel) != 0 {
+                    right += analog;
+                }
+            }
+        }
+
+        left *= f32::from(((volume >> 4) & 0x07) + 1) / 32.0;
+        right *= f32::from((volume & 0x07) + 1) / 32.0;
+        if self.samples.len() >= MAX_PENDING_SAMPLES {
+            self.samples.drain(..MAX_PENDING_SAMPLES / 2);
+        }
+        self.samples.push(left);
+        self.samples.push(right);
+    }
+
+    #[must_use]
+    pub fn read(&self, address: u16) -> u8 {
+        if WAVE_RAM_RANGE.contains(&address) {
+            return self.wave.ram[usize::from(address - WAVE_RAM_RANGE.start())];
+        }
+
+        let index = usize::from(address - SOUND_REGISTER_RANGE.start());
+        if address == POWER_REGISTER_INDEX {
+            let status = u8::from(self.square1.active)
+                | (u8::from(self.square2.active) << 1)
+                | (u8::from(self.wave.active) << 2)
+                | (u8::from(self.noise.active) << 3);
+            return 0x70 | (u8::from(self.enabled) << 7) | status;
+        }
+
+        READ_MASKS
+            .get(index)
+            .map_or(0xFF, |mask| self.registers[index] | mask)
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        if WAVE_RAM_RANGE.contains(&address) {
+            self.wave.ram[usize::from(address - WAVE_RAM_RANGE.start())] = value;
+            return;
+        }
+
+        if address == POWER_REGISTER_INDEX {
+            let enabled = value & 0x80 != 0;
+            if !enabled && self.enabled {
+                let wave_ram = self.wave.ram;
+                let samples = std::mem::take(&mut self.samples);
+                *self = Self {
+                    samples,
+                    sample_cycles: self.sample_cycles,
+                    ..Self::default()
+                };
+                self.wave.ram = wave_ram;
+            } else if enabled && !self.enabled {
+                self.sequencer_step = 0;
+            }
+            self.enabled = enabled;
+            return;
+        }
+
+        let index = usize::from(address - SOUND_REGISTER_RANGE.start());
+        if !self.enabled || index >= self.registers.len() {
+            return;
+        }
+        self.registers[index] = value;
+
+        match address {
+            0xFF10 => {
+                self.square1.sweep_period = (value >> 4) & 0x07;
+                self.square1.sweep_negate = value & 0x08 != 0;
+                self.square1.sweep_shift = value & 0x07;
+            }
+            0xFF11..=0xFF14 => self.square1.write(address - 0xFF10, value),
+            0xFF16..=0xFF19 => self.square2.write(address - 0xFF15, value),
+            0xFF1A..=0xFF1E => self.wave.write(address - 0xFF1A, value),
+            0xFF20..=0xFF23 => self.noise.write(address - 0xFF1F, value),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trigger_sets_channel_status() {
+        let mut apu = Apu::default();
+        apu.reset_post_boot();
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF14, 0x80);
+        assert_eq!(0xF1, apu.read(POWER_REGISTER_INDEX));
+    }
+
+    #[test]
+    fn test_length_counter_disables_channel() {
+        let mut apu = Apu::default();
+        apu.reset_post_boot();
+        apu.write(0xFF17, 0xF0);
+        apu.write(0xFF16, 0x3F);
+        apu.write(0xFF19, 0xC0);
+        apu.tick(FRAME_SEQUENCER_PERIOD * 2);
+        assert_eq!(0xF0, apu.read(POWER_REGISTER_INDEX));
+    }
+
+    #[test]
+    fn test_samples_are_produced_at_sample_rate() {
+        let mut apu = Apu::default();
+        apu.reset_post_boot();
+        apu.tick(CYCLES_PER_SECOND / 64);
+        assert_eq!(2 * SAMPLE_RATE as usize / 64, apu.take_samples().len());
+
+        apu.tick(CYCLES_PER_SECOND * 3);
+        assert!(apu.samples().len() <= MAX_PENDING_SAMPLES);
+    }
+
+    #[test]
+    fn test_load_state_masks_corrupt_fields() {
+        let mut apu = Apu::default();
+        apu.reset_post_boot();
+        apu.write(0xFF10, 0x11);
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF14, 0x87);
+        apu.write(0xFF1A, 0x80);
+        apu.write(0xFF1C, 0x20);
+        apu.write(0xFF1E, 0x80);
+        apu.square1.sweep_period = 0xFF;
+        apu.square1.sweep_shift = 0xFF;
+        apu.square1.envelope.volume = 0xFF;
+        apu.wave.volume_shift = 0xFF;
+        let mut state = StateWriter::default();
+        apu.save_state(&mut state);
+        let state = state.into_bytes();
+
+        let mut loaded = Apu::default();
+        loaded.load_state(&mut StateReader::new(&state)).unwrap();
+        assert_eq!(0x07, loaded.square1.sweep_shift);
+        assert_eq!(0x03, loaded.wave.volume_shift);
+        loaded.tick(FRAME_SEQUENCER_PERIOD * 8);
+    }
+}
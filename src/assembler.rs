@@ -0,0 +1,805 @@
+//! An SM83 assembler for RGBDS-style source, used to build test ROMs.
+//!
+//! Source is assembled in two passes, the first to find the address of every label. Operands
+//! are sums of numbers, labels, character literals and `$`; numbers are decimal or prefixed
+//! with `$` or `0x` for hex and `%` or `0b` for binary. The instruction encodings come from
+//! the disassembler, so the two accept the same syntax.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::cpu::{
+    AddTarget, CpTarget, HighTarget, Instruction, LdaTarget, LdfaTarget, LogicTarget, SubTarget,
+};
+use crate::memory_map::{
+    CARTRIDGE_TYPE_INDEX, CHECKSUM_INDEX, COMPLEMENT_CHECK_INDEX, EXECUTION_START_INDEX,
+    GAME_TITLE_INDEX, NINTENDO_SCROLL_INDEX, ROM_BANK_RANGE, ROM_SIZE_INDEX,
+    SWITCHABLE_ROM_BANK_RANGE,
+};
+
+const BANK_SIZE: usize = 0x4000;
+/// The highest ROM bank an MBC5, the largest cartridge, can map.
+const MAX_ROM_BANK: u16 = 0x1FF;
+const SENTINEL: u8 = 0xA5;
+const ALU_MNEMONICS: [&str; 8] = ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"];
+const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+const DEFAULT_ENTRY: [u8; 4] = [0x00, 0xC3, 0x50, 0x01];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Immediate {
+    None,
+    Byte,
+    Word,
+    High,
+    Relative,
+    Signed,
+}
+
+/// One encoding, with its operands written the way [`Instruction`]'s `Display` prints them
+/// and the immediate replaced by `n`.
+#[derive(Debug)]
+struct Template {
+    mnemonic: String,
+    operands: Vec<String>,
+    opcode: Vec<u8>,
+    immediate: Immediate,
+}
+
+const fn immediate(instruction: Instruction) -> Immediate {
+    match instruction {
+        Instruction::LDN(..)
+        | Instruction::ADD(AddTarget::Value(_))
+        | Instruction::ADC(AddTarget::Value(_))
+        | Instruction::SUB(SubTarget::Value(_))
+        | Instruction::SBC(SubTarget::Value(_))
+        | Instruction::CP(CpTarget::Value(_))
+        | Instruction::AND(LogicTarget::Value(_))
+        | Instruction::OR(LogicTarget::Value(_))
+        | Instruction::XOR(LogicTarget::Value(_)) => Immediate::Byte,
+        Instruction::LD16(..)
+        | Instruction::JP(..)
+        | Instruction::CALL(..)
+        | Instruction::LDA(LdaTarget::Addr(_))
+        | Instruction::LDFA(LdfaTarget::Addr(_))
+        | Instruction::LDNNSP(_) => Immediate::Word,
+        Instruction::LDHA(HighTarget::Addr(_)) | Instruction::LDHFA(HighTarget::Addr(_)) => {
+            Immediate::High
+        }
+        Instruction::JR(..) => Immediate::Relative,
+        Instruction::LDHLSP(_) | Instruction::ADDSP(_) => Immediate::Signed,
+        _ => Immediate::None,
+    }
+}
+
+fn templates() -> Vec<Template> {
+    let prefixes = [None, Some(0xCB)];
+    let mut templates = Vec::new();
+    for prefix in prefixes {
+        for opcode in 0..=u8::MAX {
+            if prefix.is_none() && opcode == 0xCB {
+                continue;
+            }
+            let mut bytes = prefix.into_iter().chain([opcode]).chain([SENTINEL; 2]);
+            let instruction = Instruction::decode(|| bytes.next().unwrap_or_default());
+            if matches!(instruction, Instruction::ILLEGAL(_)) {
+                continue;
+            }
+
+            let immediate = immediate(instruction);
+            let sentinel = i16::from(SENTINEL.cast_signed());
+            let text = instruction.to_string();
+            let text = match immediate {
+                Immediate::None => text,
+                Immediate::Byte => text.replace(&format!("${SENTINEL:02x}"), "n"),
+                Immediate::Word => text.replace(&format!("${SENTINEL:02x}{SENTINEL:02x}"), "n"),
+                Immediate::High => text.replace(&format!("$ff{SENTINEL:02x}"), "n"),
+                Immediate::Relative => text.replace(&format!("${:+}", sentinel + 2), "n"),
+                Immediate::Signed => text
+                    .replace(&format!("sp{sentinel}"), "sp+n")
+                    .replace(&sentinel.to_string(), "n"),
+            };
+            let (mnemonic, operands) = text.split_once(' ').unwrap_or((&text, ""));
+            let mut opcode: Vec<u8> = prefix.into_iter().chain([opcode]).collect();
+            if instruction == Instruction::STOP {
+                opcode.push(0x00);
+            }
+            templates.push(Template {
+                mnemonic: mnemonic.to_string(),
+                operands: split_operands(operands),
+                opcode,
+                immediate,
+            });
+        }
+    }
+    templates
+}
+
+fn split_operands(text: &str) -> Vec<String> {
+    let mut operands = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for character in text.chars() {
+        match character {
+            '"' => {
+                quoted = !quoted;
+                current.push(character);
+            }
+            ',' if !quoted => operands.push(std::mem::take(&mut current).trim().to_string()),
+            _ => current.push(character),
+        }
+    }
+    if !current.trim().is_empty() || !operands.is_empty() {
+        operands.push(current.trim().to_string());
+    }
+    operands
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut characters = text.chars();
+    while let Some(character) = characters.next() {
+        if character != '\\' {
+            unescaped.push(character);
+            continue;
+        }
+        match characters.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('0') => unescaped.push('\0'),
+            Some(escaped) => unescaped.push(escaped),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (index, character) in line.char_indices() {
+        match character {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn is_identifier(text: &str) -> bool {
+    !text.is_empty()
+        && !text.starts_with(|character: char| character.is_ascii_digit())
+        && text
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || matches!(character, '_' | '.'))
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let text = text.trim();
+    if let Some(hex) = text.strip_prefix('$').or_else(|| text.strip_prefix("0x")) {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = text.strip_prefix('%').or_else(|| text.strip_prefix("0b")) {
+        i64::from_str_radix(binary, 2).ok()
+    } else if let Some(character) = text
+        .strip_prefix('\'')
+        .and_then(|text| text.strip_suffix('\''))
+    {
+        let mut characters = character.chars();
+        let value = characters.next()?;
+        characters
+            .next()
+            .is_none()
+            .then(|| i64::from(u32::from(value)))
+    } else {
+        text.parse().ok()
+    }
+}
+
+/// Classifies an operand as a register, condition or memory form, or an `n`-shaped immediate
+/// carrying its expression.
+fn classify(operand: &str) -> (String, Option<String>) {
+    let compact: String = operand
+        .chars()
+        .filter(|character| !character.is_whitespace())
+        .collect();
+    let lower = compact.to_ascii_lowercase();
+    match lower.as_str() {
+        "a" | "b" | "c" | "d" | "e" | "h" | "l" | "af" | "bc" | "de" | "hl" | "sp" | "nz" | "z"
+        | "nc" | "[hl]" | "[bc]" | "[de]" | "[c]" | "[hl+]" | "[hl-]" => (lower, None),
+        "[hli]" => ("[hl+]".to_string(), None),
+        "[hld]" => ("[hl-]".to_string(), None),
+        "[$ff00+c]" | "[0xff00+c]" => ("[c]".to_string(), None),
+        _ if lower.starts_with('[') && lower.ends_with(']') => (
+            "[n]".to_string(),
+            Some(operand.trim()[1..operand.trim().len() - 1].to_string()),
+        ),
+        _ if lower.starts_with("sp+") => ("sp+n".to_string(), Some(compact[3..].to_string())),
+        _ if lower.starts_with("sp-") => ("sp+n".to_string(), Some(compact[2..].to_string())),
+        _ => ("n".to_string(), Some(operand.trim().to_string())),
+    }
+}
+
+#[derive(Debug)]
+struct Chunk {
+    bank: u16,
+    address: u16,
+    bytes: Vec<u8>,
+    line: usize,
+}
+
+struct Pass<'a> {
+    templates: &'a [Template],
+    known: Option<&'a HashMap<String, u16>>,
+    labels: HashMap<String, u16>,
+    scope: String,
+    chunks: Vec<Chunk>,
+    open: bool,
+    rom: bool,
+    bank: u16,
+    address: u16,
+    line: usize,
+}
+
+impl Pass<'_> {
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, AssembleError> {
+        Err(AssembleError {
+            line: self.line,
+            message: message.into(),
+        })
+    }
+
+    fn qualify(&self, name: &str) -> String {
+        if name.starts_with('.') {
+            format!("{}{name}", self.scope)
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn term(&self, term: &str) -> Result<i64, AssembleError> {
+        let term = term.trim();
+        if term == "$" || term == "@" {
+            return Ok(i64::from(self.address));
+        }
+        if let Some(value) = parse_number(term) {
+            return Ok(value);
+        }
+        if !is_identifier(term) {
+            return self.error(format!("invalid expression: {term}"));
+        }
+        let Some(known) = self.known else {
+            return Ok(0);
+        };
+        match known.get(&self.qualify(term)) {
+            Some(&address) => Ok(i64::from(address)),
+            None => self.error(format!("unknown label: {term}")),
+        }
+    }
+
+    /// Evaluates a sum of numbers, labels and `$`.
+    fn evaluate(&self, expression: &str) -> Result<i64, AssembleError> {
+        let expression = expression.trim();
+        if expression.is_empty() {
+            return self.error("missing value");
+        }
+        let mut total = 0;
+        let mut sign = 1;
+        let mut term = String::new();
+        let mut quoted = false;
+        for character in expression.chars() {
+            match character {
+                '\'' => {
+                    quoted = !quoted;
+                    term.push(character);
+                }
+                '+' | '-' if !quoted && !term.trim().is_empty() => {
+                    total += sign * self.term(&term)?;
+                    term.clear();
+                    sign = if character == '-' { -1 } else { 1 };
+                }
+                '-' if !quoted => sign = -sign,
+                '+' if !quoted => {}
+                _ => term.push(character),
+            }
+        }
+        Ok(total + sign * self.term(&term)?)
+    }
+
+    fn byte(&self, expression: &str) -> Result<u8, AssembleError> {
+        let value = self.evaluate(expression)?;
+        u8::try_from(value)
+            .or_else(|_| i8::try_from(value).map(i8::cast_unsigned))
+            .or_else(|_| self.error(format!("{value} does not fit in a byte")))
+    }
+
+    fn signed(&self, expression: &str) -> Result<u8, AssembleError> {
+        let value = self.evaluate(expression)?;
+        i8::try_from(value)
+            .map(i8::cast_unsigned)
+            .or_else(|_| self.error(format!("{value} does not fit in a signed byte")))
+    }
+
+    fn word(&self, expression: &str) -> Result<u16, AssembleError> {
+        let value = self.evaluate(expression)?;
+        u16::try_from(value)
+            .or_else(|_| i16::try_from(value).map(i16::cast_unsigned))
+            .or_else(|_| self.error(format!("{value} does not fit in a word")))
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), AssembleError> {
+        if !self.rom {
+            return self.error("only ds is allowed outside ROM sections");
+        }
+        if !self.open {
+            self.chunks.push(Chunk {
+                bank: self.bank,
+                address: self.address,
+                bytes: Vec::new(),
+                line: self.line,
+            });
+            self.open = true;
+        }
+        if let Some(chunk) = self.chunks.last_mut() {
+            chunk.bytes.extend_from_slice(bytes);
+        }
+        self.advance(bytes.len())
+    }
+
+    fn advance(&mut self, length: usize) -> Result<(), AssembleError> {
+        let end = usize::from(self.address) + length;
+        match u16::try_from(end) {
+            Ok(address) => {
+                self.address = address;
+                Ok(())
+            }
+            Err(_) => self.error("address past $ffff"),
+        }
+    }
+
+    fn define(&mut self, name: &str) -> Result<(), AssembleError> {
+        if !is_identifier(name) {
+            return self.error(format!("invalid label: {name}"));
+        }
+        if !name.starts_with('.') {
+            self.scope = name.to_string();
+        }
+        let name = self.qualify(name);
+        if self.labels.insert(name.clone(), self.address).is_some() {
+            return self.error(format!("label defined twice: {name}"));
+        }
+        Ok(())
+    }
+
+    fn section(&mut self, operands: &[String]) -> Result<(), AssembleError> {
+        let [name, kind, rest @ ..] = operands else {
+            return self.error("section expects a name and a type");
+        };
+        if !(name.starts_with('"') && name.ends_with('"') && name.len() >= 2) {
+            return self.error("section name must be quoted");
+        }
+        let lower = kind.to_ascii_lowercase();
+        let (kind, address) = match lower.split_once('[') {
+            Some((kind, address)) => {
+                let address = address.strip_suffix(']').unwrap_or(address);
+                (kind.trim().to_string(), Some(self.word(address)?))
+            }
+            None => (lower.trim().to_string(), None),
+        };
+        let (rom, start, mut bank) = match kind.as_str() {
+            "rom0" => (true, ROM_BANK_RANGE.start, 0),
+            "romx" => (true, SWITCHABLE_ROM_BANK_RANGE.start, 1),
+            "vram" => (false, 0x8000, 0),
+            "sram" => (false, 0xA000, 0),
+            "wram0" => (false, 0xC000, 0),
+            "wramx" => (false, 0xD000, 1),
+            "hram" => (false, 0xFF80, 0),
+            _ => return self.error(format!("unknown section type: {kind}")),
+        };
+        for option in rest {
+            let lower = option.to_ascii_lowercase();
+            let Some(value) = lower
+                .strip_prefix("bank[")
+                .and_then(|value| value.strip_suffix(']'))
+            else {
+                return self.error(format!("unknown section option: {option}"));
+            };
+            bank = self.word(value)?;
+        }
+        if rom && bank > MAX_ROM_BANK {
+            return self.error(format!(
+                "ROM bank ${bank:x} is past the last bank, ${MAX_ROM_BANK:x}"
+            ));
+        }
+        self.rom = rom;
+        self.bank = bank;
+        self.address = address.unwrap_or(start);
+        self.open = false;
+        Ok(())
+    }
+
+    fn directive(&mut self, mnemonic: &str, operands: &[String]) -> Result<bool, AssembleError> {
+        match mnemonic {
+            "db" => {
+                for operand in operands {
+                    if let Some(text) = operand
+                        .strip_prefix('"')
+                        .and_then(|text| text.strip_suffix('"'))
+                    {
+                        self.emit(unescape(text).as_bytes())?;
+                    } else {
+                        let byte = self.byte(operand)?;
+                        self.emit(&[byte])?;
+                    }
+                }
+            }
+            "dw" => {
+                for operand in operands {
+                    let word = self.word(operand)?;
+                    self.emit(&word.to_le_bytes())?;
+                }
+            }
+            "ds" => {
+                let length = match operands.first() {
+                    Some(length) => self.word(length)?,
+                    None => return self.error("ds expects a length"),
+                };
+                if self.rom {
+                    let fill = operands.get(1).map_or(Ok(0), |fill| self.byte(fill))?;
+                    self.emit(&vec![fill; usize::from(length)])?;
+                } else {
+                    self.advance(usize::from(length))?;
+                }
+            }
+            "org" => {
+                let [address] = operands else {
+                    return self.error("org expects an address");
+                };
+                self.address = self.word(address)?;
+                self.open = false;
+            }
+            "section" => self.section(operands)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn find(
+        &self,
+        mnemonic: &str,
+        operands: &[(String, Option<String>)],
+    ) -> Result<(&Template, Option<String>), AssembleError> {
+        let mut found = None;
+        'templates: for template in self.templates {
+            if template.mnemonic != mnemonic || template.operands.len() != operands.len() {
+                continue;
+            }
+            let mut expression = None;
+            for (expected, (kind, value)) in template.operands.iter().zip(operands) {
+                if expected == kind {
+                    expression = expression.or_else(|| value.clone());
+                } else if let (Some(fixed), "n", Some(value)) =
+                    (parse_number(expected), kind.as_str(), value)
+                {
+                    if self.evaluate(value)? != fixed {
+                        continue 'templates;
+                    }
+                } else {
+                    continue 'templates;
+                }
+            }
+            found = Some((template, expression));
+            break;
+        }
+        found.map_or_else(
+            || self.error(format!("invalid operands for {mnemonic}")),
+            Ok,
+        )
+    }
+
+    fn instruction(&mut self, mnemonic: &str, operands: &[String]) -> Result<(), AssembleError> {
+        let mut mnemonic = mnemonic.to_string();
+        let mut operands: Vec<(String, Option<String>)> =
+            operands.iter().map(|operand| classify(operand)).collect();
+        match mnemonic.as_str() {
+            "ldi" | "ldd" => {
+                let replacement = if mnemonic == "ldi" { "[hl+]" } else { "[hl-]" };
+                for (kind, _) in &mut operands {
+                    if kind == "[hl]" {
+                        *kind = replacement.to_string();
+                    }
+                }
+                mnemonic = "ld".to_string();
+            }
+            "ld" if operands.iter().any(|(kind, _)| kind == "[c]") => mnemonic = "ldh".to_string(),
+            "jp" if operands.len() == 1 && operands[0].0 == "[hl]" => {
+                operands[0].0 = "hl".to_string();
+            }
+            _ if ALU_MNEMONICS.contains(&mnemonic.as_str()) && operands.len() == 1 => {
+                operands.insert(0, ("a".to_string(), None));
+            }
+            _ => {}
+        }
+
+        let (template, expression) = self.find(&mnemonic, &operands)?;
+        let mut bytes = template.opcode.clone();
+        let immediate = template.immediate;
+        let value = || expression.clone().unwrap_or_default();
+        match immediate {
+            Immediate::None => {}
+            Immediate::Byte => bytes.push(self.byte(&value())?),
+            Immediate::Signed => bytes.push(self.signed(&value())?),
+            Immediate::Word => bytes.extend_from_slice(&self.word(&value())?.to_le_bytes()),
+            Immediate::High => {
+                let address = self.evaluate(&value())?;
+                let offset = if (0xFF00..=0xFFFF).contains(&address) {
+                    address - 0xFF00
+                } else {
+                    address
+                };
+                match u8::try_from(offset) {
+                    Ok(offset) => bytes.push(offset),
+                    Err(_) => return self.error(format!("${address:x} is not in the $ff00 page")),
+                }
+            }
+            Immediate::Relative => {
+                let target = self.evaluate(&value())?;
+                let offset = target - i64::from(self.address) - 2;
+                match i8::try_from(offset) {
+                    Ok(offset) => bytes.push(offset.cast_unsigned()),
+                    Err(_) if self.known.is_none() => bytes.push(0),
+                    Err(_) => return self.error(format!("jump target out of range ({offset})")),
+                }
+            }
+        }
+        self.emit(&bytes)
+    }
+
+    fn line(&mut self, text: &str) -> Result<(), AssembleError> {
+        let mut text = strip_comment(text).trim();
+        if let Some((label, rest)) = text.split_once(':') {
+            if is_identifier(label.trim()) {
+                self.define(label.trim())?;
+                text = rest.trim_start_matches(':').trim();
+            }
+        }
+        if text.is_empty() {
+            return Ok(());
+        }
+        let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let mnemonic = mnemonic.to_ascii_lowercase();
+        let operands = split_operands(rest);
+        if self.directive(&mnemonic, &operands)? {
+            return Ok(());
+        }
+        self.instruction(&mnemonic, &operands)
+    }
+
+    fn run(mut self, source: &str) -> Result<(Vec<Chunk>, HashMap<String, u16>), AssembleError> {
+        for (index, text) in source.lines().enumerate() {
+            self.line = index + 1;
+            self.line(text)?;
+        }
+        Ok((self.chunks, self.labels))
+    }
+}
+
+fn assemble_chunks(source: &str) -> Result<Vec<Chunk>, AssembleError> {
+    let templates = templates();
+    let pass = |known| Pass {
+        templates: &templates,
+        known,
+        labels: HashMap::new(),
+        scope: String::new(),
+        chunks: Vec::new(),
+        open: false,
+        rom: true,
+        bank: 0,
+        address: 0,
+        line: 0,
+    };
+    let (_, labels) = pass(None).run(source)?;
+    let (chunks, _) = pass(Some(&labels)).run(source)?;
+    Ok(chunks)
+}
+
+/// Assembles SM83 source in RGBDS syntax, returning the emitted bytes in source order.
+///
+/// Besides instructions, `db`, `dw`, `ds`, `org` and `section` are understood, and labels
+/// starting with `.` are local to the preceding label.
+///
+/// # Errors
+///
+/// Returns the first line that fails to assemble.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    Ok(assemble_chunks(source)?
+        .into_iter()
+        .flat_map(|chunk| chunk.bytes)
+        .collect())
+}
+
+/// Assembles source into a cartridge image with a valid header and checksums.
+///
+/// Sections are placed by bank and address. When nothing is placed at $0100 the entry
+/// point jumps to $0150. Images of more than two banks are marked as MBC5.
+///
+/// # Errors
+///
+/// Fails when the source does not assemble or sections overlap each other or the header.
+pub fn assemble_rom(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let chunks = assemble_chunks(source)?;
+    let banks = chunks
+        .iter()
+        .map(|chunk| usize::from(chunk.bank) + 1)
+        .max()
+        .unwrap_or(0)
+        .max(2)
+        .next_power_of_two();
+    let mut rom = vec![0; banks * BANK_SIZE];
+    let mut used = vec![false; rom.len()];
+    let logo = usize::from(*NINTENDO_SCROLL_INDEX.start());
+    let header = logo..=usize::from(*CHECKSUM_INDEX.end());
+    used[header].fill(true);
+
+    for chunk in &chunks {
+        let error = |message: &str| AssembleError {
+            line: chunk.line,
+            message: message.to_string(),
+        };
+        let range = if chunk.bank == 0 {
+            ROM_BANK_RANGE
+        } else {
+            SWITCHABLE_ROM_BANK_RANGE
+        };
+        let end = usize::from(chunk.address) + chunk.bytes.len();
+        if !range.contains(&chunk.address) || end > usize::from(range.end) {
+            return Err(error("section does not fit in its bank"));
+        }
+        let start = usize::from(chunk.bank) * BANK_SIZE + usize::from(chunk.address - range.start);
+        for (offset, &byte) in (start..).zip(&chunk.bytes) {
+            if std::mem::replace(&mut used[offset], true) {
+                return Err(error("section overlaps other data or the header"));
+            }
+            rom[offset] = byte;
+        }
+    }
+
+    let entry = usize::from(*EXECUTION_START_INDEX.start());
+    if !used[entry] {
+        rom[entry..entry + DEFAULT_ENTRY.len()].copy_from_slice(&DEFAULT_ENTRY);
+    }
+    rom[logo..logo + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+    rom[usize::from(CARTRIDGE_TYPE_INDEX)] = if banks > 2 { 0x19 } else { 0x00 };
+    rom[usize::from(ROM_SIZE_INDEX)] = u8::try_from(banks.trailing_zeros() - 1).unwrap_or(0);
+
+    let complement = usize::from(COMPLEMENT_CHECK_INDEX);
+    rom[complement] = rom[usize::from(*GAME_TITLE_INDEX.start())..complement]
+        .iter()
+        .fold(0u8, |sum, &byte| sum.wrapping_sub(byte).wrapping_sub(1));
+    // The checksum bytes are still zero, so they drop out of the sum.
+    let checksum = rom
+        .iter()
+        .fold(0u16, |sum, &byte| sum.wrapping_add(u16::from(byte)));
+    let checksum_start = usize::from(*CHECKSUM_INDEX.start());
+    rom[checksum_start..checksum_start + 2].copy_from_slice(&checksum.to_be_bytes());
+    Ok(rom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+    use crate::disassembler::disassemble;
+
+    #[test]
+    fn test_assemble_snippet() {
+        assert_eq!(Ok(vec![0x3E, 0x05, 0x80]), assemble("ld a, 5\nadd a, b"));
+        assert_eq!(
+            Ok(vec![0x2A, 0x32, 0xE0, 0x40, 0xF2, 0xCB, 0x7E, 0x10, 0x00, 0xFF]),
+            assemble("ld a, [hl+]\nldd [hl], a\nldh [$ff40], a\nld a, [$ff00+c]\nbit 7, [hl]\nstop\nrst $38")
+        );
+        assert_eq!(
+            Ok(vec![0xF8, 0xFE, 0xE8, 0x03]),
+            assemble("ld hl, sp-2\nadd sp, 3")
+        );
+        assert_eq!(
+            Ok(vec![0x90, 0xFE, 0x10]),
+            assemble("sub b ; short form\ncp 16")
+        );
+    }
+
+    #[test]
+    fn test_labels_and_directives() {
+        let source = "
+            org $0150
+        start:
+            ld b, 3
+        .loop:
+            dec b
+            jr nz, .loop
+            jp start
+        data:
+            db 1, \"Hi\\n\", -1
+            dw data
+        ";
+        assert_eq!(
+            Ok(vec![
+                0x06, 0x03, 0x05, 0x20, 0xFD, 0xC3, 0x50, 0x01, 0x01, b'H', b'i', b'\n', 0xFF,
+                0x58, 0x01,
+            ]),
+            assemble(source)
+        );
+        let error = assemble("jr nowhere").unwrap_err();
+        assert_eq!(1, error.line);
+        assert!(assemble("ld a, [hl], b").is_err());
+        assert_eq!(Ok(vec![0xE8, 0x80]), assemble("add sp, -128"));
+        assert!(assemble("add sp, 200").is_err());
+    }
+
+    #[test]
+    fn test_round_trips_every_opcode() {
+        let unprefixed = (0..=u8::MAX).map(|opcode| match opcode {
+            0x10 => vec![0x10, 0x00],
+            _ => vec![opcode, 0x34, 0x12],
+        });
+        let prefixed = (0..=u8::MAX).map(|opcode| vec![0xCB, opcode]);
+        for bytes in unprefixed.filter(|bytes| bytes[0] != 0xCB).chain(prefixed) {
+            let (text, length) = disassemble(bytes.as_slice(), 0);
+            if text.starts_with("db") {
+                continue;
+            }
+            assert_eq!(
+                Ok(bytes[..usize::from(length)].to_vec()),
+                assemble(&text),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_rom_image_has_valid_header() {
+        let rom = assemble_rom(
+            "section \"main\", rom0[$150]\nhalt\nsection \"far\", romx, bank[3]\ndb 1",
+        )
+        .unwrap();
+        assert_eq!(0x10000, rom.len());
+        assert_eq!(0x76, rom[0x150]);
+        assert_eq!(1, rom[0xC000]);
+        let cartridge = Cartridge::from_rom(rom.clone()).unwrap();
+        let complement = rom[0x134..0x14D]
+            .iter()
+            .fold(0u8, |sum, &byte| sum.wrapping_sub(byte).wrapping_sub(1));
+        assert_eq!(complement, rom[0x14D]);
+        let checksum = rom
+            .iter()
+            .enumerate()
+            .filter(|&(offset, _)| offset != 0x14E && offset != 0x14F)
+            .fold(0u16, |sum, (_, &byte)| sum.wrapping_add(u16::from(byte)));
+        assert_eq!(checksum, cartridge.header().checksum);
+        assert!(assemble_rom("org $140\nnop").is_err());
+        assert!(assemble_rom("section \"far\", romx, bank[$ffff]\nnop").is_err());
+
+        let rom = assemble_rom(
+            "section \"low\", wram0\nlow: ds 1\nsection \"high\", wramx\nhigh: ds 1
+            section \"main\", rom0[$150]\nld a, [low]\nld a, [high]",
+        )
+        .unwrap();
+        assert_eq!([0xFA, 0x00, 0xC0, 0xFA, 0x00, 0xD0], rom[0x150..0x156]);
+    }
+}
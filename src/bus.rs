@@ -0,0 +1,723 @@
+use std::ops::Range;
+
+use crate::apu::Apu;
+use crate::cartridge::Cartridge;
+use crate::cheat::{Cheat, CheatCode, GameSharkBank};
+use crate::compatibility::{self, CompatibilityPalette};
+use crate::hdma::{Hdma, BLOCK_SIZE};
+use crate::joypad::Joypad;
+use crate::memory_map::{
+    BG_COLOR_PALETTE_INDEX_REGISTER_INDEX, BOOT_ROM_DISABLE_REGISTER_INDEX, DIVIDER_REGISTER_INDEX,
+    DMA_REGISTER_INDEX, ECHO_INTERNAL_RAM_RANGE, EMPTY2_RANGE, HDMA_LENGTH_REGISTER_INDEX,
+    HDMA_SOURCE_HIGH_REGISTER_INDEX, INTERNAL_RAM_RANGE, INTERUPT_ENABLE_REGISTER_INDEX,
+    INTERUPT_FLAG_REGISTER_INDEX, IO_PORT_RANGE, JOYPAD_REGISTER_INDEX, K8_INTERNAL_RAM_RANGE,
+    KEY1_REGISTER_INDEX, LCDC_REGISTER_INDEX, OBJ_PRIORITY_MODE_REGISTER_INDEX, ROM_BANK_RANGE,
+    SERIAL_CONTROL_REGISTER_INDEX, SERIAL_DATA_REGISTER_INDEX, SOUND_REGISTER_RANGE,
+    SPRITE_ATTRIB_RANGE, SWITCHABLE_RAM_BANK_RANGE, SWITCHABLE_ROM_BANK_RANGE,
+    TIMER_CONTROL_REGISTER_INDEX, VIDEO_RAM_RANGE, VRAM_BANK_REGISTER_INDEX,
+    WINDOW_X_REGISTER_INDEX, WRAM_BANK_REGISTER_INDEX,
+};
+use crate::model::Model;
+use crate::ppu::Ppu;
+use crate::serial::Serial;
+use crate::sgb::Sgb;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::timer::Timer;
+use crate::watchpoint::{Access, WatchHit, Watchpoint};
+
+pub trait MemoryBus {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    fn peek(&self, address: u16) -> u8;
+}
+
+impl MemoryBus for [u8] {
+    fn read(&mut self, address: u16) -> u8 {
+        self[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self[address as usize] = value;
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self[address as usize]
+    }
+}
+
+impl<const N: usize> MemoryBus for [u8; N] {
+    fn read(&mut self, address: u16) -> u8 {
+        self[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self[address as usize] = value;
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self[address as usize]
+    }
+}
+
+const WRAM_BANK_SIZE: u16 = 0x1000;
+const WRAM_BANKS: usize = 8;
+/// The switchable WRAM bank.
+const WRAMX_RANGE: Range<u16> = 0xD000..0xE000;
+/// The cartridge header, which stays visible while a CGB boot ROM is mapped around it.
+const CARTRIDGE_HEADER_RANGE: Range<u16> = 0x0100..0x0200;
+/// CPU cycles a VRAM DMA block takes at normal speed; twice as many in double speed.
+const HDMA_BLOCK_CYCLES: u32 = 32;
+
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone)]
+pub struct Bus {
+    model: Model,
+    /// Whether the cartridge runs in CGB mode, with double speed and banked VRAM and WRAM.
+    cgb: bool,
+    double_speed: bool,
+    speed_switch_armed: bool,
+    cartridge: Cartridge,
+    ppu: Ppu,
+    apu: Apu,
+    timer: Timer,
+    joypad: Joypad,
+    serial: Serial,
+    hdma: Hdma,
+    /// CPU cycles the CPU must stay halted for VRAM DMA copies.
+    dma_stall: u32,
+    /// Eight banks in CGB mode, only the first two are used otherwise.
+    wram: Vec<u8>,
+    wram_bank: u8,
+    hram: Vec<u8>,
+    interrupt_flag: u8,
+    interrupt_enable: u8,
+    boot_rom: Option<Vec<u8>>,
+    boot_rom_mapped: bool,
+    watchpoints: Vec<(usize, Watchpoint)>,
+    next_watchpoint: usize,
+    watch_hits: Vec<WatchHit>,
+    cheats: Vec<(usize, Cheat)>,
+    next_cheat: usize,
+    compatibility_palette: Option<CompatibilityPalette>,
+    /// The SGB's packet receiver, palettes and border, on SGB hardware.
+    sgb: Option<Sgb>,
+}
+
+impl Bus {
+    /// A bus for `model`. On CGB hardware a DMG cartridge gets the colours the boot ROM picks
+    /// for its title.
+    #[must_use]
+    pub fn new(cartridge: Cartridge, model: Model) -> Self {
+        let cgb = model.is_cgb() && cartridge.header().cgb_flag & 0x80 != 0;
+        let compatibility_palette =
+            (model.is_cgb() && !cgb).then(|| compatibility::title_palette(&cartridge));
+        let sgb = (model == Model::Sgb).then(|| Sgb::new(cartridge.header().supports_sgb()));
+        Self {
+            model,
+            cgb,
+            double_speed: false,
+            speed_switch_armed: false,
+            cartridge,
+            ppu: Ppu::new(cgb),
+            apu: Apu::default(),
+            timer: Timer::default(),
+            joypad: Joypad::default(),
+            serial: Serial::default(),
+            hdma: Hdma::default(),
+            dma_stall: 0,
+            wram: vec![0; usize::from(WRAM_BANK_SIZE) * WRAM_BANKS],
+            wram_bank: 0,
+            hram: vec![0; INTERNAL_RAM_RANGE.len()],
+            interrupt_flag: 0,
+            interrupt_enable: 0,
+            boot_rom: None,
+            boot_rom_mapped: false,
+            watchpoints: Vec::new(),
+            next_watchpoint: 1,
+            watch_hits: Vec::new(),
+            cheats: Vec::new(),
+            next_cheat: 1,
+            compatibility_palette,
+            sgb,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.cartridge.reset();
+        self.wram.fill(0);
+        self.wram_bank = 0;
+        self.hram.fill(0);
+        self.double_speed = false;
+        self.speed_switch_armed = false;
+        self.interrupt_enable = 0;
+        self.joypad = Joypad::default();
+        if let Some(sgb) = &mut self.sgb {
+            *sgb = Sgb::new(self.cartridge.header().supports_sgb());
+        }
+        self.serial = Serial::default();
+        self.hdma = Hdma::default();
+        self.dma_stall = 0;
+        self.boot_rom_mapped = self.boot_rom.is_some();
+        self.watch_hits.clear();
+
+        if self.boot_rom_mapped {
+            self.ppu = Ppu::new(self.cgb);
+            self.apu = Apu::default();
+            self.timer = Timer::default();
+            self.interrupt_flag = 0;
+        } else {
+            self.ppu.reset_post_boot();
+            if let Some(palette) = &self.compatibility_palette {
+                self.ppu.set_compatibility_palette(palette);
+            }
+            self.apu.reset_post_boot();
+            self.timer.reset_post_boot();
+            self.interrupt_flag = 0x01;
+        }
+    }
+
+    #[must_use]
+    pub const fn model(&self) -> Model {
+        self.model
+    }
+
+    /// Whether a CGB cartridge runs in CGB mode, which takes CGB hardware.
+    #[must_use]
+    pub const fn cgb(&self) -> bool {
+        self.cgb
+    }
+
+    /// The colours the CGB boot ROM gave a DMG game, when running one on CGB hardware.
+    #[must_use]
+    pub const fn compatibility_palette(&self) -> Option<&CompatibilityPalette> {
+        self.compatibility_palette.as_ref()
+    }
+
+    /// Colours a DMG game on CGB hardware with `palette` from the next reset without a boot
+    /// ROM. Ignored on other hardware and for CGB games.
+    pub const fn set_compatibility_palette(&mut self, palette: CompatibilityPalette) {
+        if self.compatibility_palette.is_some() {
+            self.compatibility_palette = Some(palette);
+        }
+    }
+
+    /// The SGB state on SGB hardware.
+    #[must_use]
+    pub const fn sgb(&self) -> Option<&Sgb> {
+        self.sgb.as_ref()
+    }
+
+    pub const fn sgb_mut(&mut self) -> Option<&mut Sgb> {
+        self.sgb.as_mut()
+    }
+
+    /// Whether the CPU, timer and serial port run at twice the normal clock.
+    #[must_use]
+    pub const fn double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    /// Whether KEY1 asks for the next STOP to switch speed.
+    #[must_use]
+    pub const fn speed_switch_armed(&self) -> bool {
+        self.speed_switch_armed
+    }
+
+    pub(crate) const fn switch_speed(&mut self) {
+        self.double_speed = !self.double_speed;
+        self.speed_switch_armed = false;
+    }
+
+    /// CPU cycles the CPU is halted for by the VRAM DMA copies since the last call. The
+    /// hardware keeps running while it is, so callers must tick the bus for them.
+    pub fn take_dma_stall(&mut self) -> u32 {
+        std::mem::take(&mut self.dma_stall)
+    }
+
+    /// The WRAM bank mapped at 0xD000, which is always 1 outside CGB mode.
+    #[must_use]
+    pub fn wram_bank(&self) -> usize {
+        usize::from(self.wram_bank & 0x07).max(1)
+    }
+
+    fn wram_offset(&self, address: u16) -> usize {
+        let local = (address - K8_INTERNAL_RAM_RANGE.start) % 0x2000;
+        if local < WRAM_BANK_SIZE {
+            usize::from(local)
+        } else {
+            self.wram_bank() * usize::from(WRAM_BANK_SIZE) + usize::from(local - WRAM_BANK_SIZE)
+        }
+    }
+
+    pub fn set_boot_rom(&mut self, boot_rom: Option<Vec<u8>>) {
+        self.boot_rom = boot_rom;
+    }
+
+    #[must_use]
+    pub fn boot_rom(&self) -> Option<&[u8]> {
+        self.boot_rom.as_deref()
+    }
+
+    #[must_use]
+    pub const fn boot_rom_mapped(&self) -> bool {
+        self.boot_rom_mapped
+    }
+
+    /// The bank mapped at `address`, or `None` outside the banked ROM and RAM areas. In CGB
+    /// mode this includes the WRAM bank at 0xD000.
+    #[must_use]
+    pub fn bank_at(&self, address: u16) -> Option<usize> {
+        if ROM_BANK_RANGE.contains(&address) || SWITCHABLE_ROM_BANK_RANGE.contains(&address) {
+            Some(self.cartridge.rom_bank_at(address))
+        } else if SWITCHABLE_RAM_BANK_RANGE.contains(&address) {
+            Some(self.cartridge.current_ram_bank())
+        } else if self.cgb && WRAMX_RANGE.contains(&address) {
+            Some(self.wram_bank())
+        } else {
+            None
+        }
+    }
+
+    /// Adds a watchpoint checked on every read and write through [`MemoryBus`] and returns its
+    /// id. [`MemoryBus::peek`] never triggers watchpoints.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        let id = self.next_watchpoint;
+        self.next_watchpoint += 1;
+        self.watchpoints.push((id, watchpoint));
+        id
+    }
+
+    pub fn remove_watchpoint(&mut self, id: usize) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|&(existing, _)| existing != id);
+        self.watchpoints.len() != count
+    }
+
+    #[must_use]
+    pub fn watchpoints(&self) -> &[(usize, Watchpoint)] {
+        &self.watchpoints
+    }
+
+    /// Returns the watchpoint hits recorded since the last call, oldest first.
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.watch_hits)
+    }
+
+    fn check_watchpoints(
+        &mut self,
+        access: Access,
+        address: u16,
+        bank: Option<usize>,
+        old: u8,
+        value: u8,
+    ) {
+        let new = self.peek(address);
+        for (id, watchpoint) in &self.watchpoints {
+            if watchpoint.matches(access, address, bank, old, new) {
+                self.watch_hits.push(WatchHit {
+                    id: *id,
+                    action: watchpoint.action,
+                    access,
+                    address,
+                    bank,
+                    old,
+                    value,
+                });
+            }
+        }
+    }
+
+    /// Adds a cheat and returns its id. Game Genie codes patch ROM reads, including
+    /// [`MemoryBus::peek`]; GameShark codes write memory whenever vertical blank starts.
+    pub fn add_cheat(&mut self, cheat: Cheat) -> usize {
+        let id = self.next_cheat;
+        self.next_cheat += 1;
+        self.cheats.push((id, cheat));
+        id
+    }
+
+    pub fn remove_cheat(&mut self, id: usize) -> bool {
+        let count = self.cheats.len();
+        self.cheats.retain(|&(existing, _)| existing != id);
+        self.cheats.len() != count
+    }
+
+    /// Returns false when there is no cheat with this id.
+    pub fn set_cheat_enabled(&mut self, id: usize, enabled: bool) -> bool {
+        self.cheats
+            .iter_mut()
+            .find(|(existing, _)| *existing == id)
+            .map(|(_, cheat)| cheat.enabled = enabled)
+            .is_some()
+    }
+
+    #[must_use]
+    pub fn cheats(&self) -> &[(usize, Cheat)] {
+        &self.cheats
+    }
+
+    fn enabled_codes(&self) -> impl Iterator<Item = &CheatCode> {
+        self.cheats
+            .iter()
+            .filter(|(_, cheat)| cheat.enabled)
+            .flat_map(|(_, cheat)| &cheat.codes)
+    }
+
+    fn patch_rom(&self, address: u16, value: u8) -> u8 {
+        self.enabled_codes()
+            .find_map(|code| match code {
+                CheatCode::GameGenie(code) => code.patch(address, value),
+                CheatCode::GameShark(_) => None,
+            })
+            .unwrap_or(value)
+    }
+
+    fn apply_game_shark(&mut self) {
+        let codes: Vec<_> = self
+            .enabled_codes()
+            .filter_map(|code| match code {
+                CheatCode::GameShark(code) => Some(*code),
+                CheatCode::GameGenie(_) => None,
+            })
+            .collect();
+        for code in codes {
+            match code.bank {
+                GameSharkBank::CartRam(bank)
+                    if SWITCHABLE_RAM_BANK_RANGE.contains(&code.address) =>
+                {
+                    self.cartridge.poke_ram(bank, code.address, code.value);
+                }
+                GameSharkBank::Wram(bank) if WRAMX_RANGE.contains(&code.address) => {
+                    let offset = bank.max(1) * usize::from(WRAM_BANK_SIZE)
+                        + usize::from(code.address - WRAMX_RANGE.start);
+                    self.wram[offset] = code.value;
+                }
+                // A banked code outside its own area only writes to memory that is not banked.
+                GameSharkBank::CartRam(_) | GameSharkBank::Wram(_)
+                    if self.bank_at(code.address).is_some() => {}
+                _ => self.store(code.address, code.value),
+            }
+        }
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        self.cartridge.save_state(state);
+        self.ppu.save_state(state);
+        self.apu.save_state(state);
+        self.timer.save_state(state);
+        self.joypad.save_state(state);
+        self.serial.save_state(state);
+        state.bytes(&self.wram);
+        state.bytes(&self.hram);
+        state.u8(self.interrupt_flag);
+        state.u8(self.interrupt_enable);
+        state.bool(self.boot_rom_mapped);
+        state.u8(self.wram_bank);
+        state.bool(self.double_speed);
+        state.bool(self.speed_switch_armed);
+        self.hdma.save_state(state);
+        state.u32(self.dma_stall);
+        state.u8(self.model.bits());
+        if let Some(sgb) = &self.sgb {
+            sgb.save_state(state);
+        }
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.cartridge.load_state(state)?;
+        self.ppu.load_state(state)?;
+        self.apu.load_state(state)?;
+        self.timer.load_state(state)?;
+        self.joypad.load_state(state)?;
+        self.serial.load_state(state)?;
+        state.bytes_into(&mut self.wram, "WRAM size")?;
+        state.bytes_into(&mut self.hram, "HRAM size")?;
+        self.interrupt_flag = state.u8()?;
+        self.interrupt_enable = state.u8()?;
+        self.boot_rom_mapped = state.bool()?;
+        if self.boot_rom_mapped && self.boot_rom.is_none() {
+            return Err(StateError::Invalid("boot ROM mapping without a boot ROM"));
+        }
+        self.wram_bank = state.u8()? & 0x07;
+        self.double_speed = state.bool()?;
+        self.speed_switch_armed = state.bool()?;
+        self.hdma.load_state(state)?;
+        self.dma_stall = state.u32()?;
+        if state.u8()? != self.model.bits() {
+            return Err(StateError::Invalid("hardware model"));
+        }
+        if let Some(sgb) = &mut self.sgb {
+            sgb.load_state(state)?;
+        }
+        if !self.cgb
+            && (self.wram_bank != 0
+                || self.double_speed
+                || self.speed_switch_armed
+                || self.hdma.hblank_active())
+        {
+            return Err(StateError::Invalid("CGB register outside CGB mode"));
+        }
+        self.watch_hits.clear();
+        Ok(())
+    }
+
+    #[must_use]
+    pub const fn cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    pub const fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cartridge
+    }
+
+    #[must_use]
+    pub const fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    pub const fn ppu_mut(&mut self) -> &mut Ppu {
+        &mut self.ppu
+    }
+
+    #[must_use]
+    pub const fn apu(&self) -> &Apu {
+        &self.apu
+    }
+
+    pub const fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
+    #[must_use]
+    pub const fn timer(&self) -> &Timer {
+        &self.timer
+    }
+
+    #[must_use]
+    pub const fn joypad(&self) -> &Joypad {
+        &self.joypad
+    }
+
+    pub const fn joypad_mut(&mut self) -> &mut Joypad {
+        &mut self.joypad
+    }
+
+    #[must_use]
+    pub const fn serial(&self) -> &Serial {
+        &self.serial
+    }
+
+    pub const fn serial_mut(&mut self) -> &mut Serial {
+        &mut self.serial
+    }
+
+    /// Advances the hardware by `cycles` CPU cycles. In double speed mode the PPU, APU and
+    /// real-time clock see half as many.
+    pub fn tick(&mut self, cycles: u32) {
+        let normal_cycles = if self.double_speed {
+            cycles / 2
+        } else {
+            cycles
+        };
+        self.timer.tick(cycles);
+        self.ppu.tick(normal_cycles);
+        self.apu.tick(normal_cycles);
+        self.serial.tick(cycles);
+        self.cartridge.tick(normal_cycles);
+        if self.ppu.take_hblank_started() {
+            self.hdma_transfer(true);
+        }
+
+        let vblank = self.ppu.take_vblank_interrupt();
+        if vblank && !self.cheats.is_empty() {
+            self.apply_game_shark();
+        }
+        if let (true, Some(sgb)) = (vblank, &mut self.sgb) {
+            sgb.finish_frame(&self.ppu);
+        }
+        let requests = [
+            vblank,
+            self.ppu.take_stat_interrupt(),
+            self.timer.take_interrupt(),
+            self.serial.take_interrupt(),
+            self.joypad.take_interrupt(),
+        ];
+        for (bit, requested) in requests.into_iter().enumerate() {
+            if requested {
+                self.interrupt_flag |= 1 << bit;
+            }
+        }
+    }
+
+    fn oam_dma(&mut self, source: u8) {
+        let base = u16::from(source) << 8;
+        for address in SPRITE_ATTRIB_RANGE {
+            let value = self.peek(base + (address - SPRITE_ATTRIB_RANGE.start));
+            self.ppu.write_oam(address, value);
+        }
+    }
+
+    /// Copies the blocks the VRAM DMA has ready, in `hblank` the one for the HBlank that just
+    /// started, and halts the CPU for them.
+    fn hdma_transfer(&mut self, hblank: bool) {
+        while let Some((source, destination)) = self.hdma.next_block(hblank) {
+            for offset in 0..BLOCK_SIZE {
+                let value = self.peek(source.wrapping_add(offset));
+                self.ppu.write_vram(destination + offset, value);
+            }
+            self.dma_stall += HDMA_BLOCK_CYCLES << u32::from(self.double_speed);
+            if hblank {
+                break;
+            }
+        }
+    }
+
+    fn store(&mut self, address: u16, value: u8) {
+        match address {
+            _ if ROM_BANK_RANGE.contains(&address)
+                || SWITCHABLE_ROM_BANK_RANGE.contains(&address) =>
+            {
+                self.cartridge.write_rom(address, value);
+            }
+            _ if VIDEO_RAM_RANGE.contains(&address) => self.ppu.write_vram(address, value),
+            _ if SWITCHABLE_RAM_BANK_RANGE.contains(&address) => {
+                self.cartridge.write_ram(address, value);
+            }
+            _ if K8_INTERNAL_RAM_RANGE.contains(&address)
+                || ECHO_INTERNAL_RAM_RANGE.contains(&address) =>
+            {
+                let offset = self.wram_offset(address);
+                self.wram[offset] = value;
+            }
+            _ if SPRITE_ATTRIB_RANGE.contains(&address) => self.ppu.write_oam(address, value),
+            _ if IO_PORT_RANGE.contains(&address) || EMPTY2_RANGE.contains(&address) => {
+                self.write_io(address, value);
+            }
+            _ if INTERNAL_RAM_RANGE.contains(&address) => {
+                self.hram[(address - INTERNAL_RAM_RANGE.start) as usize] = value;
+            }
+            INTERUPT_ENABLE_REGISTER_INDEX => self.interrupt_enable = value,
+            _ => {}
+        }
+    }
+
+    fn read_io(&self, address: u16) -> u8 {
+        match address {
+            JOYPAD_REGISTER_INDEX => self
+                .sgb
+                .as_ref()
+                .map_or_else(|| self.joypad.read(), |sgb| sgb.read_joypad(&self.joypad)),
+            SERIAL_DATA_REGISTER_INDEX | SERIAL_CONTROL_REGISTER_INDEX => self.serial.read(address),
+            DIVIDER_REGISTER_INDEX..=TIMER_CONTROL_REGISTER_INDEX => self.timer.read(address),
+            INTERUPT_FLAG_REGISTER_INDEX => 0xE0 | self.interrupt_flag,
+            _ if SOUND_REGISTER_RANGE.contains(&address) => self.apu.read(address),
+            LCDC_REGISTER_INDEX..=WINDOW_X_REGISTER_INDEX
+            | VRAM_BANK_REGISTER_INDEX
+            | BG_COLOR_PALETTE_INDEX_REGISTER_INDEX..=OBJ_PRIORITY_MODE_REGISTER_INDEX => {
+                self.ppu.read(address)
+            }
+            KEY1_REGISTER_INDEX if self.cgb => {
+                0x7E | (u8::from(self.double_speed) << 7) | u8::from(self.speed_switch_armed)
+            }
+            HDMA_SOURCE_HIGH_REGISTER_INDEX..=HDMA_LENGTH_REGISTER_INDEX if self.cgb => {
+                self.hdma.read(address)
+            }
+            WRAM_BANK_REGISTER_INDEX if self.cgb => 0xF8 | self.wram_bank,
+            _ => 0xFF,
+        }
+    }
+
+    fn write_io(&mut self, address: u16, value: u8) {
+        match address {
+            JOYPAD_REGISTER_INDEX => {
+                self.joypad.write(value);
+                if let Some(sgb) = &mut self.sgb {
+                    sgb.write_joypad(value);
+                }
+            }
+            SERIAL_DATA_REGISTER_INDEX | SERIAL_CONTROL_REGISTER_INDEX => {
+                self.serial.write(address, value);
+            }
+            DIVIDER_REGISTER_INDEX..=TIMER_CONTROL_REGISTER_INDEX => {
+                self.timer.write(address, value);
+            }
+            INTERUPT_FLAG_REGISTER_INDEX => self.interrupt_flag = value & 0x1F,
+            _ if SOUND_REGISTER_RANGE.contains(&address) => self.apu.write(address, value),
+            DMA_REGISTER_INDEX => self.oam_dma(value),
+            LCDC_REGISTER_INDEX..=WINDOW_X_REGISTER_INDEX
+            | VRAM_BANK_REGISTER_INDEX
+            | BG_COLOR_PALETTE_INDEX_REGISTER_INDEX..=OBJ_PRIORITY_MODE_REGISTER_INDEX => {
+                self.ppu.write(address, value);
+            }
+            KEY1_REGISTER_INDEX if self.cgb => self.speed_switch_armed = value & 0x01 != 0,
+            HDMA_SOURCE_HIGH_REGISTER_INDEX..=HDMA_LENGTH_REGISTER_INDEX if self.cgb => {
+                self.hdma.write(address, value);
+                self.hdma_transfer(false);
+            }
+            WRAM_BANK_REGISTER_INDEX if self.cgb => self.wram_bank = value & 0x07,
+            BOOT_ROM_DISABLE_REGISTER_INDEX if value != 0 => {
+                self.boot_rom_mapped = false;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl MemoryBus for Bus {
+    fn read(&mut self, address: u16) -> u8 {
+        let value = self.peek(address);
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(Access::Read, address, self.bank_at(address), value, value);
+        }
+        value
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if self.watchpoints.is_empty() {
+            self.store(address, value);
+        } else {
+            let (bank, old) = (self.bank_at(address), self.peek(address));
+            self.store(address, value);
+            self.check_watchpoints(Access::Write, address, bank, old, value);
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            _ if self.boot_rom_mapped
+                && !CARTRIDGE_HEADER_RANGE.contains(&address)
+                && usize::from(address) < self.boot_rom.as_ref().map_or(0, Vec::len) =>
+            {
+                self.boot_rom
+                    .as_ref()
+                    .map_or(0xFF, |boot_rom| boot_rom[usize::from(address)])
+            }
+            _ if ROM_BANK_RANGE.contains(&address)
+                || SWITCHABLE_ROM_BANK_RANGE.contains(&address) =>
+            {
+                let value = self.cartridge.read_rom(address);
+                if self.cheats.is_empty() {
+                    value
+                } else {
+                    self.patch_rom(address, value)
+                }
+            }
+            _ if VIDEO_RAM_RANGE.contains(&address) => self.ppu.read_vram(address),
+            _ if SWITCHABLE_RAM_BANK_RANGE.contains(&address) => self.cartridge.read_ram(address),
+            _ if K8_INTERNAL_RAM_RANGE.contains(&address)
+                || ECHO_INTERNAL_RAM_RANGE.contains(&address) =>
+            {
+                self.wram[self.wram_offset(address)]
+            }
+            _ if SPRITE_ATTRIB_RANGE.contains(&address) => self.ppu.read_oam(address),
+            _ if IO_PORT_RANGE.contains(&address) || EMPTY2_RANGE.contains(&address) => {
+                self.read_io(address)
+            }
+            _ if INTERNAL_RAM_RANGE.contains(&address) => {
+                self.hram[(address - INTERNAL_RAM_RANGE.start) as usize]
+            }
+            INTERUPT_ENABLE_REGISTER_INDEX => self.interrupt_enable,
+            _ => 0xFF,
+        }
+    }
+}
@@ -0,0 +1,658 @@
+use std::fmt;
+
+use crate::memory_map::{
+    CARTRIDGE_TYPE_INDEX, CHECKSUM_INDEX, COMPLEMENT_CHECK_INDEX, GAME_TITLE_INDEX,
+    HIGH_NIB_LICENCE_INDEX, IS_CGB_INDEX, IS_SGB_INDEX, LICENCE_CODE_INDEX, LOW_NIB_LICENCE_INDEX,
+    RAM_SIZE_INDEX, ROM_BANK_RANGE, ROM_SIZE_INDEX, SWITCHABLE_RAM_BANK_RANGE,
+};
+use crate::state::{StateError, StateReader, StateWriter};
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_BANK_SIZE: usize = 0x2000;
+const CYCLES_PER_SECOND: u32 = 4_194_304;
+const SGB_FLAG: u8 = 0x03;
+/// SGB functions are only enabled when the old licensee says the new one is used.
+const NEW_LICENSEE_FOLLOWS: u8 = 0x33;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CartridgeError {
+    TooSmall(usize),
+    UnsupportedType(u8),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooSmall(size) => {
+                write!(f, "ROM is too small to contain a header ({size} bytes)")
+            }
+            Self::UnsupportedType(kind) => write!(f, "unsupported cartridge type 0x{kind:02X}"),
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {}
+
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub title: String,
+    pub cgb_flag: u8,
+    pub sgb_flag: u8,
+    pub cartridge_type: u8,
+    pub rom_size: u8,
+    pub ram_size: u8,
+    pub old_licensee: u8,
+    pub new_licensee: [u8; 2],
+    pub complement_check: u8,
+    pub checksum: u16,
+}
+
+impl Header {
+    fn parse(rom: &[u8]) -> Self {
+        let byte = |index: u16| rom[index as usize];
+        let title = rom[*GAME_TITLE_INDEX.start() as usize..=*GAME_TITLE_INDEX.end() as usize]
+            .iter()
+            .take_while(|&&c| c != 0)
+            .filter(|c| c.is_ascii_graphic() || **c == b' ')
+            .map(|&c| char::from(c))
+            .collect();
+
+        Self {
+            title,
+            cgb_flag: byte(IS_CGB_INDEX),
+            sgb_flag: byte(IS_SGB_INDEX),
+            cartridge_type: byte(CARTRIDGE_TYPE_INDEX),
+            rom_size: byte(ROM_SIZE_INDEX),
+            ram_size: byte(RAM_SIZE_INDEX),
+            old_licensee: byte(LICENCE_CODE_INDEX),
+            new_licensee: [byte(HIGH_NIB_LICENCE_INDEX), byte(LOW_NIB_LICENCE_INDEX)],
+            complement_check: byte(COMPLEMENT_CHECK_INDEX),
+            checksum: u16::from_be_bytes([
+                byte(*CHECKSUM_INDEX.start()),
+                byte(*CHECKSUM_INDEX.end()),
+            ]),
+        }
+    }
+
+    /// Whether the cartridge enables SGB functions, so that an SGB listens to its packets.
+    #[must_use]
+    pub const fn supports_sgb(&self) -> bool {
+        self.sgb_flag == SGB_FLAG && self.old_licensee == NEW_LICENSEE_FOLLOWS
+    }
+
+    const fn ram_bytes(&self) -> usize {
+        match self.ram_size {
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x2_0000,
+            0x05 => 0x1_0000,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Rtc {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16,
+    halted: bool,
+    day_carry: bool,
+    latched: [u8; 5],
+    latch_armed: bool,
+    sub_second_cycles: u32,
+}
+
+impl Rtc {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.seconds);
+        state.u8(self.minutes);
+        state.u8(self.hours);
+        state.u16(self.days);
+        state.bool(self.halted);
+        state.bool(self.day_carry);
+        for value in self.latched {
+            state.u8(value);
+        }
+        state.bool(self.latch_armed);
+        state.u32(self.sub_second_cycles);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.seconds = state.u8()?;
+        self.minutes = state.u8()?;
+        self.hours = state.u8()?;
+        self.days = state.u16()?;
+        self.halted = state.bool()?;
+        self.day_carry = state.bool()?;
+        for value in &mut self.latched {
+            *value = state.u8()?;
+        }
+        self.latch_armed = state.bool()?;
+        self.sub_second_cycles = state.u32()?;
+        Ok(())
+    }
+
+    const fn tick(&mut self, cycles: u32) {
+        if self.halted {
+            return;
+        }
+
+        self.sub_second_cycles += cycles;
+        while self.sub_second_cycles >= CYCLES_PER_SECOND {
+            self.sub_second_cycles -= CYCLES_PER_SECOND;
+            self.advance_second();
+        }
+    }
+
+    const fn advance_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+        self.days += 1;
+        if self.days > 0x1FF {
+            self.days = 0;
+            self.day_carry = true;
+        }
+    }
+
+    fn latch(&mut self, value: u8) {
+        if self.latch_armed && value == 0x01 {
+            let [days_high, days_low] = self.days.to_be_bytes();
+            self.latched = [
+                self.seconds,
+                self.minutes,
+                self.hours,
+                days_low,
+                (days_high & 0x01) | (u8::from(self.halted) << 6) | (u8::from(self.day_carry) << 7),
+            ];
+        }
+        self.latch_armed = value == 0x00;
+    }
+
+    const fn read(&self, register: u8) -> u8 {
+        match register {
+            0x08..=0x0C => self.latched[(register - 0x08) as usize],
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0x08 => {
+                self.seconds = value & 0x3F;
+                self.sub_second_cycles = 0;
+            }
+            0x09 => self.minutes = value & 0x3F,
+            0x0A => self.hours = value & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | u16::from(value),
+            0x0C => {
+                self.days = (self.days & 0xFF) | (u16::from(value & 0x01) << 8);
+                self.halted = value & 0x40 != 0;
+                self.day_carry = value & 0x80 != 0;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Mbc {
+    None,
+    Mbc1 {
+        rom_bank: u8,
+        upper_bits: u8,
+        advanced_banking: bool,
+    },
+    Mbc2 {
+        rom_bank: u8,
+    },
+    Mbc3 {
+        rom_bank: u8,
+        ram_bank: u8,
+        rtc: Option<Rtc>,
+    },
+    Mbc5 {
+        rom_bank: u16,
+        ram_bank: u8,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Cartridge {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    header: Header,
+    mbc: Mbc,
+    ram_enabled: bool,
+    has_battery: bool,
+}
+
+impl Cartridge {
+    /// Parses the header and picks the memory bank controller.
+    ///
+    /// # Errors
+    ///
+    /// Fails when the ROM is shorter than its header or uses an unsupported cartridge type.
+    pub fn from_rom(rom: Vec<u8>) -> Result<Self, CartridgeError> {
+        if rom.len() < 0x150 {
+            return Err(CartridgeError::TooSmall(rom.len()));
+        }
+
+        let header = Header::parse(&rom);
+        let (mbc, has_battery) = match header.cartridge_type {
+            0x00 | 0x08 => (Mbc::None, false),
+            0x09 => (Mbc::None, true),
+            0x01..=0x03 => (
+                Mbc::Mbc1 {
+                    rom_bank: 1,
+                    upper_bits: 0,
+                    advanced_banking: false,
+                },
+                header.cartridge_type == 0x03,
+            ),
+            0x05 | 0x06 => (Mbc::Mbc2 { rom_bank: 1 }, header.cartridge_type == 0x06),
+            0x0F..=0x13 => (
+                Mbc::Mbc3 {
+                    rom_bank: 1,
+                    ram_bank: 0,
+                    rtc: matches!(header.cartridge_type, 0x0F | 0x10).then(Rtc::default),
+                },
+                matches!(header.cartridge_type, 0x0F | 0x10 | 0x13),
+            ),
+            0x19..=0x1E => (
+                Mbc::Mbc5 {
+                    rom_bank: 1,
+                    ram_bank: 0,
+                },
+                matches!(header.cartridge_type, 0x1B | 0x1E),
+            ),
+            other => return Err(CartridgeError::UnsupportedType(other)),
+        };
+
+        let ram_size = if matches!(mbc, Mbc::Mbc2 { .. }) {
+            0x200
+        } else {
+            header.ram_bytes()
+        };
+
+        Ok(Self {
+            rom,
+            ram: vec![0; ram_size],
+            header,
+            mbc,
+            ram_enabled: false,
+            has_battery,
+        })
+    }
+
+    #[must_use]
+    pub const fn header(&self) -> &Header {
+        &self.header
+    }
+
+    #[must_use]
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    #[must_use]
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    #[must_use]
+    pub const fn has_battery(&self) -> bool {
+        self.has_battery
+    }
+
+    pub fn reset(&mut self) {
+        self.ram_enabled = false;
+        self.mbc = match &self.mbc {
+            Mbc::None => Mbc::None,
+            Mbc::Mbc1 { .. } => Mbc::Mbc1 {
+                rom_bank: 1,
+                upper_bits: 0,
+                advanced_banking: false,
+            },
+            Mbc::Mbc2 { .. } => Mbc::Mbc2 { rom_bank: 1 },
+            Mbc::Mbc3 { rtc, .. } => Mbc::Mbc3 {
+                rom_bank: 1,
+                ram_bank: 0,
+                rtc: rtc.clone(),
+            },
+            Mbc::Mbc5 { .. } => Mbc::Mbc5 {
+                rom_bank: 1,
+                ram_bank: 0,
+            },
+        };
+    }
+
+    /// Saves the RAM and banking registers; the ROM itself is not part of the state.
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.ram);
+        state.bool(self.ram_enabled);
+        match &self.mbc {
+            Mbc::None => state.u8(0),
+            Mbc::Mbc1 {
+                rom_bank,
+                upper_bits,
+                advanced_banking,
+            } => {
+                state.u8(1);
+                state.u8(*rom_bank);
+                state.u8(*upper_bits);
+                state.bool(*advanced_banking);
+            }
+            Mbc::Mbc2 { rom_bank } => {
+                state.u8(2);
+                state.u8(*rom_bank);
+            }
+            Mbc::Mbc3 {
+                rom_bank,
+                ram_bank,
+                rtc,
+            } => {
+                state.u8(3);
+                state.u8(*rom_bank);
+                state.u8(*ram_bank);
+                if let Some(rtc) = rtc {
+                    rtc.save_state(state);
+                }
+            }
+            Mbc::Mbc5 { rom_bank, ram_bank } => {
+                state.u8(5);
+                state.u16(*rom_bank);
+                state.u8(*ram_bank);
+            }
+        }
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.bytes_into(&mut self.ram, "cartridge RAM size")?;
+        self.ram_enabled = state.bool()?;
+        let kind = state.u8()?;
+        match &mut self.mbc {
+            Mbc::None if kind == 0 => {}
+            Mbc::Mbc1 {
+                rom_bank,
+                upper_bits,
+                advanced_banking,
+            } if kind == 1 => {
+                *rom_bank = state.u8()?;
+                *upper_bits = state.u8()?;
+                *advanced_banking = state.bool()?;
+            }
+            Mbc::Mbc2 { rom_bank } if kind == 2 => *rom_bank = state.u8()?,
+            Mbc::Mbc3 {
+                rom_bank,
+                ram_bank,
+                rtc,
+            } if kind == 3 => {
+                *rom_bank = state.u8()?;
+                *ram_bank = state.u8()?;
+                if let Some(rtc) = rtc {
+                    rtc.load_state(state)?;
+                }
+            }
+            Mbc::Mbc5 { rom_bank, ram_bank } if kind == 5 => {
+                *rom_bank = state.u16()?;
+                *ram_bank = state.u8()?;
+            }
+            _ => return Err(StateError::Invalid("memory bank controller")),
+        }
+        Ok(())
+    }
+
+    fn rom_bank_count(&self) -> usize {
+        (self.rom.len() / ROM_BANK_SIZE).max(1)
+    }
+
+    #[must_use]
+    pub fn current_rom_bank(&self) -> usize {
+        let bank = match &self.mbc {
+            Mbc::None => 1,
+            Mbc::Mbc1 {
+                rom_bank,
+                upper_bits,
+                ..
+            } => usize::from(*rom_bank) | (usize::from(*upper_bits) << 5),
+            Mbc::Mbc2 { rom_bank } | Mbc::Mbc3 { rom_bank, .. } => usize::from(*rom_bank),
+            Mbc::Mbc5 { rom_bank, .. } => usize::from(*rom_bank),
+        };
+        bank % self.rom_bank_count()
+    }
+
+    /// The ROM bank mapped at `address`, for addresses in the two ROM windows.
+    #[must_use]
+    pub fn rom_bank_at(&self, address: u16) -> usize {
+        if ROM_BANK_RANGE.contains(&address) {
+            self.zero_rom_bank()
+        } else {
+            self.current_rom_bank()
+        }
+    }
+
+    fn zero_rom_bank(&self) -> usize {
+        match &self.mbc {
+            Mbc::Mbc1 {
+                upper_bits,
+                advanced_banking: true,
+                ..
+            } => (usize::from(*upper_bits) << 5) % self.rom_bank_count(),
+            _ => 0,
+        }
+    }
+
+    #[must_use]
+    pub const fn current_ram_bank(&self) -> usize {
+        match &self.mbc {
+            Mbc::Mbc1 {
+                upper_bits,
+                advanced_banking: true,
+                ..
+            } => *upper_bits as usize,
+            Mbc::Mbc3 { ram_bank, .. } | Mbc::Mbc5 { ram_bank, .. } => *ram_bank as usize,
+            _ => 0,
+        }
+    }
+
+    pub const fn tick(&mut self, cycles: u32) {
+        if let Mbc::Mbc3 { rtc: Some(rtc), .. } = &mut self.mbc {
+            rtc.tick(cycles);
+        }
+    }
+
+    #[must_use]
+    pub fn read_rom(&self, address: u16) -> u8 {
+        let offset =
+            self.rom_bank_at(address) * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1));
+        self.rom.get(offset).copied().unwrap_or(0xFF)
+    }
+
+    pub fn write_rom(&mut self, address: u16, value: u8) {
+        match &mut self.mbc {
+            Mbc::None => {}
+            Mbc::Mbc1 {
+                rom_bank,
+                upper_bits,
+                advanced_banking,
+            } => match address {
+                0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+                0x2000..=0x3FFF => *rom_bank = (value & 0x1F).max(1),
+                0x4000..=0x5FFF => *upper_bits = value & 0x03,
+                _ => *advanced_banking = value & 0x01 != 0,
+            },
+            Mbc::Mbc2 { rom_bank } => {
+                if address < 0x4000 {
+                    if address & 0x0100 == 0 {
+                        self.ram_enabled = value & 0x0F == 0x0A;
+                    } else {
+                        *rom_bank = (value & 0x0F).max(1);
+                    }
+                }
+            }
+            Mbc::Mbc3 {
+                rom_bank,
+                ram_bank,
+                rtc,
+            } => match address {
+                0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+                0x2000..=0x3FFF => *rom_bank = (value & 0x7F).max(1),
+                0x4000..=0x5FFF => *ram_bank = value & 0x0F,
+                _ => {
+                    if let Some(rtc) = rtc {
+                        rtc.latch(value);
+                    }
+                }
+            },
+            Mbc::Mbc5 { rom_bank, ram_bank } => match address {
+                0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+                0x2000..=0x2FFF => *rom_bank = (*rom_bank & 0x100) | u16::from(value),
+                0x3000..=0x3FFF => *rom_bank = (*rom_bank & 0xFF) | (u16::from(value & 0x01) << 8),
+                0x4000..=0x5FFF => *ram_bank = value & 0x0F,
+                _ => {}
+            },
+        }
+    }
+
+    const fn ram_offset(&self, address: u16) -> Option<usize> {
+        if self.ram.is_empty() {
+            return None;
+        }
+        let local = (address - SWITCHABLE_RAM_BANK_RANGE.start) as usize;
+        let offset = match self.mbc {
+            Mbc::Mbc2 { .. } => local & 0x1FF,
+            _ => self.current_ram_bank() * RAM_BANK_SIZE + local,
+        };
+        Some(offset % self.ram.len())
+    }
+
+    /// Writes to RAM bank `bank` whichever bank is mapped and whether or not RAM is enabled.
+    pub fn poke_ram(&mut self, bank: usize, address: u16, value: u8) {
+        if self.ram.is_empty() {
+            return;
+        }
+        let local = usize::from(address - SWITCHABLE_RAM_BANK_RANGE.start);
+        let offset = match self.mbc {
+            Mbc::Mbc2 { .. } => local & 0x1FF,
+            _ => bank * RAM_BANK_SIZE + local,
+        };
+        let length = self.ram.len();
+        self.ram[offset % length] = value;
+    }
+
+    #[must_use]
+    pub fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled && !matches!(self.mbc, Mbc::None) {
+            return 0xFF;
+        }
+
+        if let Mbc::Mbc3 {
+            ram_bank,
+            rtc: Some(rtc),
+            ..
+        } = &self.mbc
+        {
+            if *ram_bank >= 0x08 {
+                return rtc.read(*ram_bank);
+            }
+        }
+
+        match self.ram_offset(address) {
+            Some(offset) if matches!(self.mbc, Mbc::Mbc2 { .. }) => self.ram[offset] | 0xF0,
+            Some(offset) => self.ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled && !matches!(self.mbc, Mbc::None) {
+            return;
+        }
+
+        if let Mbc::Mbc3 {
+            ram_bank,
+            rtc: Some(rtc),
+            ..
+        } = &mut self.mbc
+        {
+            if *ram_bank >= 0x08 {
+                rtc.write(*ram_bank, value);
+                return;
+            }
+        }
+
+        if let Some(offset) = self.ram_offset(address) {
+            self.ram[offset] = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom_with_type(cartridge_type: u8, banks: usize) -> Vec<u8> {
+        let mut rom = vec![0; banks * ROM_BANK_SIZE];
+        rom[CARTRIDGE_TYPE_INDEX as usize] = cartridge_type;
+        rom[RAM_SIZE_INDEX as usize] = 0x03;
+        for (bank, chunk) in rom.chunks_mut(ROM_BANK_SIZE).enumerate().skip(1) {
+            chunk[0] = u8::try_from(bank).unwrap();
+        }
+        rom
+    }
+
+    #[test]
+    fn test_mbc1_switches_rom_bank() {
+        let mut cartridge = Cartridge::from_rom(rom_with_type(0x01, 8)).unwrap();
+        assert_eq!(1, cartridge.read_rom(0x4000));
+        cartridge.write_rom(0x2000, 5);
+        assert_eq!(5, cartridge.read_rom(0x4000));
+        cartridge.write_rom(0x2000, 0);
+        assert_eq!(1, cartridge.read_rom(0x4000));
+    }
+
+    #[test]
+    fn test_ram_requires_enable() {
+        let mut cartridge = Cartridge::from_rom(rom_with_type(0x03, 2)).unwrap();
+        cartridge.write_ram(0xA000, 0x42);
+        assert_eq!(0xFF, cartridge.read_ram(0xA000));
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_ram(0xA000, 0x42);
+        assert_eq!(0x42, cartridge.read_ram(0xA000));
+    }
+
+    #[test]
+    fn test_mbc5_high_rom_bank_bit() {
+        let mut cartridge = Cartridge::from_rom(rom_with_type(0x19, 4)).unwrap();
+        cartridge.write_rom(0x2000, 3);
+        assert_eq!(3, cartridge.read_rom(0x4000));
+        assert_eq!(3, cartridge.current_rom_bank());
+    }
+
+    #[test]
+    fn test_rejects_unknown_type() {
+        assert_eq!(
+            Some(CartridgeError::UnsupportedType(0xFC)),
+            Cartridge::from_rom(rom_with_type(0xFC, 2)).err()
+        );
+    }
+}
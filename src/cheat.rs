@@ -0,0 +1,383 @@
+//! Game Genie and GameShark cheat codes.
+//!
+//! A Game Genie code, `ABC-DEF` or `ABC-DEF-GHI`, replaces the ROM byte at address `FCDE`
+//! (with `F` inverted) by `AB` on every read. The nine-digit form only patches when the
+//! original byte equals `GI`, rotated right by two and XOR-ed with `BA`, which keeps the patch
+//! from hitting other banks mapped at the same address.
+//!
+//! A GameShark code, `TTVVLLHH`, writes `VV` to address `HHLL` at the start of every vertical
+//! blank. Type `01` writes through whatever bank is mapped; types `80` to `8F` write to the
+//! cartridge RAM bank and `90` to `97` to the WRAM bank in the low digit.
+//!
+//! Cheat files hold one cheat per line: one or more codes joined by `+`, then an optional
+//! description. A leading `!` marks a disabled cheat and `#` starts a comment line.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::memory_map::{ROM_BANK_RANGE, SWITCHABLE_ROM_BANK_RANGE};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameGenieCode {
+    pub address: u16,
+    pub value: u8,
+    /// Only patch when the ROM holds this byte.
+    pub compare: Option<u8>,
+}
+
+impl GameGenieCode {
+    /// The byte read at `address` when the ROM holds `original` there.
+    #[must_use]
+    pub fn patch(&self, address: u16, original: u8) -> Option<u8> {
+        let matches = address == self.address && self.compare.is_none_or(|c| c == original);
+        matches.then_some(self.value)
+    }
+}
+
+/// The memory a GameShark code writes to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameSharkBank {
+    /// Whatever bank is mapped at the address, type `01`.
+    Mapped,
+    /// A cartridge RAM bank, types `80` to `8F`.
+    CartRam(usize),
+    /// A WRAM bank, types `90` to `97`.
+    Wram(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameSharkCode {
+    pub bank: GameSharkBank,
+    pub value: u8,
+    pub address: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheatCode {
+    GameGenie(GameGenieCode),
+    GameShark(GameSharkCode),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseCodeError(String);
+
+impl fmt::Display for ParseCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid cheat code {:?}, expected a Game Genie code like 00A-17B-C49 or a GameShark code like 010238CD",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseCodeError {}
+
+fn hex_digits(s: &str) -> Option<Vec<u8>> {
+    s.chars()
+        .map(|c| c.to_digit(16).and_then(|digit| u8::try_from(digit).ok()))
+        .collect()
+}
+
+fn parse_game_genie(s: &str) -> Option<GameGenieCode> {
+    let digits = hex_digits(&s.replace('-', ""))?;
+    let groups: Vec<usize> = s.split('-').map(str::len).collect();
+    if groups != [3, 3] && groups != [3, 3, 3] {
+        return None;
+    }
+    let [d1, d2, d3, d4, d5, d6] = digits[..6] else {
+        return None;
+    };
+    let address = u16::from_be_bytes([((d6 ^ 0xF) << 4) | d3, (d4 << 4) | d5]);
+    if !ROM_BANK_RANGE.contains(&address) && !SWITCHABLE_ROM_BANK_RANGE.contains(&address) {
+        return None;
+    }
+    let compare = digits
+        .get(6..)
+        .filter(|rest| !rest.is_empty())
+        .map(|rest| ((rest[0] << 4) | rest[2]).rotate_right(2) ^ 0xBA);
+    Some(GameGenieCode {
+        address,
+        value: (d1 << 4) | d2,
+        compare,
+    })
+}
+
+fn parse_game_shark(s: &str) -> Option<GameSharkCode> {
+    if s.len() != 8 {
+        return None;
+    }
+    let [kind, value, low, high] = u32::from_str_radix(s, 16).ok()?.to_be_bytes();
+    let bank = match kind {
+        0x01 => GameSharkBank::Mapped,
+        0x80..=0x8F => GameSharkBank::CartRam(usize::from(kind & 0x0F)),
+        0x90..=0x97 => GameSharkBank::Wram(usize::from(kind & 0x07)),
+        _ => return None,
+    };
+    Some(GameSharkCode {
+        bank,
+        value,
+        address: u16::from_le_bytes([low, high]),
+    })
+}
+
+impl FromStr for CheatCode {
+    type Err = ParseCodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = if s.contains('-') {
+            parse_game_genie(s).map(Self::GameGenie)
+        } else {
+            parse_game_shark(s).map(Self::GameShark)
+        };
+        code.ok_or_else(|| ParseCodeError(s.to_string()))
+    }
+}
+
+impl fmt::Display for CheatCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::GameGenie(GameGenieCode {
+                address,
+                value,
+                compare,
+            }) => {
+                let [high, low] = address.to_be_bytes();
+                write!(
+                    f,
+                    "{value:02X}{:X}-{low:02X}{:X}",
+                    high & 0xF,
+                    (high >> 4) ^ 0xF
+                )?;
+                if let Some(compare) = compare {
+                    // The middle digit is ignored, so it is not kept when parsing.
+                    let encoded = (compare ^ 0xBA).rotate_left(2);
+                    write!(f, "-{:X}0{:X}", encoded >> 4, encoded & 0xF)?;
+                }
+                Ok(())
+            }
+            Self::GameShark(GameSharkCode {
+                bank,
+                value,
+                address,
+            }) => {
+                let kind = match bank {
+                    GameSharkBank::Mapped => 0x01,
+                    GameSharkBank::CartRam(bank) => 0x80 | bank,
+                    GameSharkBank::Wram(bank) => 0x90 | bank,
+                };
+                let [low, high] = address.to_le_bytes();
+                write!(f, "{kind:02X}{value:02X}{low:02X}{high:02X}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    pub codes: Vec<CheatCode>,
+    pub description: String,
+    pub enabled: bool,
+}
+
+impl Cheat {
+    #[must_use]
+    pub fn new(code: CheatCode) -> Self {
+        Self {
+            codes: vec![code],
+            description: String::new(),
+            enabled: true,
+        }
+    }
+}
+
+impl FromStr for Cheat {
+    type Err = ParseCodeError;
+
+    /// Parses one line of a cheat file, see the [module documentation](self).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (enabled, s) = s
+            .strip_prefix('!')
+            .map_or((true, s), |rest| (false, rest.trim_start()));
+        let (codes, description) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        Ok(Self {
+            codes: codes.split('+').map(str::parse).collect::<Result<_, _>>()?,
+            description: description.trim().to_string(),
+            enabled,
+        })
+    }
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.enabled {
+            write!(f, "!")?;
+        }
+        for (index, code) in self.codes.iter().enumerate() {
+            if index > 0 {
+                write!(f, "+")?;
+            }
+            write!(f, "{code}")?;
+        }
+        if !self.description.is_empty() {
+            write!(f, " {}", self.description)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheatError {
+    pub line: usize,
+    pub error: ParseCodeError,
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+impl std::error::Error for CheatError {}
+
+/// Parses a cheat file, skipping blank lines and `#` comments.
+///
+/// # Errors
+///
+/// Returns the first line holding an invalid code.
+pub fn parse_cheats(text: &str) -> Result<Vec<Cheat>, CheatError> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(index, line)| {
+            line.parse().map_err(|error| CheatError {
+                line: index + 1,
+                error,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble_rom;
+    use crate::bus::MemoryBus;
+    use crate::gameboy::GameBoy;
+
+    #[test]
+    fn test_parse_codes() {
+        assert_eq!(
+            Ok(CheatCode::GameGenie(GameGenieCode {
+                address: 0x4A17,
+                value: 0x00,
+                compare: Some(0xC8),
+            })),
+            "00A-17B-C49".parse()
+        );
+        assert_eq!(
+            Ok(CheatCode::GameGenie(GameGenieCode {
+                address: 0x0150,
+                value: 0x3E,
+                compare: None,
+            })),
+            "3E1-50F".parse()
+        );
+        assert_eq!(
+            Ok(CheatCode::GameShark(GameSharkCode {
+                bank: GameSharkBank::Mapped,
+                value: 0x02,
+                address: 0xCD38,
+            })),
+            "010238CD".parse()
+        );
+        assert_eq!(
+            Ok(CheatCode::GameShark(GameSharkCode {
+                bank: GameSharkBank::CartRam(2),
+                value: 0x63,
+                address: 0xA000,
+            })),
+            "826300A0".parse()
+        );
+        assert_eq!(
+            Ok(CheatCode::GameShark(GameSharkCode {
+                bank: GameSharkBank::Wram(1),
+                value: 0x63,
+                address: 0xD000,
+            })),
+            "916300D0".parse()
+        );
+        for invalid in [
+            "00A-17B-C4",
+            "00A-177-C49",
+            "00A17BC49",
+            "020238CD",
+            "986300D0",
+            "0102",
+        ] {
+            assert!(invalid.parse::<CheatCode>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        for code in ["00A-17B-C09", "3E1-50F", "010238CD", "826300A0", "916300D0"] {
+            let parsed: CheatCode = code.parse().unwrap();
+            assert_eq!(code, parsed.to_string());
+        }
+    }
+
+    #[test]
+    fn test_parse_cheat_file() {
+        let cheats = parse_cheats(
+            "# Lives and health\n010238CD+0163DACF Max health\n\n! 00A-17B-C49 Skip intro\n",
+        )
+        .unwrap();
+        assert_eq!(2, cheats.len());
+        assert_eq!(2, cheats[0].codes.len());
+        assert_eq!("Max health", cheats[0].description);
+        assert!(cheats[0].enabled);
+        assert!(!cheats[1].enabled);
+        assert_eq!("!00A-17B-C09 Skip intro", cheats[1].to_string());
+
+        let error = parse_cheats("010238CD\nbogus\n").unwrap_err();
+        assert_eq!(2, error.line);
+    }
+
+    #[test]
+    fn test_cheats_on_the_bus() {
+        let rom = assemble_rom(
+            "
+            section \"main\", rom0[$150]
+            .loop:
+                halt
+                jr .loop
+            ",
+        )
+        .unwrap();
+        let mut gameboy = GameBoy::from_rom(rom).unwrap();
+        let bus = gameboy.bus_mut();
+        let original = bus.peek(0x0150);
+        let patch = |compare| {
+            Cheat::new(CheatCode::GameGenie(GameGenieCode {
+                address: 0x0150,
+                value: 0x00,
+                compare,
+            }))
+        };
+        let id = bus.add_cheat(patch(Some(original.wrapping_add(1))));
+        assert_eq!(original, bus.peek(0x0150));
+        bus.remove_cheat(id);
+        let id = bus.add_cheat(patch(Some(original)));
+        assert_eq!(0x00, bus.read(0x0150));
+        assert!(bus.set_cheat_enabled(id, false));
+        assert_eq!(original, bus.peek(0x0150));
+
+        bus.add_cheat("010238CD".parse().unwrap());
+        bus.add_cheat("91AA10D0".parse().unwrap());
+        gameboy.run_frame();
+        assert_eq!(0x02, gameboy.bus().peek(0xCD38));
+        assert_eq!(0xAA, gameboy.bus().peek(0xD010));
+    }
+}
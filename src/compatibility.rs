@@ -0,0 +1,227 @@
+//! The colours the CGB boot ROM gives DMG games.
+//!
+//! The boot ROM sums the 16 bytes from the title to the CGB flag. When the licensee is
+//! Nintendo it looks that sum up in a table of known games. A few sums are shared by several
+//! games, and for those the fourth letter of the title picks the entry. Any other game gets
+//! the default palette. Holding a direction, alone or with A or B, while the logo shows picks
+//! one of twelve palettes instead.
+//!
+//! The table below holds the boot ROM's entries that use one of those twelve palettes. The
+//! remaining entries use palettes not reproduced here and get the default.
+
+use crate::cartridge::Cartridge;
+use crate::joypad::Button;
+use crate::memory_map::{GAME_TITLE_INDEX, IS_CGB_INDEX};
+
+const NINTENDO_OLD_LICENSEE: u8 = 0x01;
+const NEW_LICENSEE_FOLLOWS: u8 = 0x33;
+const NINTENDO_NEW_LICENSEE: [u8; 2] = *b"01";
+
+/// 15-bit colours for background, OBJ0 and OBJ1 shades 0 (lightest) to 3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompatibilityPalette {
+    pub background: [u16; 4],
+    pub obj0: [u16; 4],
+    pub obj1: [u16; 4],
+}
+
+const fn rgb(color: u32) -> u16 {
+    let red = (color >> 19) & 0x1F;
+    let green = (color >> 11) & 0x1F;
+    let blue = (color >> 3) & 0x1F;
+    #[allow(clippy::cast_possible_truncation)]
+    let color = (red | (green << 5) | (blue << 10)) as u16;
+    color
+}
+
+const fn colors(shades: [u32; 4]) -> [u16; 4] {
+    [
+        rgb(shades[0]),
+        rgb(shades[1]),
+        rgb(shades[2]),
+        rgb(shades[3]),
+    ]
+}
+
+const fn single(shades: [u32; 4]) -> CompatibilityPalette {
+    CompatibilityPalette {
+        background: colors(shades),
+        obj0: colors(shades),
+        obj1: colors(shades),
+    }
+}
+
+const RED: [u32; 4] = [0xFF_FFFF, 0xFF_8484, 0x94_3A3A, 0x00_0000];
+const GREEN: [u32; 4] = [0xFF_FFFF, 0x7B_FF31, 0x00_8400, 0x00_0000];
+const BLUE: [u32; 4] = [0xFF_FFFF, 0x63_A5FF, 0x00_00FF, 0x00_0000];
+const BROWN: [u32; 4] = [0xFF_FFFF, 0xFF_AD63, 0x84_3100, 0x00_0000];
+
+pub const UP: CompatibilityPalette = single(BROWN);
+pub const UP_A: CompatibilityPalette = CompatibilityPalette {
+    background: colors(RED),
+    obj0: colors(GREEN),
+    obj1: colors(BLUE),
+};
+pub const UP_B: CompatibilityPalette = single([0xFF_E6C5, 0xCE_9C84, 0x84_6B29, 0x5A_3108]);
+pub const LEFT: CompatibilityPalette = CompatibilityPalette {
+    background: colors(BLUE),
+    obj0: colors(RED),
+    obj1: colors(GREEN),
+};
+pub const LEFT_A: CompatibilityPalette = CompatibilityPalette {
+    background: colors([0xFF_FFFF, 0x8C_8CDE, 0x52_528C, 0x00_0000]),
+    obj0: colors(RED),
+    obj1: colors(BROWN),
+};
+pub const LEFT_B: CompatibilityPalette = single([0xFF_FFFF, 0xA5_A5A5, 0x52_5252, 0x00_0000]);
+pub const DOWN: CompatibilityPalette = single([0xFF_FFA5, 0xFF_9494, 0x94_94FF, 0x00_0000]);
+pub const DOWN_A: CompatibilityPalette = single([0xFF_FFFF, 0xFF_FF00, 0xFF_0000, 0x00_0000]);
+pub const DOWN_B: CompatibilityPalette = CompatibilityPalette {
+    background: colors([0xFF_FFFF, 0xFF_FF00, 0x7B_4A00, 0x00_0000]),
+    obj0: colors(BLUE),
+    obj1: colors(GREEN),
+};
+pub const RIGHT: CompatibilityPalette = single([0xFF_FFFF, 0x52_FF00, 0xFF_4200, 0x00_0000]);
+/// The palette of games the boot ROM does not know.
+pub const RIGHT_A: CompatibilityPalette = CompatibilityPalette {
+    background: colors([0xFF_FFFF, 0x7B_FF31, 0x00_63C5, 0x00_0000]),
+    obj0: colors(RED),
+    obj1: colors(RED),
+};
+pub const RIGHT_B: CompatibilityPalette = single([0x00_0000, 0x00_8484, 0xFF_DE00, 0xFF_FFFF]);
+
+pub const DEFAULT: CompatibilityPalette = RIGHT_A;
+
+/// Title sums, the fourth title letter where the sum is shared, and the palette.
+const TITLES: [(u8, Option<u8>, CompatibilityPalette); 24] = [
+    (0x16, None, UP),     // YAKUMAN
+    (0xDB, None, DOWN_A), // TETRIS
+    (0xF2, None, DOWN_A), // QIX
+    (0x92, None, UP),     // F1RACE
+    (0x3D, None, RIGHT),  // YOSSY NO TAMAGO
+    (0x58, None, LEFT_B), // X
+    (0x69, None, DOWN_A), // TETRIS FLASH
+    (0x35, None, UP),     // MARIO'S PICROSS
+    (0x75, None, UP),     // PICROSS 2
+    (0x95, None, RIGHT),  // YOSSY NO PANEPON
+    (0x99, None, UP),     // KIRAKIRA KIDS
+    (0x15, None, DOWN_A),
+    (0xF7, None, UP),      // BOY AND BLOB GB2
+    (0xA2, None, UP),      // STAR WARS-NOA
+    (0x0C, None, UP),      // MANSELL
+    (0xE8, None, RIGHT_B), // SPACE INVADERS
+    (0xB7, None, UP),      // GAME&WATCH
+    (0x67, None, UP),
+    (0xA5, Some(b'A'), RIGHT_B), // SOLARSTRIKER
+    (0xA5, Some(b'R'), UP),
+    (0x28, Some(b'A'), RIGHT_B),
+    (0x0D, Some(b'R'), DOWN_A), // TETRIS2
+    (0x6A, Some(b'I'), RIGHT),  // MARIO & YOSHI
+    (0xB3, Some(b'R'), RIGHT),
+];
+
+fn is_nintendo(cartridge: &Cartridge) -> bool {
+    let header = cartridge.header();
+    header.old_licensee == NINTENDO_OLD_LICENSEE
+        || (header.old_licensee == NEW_LICENSEE_FOLLOWS
+            && header.new_licensee == NINTENDO_NEW_LICENSEE)
+}
+
+/// The sum of the title bytes, through the CGB flag which DMG titles may run into.
+#[must_use]
+pub fn title_hash(rom: &[u8]) -> u8 {
+    let start = usize::from(*GAME_TITLE_INDEX.start());
+    rom[start..=usize::from(IS_CGB_INDEX)]
+        .iter()
+        .fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// The palette the boot ROM picks for the game on `cartridge` from its title.
+#[must_use]
+pub fn title_palette(cartridge: &Cartridge) -> CompatibilityPalette {
+    if !is_nintendo(cartridge) {
+        return DEFAULT;
+    }
+    let rom = cartridge.rom();
+    let hash = title_hash(rom);
+    let fourth_letter = rom[usize::from(*GAME_TITLE_INDEX.start()) + 3];
+    TITLES
+        .iter()
+        .find(|(sum, letter, _)| *sum == hash && letter.is_none_or(|l| l == fourth_letter))
+        .map_or(DEFAULT, |&(_, _, palette)| palette)
+}
+
+/// The palette picked by holding `held` during the boot logo: one direction, alone or with A
+/// or B. Other combinations pick nothing.
+#[must_use]
+pub fn button_palette(held: &[Button]) -> Option<CompatibilityPalette> {
+    let directions = [Button::Up, Button::Left, Button::Down, Button::Right];
+    let mut direction = directions.iter().filter(|button| held.contains(button));
+    let (Some(&direction), None) = (direction.next(), direction.next()) else {
+        return None;
+    };
+    let a = held.contains(&Button::A);
+    let b = held.contains(&Button::B);
+    let palette = match (direction, a, b) {
+        (Button::Up, false, false) => UP,
+        (Button::Up, true, false) => UP_A,
+        (Button::Up, false, true) => UP_B,
+        (Button::Left, false, false) => LEFT,
+        (Button::Left, true, false) => LEFT_A,
+        (Button::Left, false, true) => LEFT_B,
+        (Button::Down, false, false) => DOWN,
+        (Button::Down, true, false) => DOWN_A,
+        (Button::Down, false, true) => DOWN_B,
+        (Button::Right, false, false) => RIGHT,
+        (Button::Right, true, false) => RIGHT_A,
+        (Button::Right, false, true) => RIGHT_B,
+        _ => return None,
+    };
+    Some(palette)
+}
+
+/// The palette a DMG game on `cartridge` boots with when `held` are held during the logo.
+#[must_use]
+pub fn boot_palette(cartridge: &Cartridge, held: &[Button]) -> CompatibilityPalette {
+    button_palette(held).unwrap_or_else(|| title_palette(cartridge))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble_rom;
+
+    fn cartridge(title: &[u8], licensee: u8) -> Cartridge {
+        let mut rom = assemble_rom("section \"main\", rom0[$150]\nhalt\n").unwrap();
+        let start = usize::from(*GAME_TITLE_INDEX.start());
+        rom[start..=usize::from(IS_CGB_INDEX)].fill(0);
+        rom[start..start + title.len()].copy_from_slice(title);
+        rom[0x14B] = licensee;
+        Cartridge::from_rom(rom).unwrap()
+    }
+
+    #[test]
+    fn test_title_palettes() {
+        assert_eq!(0xDB, title_hash(cartridge(b"TETRIS", 0x01).rom()));
+        assert_eq!(DOWN_A, title_palette(&cartridge(b"TETRIS", 0x01)));
+        assert_eq!(DEFAULT, title_palette(&cartridge(b"TETRIS", 0x08)));
+        assert_eq!(DEFAULT, title_palette(&cartridge(b"UNKNOWN", 0x01)));
+        assert_eq!(RIGHT_B, title_palette(&cartridge(b"SOLARSTRIKER", 0x01)));
+        // Same sum as SOLARSTRIKER, told apart by the fourth letter.
+        assert_eq!(UP, title_palette(&cartridge(b"SOLRASTRIKER", 0x01)));
+        assert_eq!(DEFAULT, title_palette(&cartridge(b"SOLSRATRIKER", 0x01)));
+    }
+
+    #[test]
+    fn test_button_palettes() {
+        assert_eq!(Some(UP_A), button_palette(&[Button::A, Button::Up]));
+        assert_eq!(Some(RIGHT), button_palette(&[Button::Right, Button::Start]));
+        assert_eq!(None, button_palette(&[Button::Right, Button::A, Button::B]));
+        assert_eq!(None, button_palette(&[Button::Up, Button::Down]));
+        assert_eq!(None, button_palette(&[Button::A]));
+
+        let tetris = cartridge(b"TETRIS", 0x01);
+        assert_eq!(LEFT, boot_palette(&tetris, &[Button::Left]));
+        assert_eq!(DOWN_A, boot_palette(&tetris, &[]));
+    }
+}
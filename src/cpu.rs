@@ -1,8 +1,19 @@
 #![allow(dead_code, unused)]
+use std::fmt;
+
 use bitflags::bitflags;
 
+use crate::bus::MemoryBus;
+use crate::memory_map::{
+    HIGH_TO_LOW_INTERUPT_START_INDEX, INTERUPT_ENABLE_REGISTER_INDEX, INTERUPT_FLAG_REGISTER_INDEX,
+    LCDC_STATUS_INTERUPT_START_INDEX, SERIAL_TRANSFER_COMPLETION_INTERUPT_START_INDEX,
+    TIMER_OVERFLOW_INTERUPT_START_INDEX, VERTICAL_BLANK_INTERUPT_START_INDEX,
+};
+use crate::model::Model;
+use crate::state::{StateError, StateReader, StateWriter};
+
 bitflags! {
-    #[derive(Debug, Clone, Default)]
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
     pub struct CpuFlags: u8 {
         const ZERO = 0b1000_0000;
         const SUBSTRACTION = 0b0100_0000;
@@ -12,7 +23,7 @@ bitflags! {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Registers {
     a: u8,
     b: u8,
@@ -22,45 +33,332 @@ pub struct Registers {
     f: CpuFlags,
     h: u8,
     l: u8,
-    sp: i16,
+    sp: u16,
     pc: u16,
 }
 
+macro_rules! reg8 {
+    ($reg:ident, $set_reg:ident) => {
+        #[must_use]
+        pub const fn $reg(&self) -> u8 {
+            self.$reg
+        }
+
+        pub const fn $set_reg(&mut self, value: u8) {
+            self.$reg = value;
+        }
+    };
+}
+
 macro_rules! reg16 {
     ($reg:ident, $set_reg:ident, $reg1:ident, $reg2:ident) => {
-        pub fn $reg(&self) -> u16 {
-            ((self.$reg1 as u16) << 8) | self.$reg2 as u16
+        #[must_use]
+        pub const fn $reg(&self) -> u16 {
+            u16::from_be_bytes([self.$reg1, self.$reg2])
         }
 
-        pub fn $set_reg(&mut self, value: u16) {
-            self.$reg1 = ((value & 0xFF00) >> 8) as u8;
-            self.$reg2 = (value & 0x00FF) as u8;
+        pub const fn $set_reg(&mut self, value: u16) {
+            [self.$reg1, self.$reg2] = value.to_be_bytes();
         }
     };
 }
 
 impl Registers {
+    reg8!(a, set_a);
+    reg8!(b, set_b);
+    reg8!(c, set_c);
+    reg8!(d, set_d);
+    reg8!(e, set_e);
+    reg8!(h, set_h);
+    reg8!(l, set_l);
     reg16!(bc, set_bc, b, c);
     reg16!(de, set_de, d, e);
     reg16!(hl, set_hl, h, l);
 
-    pub fn af(&self) -> u16 {
-        ((self.a as u16) << 8) | self.f.bits() as u16
+    #[must_use]
+    pub const fn af(&self) -> u16 {
+        u16::from_be_bytes([self.a, self.f.bits()])
+    }
+
+    pub const fn set_af(&mut self, value: u16) {
+        let [a, f] = value.to_be_bytes();
+        self.a = a;
+        self.f = CpuFlags::from_bits_truncate(f & 0xF0);
+    }
+
+    #[must_use]
+    pub const fn f(&self) -> CpuFlags {
+        self.f
+    }
+
+    pub const fn set_f(&mut self, flags: CpuFlags) {
+        self.f = CpuFlags::from_bits_truncate(flags.bits() & 0xF0);
+    }
+
+    #[must_use]
+    pub const fn sp(&self) -> u16 {
+        self.sp
+    }
+
+    pub const fn set_sp(&mut self, value: u16) {
+        self.sp = value;
+    }
+
+    #[must_use]
+    pub const fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub const fn set_pc(&mut self, value: u16) {
+        self.pc = value;
     }
+}
 
-    pub fn set_af(&mut self, value: u16) {
-        self.a = ((value & 0xFF00) >> 8) as u8;
-        self.f = CpuFlags::from_bits_truncate((value & 0x00FF) as u8);
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X}",
+            self.a,
+            self.f.bits(),
+            self.b,
+            self.c,
+            self.d,
+            self.e,
+            self.h,
+            self.l,
+            self.sp,
+            self.pc
+        )
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CpuState {
+    #[default]
+    Running,
+    Halted,
+    Stopped,
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct Cpu {
     registers: Registers,
+    ime: bool,
+    ime_scheduled: bool,
+    halt_bug: bool,
+    state: CpuState,
 }
 
+const INTERRUPT_VECTORS: [u16; 5] = [
+    VERTICAL_BLANK_INTERUPT_START_INDEX,
+    LCDC_STATUS_INTERUPT_START_INDEX,
+    TIMER_OVERFLOW_INTERUPT_START_INDEX,
+    SERIAL_TRANSFER_COMPLETION_INTERUPT_START_INDEX,
+    HIGH_TO_LOW_INTERUPT_START_INDEX,
+];
+
 impl Cpu {
-    fn ldn(&mut self, target: LdnTarget, value: u8) {
+    /// Sets the registers the boot ROM of `model` leaves. On CGB hardware they also depend
+    /// on whether it started a CGB cartridge in CGB mode, `cgb_mode`. Software tells the
+    /// models apart by A, and the AGB from the CGB by B.
+    pub fn reset_post_boot(&mut self, model: Model, cgb_mode: bool) {
+        *self = Self::default();
+        let dmg_flags = CpuFlags::ZERO | CpuFlags::HALF_CARRY | CpuFlags::CARRY;
+        let (a, f, bc, de, hl) = match model {
+            Model::Dmg0 => (0x01, CpuFlags::empty(), 0xFF13, 0x00C1, 0x8403),
+            Model::Dmg => (0x01, dmg_flags, 0x0013, 0x00D8, 0x014D),
+            Model::Mgb => (0xFF, dmg_flags, 0x0013, 0x00D8, 0x014D),
+            Model::Sgb => (0x01, CpuFlags::empty(), 0x0014, 0x0000, 0xC060),
+            Model::Cgb | Model::Agb => {
+                let (de, hl) = if cgb_mode {
+                    (0xFF56, 0x000D)
+                } else {
+                    (0x0008, 0x007C)
+                };
+                if model == Model::Cgb {
+                    (0x11, CpuFlags::ZERO, 0x0000, de, hl)
+                } else {
+                    (0x11, CpuFlags::empty(), 0x0100, de, hl)
+                }
+            }
+        };
+        let registers = &mut self.registers;
+        registers.a = a;
+        registers.f = f;
+        registers.set_bc(bc);
+        registers.set_de(de);
+        registers.set_hl(hl);
+        registers.sp = 0xFFFE;
+        registers.pc = 0x0100;
+    }
+
+    #[must_use]
+    pub const fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        let registers = &self.registers;
+        for value in [
+            registers.af(),
+            registers.bc(),
+            registers.de(),
+            registers.hl(),
+            registers.sp,
+            registers.pc,
+        ] {
+            state.u16(value);
+        }
+        state.bool(self.ime);
+        state.bool(self.ime_scheduled);
+        state.bool(self.halt_bug);
+        state.u8(self.state as u8);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.registers.set_af(state.u16()?);
+        self.registers.set_bc(state.u16()?);
+        self.registers.set_de(state.u16()?);
+        self.registers.set_hl(state.u16()?);
+        self.registers.sp = state.u16()?;
+        self.registers.pc = state.u16()?;
+        self.ime = state.bool()?;
+        self.ime_scheduled = state.bool()?;
+        self.halt_bug = state.bool()?;
+        self.state = match state.u8()? {
+            0 => CpuState::Running,
+            1 => CpuState::Halted,
+            2 => CpuState::Stopped,
+            _ => return Err(StateError::Invalid("CPU state")),
+        };
+        Ok(())
+    }
+
+    pub const fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.registers
+    }
+
+    #[must_use]
+    pub const fn state(&self) -> CpuState {
+        self.state
+    }
+
+    /// Leaves the stopped state, as the CGB does once STOP has switched speed.
+    pub(crate) const fn resume(&mut self) {
+        self.state = CpuState::Running;
+    }
+
+    #[must_use]
+    pub const fn ime(&self) -> bool {
+        self.ime
+    }
+
+    /// Whether the next [`Cpu::step`] fetches and executes an instruction, rather than idling
+    /// while halted or stopped, or dispatching an interrupt.
+    #[must_use]
+    pub fn executes_next<M: MemoryBus + ?Sized>(&self, memory: &M) -> bool {
+        let pending = memory.peek(INTERUPT_ENABLE_REGISTER_INDEX)
+            & memory.peek(INTERUPT_FLAG_REGISTER_INDEX)
+            & 0x1F;
+        let awake = match self.state {
+            CpuState::Running => true,
+            CpuState::Halted => pending != 0,
+            CpuState::Stopped => memory.peek(INTERUPT_FLAG_REGISTER_INDEX) & 0x10 != 0,
+        };
+        awake && !(self.ime && pending != 0)
+    }
+
+    pub fn step<M: MemoryBus + ?Sized>(&mut self, memory: &mut M) -> u8 {
+        let pending = memory.peek(INTERUPT_ENABLE_REGISTER_INDEX)
+            & memory.peek(INTERUPT_FLAG_REGISTER_INDEX)
+            & 0x1F;
+
+        if self.state == CpuState::Halted {
+            if pending == 0 {
+                return 4;
+            }
+            self.state = CpuState::Running;
+        }
+
+        if self.state == CpuState::Stopped {
+            if memory.peek(INTERUPT_FLAG_REGISTER_INDEX) & 0x10 == 0 {
+                return 4;
+            }
+            self.state = CpuState::Running;
+        }
+
+        if self.ime && pending != 0 {
+            return self.service_interrupt(pending, memory);
+        }
+
+        if self.ime_scheduled {
+            self.ime_scheduled = false;
+            self.ime = true;
+        }
+
+        let mut repeat_byte = std::mem::take(&mut self.halt_bug);
+        let instruction = Instruction::decode(|| {
+            let byte = memory.read(self.registers.pc);
+            if repeat_byte {
+                repeat_byte = false;
+            } else {
+                self.registers.pc = self.registers.pc.wrapping_add(1);
+            }
+            byte
+        });
+
+        self.execute(instruction, memory)
+    }
+
+    fn service_interrupt<M: MemoryBus + ?Sized>(&mut self, pending: u8, memory: &mut M) -> u8 {
+        let index = pending.trailing_zeros() as usize;
+        let flags = memory.peek(INTERUPT_FLAG_REGISTER_INDEX);
+        memory.write(INTERUPT_FLAG_REGISTER_INDEX, flags & !(1 << index));
+        self.ime = false;
+        self.ime_scheduled = false;
+        self.push_u16(self.registers.pc, memory);
+        self.registers.pc = INTERRUPT_VECTORS[index];
+        20
+    }
+
+    fn read_u16<M: MemoryBus + ?Sized>(address: u16, memory: &mut M) -> u16 {
+        u16::from_le_bytes([memory.read(address), memory.read(address.wrapping_add(1))])
+    }
+
+    fn write_u16<M: MemoryBus + ?Sized>(address: u16, value: u16, memory: &mut M) {
+        let [low, high] = value.to_le_bytes();
+        memory.write(address, low);
+        memory.write(address.wrapping_add(1), high);
+    }
+
+    fn push_u16<M: MemoryBus + ?Sized>(&mut self, value: u16, memory: &mut M) {
+        let [high, low] = value.to_be_bytes();
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        memory.write(self.registers.sp, high);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        memory.write(self.registers.sp, low);
+    }
+
+    fn pop_u16<M: MemoryBus + ?Sized>(&mut self, memory: &mut M) -> u16 {
+        let low = memory.read(self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+        let high = memory.read(self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+        u16::from_be_bytes([high, low])
+    }
+
+    const fn condition(&self, condition: JumpCondition) -> bool {
+        match condition {
+            JumpCondition::Always => true,
+            JumpCondition::NotZero => !self.registers.f.contains(CpuFlags::ZERO),
+            JumpCondition::Zero => self.registers.f.contains(CpuFlags::ZERO),
+            JumpCondition::NotCarry => !self.registers.f.contains(CpuFlags::CARRY),
+            JumpCondition::Carry => self.registers.f.contains(CpuFlags::CARRY),
+        }
+    }
+
+    fn ldn<M: MemoryBus + ?Sized>(&mut self, target: LdnTarget, value: u8, memory: &mut M) {
         match target {
             LdnTarget::A => {
                 self.registers.a = value;
@@ -83,78 +381,108 @@ impl Cpu {
             LdnTarget::L => {
                 self.registers.l = value;
             }
-            LdnTarget::BC => {
-                self.registers.set_bc(value as u16);
-            }
-            LdnTarget::DE => {
-                self.registers.set_de(value as u16);
-            }
             LdnTarget::HL => {
-                self.registers.set_hl(value as u16);
+                memory.write(self.registers.hl(), value);
             }
         }
     }
 
-    fn ldrr(&mut self, to: LdrrTarget, from: LdrrTarget, memory: &mut [u8]) {
+    fn ldrr<M: MemoryBus + ?Sized>(&mut self, to: LdrrTarget, from: LdrrTarget, memory: &mut M) {
         let value = match from {
-            LdrrTarget::A => self.registers.a as u16,
-            LdrrTarget::B => self.registers.b as u16,
-            LdrrTarget::C => self.registers.c as u16,
-            LdrrTarget::D => self.registers.d as u16,
-            LdrrTarget::E => self.registers.e as u16,
-            LdrrTarget::H => self.registers.h as u16,
-            LdrrTarget::L => self.registers.l as u16,
-            LdrrTarget::HL => memory[self.registers.hl() as usize] as u16,
+            LdrrTarget::A => self.registers.a,
+            LdrrTarget::B => self.registers.b,
+            LdrrTarget::C => self.registers.c,
+            LdrrTarget::D => self.registers.d,
+            LdrrTarget::E => self.registers.e,
+            LdrrTarget::H => self.registers.h,
+            LdrrTarget::L => self.registers.l,
+            LdrrTarget::HL => memory.read(self.registers.hl()),
         };
 
         match to {
-            LdrrTarget::A => self.registers.a = (value & 0x00FF) as u8,
-            LdrrTarget::B => self.registers.b = (value & 0x00FF) as u8,
-            LdrrTarget::C => self.registers.c = (value & 0x00FF) as u8,
-            LdrrTarget::D => self.registers.d = (value & 0x00FF) as u8,
-            LdrrTarget::E => self.registers.e = (value & 0x00FF) as u8,
-            LdrrTarget::H => self.registers.h = (value & 0x00FF) as u8,
-            LdrrTarget::L => self.registers.l = (value & 0x00FF) as u8,
-            LdrrTarget::HL => memory[self.registers.hl() as usize] = (value & 0x00FF) as u8,
-        };
+            LdrrTarget::A => self.registers.a = value,
+            LdrrTarget::B => self.registers.b = value,
+            LdrrTarget::C => self.registers.c = value,
+            LdrrTarget::D => self.registers.d = value,
+            LdrrTarget::E => self.registers.e = value,
+            LdrrTarget::H => self.registers.h = value,
+            LdrrTarget::L => self.registers.l = value,
+            LdrrTarget::HL => memory.write(self.registers.hl(), value),
+        }
     }
 
-    fn lda(&mut self, from: LdaTarget, memory: &mut [u8]) {
+    fn lda<M: MemoryBus + ?Sized>(&mut self, from: LdaTarget, memory: &mut M) {
         let value = match from {
-            LdaTarget::A => self.registers.a as u16,
-            LdaTarget::B => self.registers.b as u16,
-            LdaTarget::C => self.registers.c as u16,
-            LdaTarget::D => self.registers.d as u16,
-            LdaTarget::E => self.registers.e as u16,
-            LdaTarget::H => self.registers.h as u16,
-            LdaTarget::L => self.registers.l as u16,
-            LdaTarget::BC => memory[self.registers.bc() as usize] as u16,
-            LdaTarget::DE => memory[self.registers.de() as usize] as u16,
-            LdaTarget::HL => memory[self.registers.hl() as usize] as u16,
-            LdaTarget::Addr(addr) => memory[addr as usize] as u16,
-            LdaTarget::Value(value) => value as u16,
+            LdaTarget::A => self.registers.a,
+            LdaTarget::B => self.registers.b,
+            LdaTarget::C => self.registers.c,
+            LdaTarget::D => self.registers.d,
+            LdaTarget::E => self.registers.e,
+            LdaTarget::H => self.registers.h,
+            LdaTarget::L => self.registers.l,
+            LdaTarget::BC => memory.read(self.registers.bc()),
+            LdaTarget::DE => memory.read(self.registers.de()),
+            LdaTarget::HL => memory.read(self.registers.hl()),
+            LdaTarget::Addr(addr) => memory.read(addr),
+            LdaTarget::Value(value) => value,
         };
 
-        self.registers.a = (value & 0x00FF) as u8;
+        self.registers.a = value;
     }
 
-    fn ldfa(&mut self, to: LdfaTarget, memory: &mut [u8]) {
+    fn ldfa<M: MemoryBus + ?Sized>(&mut self, to: LdfaTarget, memory: &mut M) {
         match to {
-            LdfaTarget::A => self.registers.a = self.registers.a,
+            LdfaTarget::A => {}
             LdfaTarget::B => self.registers.b = self.registers.a,
             LdfaTarget::C => self.registers.c = self.registers.a,
             LdfaTarget::D => self.registers.d = self.registers.a,
             LdfaTarget::E => self.registers.e = self.registers.a,
             LdfaTarget::H => self.registers.h = self.registers.a,
             LdfaTarget::L => self.registers.l = self.registers.a,
-            LdfaTarget::BC => memory[self.registers.bc() as usize] = self.registers.a,
-            LdfaTarget::DE => memory[self.registers.de() as usize] = self.registers.a,
-            LdfaTarget::HL => memory[self.registers.hl() as usize] = self.registers.a,
-            LdfaTarget::Addr(addr) => memory[addr as usize] = self.registers.a,
+            LdfaTarget::BC => memory.write(self.registers.bc(), self.registers.a),
+            LdfaTarget::DE => memory.write(self.registers.de(), self.registers.a),
+            LdfaTarget::HL => memory.write(self.registers.hl(), self.registers.a),
+            LdfaTarget::Addr(addr) => memory.write(addr, self.registers.a),
+        }
+    }
+
+    fn ldh<M: MemoryBus + ?Sized>(&mut self, target: HighTarget, load_a: bool, memory: &mut M) {
+        let address = match target {
+            HighTarget::C => 0xFF00 | u16::from(self.registers.c),
+            HighTarget::Addr(offset) => 0xFF00 | u16::from(offset),
         };
+
+        if load_a {
+            self.registers.a = memory.read(address);
+        } else {
+            memory.write(address, self.registers.a);
+        }
+    }
+
+    fn ld_indirect_hl<M: MemoryBus + ?Sized>(
+        &mut self,
+        target: LdiTarget,
+        step: u16,
+        memory: &mut M,
+    ) {
+        let address = self.registers.hl();
+        match target {
+            LdiTarget::A => self.registers.a = memory.read(address),
+            LdiTarget::HL => memory.write(address, self.registers.a),
+        }
+        self.registers.set_hl(address.wrapping_add(step));
+    }
+
+    const fn ld16(&mut self, target: Ld16Target, value: u16) {
+        match target {
+            Ld16Target::BC => self.registers.set_bc(value),
+            Ld16Target::DE => self.registers.set_de(value),
+            Ld16Target::HL => self.registers.set_hl(value),
+            Ld16Target::SP => self.registers.sp = value,
+        }
     }
 
-    fn push(&mut self, target: StackTarget, memory: &mut [u8]) {
+    fn push<M: MemoryBus + ?Sized>(&mut self, target: StackTarget, memory: &mut M) {
         let value = match target {
             StackTarget::AF => self.registers.af(),
             StackTarget::BC => self.registers.bc(),
@@ -162,452 +490,219 @@ impl Cpu {
             StackTarget::HL => self.registers.hl(),
         };
 
-        memory[self.registers.sp as usize] = ((value & 0xFF00) >> 8) as u8;
-        self.registers.sp -= 1;
-        memory[self.registers.sp as usize] = (value & 0x00FF) as u8;
-        self.registers.sp -= 1;
+        self.push_u16(value, memory);
     }
 
-    fn pop(&mut self, target: StackTarget, memory: &mut [u8]) {
-        let mut value: u16 = 0;
-        self.registers.sp += 1;
-        value += memory[self.registers.sp as usize] as u16;
-        self.registers.sp += 1;
-        value += (memory[self.registers.sp as usize] as u16) << 8;
-    }
+    fn pop<M: MemoryBus + ?Sized>(&mut self, target: StackTarget, memory: &mut M) {
+        let value = self.pop_u16(memory);
 
-    fn add(&mut self, target: AddTarget, memory: &mut [u8]) {
         match target {
-            AddTarget::HL => {
-                let value = memory[self.registers.hl() as usize];
-                let (new_value, overflow) = self.registers.a.overflowing_add(value);
-                let mut flags = CpuFlags::empty();
-
-                if overflow {
-                    flags.set(CpuFlags::CARRY, true);
-                }
-
-                if new_value == 0 {
-                    flags.set(CpuFlags::ZERO, true);
-                }
-
-                if (self.registers.a <= 0xF || value <= 0xF) && new_value > 0xF {
-                    flags.set(CpuFlags::HALF_CARRY, true);
-                }
-
-                self.registers.f = flags;
-                self.registers.a = new_value;
-            }
-            AddTarget::Value(value) => {
-                let (new_value, overflow) = self.registers.a.overflowing_add(value);
-                let mut flags = CpuFlags::empty();
-
-                if overflow {
-                    flags.set(CpuFlags::CARRY, true);
-                }
-
-                if new_value == 0 {
-                    flags.set(CpuFlags::ZERO, true);
-                }
-
-                if (self.registers.a <= 0xF || value <= 0xF) && new_value > 0xF {
-                    flags.set(CpuFlags::HALF_CARRY, true);
-                }
-
-                self.registers.f = flags;
-                self.registers.a = new_value;
-            }
-            other => {
-                let value = match other {
-                    AddTarget::A => self.registers.a,
-                    AddTarget::B => self.registers.b,
-                    AddTarget::C => self.registers.c,
-                    AddTarget::D => self.registers.d,
-                    AddTarget::E => self.registers.e,
-                    AddTarget::H => self.registers.h,
-                    AddTarget::L => self.registers.l,
-                    _ => unreachable!("Other targets must be checked before."),
-                };
-                let (new_value, overflow) = self.registers.a.overflowing_add(value);
-                let mut flags = CpuFlags::empty();
-
-                if overflow {
-                    flags.set(CpuFlags::CARRY, true);
-                }
-
-                if new_value == 0 {
-                    flags.set(CpuFlags::ZERO, true);
-                }
-
-                if (self.registers.a <= 0xF || value <= 0xF) && new_value > 0xF {
-                    flags.set(CpuFlags::HALF_CARRY, true);
-                }
-
-                self.registers.f = flags;
-                self.registers.a = new_value;
-            }
+            StackTarget::AF => self.registers.set_af(value),
+            StackTarget::BC => self.registers.set_bc(value),
+            StackTarget::DE => self.registers.set_de(value),
+            StackTarget::HL => self.registers.set_hl(value),
         }
     }
 
-    fn adc(&mut self, target: AddTarget, memory: &mut [u8]) {
-        match target {
-            AddTarget::HL => {
-                let value = memory[self.registers.hl() as usize];
-                let (new_value, overflow) = self.registers.a.overflowing_add((value & 0xFF) as u8);
-                let mut flags = CpuFlags::empty();
-
-                if overflow {
-                    flags |= CpuFlags::CARRY;
-                }
-
-                if new_value == 0 {
-                    flags |= CpuFlags::ZERO;
-                }
-
-                if (self.registers.a <= 0xF || value <= 0xF) && new_value > 0xF {
-                    flags |= CpuFlags::HALF_CARRY;
-                }
-
-                self.registers.f = flags;
-                self.registers.a = new_value + self.registers.f.contains(CpuFlags::CARRY) as u8;
-            }
-            AddTarget::Value(value) => {
-                let (new_value, overflow) = self.registers.a.overflowing_add(value);
-                let mut flags = CpuFlags::empty();
-
-                if overflow {
-                    flags |= CpuFlags::CARRY;
-                }
-
-                if new_value == 0 {
-                    flags |= CpuFlags::ZERO;
-                }
-
-                if (self.registers.a <= 0xF || value <= 0xF) && new_value > 0xF {
-                    flags |= CpuFlags::HALF_CARRY;
-                }
-
-                self.registers.f = flags;
-                self.registers.a = new_value + self.registers.f.contains(CpuFlags::CARRY) as u8;
-            }
-            other => {
-                let value = match other {
-                    AddTarget::A => self.registers.a,
-                    AddTarget::B => self.registers.b,
-                    AddTarget::C => self.registers.c,
-                    AddTarget::D => self.registers.d,
-                    AddTarget::E => self.registers.e,
-                    AddTarget::H => self.registers.h,
-                    AddTarget::L => self.registers.l,
-                    _ => unreachable!("Other targets must be checked before."),
-                };
-                let (new_value, overflow) = self.registers.a.overflowing_add(value);
-                let mut flags = CpuFlags::empty();
-
-                if overflow {
-                    flags |= CpuFlags::CARRY;
-                }
-
-                if new_value == 0 {
-                    flags |= CpuFlags::ZERO;
-                }
-
-                if (self.registers.a <= 0xF || value <= 0xF) && new_value > 0xF {
-                    flags |= CpuFlags::HALF_CARRY;
-                }
+    fn add<M: MemoryBus + ?Sized>(&mut self, target: AddTarget, memory: &mut M) {
+        let value = self.add_operand(target, memory);
+        self.add_with_carry(value, 0);
+    }
 
-                self.registers.f = flags;
-                self.registers.a = new_value + self.registers.f.contains(CpuFlags::CARRY) as u8;
-            }
-        }
+    fn adc<M: MemoryBus + ?Sized>(&mut self, target: AddTarget, memory: &mut M) {
+        let value = self.add_operand(target, memory);
+        let carry = u8::from(self.registers.f.contains(CpuFlags::CARRY));
+        self.add_with_carry(value, carry);
     }
 
-    fn sub(&mut self, target: SubTarget, memory: &mut [u8]) {
+    fn add_operand<M: MemoryBus + ?Sized>(&self, target: AddTarget, memory: &mut M) -> u8 {
         match target {
-            SubTarget::HL => {
-                let value = memory[self.registers.hl() as usize];
-                let (new_value, overflow) = self.registers.a.overflowing_sub((value & 0xFF) as u8);
-                let mut flags = CpuFlags::empty();
-                flags |= CpuFlags::SUBSTRACTION;
-
-                if overflow {
-                    flags |= CpuFlags::CARRY;
-                }
-
-                if new_value == 0 {
-                    flags |= CpuFlags::ZERO;
-                }
-
-                if (self.registers.a > 0xF || value > 0xF) && new_value <= 0xF {
-                    flags |= CpuFlags::HALF_CARRY;
-                }
-
-                self.registers.f = flags;
-                self.registers.a = new_value;
-            }
-            SubTarget::Value(value) => {
-                let (new_value, overflow) = self.registers.a.overflowing_sub(value);
-                let mut flags = CpuFlags::empty();
-                flags |= CpuFlags::SUBSTRACTION;
-
-                if overflow {
-                    flags |= CpuFlags::CARRY;
-                }
+            AddTarget::A => self.registers.a,
+            AddTarget::B => self.registers.b,
+            AddTarget::C => self.registers.c,
+            AddTarget::D => self.registers.d,
+            AddTarget::E => self.registers.e,
+            AddTarget::H => self.registers.h,
+            AddTarget::L => self.registers.l,
+            AddTarget::HL => memory.read(self.registers.hl()),
+            AddTarget::Value(value) => value,
+        }
+    }
 
-                if new_value == 0 {
-                    flags |= CpuFlags::ZERO;
-                }
+    /// Adds `value` and the carry-in `carry` to A.
+    fn add_with_carry(&mut self, value: u8, carry: u8) {
+        let a = self.registers.a;
+        let new_value = a.wrapping_add(value).wrapping_add(carry);
+        let mut flags = CpuFlags::empty();
 
-                if (self.registers.a > 0xF || value > 0xF) && new_value <= 0xF {
-                    flags |= CpuFlags::HALF_CARRY;
-                }
+        if u16::from(a) + u16::from(value) + u16::from(carry) > 0xFF {
+            flags |= CpuFlags::CARRY;
+        }
 
-                self.registers.f = flags;
-                self.registers.a = new_value;
-            }
-            other => {
-                let value = match other {
-                    SubTarget::A => self.registers.a,
-                    SubTarget::B => self.registers.b,
-                    SubTarget::C => self.registers.c,
-                    SubTarget::D => self.registers.d,
-                    SubTarget::E => self.registers.e,
-                    SubTarget::H => self.registers.h,
-                    SubTarget::L => self.registers.l,
-                    _ => unreachable!("Other targets must be checked before."),
-                };
-                let (new_value, overflow) = self.registers.a.overflowing_sub(value);
-                let mut flags = CpuFlags::empty();
-                flags |= CpuFlags::SUBSTRACTION;
+        if new_value == 0 {
+            flags |= CpuFlags::ZERO;
+        }
 
-                if overflow {
-                    flags |= CpuFlags::CARRY;
-                }
+        if (a & 0xF) + (value & 0xF) + carry > 0xF {
+            flags |= CpuFlags::HALF_CARRY;
+        }
 
-                if new_value == 0 {
-                    flags |= CpuFlags::ZERO;
-                }
+        self.registers.f = flags;
+        self.registers.a = new_value;
+    }
 
-                if (self.registers.a > 0xF || value > 0xF) && new_value <= 0xF {
-                    flags |= CpuFlags::HALF_CARRY;
-                }
+    fn sub<M: MemoryBus + ?Sized>(&mut self, target: SubTarget, memory: &mut M) {
+        let value = self.sub_operand(target, memory);
+        self.registers.a = self.compare(value, 0);
+    }
 
-                self.registers.f = flags;
-                self.registers.a = new_value;
-            }
-        }
+    fn sbc<M: MemoryBus + ?Sized>(&mut self, target: SubTarget, memory: &mut M) {
+        let value = self.sub_operand(target, memory);
+        let carry = u8::from(self.registers.f.contains(CpuFlags::CARRY));
+        self.registers.a = self.compare(value, carry);
     }
 
-    fn sbc(&mut self, target: SubTarget, memory: &mut [u8]) {
+    fn sub_operand<M: MemoryBus + ?Sized>(&self, target: SubTarget, memory: &mut M) -> u8 {
         match target {
-            SubTarget::HL => {
-                let value = memory[self.registers.hl() as usize]
-                    + self.registers.f.contains(CpuFlags::CARRY) as u8;
-                let (new_value, overflow) = self.registers.a.overflowing_sub((value & 0xFF) as u8);
-                let mut flags = CpuFlags::empty();
-                flags |= CpuFlags::SUBSTRACTION;
-
-                if overflow {
-                    flags |= CpuFlags::CARRY;
-                }
-
-                if new_value == 0 {
-                    flags |= CpuFlags::ZERO;
-                }
-
-                if (self.registers.a & 0xF) - ((value & 0xF) as u8) > 0xF {
-                    flags |= CpuFlags::HALF_CARRY;
-                }
-
-                self.registers.a = new_value;
-            }
-            SubTarget::Value(value) => {
-                let value = value + self.registers.f.contains(CpuFlags::CARRY) as u8;
-                let (new_value, overflow) = self.registers.a.overflowing_sub((value & 0xFF) as u8);
-                let mut flags = CpuFlags::empty();
-                flags |= CpuFlags::SUBSTRACTION;
+            SubTarget::A => self.registers.a,
+            SubTarget::B => self.registers.b,
+            SubTarget::C => self.registers.c,
+            SubTarget::D => self.registers.d,
+            SubTarget::E => self.registers.e,
+            SubTarget::H => self.registers.h,
+            SubTarget::L => self.registers.l,
+            SubTarget::HL => memory.read(self.registers.hl()),
+            SubTarget::Value(value) => value,
+        }
+    }
 
-                if overflow {
-                    flags |= CpuFlags::CARRY;
-                }
+    /// Sets the flags for subtracting `value` and the carry-in `carry` from A, and returns the
+    /// result without storing it.
+    fn compare(&mut self, value: u8, carry: u8) -> u8 {
+        let a = self.registers.a;
+        let new_value = a.wrapping_sub(value).wrapping_sub(carry);
+        let mut flags = CpuFlags::empty();
+        flags |= CpuFlags::SUBSTRACTION;
 
-                if new_value == 0 {
-                    flags |= CpuFlags::ZERO;
-                }
+        if u16::from(a) < u16::from(value) + u16::from(carry) {
+            flags |= CpuFlags::CARRY;
+        }
 
-                if (self.registers.a & 0xF) - ((value & 0xF) as u8) > 0xF {
-                    flags |= CpuFlags::HALF_CARRY;
-                }
+        if new_value == 0 {
+            flags |= CpuFlags::ZERO;
+        }
 
-                self.registers.a = new_value;
-            }
-            other => {
-                let value = match other {
-                    SubTarget::A => self.registers.a,
-                    SubTarget::B => self.registers.b,
-                    SubTarget::C => self.registers.c,
-                    SubTarget::D => self.registers.d,
-                    SubTarget::E => self.registers.e,
-                    SubTarget::H => self.registers.h,
-                    SubTarget::L => self.registers.l,
-                    _ => unreachable!("Other targets must be checked before."),
-                };
-                let value = value + self.registers.f.contains(CpuFlags::CARRY) as u8;
-                let (mut new_value, overflow) = self.registers.a.overflowing_sub(value);
-                let mut flags = CpuFlags::empty();
-                flags |= CpuFlags::SUBSTRACTION;
-
-                if overflow {
-                    flags |= CpuFlags::CARRY;
-                    new_value += 1;
-                }
+        if (a & 0xF) < (value & 0xF) + carry {
+            flags |= CpuFlags::HALF_CARRY;
+        }
 
-                if new_value == 0 {
-                    flags |= CpuFlags::ZERO;
-                }
+        self.registers.f = flags;
+        new_value
+    }
 
-                if (self.registers.a & 0xF) - ((value & 0xF) as u8) > 0xF {
-                    flags |= CpuFlags::HALF_CARRY;
-                }
+    fn cp<M: MemoryBus + ?Sized>(&mut self, target: CpTarget, memory: &mut M) {
+        let n = match target {
+            CpTarget::A => self.registers.a,
+            CpTarget::B => self.registers.b,
+            CpTarget::C => self.registers.c,
+            CpTarget::D => self.registers.d,
+            CpTarget::E => self.registers.e,
+            CpTarget::H => self.registers.h,
+            CpTarget::L => self.registers.l,
+            CpTarget::HL => memory.read(self.registers.hl()),
+            CpTarget::Addr(value) => memory.read(u16::from(value)),
+            CpTarget::Value(value) => value,
+        };
+        self.compare(n, 0);
+    }
 
-                self.registers.a = new_value;
-            }
+    fn inc_dec_value<M: MemoryBus + ?Sized>(&self, target: IncTarget, memory: &mut M) -> u8 {
+        match target {
+            IncTarget::A => self.registers.a,
+            IncTarget::B => self.registers.b,
+            IncTarget::C => self.registers.c,
+            IncTarget::D => self.registers.d,
+            IncTarget::E => self.registers.e,
+            IncTarget::H => self.registers.h,
+            IncTarget::L => self.registers.l,
+            IncTarget::HL => memory.read(self.registers.hl()),
         }
     }
 
-    fn cp(&mut self, target: CpTarget, memory: &mut [u8]) {
+    fn set_inc_dec_value<M: MemoryBus + ?Sized>(
+        &mut self,
+        target: IncTarget,
+        value: u8,
+        memory: &mut M,
+    ) {
         match target {
-            CpTarget::HL => {
-                let n = memory[self.registers.hl() as usize];
-                let a = self.registers.a;
-                let mut flags = CpuFlags::empty();
-                flags |= CpuFlags::SUBSTRACTION;
-
-                if a < n {
-                    flags |= CpuFlags::CARRY;
-                }
-
-                if n == a {
-                    flags |= CpuFlags::ZERO;
-                }
-
-                if a > n && a > 0xF && a - n <= 0xF {
-                    flags |= CpuFlags::HALF_CARRY;
-                }
+            IncTarget::A => self.registers.a = value,
+            IncTarget::B => self.registers.b = value,
+            IncTarget::C => self.registers.c = value,
+            IncTarget::D => self.registers.d = value,
+            IncTarget::E => self.registers.e = value,
+            IncTarget::H => self.registers.h = value,
+            IncTarget::L => self.registers.l = value,
+            IncTarget::HL => memory.write(self.registers.hl(), value),
+        }
+    }
 
-                self.registers.f = flags;
-            }
-            CpTarget::Addr(value) => {
-                let n = memory[value as usize];
-                let a = self.registers.a;
-                let mut flags = CpuFlags::empty();
-                flags |= CpuFlags::SUBSTRACTION;
+    fn inc<M: MemoryBus + ?Sized>(&mut self, target: IncTarget, memory: &mut M) {
+        let value = self.inc_dec_value(target, memory);
+        let new_value = value.wrapping_add(1);
 
-                if a < n {
-                    flags |= CpuFlags::CARRY;
-                }
+        let mut flags = self.registers.f & CpuFlags::CARRY;
 
-                if n == a {
-                    flags |= CpuFlags::ZERO;
-                }
+        if new_value == 0 {
+            flags |= CpuFlags::ZERO;
+        }
 
-                if a > n && a > 0xF && a - n <= 0xF {
-                    flags |= CpuFlags::HALF_CARRY;
-                }
+        if value & 0x0F == 0x0F {
+            flags |= CpuFlags::HALF_CARRY;
+        }
 
-                self.registers.f = flags;
-            }
-            other => {
-                let n = match other {
-                    CpTarget::A => self.registers.a,
-                    CpTarget::B => self.registers.b,
-                    CpTarget::C => self.registers.c,
-                    CpTarget::D => self.registers.d,
-                    CpTarget::E => self.registers.e,
-                    CpTarget::H => self.registers.h,
-                    CpTarget::L => self.registers.l,
-                    _ => unreachable!("Other targets must be checked before."),
-                };
-                let a = self.registers.a;
-                let mut flags = CpuFlags::empty();
-                flags |= CpuFlags::SUBSTRACTION;
+        self.registers.f = flags;
+        self.set_inc_dec_value(target, new_value, memory);
+    }
 
-                if a < n {
-                    flags |= CpuFlags::CARRY;
-                }
+    fn dec<M: MemoryBus + ?Sized>(&mut self, target: IncTarget, memory: &mut M) {
+        let value = self.inc_dec_value(target, memory);
+        let new_value = value.wrapping_sub(1);
 
-                if n == a {
-                    flags |= CpuFlags::ZERO;
-                }
+        let mut flags = self.registers.f & CpuFlags::CARRY;
+        flags |= CpuFlags::SUBSTRACTION;
 
-                if a > n && a > 0xF && a - n <= 0xF {
-                    flags |= CpuFlags::HALF_CARRY;
-                }
+        if new_value == 0 {
+            flags |= CpuFlags::ZERO;
+        }
 
-                self.registers.f = flags;
-            }
+        if value.trailing_zeros() >= 4 {
+            flags |= CpuFlags::HALF_CARRY;
         }
+
+        self.registers.f = flags;
+        self.set_inc_dec_value(target, new_value, memory);
     }
 
-    fn inc(&mut self, target: IncTarget) {
+    const fn inc16(&mut self, target: Inc16Target, delta: u16) {
         match target {
-            IncTarget::HL => {
-                let value = self.registers.hl();
-                let (new_value, overflow) = value.overflowing_add(1);
-
-                let mut flags = self.registers.f.clone();
-
-                if new_value == 0 {
-                    flags.set(CpuFlags::ZERO, true);
-                }
-
-                if value == 0b0000_0000_0000_1111 {
-                    flags.set(CpuFlags::HALF_CARRY, true);
-                }
-
-                self.registers.set_hl(new_value);
-            }
-            other => {
-                let value = match other {
-                    IncTarget::A => self.registers.a,
-                    IncTarget::B => self.registers.b,
-                    IncTarget::C => self.registers.c,
-                    IncTarget::D => self.registers.d,
-                    IncTarget::E => self.registers.e,
-                    IncTarget::H => self.registers.h,
-                    IncTarget::L => self.registers.l,
-                    _ => unreachable!("Other targets must be checked before."),
-                };
-                let (new_value, overflow) = value.overflowing_add(1);
-
-                let mut flags = self.registers.f.clone();
-
-                if new_value == 0 {
-                    flags.set(CpuFlags::ZERO, true);
-                }
-
-                if value == 0b0000_1111 {
-                    flags.set(CpuFlags::HALF_CARRY, true);
-                }
-
-                let value = match other {
-                    IncTarget::A => self.registers.a = new_value,
-                    IncTarget::B => self.registers.b = new_value,
-                    IncTarget::C => self.registers.c = new_value,
-                    IncTarget::D => self.registers.d = new_value,
-                    IncTarget::E => self.registers.e = new_value,
-                    IncTarget::H => self.registers.h = new_value,
-                    IncTarget::L => self.registers.l = new_value,
-                    _ => unreachable!("Other targets must be checked before."),
-                };
-            }
+            Inc16Target::BC => self
+                .registers
+                .set_bc(self.registers.bc().wrapping_add(delta)),
+            Inc16Target::DE => self
+                .registers
+                .set_de(self.registers.de().wrapping_add(delta)),
+            Inc16Target::HL => self
+                .registers
+                .set_hl(self.registers.hl().wrapping_add(delta)),
+            Inc16Target::SP => self.registers.sp = self.registers.sp.wrapping_add(delta),
         }
     }
 
-    fn and(&mut self, target: LogicTarget, memory: &mut [u8]) {
+    fn and<M: MemoryBus + ?Sized>(&mut self, target: LogicTarget, memory: &mut M) {
         match target {
             LogicTarget::HL => {
-                let value = memory[self.registers.hl() as usize];
+                let value = memory.read(self.registers.hl());
                 self.registers.a &= value;
                 let mut flag = CpuFlags::empty();
                 if self.registers.a == 0 {
@@ -651,10 +746,10 @@ impl Cpu {
         }
     }
 
-    fn or(&mut self, target: LogicTarget, memory: &mut [u8]) {
+    fn or<M: MemoryBus + ?Sized>(&mut self, target: LogicTarget, memory: &mut M) {
         match target {
             LogicTarget::HL => {
-                let value = memory[self.registers.hl() as usize];
+                let value = memory.read(self.registers.hl());
                 self.registers.a |= value;
                 let mut flag = CpuFlags::empty();
                 if self.registers.a == 0 {
@@ -695,10 +790,10 @@ impl Cpu {
         }
     }
 
-    fn xor(&mut self, target: LogicTarget, memory: &mut [u8]) {
+    fn xor<M: MemoryBus + ?Sized>(&mut self, target: LogicTarget, memory: &mut M) {
         match target {
             LogicTarget::HL => {
-                let value = memory[self.registers.hl() as usize];
+                let value = memory.read(self.registers.hl());
                 self.registers.a ^= value;
                 let mut flag = CpuFlags::empty();
                 if self.registers.a == 0 {
@@ -746,14 +841,15 @@ impl Cpu {
             Add16Target::HL => self.registers.hl(),
             Add16Target::SP => self.registers.sp,
         };
-        let (new_value, overflow) = self.registers.hl().overflowing_add(value);
-        let mut flags = CpuFlags::empty();
+        let hl = self.registers.hl();
+        let (new_value, overflow) = hl.overflowing_add(value);
+        let mut flags = self.registers.f & CpuFlags::ZERO;
 
         if overflow {
             flags |= CpuFlags::CARRY;
         }
 
-        if (self.registers.hl() <= 0x0FFF || value <= 0x0FFF) && new_value > 0x0FFF {
+        if (hl & 0x0FFF) + (value & 0x0FFF) > 0x0FFF {
             flags |= CpuFlags::HALF_CARRY;
         }
 
@@ -761,92 +857,518 @@ impl Cpu {
         self.registers.set_hl(new_value);
     }
 
+    fn sp_plus_offset(&mut self, value: i8) -> u16 {
+        let sp = self.registers.sp;
+        let offset = u16::from(value.cast_unsigned());
+        let mut flags = CpuFlags::empty();
+
+        if (sp & 0x000F) + (offset & 0x000F) > 0x000F {
+            flags |= CpuFlags::HALF_CARRY;
+        }
+
+        if (sp & 0x00FF) + (offset & 0x00FF) > 0x00FF {
+            flags |= CpuFlags::CARRY;
+        }
+
+        self.registers.f = flags;
+        sp.wrapping_add_signed(i16::from(value))
+    }
+
     fn add_sp(&mut self, value: i8) {
-        let (new_value, overflow) = if value > 0 {
-            self.registers.sp.overflowing_add(value as _)
-        } else {
-            self.registers.sp.overflowing_sub(value as _)
+        self.registers.sp = self.sp_plus_offset(value);
+    }
+
+    fn rotate<M: MemoryBus + ?Sized>(
+        &mut self,
+        kind: RotateKind,
+        target: PrefixTarget,
+        memory: &mut M,
+    ) {
+        let value = self.prefix_value(target, memory);
+        let new_value = self.rotate_value(kind, value);
+        self.set_prefix_value(target, new_value, memory);
+    }
+
+    fn rotate_value(&mut self, kind: RotateKind, value: u8) -> u8 {
+        let carry_in = self.registers.f.contains(CpuFlags::CARRY);
+        let (new_value, carry_out) = match kind {
+            RotateKind::Rlc => (value.rotate_left(1), value & 0x80 != 0),
+            RotateKind::Rrc => (value.rotate_right(1), value & 0x01 != 0),
+            RotateKind::Rl => ((value << 1) | u8::from(carry_in), value & 0x80 != 0),
+            RotateKind::Rr => ((value >> 1) | (u8::from(carry_in) << 7), value & 0x01 != 0),
+            RotateKind::Sla => (value << 1, value & 0x80 != 0),
+            RotateKind::Sra => ((value >> 1) | (value & 0x80), value & 0x01 != 0),
+            RotateKind::Swap => (value.rotate_left(4), false),
+            RotateKind::Srl => (value >> 1, value & 0x01 != 0),
         };
         let mut flags = CpuFlags::empty();
 
-        if overflow {
+        if carry_out {
             flags |= CpuFlags::CARRY;
         }
 
-        if (self.registers.hl() <= 0x0FFF || value <= 0x0FFF) && new_value > 0x0FFF {
-            flags |= CpuFlags::HALF_CARRY;
+        if new_value == 0 {
+            flags |= CpuFlags::ZERO;
         }
-        if (self.registers.hl() > 0x0FFF || value > 0x0FFF) && new_value <= 0x0FFF {
-            flags |= CpuFlags::HALF_CARRY;
+
+        self.registers.f = flags;
+        new_value
+    }
+
+    fn rotate_a(&mut self, kind: RotateKind) {
+        self.registers.a = self.rotate_value(kind, self.registers.a);
+        self.registers.f.remove(CpuFlags::ZERO);
+    }
+
+    fn bit<M: MemoryBus + ?Sized>(&mut self, bit: u8, target: PrefixTarget, memory: &mut M) {
+        let value = self.prefix_value(target, memory);
+        let mut flags = self.registers.f & CpuFlags::CARRY;
+        flags |= CpuFlags::HALF_CARRY;
+
+        if value & (1 << bit) == 0 {
+            flags |= CpuFlags::ZERO;
+        }
+
+        self.registers.f = flags;
+    }
+
+    fn set_bit<M: MemoryBus + ?Sized>(
+        &mut self,
+        bit: u8,
+        set: bool,
+        target: PrefixTarget,
+        memory: &mut M,
+    ) {
+        let value = self.prefix_value(target, memory);
+        let new_value = if set {
+            value | (1 << bit)
+        } else {
+            value & !(1 << bit)
+        };
+        self.set_prefix_value(target, new_value, memory);
+    }
+
+    fn prefix_value<M: MemoryBus + ?Sized>(&self, target: PrefixTarget, memory: &mut M) -> u8 {
+        match target {
+            PrefixTarget::A => self.registers.a,
+            PrefixTarget::B => self.registers.b,
+            PrefixTarget::C => self.registers.c,
+            PrefixTarget::D => self.registers.d,
+            PrefixTarget::E => self.registers.e,
+            PrefixTarget::H => self.registers.h,
+            PrefixTarget::L => self.registers.l,
+            PrefixTarget::HL => memory.read(self.registers.hl()),
+        }
+    }
+
+    fn set_prefix_value<M: MemoryBus + ?Sized>(
+        &mut self,
+        target: PrefixTarget,
+        value: u8,
+        memory: &mut M,
+    ) {
+        match target {
+            PrefixTarget::A => self.registers.a = value,
+            PrefixTarget::B => self.registers.b = value,
+            PrefixTarget::C => self.registers.c = value,
+            PrefixTarget::D => self.registers.d = value,
+            PrefixTarget::E => self.registers.e = value,
+            PrefixTarget::H => self.registers.h = value,
+            PrefixTarget::L => self.registers.l = value,
+            PrefixTarget::HL => memory.write(self.registers.hl(), value),
+        }
+    }
+
+    fn daa(&mut self) {
+        let mut a = self.registers.a;
+        let subtract = self.registers.f.contains(CpuFlags::SUBSTRACTION);
+        let half_carry = self.registers.f.contains(CpuFlags::HALF_CARRY);
+        let mut carry = self.registers.f.contains(CpuFlags::CARRY);
+
+        if subtract {
+            if carry {
+                a = a.wrapping_sub(0x60);
+            }
+            if half_carry {
+                a = a.wrapping_sub(0x06);
+            }
+        } else {
+            if carry || a > 0x99 {
+                a = a.wrapping_add(0x60);
+                carry = true;
+            }
+            if half_carry || a & 0x0F > 0x09 {
+                a = a.wrapping_add(0x06);
+            }
+        }
+
+        let mut flags = self.registers.f & CpuFlags::SUBSTRACTION;
+
+        if carry {
+            flags |= CpuFlags::CARRY;
+        }
+
+        if a == 0 {
+            flags |= CpuFlags::ZERO;
         }
 
         self.registers.f = flags;
-        self.registers.sp = new_value;
+        self.registers.a = a;
+    }
+
+    fn call<M: MemoryBus + ?Sized>(&mut self, address: u16, memory: &mut M) {
+        self.push_u16(self.registers.pc, memory);
+        self.registers.pc = address;
     }
 
-    pub fn execute(&mut self, instruction: Instruction, memory: &mut [u8]) {
+    #[allow(clippy::too_many_lines)]
+    pub fn execute<M: MemoryBus + ?Sized>(
+        &mut self,
+        instruction: Instruction,
+        memory: &mut M,
+    ) -> u8 {
         match instruction {
             Instruction::LDN(target, value) => {
-                self.ldn(target, value);
+                self.ldn(target, value, memory);
+                if target == LdnTarget::HL {
+                    12
+                } else {
+                    8
+                }
             }
             Instruction::LDRR(to, from) => {
                 self.ldrr(to, from, memory);
+                if to == LdrrTarget::HL || from == LdrrTarget::HL {
+                    8
+                } else {
+                    4
+                }
             }
             Instruction::LDA(from) => {
                 self.lda(from, memory);
+                match from {
+                    LdaTarget::Addr(_) => 16,
+                    LdaTarget::BC | LdaTarget::DE | LdaTarget::HL | LdaTarget::Value(_) => 8,
+                    _ => 4,
+                }
             }
             Instruction::LDFA(to) => {
                 self.ldfa(to, memory);
+                match to {
+                    LdfaTarget::Addr(_) => 16,
+                    LdfaTarget::BC | LdfaTarget::DE | LdfaTarget::HL => 8,
+                    _ => 4,
+                }
+            }
+            Instruction::LDHA(target) => {
+                self.ldh(target, true, memory);
+                if target == HighTarget::C {
+                    8
+                } else {
+                    12
+                }
+            }
+            Instruction::LDHFA(target) => {
+                self.ldh(target, false, memory);
+                if target == HighTarget::C {
+                    8
+                } else {
+                    12
+                }
+            }
+            Instruction::LDI(target) => {
+                self.ld_indirect_hl(target, 1, memory);
+                8
+            }
+            Instruction::LDD(target) => {
+                self.ld_indirect_hl(target, 0xFFFF, memory);
+                8
+            }
+            Instruction::LD16(target, value) => {
+                self.ld16(target, value);
+                12
+            }
+            Instruction::LDSPHL => {
+                self.registers.sp = self.registers.hl();
+                8
+            }
+            Instruction::LDHLSP(value) => {
+                let address = self.sp_plus_offset(value);
+                self.registers.set_hl(address);
+                12
+            }
+            Instruction::LDNNSP(address) => {
+                Self::write_u16(address, self.registers.sp, memory);
+                20
             }
             Instruction::PUSH(target) => {
                 self.push(target, memory);
+                16
             }
             Instruction::POP(target) => {
                 self.pop(target, memory);
+                12
             }
             Instruction::ADD(target) => {
                 self.add(target, memory);
+                if matches!(target, AddTarget::HL | AddTarget::Value(_)) {
+                    8
+                } else {
+                    4
+                }
             }
             Instruction::ADC(target) => {
                 self.adc(target, memory);
+                if matches!(target, AddTarget::HL | AddTarget::Value(_)) {
+                    8
+                } else {
+                    4
+                }
             }
             Instruction::SUB(target) => {
                 self.sub(target, memory);
+                if matches!(target, SubTarget::HL | SubTarget::Value(_)) {
+                    8
+                } else {
+                    4
+                }
             }
             Instruction::SBC(target) => {
                 self.sbc(target, memory);
+                if matches!(target, SubTarget::HL | SubTarget::Value(_)) {
+                    8
+                } else {
+                    4
+                }
             }
             Instruction::CP(target) => {
                 self.cp(target, memory);
+                if matches!(
+                    target,
+                    CpTarget::HL | CpTarget::Addr(_) | CpTarget::Value(_)
+                ) {
+                    8
+                } else {
+                    4
+                }
             }
             Instruction::INC(target) => {
-                self.inc(target);
+                self.inc(target, memory);
+                if target == IncTarget::HL {
+                    12
+                } else {
+                    4
+                }
+            }
+            Instruction::DEC(target) => {
+                self.dec(target, memory);
+                if target == IncTarget::HL {
+                    12
+                } else {
+                    4
+                }
             }
             Instruction::AND(target) => {
                 self.and(target, memory);
+                if matches!(target, LogicTarget::HL | LogicTarget::Value(_)) {
+                    8
+                } else {
+                    4
+                }
             }
             Instruction::OR(target) => {
                 self.or(target, memory);
+                if matches!(target, LogicTarget::HL | LogicTarget::Value(_)) {
+                    8
+                } else {
+                    4
+                }
             }
             Instruction::XOR(target) => {
                 self.xor(target, memory);
+                if matches!(target, LogicTarget::HL | LogicTarget::Value(_)) {
+                    8
+                } else {
+                    4
+                }
             }
             Instruction::ADD16(target) => {
                 self.add_hl(target);
+                8
             }
             Instruction::ADDSP(value) => {
                 self.add_sp(value);
+                16
+            }
+            Instruction::INC16(target) => {
+                self.inc16(target, 1);
+                8
+            }
+            Instruction::DEC16(target) => {
+                self.inc16(target, 0xFFFF);
+                8
+            }
+            Instruction::DAA => {
+                self.daa();
+                4
+            }
+            Instruction::CPL => {
+                self.registers.a = !self.registers.a;
+                self.registers.f |= CpuFlags::SUBSTRACTION | CpuFlags::HALF_CARRY;
+                4
+            }
+            Instruction::SCF => {
+                self.registers.f &= CpuFlags::ZERO;
+                self.registers.f |= CpuFlags::CARRY;
+                4
+            }
+            Instruction::CCF => {
+                let carry = self.registers.f.contains(CpuFlags::CARRY);
+                self.registers.f &= CpuFlags::ZERO;
+                self.registers.f.set(CpuFlags::CARRY, !carry);
+                4
+            }
+            Instruction::RLCA => {
+                self.rotate_a(RotateKind::Rlc);
+                4
+            }
+            Instruction::RLA => {
+                self.rotate_a(RotateKind::Rl);
+                4
+            }
+            Instruction::RRCA => {
+                self.rotate_a(RotateKind::Rrc);
+                4
+            }
+            Instruction::RRA => {
+                self.rotate_a(RotateKind::Rr);
+                4
+            }
+            Instruction::PREFIX(kind, target) => {
+                self.rotate(kind, target, memory);
+                if target == PrefixTarget::HL {
+                    16
+                } else {
+                    8
+                }
+            }
+            Instruction::BIT(bit, target) => {
+                self.bit(bit, target, memory);
+                if target == PrefixTarget::HL {
+                    12
+                } else {
+                    8
+                }
+            }
+            Instruction::RES(bit, target) => {
+                self.set_bit(bit, false, target, memory);
+                if target == PrefixTarget::HL {
+                    16
+                } else {
+                    8
+                }
+            }
+            Instruction::SET(bit, target) => {
+                self.set_bit(bit, true, target, memory);
+                if target == PrefixTarget::HL {
+                    16
+                } else {
+                    8
+                }
+            }
+            Instruction::JP(condition, address) => {
+                if self.condition(condition) {
+                    self.registers.pc = address;
+                    16
+                } else {
+                    12
+                }
+            }
+            Instruction::JPHL => {
+                self.registers.pc = self.registers.hl();
+                4
+            }
+            Instruction::JR(condition, offset) => {
+                if self.condition(condition) {
+                    self.registers.pc = self.registers.pc.wrapping_add_signed(i16::from(offset));
+                    12
+                } else {
+                    8
+                }
+            }
+            Instruction::CALL(condition, address) => {
+                if self.condition(condition) {
+                    self.call(address, memory);
+                    24
+                } else {
+                    12
+                }
+            }
+            Instruction::RET(condition) => {
+                if self.condition(condition) {
+                    self.registers.pc = self.pop_u16(memory);
+                    if condition == JumpCondition::Always {
+                        16
+                    } else {
+                        20
+                    }
+                } else {
+                    8
+                }
+            }
+            Instruction::RETI => {
+                self.registers.pc = self.pop_u16(memory);
+                self.ime = true;
+                16
+            }
+            Instruction::RST(vector) => {
+                self.call(u16::from(vector), memory);
+                16
+            }
+            Instruction::NOP => 4,
+            Instruction::HALT => {
+                let pending = memory.peek(INTERUPT_ENABLE_REGISTER_INDEX)
+                    & memory.peek(INTERUPT_FLAG_REGISTER_INDEX)
+                    & 0x1F;
+                if !self.ime && pending != 0 {
+                    self.halt_bug = true;
+                } else {
+                    self.state = CpuState::Halted;
+                }
+                4
+            }
+            Instruction::STOP | Instruction::ILLEGAL(_) => {
+                self.state = CpuState::Stopped;
+                4
+            }
+            Instruction::DI => {
+                self.ime = false;
+                self.ime_scheduled = false;
+                4
+            }
+            Instruction::EI => {
+                self.ime_scheduled = true;
+                4
             }
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Instruction {
     LDN(LdnTarget, u8),
     LDRR(LdrrTarget, LdrrTarget),
     LDA(LdaTarget),
     LDFA(LdfaTarget),
+    LDHA(HighTarget),
+    LDHFA(HighTarget),
+    LDI(LdiTarget),
+    LDD(LdiTarget),
+    LD16(Ld16Target, u16),
+    LDSPHL,
+    LDHLSP(i8),
+    LDNNSP(u16),
     PUSH(StackTarget),
     POP(StackTarget),
     ADD(AddTarget),
@@ -855,14 +1377,203 @@ pub enum Instruction {
     SBC(SubTarget),
     CP(CpTarget),
     INC(IncTarget),
+    DEC(IncTarget),
     AND(LogicTarget),
     OR(LogicTarget),
     XOR(LogicTarget),
     ADD16(Add16Target),
     ADDSP(i8),
+    INC16(Inc16Target),
+    DEC16(Inc16Target),
+    DAA,
+    CPL,
+    SCF,
+    CCF,
+    RLCA,
+    RLA,
+    RRCA,
+    RRA,
+    PREFIX(RotateKind, PrefixTarget),
+    BIT(u8, PrefixTarget),
+    RES(u8, PrefixTarget),
+    SET(u8, PrefixTarget),
+    JP(JumpCondition, u16),
+    JPHL,
+    JR(JumpCondition, i8),
+    CALL(JumpCondition, u16),
+    RET(JumpCondition),
+    RETI,
+    RST(u8),
+    NOP,
+    HALT,
+    STOP,
+    DI,
+    EI,
+    ILLEGAL(u8),
+}
+
+impl Instruction {
+    #[allow(clippy::too_many_lines)]
+    pub fn decode(mut fetch: impl FnMut() -> u8) -> Self {
+        let opcode = fetch();
+        match opcode {
+            0x00 => Self::NOP,
+            0x01 | 0x11 | 0x21 | 0x31 => {
+                let value = u16::from_le_bytes([fetch(), fetch()]);
+                Self::LD16(Ld16Target::from_index(opcode >> 4), value)
+            }
+            0x02 => Self::LDFA(LdfaTarget::BC),
+            0x12 => Self::LDFA(LdfaTarget::DE),
+            0x22 => Self::LDI(LdiTarget::HL),
+            0x32 => Self::LDD(LdiTarget::HL),
+            0x0A => Self::LDA(LdaTarget::BC),
+            0x1A => Self::LDA(LdaTarget::DE),
+            0x2A => Self::LDI(LdiTarget::A),
+            0x3A => Self::LDD(LdiTarget::A),
+            0x03 | 0x13 | 0x23 | 0x33 => Self::INC16(Inc16Target::from_index(opcode >> 4)),
+            0x0B | 0x1B | 0x2B | 0x3B => Self::DEC16(Inc16Target::from_index(opcode >> 4)),
+            0x09 | 0x19 | 0x29 | 0x39 => Self::ADD16(Add16Target::from_index(opcode >> 4)),
+            op if op & 0xC7 == 0x04 => Self::INC(IncTarget::from_index(op >> 3)),
+            op if op & 0xC7 == 0x05 => Self::DEC(IncTarget::from_index(op >> 3)),
+            op if op & 0xC7 == 0x06 => Self::LDN(LdnTarget::from_index(op >> 3), fetch()),
+            0x07 => Self::RLCA,
+            0x0F => Self::RRCA,
+            0x17 => Self::RLA,
+            0x1F => Self::RRA,
+            0x08 => Self::LDNNSP(u16::from_le_bytes([fetch(), fetch()])),
+            0x10 => {
+                fetch();
+                Self::STOP
+            }
+            0x18 => Self::JR(JumpCondition::Always, fetch().cast_signed()),
+            0x20 | 0x28 | 0x30 | 0x38 => Self::JR(
+                JumpCondition::from_index(opcode >> 3),
+                fetch().cast_signed(),
+            ),
+            0x27 => Self::DAA,
+            0x2F => Self::CPL,
+            0x37 => Self::SCF,
+            0x3F => Self::CCF,
+            0x76 => Self::HALT,
+            0x40..=0x7F => Self::LDRR(
+                LdrrTarget::from_index(opcode >> 3),
+                LdrrTarget::from_index(opcode),
+            ),
+            0x80..=0x87 => Self::ADD(AddTarget::from_index(opcode)),
+            0x88..=0x8F => Self::ADC(AddTarget::from_index(opcode)),
+            0x90..=0x97 => Self::SUB(SubTarget::from_index(opcode)),
+            0x98..=0x9F => Self::SBC(SubTarget::from_index(opcode)),
+            0xA0..=0xA7 => Self::AND(LogicTarget::from_index(opcode)),
+            0xA8..=0xAF => Self::XOR(LogicTarget::from_index(opcode)),
+            0xB0..=0xB7 => Self::OR(LogicTarget::from_index(opcode)),
+            0xB8..=0xBF => Self::CP(CpTarget::from_index(opcode)),
+            0xC0 | 0xC8 | 0xD0 | 0xD8 => Self::RET(JumpCondition::from_index(opcode >> 3)),
+            0xC9 => Self::RET(JumpCondition::Always),
+            0xD9 => Self::RETI,
+            0xC1 | 0xD1 | 0xE1 | 0xF1 => Self::POP(StackTarget::from_index(opcode >> 4)),
+            0xC5 | 0xD5 | 0xE5 | 0xF5 => Self::PUSH(StackTarget::from_index(opcode >> 4)),
+            0xC2 | 0xCA | 0xD2 | 0xDA => {
+                let address = u16::from_le_bytes([fetch(), fetch()]);
+                Self::JP(JumpCondition::from_index(opcode >> 3), address)
+            }
+            0xC3 => Self::JP(
+                JumpCondition::Always,
+                u16::from_le_bytes([fetch(), fetch()]),
+            ),
+            0xE9 => Self::JPHL,
+            0xC4 | 0xCC | 0xD4 | 0xDC => {
+                let address = u16::from_le_bytes([fetch(), fetch()]);
+                Self::CALL(JumpCondition::from_index(opcode >> 3), address)
+            }
+            0xCD => Self::CALL(
+                JumpCondition::Always,
+                u16::from_le_bytes([fetch(), fetch()]),
+            ),
+            0xC6 => Self::ADD(AddTarget::Value(fetch())),
+            0xCE => Self::ADC(AddTarget::Value(fetch())),
+            0xD6 => Self::SUB(SubTarget::Value(fetch())),
+            0xDE => Self::SBC(SubTarget::Value(fetch())),
+            0xE6 => Self::AND(LogicTarget::Value(fetch())),
+            0xEE => Self::XOR(LogicTarget::Value(fetch())),
+            0xF6 => Self::OR(LogicTarget::Value(fetch())),
+            0xFE => Self::CP(CpTarget::Value(fetch())),
+            op if op & 0xC7 == 0xC7 => Self::RST(op & 0x38),
+            0xCB => Self::decode_prefixed(fetch()),
+            0xE0 => Self::LDHFA(HighTarget::Addr(fetch())),
+            0xF0 => Self::LDHA(HighTarget::Addr(fetch())),
+            0xE2 => Self::LDHFA(HighTarget::C),
+            0xF2 => Self::LDHA(HighTarget::C),
+            0xE8 => Self::ADDSP(fetch().cast_signed()),
+            0xF8 => Self::LDHLSP(fetch().cast_signed()),
+            0xF9 => Self::LDSPHL,
+            0xEA => Self::LDFA(LdfaTarget::Addr(u16::from_le_bytes([fetch(), fetch()]))),
+            0xFA => Self::LDA(LdaTarget::Addr(u16::from_le_bytes([fetch(), fetch()]))),
+            0xF3 => Self::DI,
+            0xFB => Self::EI,
+            op => Self::ILLEGAL(op),
+        }
+    }
+
+    const fn decode_prefixed(opcode: u8) -> Self {
+        let target = PrefixTarget::from_index(opcode);
+        let bit = (opcode >> 3) & 0x07;
+        match opcode >> 6 {
+            0 => Self::PREFIX(RotateKind::from_index(bit), target),
+            1 => Self::BIT(bit, target),
+            2 => Self::RES(bit, target),
+            _ => Self::SET(bit, target),
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+macro_rules! register_index {
+    ($target:ident) => {
+        impl $target {
+            const fn from_index(index: u8) -> Self {
+                match index & 0x07 {
+                    0 => Self::B,
+                    1 => Self::C,
+                    2 => Self::D,
+                    3 => Self::E,
+                    4 => Self::H,
+                    5 => Self::L,
+                    6 => Self::HL,
+                    _ => Self::A,
+                }
+            }
+        }
+    };
+}
+
+macro_rules! register_pair_index {
+    ($target:ident, $last:ident) => {
+        impl $target {
+            const fn from_index(index: u8) -> Self {
+                match index & 0x03 {
+                    0 => Self::BC,
+                    1 => Self::DE,
+                    2 => Self::HL,
+                    _ => Self::$last,
+                }
+            }
+        }
+    };
+}
+
+register_index!(LdnTarget);
+register_index!(LdrrTarget);
+register_index!(AddTarget);
+register_index!(SubTarget);
+register_index!(CpTarget);
+register_index!(IncTarget);
+register_index!(LogicTarget);
+register_index!(PrefixTarget);
+register_pair_index!(Add16Target, SP);
+register_pair_index!(Inc16Target, SP);
+register_pair_index!(Ld16Target, SP);
+register_pair_index!(StackTarget, AF);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Add16Target {
     BC,
     DE,
@@ -870,7 +1581,23 @@ pub enum Add16Target {
     SP,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Inc16Target {
+    BC,
+    DE,
+    HL,
+    SP,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ld16Target {
+    BC,
+    DE,
+    HL,
+    SP,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LdaTarget {
     A,
     B,
@@ -886,7 +1613,7 @@ pub enum LdaTarget {
     Value(u8),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LdfaTarget {
     A,
     B,
@@ -901,7 +1628,19 @@ pub enum LdfaTarget {
     Addr(u16),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HighTarget {
+    C,
+    Addr(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LdiTarget {
+    A,
+    HL,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AddTarget {
     A,
     B,
@@ -914,7 +1653,7 @@ pub enum AddTarget {
     Value(u8),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum IncTarget {
     A,
     B,
@@ -926,7 +1665,7 @@ pub enum IncTarget {
     HL,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CpTarget {
     A,
     B,
@@ -937,9 +1676,10 @@ pub enum CpTarget {
     L,
     HL,
     Addr(u8),
+    Value(u8),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SubTarget {
     A,
     B,
@@ -952,7 +1692,7 @@ pub enum SubTarget {
     Value(u8),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StackTarget {
     AF,
     BC,
@@ -960,7 +1700,7 @@ pub enum StackTarget {
     HL,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LdnTarget {
     A,
     B,
@@ -969,12 +1709,10 @@ pub enum LdnTarget {
     E,
     H,
     L,
-    BC,
-    DE,
     HL,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LdrrTarget {
     A,
     B,
@@ -986,7 +1724,7 @@ pub enum LdrrTarget {
     HL,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LogicTarget {
     A,
     B,
@@ -999,6 +1737,65 @@ pub enum LogicTarget {
     Value(u8),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefixTarget {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    HL,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotateKind {
+    Rlc,
+    Rrc,
+    Rl,
+    Rr,
+    Sla,
+    Sra,
+    Swap,
+    Srl,
+}
+
+impl RotateKind {
+    const fn from_index(index: u8) -> Self {
+        match index & 0x07 {
+            0 => Self::Rlc,
+            1 => Self::Rrc,
+            2 => Self::Rl,
+            3 => Self::Rr,
+            4 => Self::Sla,
+            5 => Self::Sra,
+            6 => Self::Swap,
+            _ => Self::Srl,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JumpCondition {
+    Always,
+    NotZero,
+    Zero,
+    NotCarry,
+    Carry,
+}
+
+impl JumpCondition {
+    const fn from_index(index: u8) -> Self {
+        match index & 0x03 {
+            0 => Self::NotZero,
+            1 => Self::Zero,
+            2 => Self::NotCarry,
+            _ => Self::Carry,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use bitflags::Flags;
@@ -1039,14 +1836,15 @@ mod tests {
         assert!(!cpu.registers.f.contains(CpuFlags::SUBSTRACTION));
         assert!(cpu.registers.f.contains(CpuFlags::ZERO));
         assert!(cpu.registers.f.contains(CpuFlags::CARRY));
-        assert!(!cpu.registers.f.contains(CpuFlags::HALF_CARRY));
+        assert!(cpu.registers.f.contains(CpuFlags::HALF_CARRY));
     }
 
     #[test]
     fn test_add_half_carry() {
         let mut cpu = Cpu::default();
+        cpu.registers.a = 15;
         let mut memory = [0; 8192];
-        cpu.execute(Instruction::ADD(AddTarget::Value(16)), &mut memory);
+        cpu.execute(Instruction::ADD(AddTarget::Value(1)), &mut memory);
         assert_eq!(16, cpu.registers.a);
         assert!(!cpu.registers.f.contains(CpuFlags::SUBSTRACTION));
         assert!(!cpu.registers.f.contains(CpuFlags::ZERO));
@@ -1084,18 +1882,25 @@ mod tests {
         let mut memory = [0; 8192];
         cpu.execute(Instruction::ADC(AddTarget::Value(1)), &mut memory);
         cpu.execute(Instruction::ADC(AddTarget::Value(255)), &mut memory);
-        assert_eq!(1, cpu.registers.a);
+        assert_eq!(0, cpu.registers.a);
         assert!(!cpu.registers.f.contains(CpuFlags::SUBSTRACTION));
         assert!(cpu.registers.f.contains(CpuFlags::ZERO));
         assert!(cpu.registers.f.contains(CpuFlags::CARRY));
-        assert!(!cpu.registers.f.contains(CpuFlags::HALF_CARRY));
+        assert!(cpu.registers.f.contains(CpuFlags::HALF_CARRY));
+
+        cpu.execute(Instruction::ADC(AddTarget::Value(0x0F)), &mut memory);
+        assert_eq!(0x10, cpu.registers.a);
+        assert!(!cpu.registers.f.contains(CpuFlags::ZERO));
+        assert!(!cpu.registers.f.contains(CpuFlags::CARRY));
+        assert!(cpu.registers.f.contains(CpuFlags::HALF_CARRY));
     }
 
     #[test]
     fn test_adc_half_carry() {
         let mut cpu = Cpu::default();
+        cpu.registers.a = 15;
         let mut memory = [0; 8192];
-        cpu.execute(Instruction::ADC(AddTarget::Value(16)), &mut memory);
+        cpu.execute(Instruction::ADC(AddTarget::Value(1)), &mut memory);
         assert_eq!(16, cpu.registers.a);
         assert!(!cpu.registers.f.contains(CpuFlags::SUBSTRACTION));
         assert!(!cpu.registers.f.contains(CpuFlags::ZERO));
@@ -1137,7 +1942,7 @@ mod tests {
         assert!(cpu.registers.f.contains(CpuFlags::SUBSTRACTION));
         assert!(!cpu.registers.f.contains(CpuFlags::ZERO));
         assert!(cpu.registers.f.contains(CpuFlags::CARRY));
-        assert!(!cpu.registers.f.contains(CpuFlags::HALF_CARRY));
+        assert!(cpu.registers.f.contains(CpuFlags::HALF_CARRY));
     }
 
     #[test]
@@ -1185,7 +1990,7 @@ mod tests {
         assert!(cpu.registers.f.contains(CpuFlags::SUBSTRACTION));
         assert!(!cpu.registers.f.contains(CpuFlags::ZERO));
         assert!(cpu.registers.f.contains(CpuFlags::CARRY));
-        assert!(!cpu.registers.f.contains(CpuFlags::HALF_CARRY));
+        assert!(cpu.registers.f.contains(CpuFlags::HALF_CARRY));
     }
 
     #[test]
@@ -1231,10 +2036,22 @@ mod tests {
         cpu.registers.set_bc(1);
         let mut memory = [0; 8192];
         cpu.execute(Instruction::ADD16(Add16Target::BC), &mut memory);
+        assert_eq!(0, cpu.registers.hl());
         assert!(!cpu.registers.f.contains(CpuFlags::SUBSTRACTION));
         assert!(!cpu.registers.f.contains(CpuFlags::ZERO));
         assert!(cpu.registers.f.contains(CpuFlags::CARRY));
-        assert!(!cpu.registers.f.contains(CpuFlags::HALF_CARRY));
+        assert!(cpu.registers.f.contains(CpuFlags::HALF_CARRY));
+    }
+
+    #[test]
+    fn test_add_hl_keeps_zero() {
+        let mut cpu = Cpu::default();
+        cpu.registers.f = CpuFlags::ZERO | CpuFlags::SUBSTRACTION;
+        cpu.registers.set_bc(1);
+        let mut memory = [0; 8192];
+        cpu.execute(Instruction::ADD16(Add16Target::BC), &mut memory);
+        assert!(!cpu.registers.f.contains(CpuFlags::SUBSTRACTION));
+        assert!(cpu.registers.f.contains(CpuFlags::ZERO));
     }
 
     #[test]
@@ -1248,5 +2065,24 @@ mod tests {
         assert!(!cpu.registers.f.contains(CpuFlags::ZERO));
         assert!(!cpu.registers.f.contains(CpuFlags::CARRY));
         assert!(cpu.registers.f.contains(CpuFlags::HALF_CARRY));
+
+        cpu.registers.set_hl(0x10_01);
+        cpu.registers.set_bc(0x0F_FF);
+        cpu.execute(Instruction::ADD16(Add16Target::BC), &mut memory);
+        assert_eq!(0x20_00, cpu.registers.hl());
+        assert!(!cpu.registers.f.contains(CpuFlags::CARRY));
+        assert!(cpu.registers.f.contains(CpuFlags::HALF_CARRY));
+    }
+
+    #[test]
+    fn test_register_accessors() {
+        let mut registers = Registers::default();
+        registers.set_b(0x12);
+        registers.set_c(0x34);
+        registers.set_f(CpuFlags::from_bits_truncate(0xFF));
+        registers.set_pc(0x0150);
+        assert_eq!(0x1234, registers.bc());
+        assert_eq!(0xF0, registers.f().bits());
+        assert_eq!(0x0150, registers.pc());
     }
 }
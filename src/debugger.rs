@@ -0,0 +1,824 @@
+use std::fmt::{self, Write};
+
+use crate::bus::MemoryBus;
+use crate::cpu::{CpuFlags, Instruction, Registers};
+use crate::disassembler::decode_at;
+use crate::gameboy::{GameBoy, CYCLES_PER_FRAME};
+use crate::watchpoint::{WatchAction, WatchHit, WatchKind, Watchpoint};
+
+const HELP: &str = "commands (addresses and values are hex, counts are decimal):
+  step [n]                      execute n instructions (s)
+  next                          step over calls and rst, for at most 3600 frames (n)
+  finish                        run until the current function returns, for at most 3600
+                                frames
+  continue [frames]             run until a breakpoint, or for at most frames, 3600 (a
+                                minute) by default (c)
+  break [bank:]addr [if cond]   add a breakpoint, e.g. `break 02:4000 if a == 10 && hl != c000`
+  delete <id>                   remove a breakpoint
+  breakpoints                   list breakpoints
+  watch [kind] [bank:]addr[-end] [from v] [to v] [log]
+                                stop (or log) on read, write, access or change, e.g.
+                                `watch change ff80 to 0` (the default kind is write)
+  unwatch <id>                  remove a watchpoint
+  watchpoints                   list watchpoints
+  registers                     show registers and flags (r)
+  set <register> <value>        change a register
+  x <addr> [length]             hexdump memory
+  poke <addr> <byte>...         write memory through the bus
+  list [addr] [count]           disassemble around pc or from addr (l)
+  quit                          leave the debugger (q)";
+const LIST_LENGTH: usize = 10;
+const HEXDUMP_LENGTH: usize = 64;
+/// Frames `continue` runs for without a count, and `next` and `finish` at most, so they
+/// always return to the prompt.
+const CONTINUE_FRAMES: usize = 3600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+}
+
+impl Register {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "a" => Self::A,
+            "b" => Self::B,
+            "c" => Self::C,
+            "d" => Self::D,
+            "e" => Self::E,
+            "f" => Self::F,
+            "h" => Self::H,
+            "l" => Self::L,
+            "af" => Self::AF,
+            "bc" => Self::BC,
+            "de" => Self::DE,
+            "hl" => Self::HL,
+            "sp" => Self::SP,
+            "pc" => Self::PC,
+            _ => return None,
+        })
+    }
+
+    const fn is_pair(self) -> bool {
+        matches!(
+            self,
+            Self::AF | Self::BC | Self::DE | Self::HL | Self::SP | Self::PC
+        )
+    }
+
+    #[must_use]
+    pub fn get(self, registers: &Registers) -> u16 {
+        match self {
+            Self::A => registers.a().into(),
+            Self::B => registers.b().into(),
+            Self::C => registers.c().into(),
+            Self::D => registers.d().into(),
+            Self::E => registers.e().into(),
+            Self::F => registers.f().bits().into(),
+            Self::H => registers.h().into(),
+            Self::L => registers.l().into(),
+            Self::AF => registers.af(),
+            Self::BC => registers.bc(),
+            Self::DE => registers.de(),
+            Self::HL => registers.hl(),
+            Self::SP => registers.sp(),
+            Self::PC => registers.pc(),
+        }
+    }
+
+    /// Stores `value`, keeping the low byte for 8-bit registers.
+    pub const fn set(self, registers: &mut Registers, value: u16) {
+        let [_, low] = value.to_be_bytes();
+        match self {
+            Self::A => registers.set_a(low),
+            Self::B => registers.set_b(low),
+            Self::C => registers.set_c(low),
+            Self::D => registers.set_d(low),
+            Self::E => registers.set_e(low),
+            Self::F => registers.set_f(CpuFlags::from_bits_truncate(low)),
+            Self::H => registers.set_h(low),
+            Self::L => registers.set_l(low),
+            Self::AF => registers.set_af(value),
+            Self::BC => registers.set_bc(value),
+            Self::DE => registers.set_de(value),
+            Self::HL => registers.set_hl(value),
+            Self::SP => registers.set_sp(value),
+            Self::PC => registers.set_pc(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+const COMPARISONS: [(&str, Comparison); 6] = [
+    ("==", Comparison::Equal),
+    ("!=", Comparison::NotEqual),
+    ("<=", Comparison::LessOrEqual),
+    (">=", Comparison::GreaterOrEqual),
+    ("<", Comparison::Less),
+    (">", Comparison::Greater),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    #[must_use]
+    pub fn holds(&self, registers: &Registers) -> bool {
+        let current = self.register.get(registers);
+        match self.comparison {
+            Comparison::Equal => current == self.value,
+            Comparison::NotEqual => current != self.value,
+            Comparison::Less => current < self.value,
+            Comparison::LessOrEqual => current <= self.value,
+            Comparison::Greater => current > self.value,
+            Comparison::GreaterOrEqual => current >= self.value,
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let (operator, comparison) = COMPARISONS
+            .into_iter()
+            .find(|(operator, _)| text.contains(operator))
+            .ok_or_else(|| format!("missing comparison in `{text}`"))?;
+        let (register, value) = text.split_once(operator).unwrap_or_default();
+        Ok(Self {
+            register: Register::parse(register.trim())
+                .ok_or_else(|| format!("unknown register: {}", register.trim()))?,
+            comparison,
+            value: parse_value(value.trim())?,
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = COMPARISONS
+            .into_iter()
+            .find_map(|(operator, comparison)| (comparison == self.comparison).then_some(operator))
+            .unwrap_or_default();
+        write!(f, "{:?} {operator} ${:x}", self.register, self.value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    /// Only stop when this bank is mapped at `address`, see [`crate::Bus::bank_at`].
+    pub bank: Option<usize>,
+    pub address: u16,
+    pub conditions: Vec<Condition>,
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(bank) = self.bank {
+            write!(f, "{bank:02X}:")?;
+        }
+        write!(f, "{:04X}", self.address)?;
+        for (index, condition) in self.conditions.iter().enumerate() {
+            let keyword = if index == 0 { "if" } else { "&&" };
+            write!(f, " {keyword} {condition}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Stepped,
+    Breakpoint(usize),
+    /// A watchpoint with [`WatchAction::Stop`] triggered during the instruction at `pc`.
+    Watchpoint {
+        hit: WatchHit,
+        pc: u16,
+    },
+    Returned,
+    Limit,
+}
+
+/// Accepts `$`/`0x`-prefixed or bare hexadecimal.
+fn parse_value(text: &str) -> Result<u16, String> {
+    let digits = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid value: {text}"))
+}
+
+fn parse_count(text: Option<&str>, default: usize) -> Result<usize, String> {
+    text.map_or(Ok(default), |text| {
+        text.parse().map_err(|_| format!("invalid count: {text}"))
+    })
+}
+
+fn frame_cycles(frames: usize) -> u64 {
+    u64::try_from(frames)
+        .unwrap_or(u64::MAX)
+        .saturating_mul(u64::from(CYCLES_PER_FRAME))
+}
+
+#[derive(Debug, Clone)]
+pub struct Debugger {
+    gameboy: GameBoy,
+    breakpoints: Vec<(usize, Breakpoint)>,
+    next_breakpoint: usize,
+    /// Hits of [`WatchAction::Log`] watchpoints not yet shown by [`Debugger::execute`].
+    watch_log: String,
+}
+
+impl Debugger {
+    #[must_use]
+    pub const fn new(gameboy: GameBoy) -> Self {
+        Self {
+            gameboy,
+            breakpoints: Vec::new(),
+            next_breakpoint: 1,
+            watch_log: String::new(),
+        }
+    }
+
+    #[must_use]
+    pub const fn gameboy(&self) -> &GameBoy {
+        &self.gameboy
+    }
+
+    pub const fn gameboy_mut(&mut self) -> &mut GameBoy {
+        &mut self.gameboy
+    }
+
+    /// Adds a breakpoint and returns its id.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.next_breakpoint;
+        self.next_breakpoint += 1;
+        self.breakpoints.push((id, breakpoint));
+        id
+    }
+
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|&(existing, _)| existing != id);
+        self.breakpoints.len() != count
+    }
+
+    #[must_use]
+    pub fn breakpoints(&self) -> &[(usize, Breakpoint)] {
+        &self.breakpoints
+    }
+
+    const fn pc(&self) -> u16 {
+        self.gameboy.cpu().registers().pc()
+    }
+
+    fn hit_breakpoint(&self) -> Option<usize> {
+        let pc = self.pc();
+        let registers = self.gameboy.cpu().registers();
+        self.breakpoints
+            .iter()
+            .find(|(_, breakpoint)| {
+                breakpoint.address == pc
+                    && breakpoint
+                        .bank
+                        .is_none_or(|bank| self.gameboy.bus().bank_at(pc) == Some(bank))
+                    && breakpoint
+                        .conditions
+                        .iter()
+                        .all(|condition| condition.holds(registers))
+            })
+            .map(|&(id, _)| id)
+    }
+
+    /// Executes one instruction, logging or stopping on the watchpoints it triggered.
+    fn execute_instruction(&mut self) -> Option<StopReason> {
+        let pc = self.pc();
+        self.gameboy.step();
+        let mut stop = None;
+        for hit in self.gameboy.bus_mut().take_watch_hits() {
+            match hit.action {
+                WatchAction::Log => {
+                    let _ = writeln!(self.watch_log, "{hit} at {pc:04X}");
+                }
+                WatchAction::Stop => {
+                    stop = stop.or(Some(StopReason::Watchpoint { hit, pc }));
+                }
+            }
+        }
+        stop
+    }
+
+    /// Executes instructions until `done` returns true, a breakpoint is reached, or `limit`
+    /// cycles have passed. At least one instruction always runs.
+    fn run_until(
+        &mut self,
+        limit: Option<u64>,
+        mut done: impl FnMut(&GameBoy, Instruction) -> bool,
+    ) -> StopReason {
+        let start = self.gameboy.cycles();
+        loop {
+            let (instruction, _) = decode_at(self.gameboy.bus(), self.pc());
+            if let Some(reason) = self.execute_instruction() {
+                return reason;
+            }
+            if done(&self.gameboy, instruction) {
+                return StopReason::Returned;
+            }
+            if let Some(id) = self.hit_breakpoint() {
+                return StopReason::Breakpoint(id);
+            }
+            if limit.is_some_and(|limit| self.gameboy.cycles() - start >= limit) {
+                return StopReason::Limit;
+            }
+        }
+    }
+
+    pub fn step(&mut self) -> StopReason {
+        self.execute_instruction().unwrap_or_else(|| {
+            self.hit_breakpoint()
+                .map_or(StopReason::Stepped, StopReason::Breakpoint)
+        })
+    }
+
+    /// Steps, running called subroutines and `rst` handlers to completion.
+    pub fn step_over(&mut self) -> StopReason {
+        let pc = self.pc();
+        let (instruction, length) = decode_at(self.gameboy.bus(), pc);
+        if !matches!(instruction, Instruction::CALL(..) | Instruction::RST(_)) {
+            return self.step();
+        }
+        let return_address = pc.wrapping_add(length);
+        let stack = self.gameboy.cpu().registers().sp();
+        match self.run_until(Some(frame_cycles(CONTINUE_FRAMES)), |gameboy, _| {
+            let registers = gameboy.cpu().registers();
+            registers.pc() == return_address && registers.sp() >= stack
+        }) {
+            StopReason::Returned => StopReason::Stepped,
+            reason => reason,
+        }
+    }
+
+    /// Runs until the current subroutine returns to its caller.
+    pub fn finish(&mut self) -> StopReason {
+        let stack = self.gameboy.cpu().registers().sp();
+        self.run_until(
+            Some(frame_cycles(CONTINUE_FRAMES)),
+            |gameboy, instruction| {
+                matches!(instruction, Instruction::RET(_) | Instruction::RETI)
+                    && gameboy.cpu().registers().sp() > stack
+            },
+        )
+    }
+
+    pub fn resume(&mut self, limit: Option<u64>) -> StopReason {
+        self.run_until(limit, |_, _| false)
+    }
+
+    fn location(&self, address: u16) -> String {
+        self.gameboy.bus().bank_at(address).map_or_else(
+            || format!("--:{address:04X}"),
+            |bank| format!("{bank:02X}:{address:04X}"),
+        )
+    }
+
+    fn disassembly_line(&self, address: u16) -> (String, u16) {
+        let bus = self.gameboy.bus();
+        let (instruction, length) = decode_at(bus, address);
+        let bytes: Vec<String> = (0..length)
+            .map(|offset| format!("{:02X}", bus.peek(address.wrapping_add(offset))))
+            .collect();
+        let marker = if address == self.pc() { "=>" } else { "  " };
+        let line = format!(
+            "{marker} {}  {:<9} {instruction}",
+            self.location(address),
+            bytes.join(" ")
+        );
+        (line, length)
+    }
+
+    /// Finds an address a few instructions before `address` that decodes in step with it.
+    fn synchronised_start(&self, address: u16) -> u16 {
+        (1..=8)
+            .rev()
+            .map(|back| address.wrapping_sub(back))
+            .find(|&start| {
+                let mut current = start;
+                while current < address {
+                    current = current.wrapping_add(decode_at(self.gameboy.bus(), current).1);
+                }
+                current == address && start < address
+            })
+            .unwrap_or(address)
+    }
+
+    /// The disassembly of the instruction about to run, marked with `=>`.
+    #[must_use]
+    pub fn current_instruction(&self) -> String {
+        self.disassembly_line(self.pc()).0
+    }
+
+    fn list(&self, start: u16, count: usize) -> String {
+        let mut address = start;
+        let mut text = String::new();
+        for _ in 0..count {
+            let (line, length) = self.disassembly_line(address);
+            let _ = writeln!(text, "{line}");
+            address = address.wrapping_add(length);
+        }
+        text
+    }
+
+    fn hexdump(&self, start: u16, length: u16) -> String {
+        let mut text = String::new();
+        for row in (0..length).step_by(16) {
+            let address = start.wrapping_add(row);
+            let bytes: Vec<u8> = (0..16.min(length - row))
+                .map(|offset| self.gameboy.bus().peek(address.wrapping_add(offset)))
+                .collect();
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+            let ascii: String = bytes
+                .iter()
+                .map(|&byte| {
+                    if byte.is_ascii_graphic() {
+                        char::from(byte)
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            let _ = writeln!(text, "{address:04X}: {:<47}  {ascii}", hex.join(" "));
+        }
+        text
+    }
+
+    fn registers(&self) -> String {
+        let cpu = self.gameboy.cpu();
+        let flags = cpu.registers().f();
+        let flag = |flag, name| if flags.contains(flag) { name } else { "-" };
+        format!(
+            "{}\nflags: {}{}{}{}  ime: {}  state: {:?}  cycles: {}\n",
+            cpu.registers(),
+            flag(CpuFlags::ZERO, "Z"),
+            flag(CpuFlags::SUBSTRACTION, "N"),
+            flag(CpuFlags::HALF_CARRY, "H"),
+            flag(CpuFlags::CARRY, "C"),
+            cpu.ime(),
+            cpu.state(),
+            self.gameboy.cycles()
+        )
+    }
+
+    fn report(&mut self, reason: StopReason) -> String {
+        let mut text = std::mem::take(&mut self.watch_log);
+        match reason {
+            StopReason::Breakpoint(id) => {
+                let _ = writeln!(text, "breakpoint {id}");
+            }
+            StopReason::Watchpoint { hit, pc } => {
+                let _ = writeln!(text, "{hit} at {pc:04X}");
+            }
+            StopReason::Limit => text.push_str("stopped after the frame limit\n"),
+            StopReason::Stepped | StopReason::Returned => {}
+        }
+        text.push_str(&self.disassembly_line(self.pc()).0);
+        text.push('\n');
+        text
+    }
+
+    fn parse_breakpoint(arguments: &str) -> Result<Breakpoint, String> {
+        let (location, conditions) = arguments
+            .split_once(" if ")
+            .map_or((arguments, None), |(location, conditions)| {
+                (location, Some(conditions))
+            });
+        let (bank, address) = match location.trim().split_once(':') {
+            Some((bank, address)) => (Some(usize::from(parse_value(bank)?)), parse_value(address)?),
+            None => (None, parse_value(location.trim())?),
+        };
+        let conditions = conditions
+            .map(|conditions| conditions.split("&&").map(Condition::parse).collect())
+            .transpose()?
+            .unwrap_or_default();
+        Ok(Breakpoint {
+            bank,
+            address,
+            conditions,
+        })
+    }
+
+    fn parse_watchpoint(arguments: &str) -> Result<Watchpoint, String> {
+        let mut words = arguments.split_whitespace().peekable();
+        let kind = match words.peek().copied() {
+            Some("read") => Some(WatchKind::Read),
+            Some("write") => Some(WatchKind::Write),
+            Some("access") => Some(WatchKind::Access),
+            Some("change") => Some(WatchKind::Change {
+                from: None,
+                to: None,
+            }),
+            _ => None,
+        };
+        if kind.is_some() {
+            words.next();
+        }
+        let kind = kind.unwrap_or(WatchKind::Write);
+        let location = words
+            .next()
+            .ok_or("usage: watch [kind] [bank:]addr[-end] [from v] [to v] [log]")?;
+        let (bank, range) = match location.split_once(':') {
+            Some((bank, range)) => (Some(usize::from(parse_value(bank)?)), range),
+            None => (None, location),
+        };
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse_value(start)?, parse_value(end)?),
+            None => (parse_value(range)?, parse_value(range)?),
+        };
+        if end < start {
+            return Err(format!("empty range: {range}"));
+        }
+        let mut watchpoint = Watchpoint::new(kind, start..=end);
+        watchpoint.bank = bank;
+        while let Some(word) = words.next() {
+            let byte = |text: Option<&str>| {
+                let text = text.ok_or_else(|| format!("{word} expects a value"))?;
+                u8::try_from(parse_value(text)?).map_err(|_| format!("{text} is not a byte"))
+            };
+            match (word, &mut watchpoint.kind) {
+                ("from", WatchKind::Change { from, .. }) => *from = Some(byte(words.next())?),
+                ("to", WatchKind::Change { to, .. }) => *to = Some(byte(words.next())?),
+                ("from" | "to", _) => return Err(format!("{word} needs a change watchpoint")),
+                ("log", _) => watchpoint.action = WatchAction::Log,
+                _ => return Err(format!("unexpected argument: {word}")),
+            }
+        }
+        Ok(watchpoint)
+    }
+
+    fn execute_watch(&mut self, command: &str, arguments: &str) -> Result<String, String> {
+        let bus = self.gameboy.bus_mut();
+        match command {
+            "watch" => {
+                let watchpoint = Self::parse_watchpoint(arguments)?;
+                let text = format!("at {watchpoint}\n");
+                let id = bus.add_watchpoint(watchpoint);
+                Ok(format!("watchpoint {id} {text}"))
+            }
+            "unwatch" => {
+                let id = parse_count(arguments.split_whitespace().next(), 0)?;
+                if bus.remove_watchpoint(id) {
+                    Ok(String::new())
+                } else {
+                    Err(format!("no watchpoint {id}"))
+                }
+            }
+            _ => Ok(bus
+                .watchpoints()
+                .iter()
+                .fold(String::new(), |mut text, (id, watchpoint)| {
+                    let _ = writeln!(text, "{id}: {watchpoint}");
+                    text
+                })),
+        }
+    }
+
+    /// Runs one line of debugger input and returns the text to show.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message for unknown commands and malformed arguments.
+    pub fn execute(&mut self, line: &str) -> Result<String, String> {
+        let line = line.trim();
+        let (command, arguments) = line.split_once(' ').unwrap_or((line, ""));
+        let mut words = arguments.split_whitespace();
+        match command {
+            "step" | "s" => {
+                let count = parse_count(words.next(), 1)?;
+                let mut reason = StopReason::Stepped;
+                for _ in 0..count {
+                    reason = self.step();
+                    if reason != StopReason::Stepped {
+                        break;
+                    }
+                }
+                Ok(self.report(reason))
+            }
+            "next" | "n" => {
+                let reason = self.step_over();
+                Ok(self.report(reason))
+            }
+            "finish" => {
+                let reason = self.finish();
+                Ok(self.report(reason))
+            }
+            "continue" | "c" => {
+                let frames = parse_count(words.next(), CONTINUE_FRAMES)?;
+                let reason = self.resume(Some(frame_cycles(frames)));
+                Ok(self.report(reason))
+            }
+            "break" | "b" => {
+                let breakpoint = Self::parse_breakpoint(arguments)?;
+                let text = format!("breakpoint {} at {breakpoint}\n", self.next_breakpoint);
+                self.add_breakpoint(breakpoint);
+                Ok(text)
+            }
+            "delete" | "d" => {
+                let id = parse_count(words.next(), 0)?;
+                if self.remove_breakpoint(id) {
+                    Ok(String::new())
+                } else {
+                    Err(format!("no breakpoint {id}"))
+                }
+            }
+            "breakpoints" => {
+                Ok(self
+                    .breakpoints
+                    .iter()
+                    .fold(String::new(), |mut text, (id, breakpoint)| {
+                        let _ = writeln!(text, "{id}: {breakpoint}");
+                        text
+                    }))
+            }
+            "watch" | "unwatch" | "watchpoints" => self.execute_watch(command, arguments),
+            "registers" | "r" => Ok(self.registers()),
+            "set" => {
+                let (Some(register), Some(value)) = (words.next(), words.next()) else {
+                    return Err("usage: set <register> <value>".to_string());
+                };
+                let register = Register::parse(register)
+                    .ok_or_else(|| format!("unknown register: {register}"))?;
+                let value = parse_value(value)?;
+                if !register.is_pair() && value > 0xFF {
+                    return Err(format!("{value:#X} does not fit in {register:?}"));
+                }
+                register.set(self.gameboy.cpu_mut().registers_mut(), value);
+                Ok(self.registers())
+            }
+            "x" => {
+                let address = parse_value(words.next().ok_or("usage: x <addr> [length]")?)?;
+                let length = parse_count(words.next(), HEXDUMP_LENGTH)?;
+                let length =
+                    u16::try_from(length).map_err(|_| format!("invalid count: {length}"))?;
+                Ok(self.hexdump(address, length))
+            }
+            "poke" => {
+                let address = parse_value(words.next().ok_or("usage: poke <addr> <byte>...")?)?;
+                for (offset, byte) in (0..).zip(words) {
+                    let byte = u8::try_from(parse_value(byte)?)
+                        .map_err(|_| format!("{byte} is not a byte"))?;
+                    self.gameboy
+                        .bus_mut()
+                        .write(address.wrapping_add(offset), byte);
+                }
+                Ok(String::new())
+            }
+            "list" | "l" => {
+                let start = match words.next() {
+                    Some(address) => parse_value(address)?,
+                    None => self.synchronised_start(self.pc()),
+                };
+                Ok(self.list(start, parse_count(words.next(), LIST_LENGTH)?))
+            }
+            "help" | "h" => Ok(format!("{HELP}\n")),
+            _ => Err(format!("unknown command: {command}, try `help`")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble_rom;
+
+    fn debugger(source: &str) -> Debugger {
+        let rom = assemble_rom(&format!("section \"main\", rom0[$150]\n{source}")).unwrap();
+        Debugger::new(GameBoy::from_rom(rom).unwrap())
+    }
+
+    const PROGRAM: &str = "
+    main:
+        ld a, 1
+        call add_two
+        ld b, a
+    .loop:
+        inc c
+        jr .loop
+    add_two:
+        inc a
+        inc a
+        ret
+    ";
+
+    #[test]
+    fn test_stepping() {
+        let mut debugger = debugger(PROGRAM);
+        debugger.execute("step 2").unwrap();
+        assert_eq!(0x0150, debugger.pc());
+        debugger.execute("s").unwrap();
+        assert!(debugger.execute("next").unwrap().contains("ld b, a"));
+        assert_eq!(3, debugger.gameboy().cpu().registers().a());
+
+        let mut debugger = self::debugger(PROGRAM);
+        debugger.execute("step 4").unwrap();
+        assert_eq!(0x0159, debugger.pc());
+        debugger.execute("finish").unwrap();
+        assert_eq!(0x0155, debugger.pc());
+        assert_eq!(u64::MAX, frame_cycles(usize::MAX));
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut debugger = debugger(PROGRAM);
+        assert!(debugger.execute("break 00:157 if c == 3").is_ok());
+        assert!(debugger.execute("break 01:157").is_ok());
+        assert_eq!(
+            "breakpoint 1\n",
+            &debugger.execute("continue 1").unwrap()[..13]
+        );
+        assert_eq!(3, debugger.gameboy().cpu().registers().c());
+        assert!(debugger.execute("delete 1").is_ok());
+        assert!(debugger.execute("delete 1").is_err());
+        assert!(debugger.execute("c 1").unwrap().starts_with("stopped"));
+        assert_eq!("2: 01:0157\n", debugger.execute("breakpoints").unwrap());
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut debugger = debugger(
+            "
+            ld hl, $c000
+        .loop:
+            inc a
+            ld [hl], a
+            ldh [$80], a
+            jr .loop
+            ",
+        );
+        assert_eq!(
+            "watchpoint 1 at write C000\n",
+            debugger.execute("watch c000").unwrap()
+        );
+        assert!(debugger
+            .execute("c 1")
+            .unwrap()
+            .starts_with("watchpoint 1: write C000 00 -> 02 at 0154\n"));
+        debugger.execute("unwatch 1").unwrap();
+        debugger.execute("watch change ff80 to 5").unwrap();
+        assert!(debugger
+            .execute("c 1")
+            .unwrap()
+            .starts_with("watchpoint 2: write FF80 04 -> 05 at 0155\n"));
+
+        debugger.execute("watch read 00:0153 log").unwrap();
+        debugger.execute("watch access 01:0153").unwrap();
+        let output = debugger.execute("step 4").unwrap();
+        assert!(output.starts_with("watchpoint 3: read 00:0153 = 3C at 0153\n"));
+        assert_eq!(
+            "2: change FF80 to 05\n3: read 00:0153 log\n4: access 01:0153\n",
+            debugger.execute("watchpoints").unwrap()
+        );
+        assert!(debugger.execute("watch write c000 from 1").is_err());
+        assert!(debugger.execute("watch d000-c000").is_err());
+    }
+
+    #[test]
+    fn test_poking_and_inspection() {
+        let mut debugger = debugger(PROGRAM);
+        debugger.execute("set hl c000").unwrap();
+        debugger.execute("set a 42").unwrap();
+        assert!(debugger.execute("set a 100").is_err());
+        debugger.execute("poke c000 de ad").unwrap();
+        assert_eq!(0x42, debugger.gameboy().cpu().registers().a());
+        assert!(debugger
+            .execute("x c000 2")
+            .unwrap()
+            .starts_with("C000: DE AD"));
+        assert_eq!(1, debugger.execute("x c000 16").unwrap().lines().count());
+        assert!(debugger.execute("r").unwrap().contains("H:C0 L:00"));
+        debugger.execute("step 2").unwrap();
+        let listing = debugger.execute("list").unwrap();
+        assert!(listing.contains("=> 00:0150  3E 01     ld a, $01"));
+        assert!(debugger.execute("frobnicate").is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use std::fmt;
+
+use crate::bus::MemoryBus;
+use crate::cpu::{
+    Add16Target, AddTarget, CpTarget, HighTarget, Inc16Target, IncTarget, Instruction,
+    JumpCondition, Ld16Target, LdaTarget, LdfaTarget, LdiTarget, LdnTarget, LdrrTarget,
+    LogicTarget, PrefixTarget, RotateKind, StackTarget, SubTarget,
+};
+
+macro_rules! register_operand {
+    ($target:ident $(, $pattern:pat => $text:literal)*) => {
+        impl fmt::Display for $target {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match *self {
+                    Self::A => f.write_str("a"),
+                    Self::B => f.write_str("b"),
+                    Self::C => f.write_str("c"),
+                    Self::D => f.write_str("d"),
+                    Self::E => f.write_str("e"),
+                    Self::H => f.write_str("h"),
+                    Self::L => f.write_str("l"),
+                    Self::HL => f.write_str("[hl]"),
+                    $($pattern => write!(f, $text),)*
+                }
+            }
+        }
+    };
+}
+
+macro_rules! register_pair_operand {
+    ($target:ident, $last:ident, $name:literal) => {
+        impl fmt::Display for $target {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(match self {
+                    Self::BC => "bc",
+                    Self::DE => "de",
+                    Self::HL => "hl",
+                    Self::$last => $name,
+                })
+            }
+        }
+    };
+}
+
+register_operand!(LdnTarget);
+register_operand!(LdrrTarget);
+register_operand!(IncTarget);
+register_operand!(PrefixTarget);
+register_operand!(AddTarget, Self::Value(value) => "${value:02x}");
+register_operand!(SubTarget, Self::Value(value) => "${value:02x}");
+register_operand!(LogicTarget, Self::Value(value) => "${value:02x}");
+register_operand!(
+    CpTarget,
+    Self::Addr(address) => "[${address:04x}]",
+    Self::Value(value) => "${value:02x}"
+);
+register_operand!(
+    LdaTarget,
+    Self::BC => "[bc]",
+    Self::DE => "[de]",
+    Self::Addr(address) => "[${address:04x}]",
+    Self::Value(value) => "${value:02x}"
+);
+register_operand!(
+    LdfaTarget,
+    Self::BC => "[bc]",
+    Self::DE => "[de]",
+    Self::Addr(address) => "[${address:04x}]"
+);
+register_pair_operand!(Add16Target, SP, "sp");
+register_pair_operand!(Inc16Target, SP, "sp");
+register_pair_operand!(Ld16Target, SP, "sp");
+register_pair_operand!(StackTarget, AF, "af");
+
+impl fmt::Display for HighTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::C => f.write_str("[c]"),
+            Self::Addr(offset) => write!(f, "[$ff{offset:02x}]"),
+        }
+    }
+}
+
+impl fmt::Display for RotateKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Rlc => "rlc",
+            Self::Rrc => "rrc",
+            Self::Rl => "rl",
+            Self::Rr => "rr",
+            Self::Sla => "sla",
+            Self::Sra => "sra",
+            Self::Swap => "swap",
+            Self::Srl => "srl",
+        })
+    }
+}
+
+/// The condition followed by its separator, empty for unconditional jumps.
+pub(crate) const fn condition(condition: JumpCondition) -> &'static str {
+    match condition {
+        JumpCondition::Always => "",
+        JumpCondition::NotZero => "nz, ",
+        JumpCondition::Zero => "z, ",
+        JumpCondition::NotCarry => "nc, ",
+        JumpCondition::Carry => "c, ",
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::LDN(target, value) => write!(f, "ld {target}, ${value:02x}"),
+            Self::LDRR(target, source) => write!(f, "ld {target}, {source}"),
+            Self::LDA(source) => write!(f, "ld a, {source}"),
+            Self::LDFA(target) => write!(f, "ld {target}, a"),
+            Self::LDHA(source) => write!(f, "ldh a, {source}"),
+            Self::LDHFA(target) => write!(f, "ldh {target}, a"),
+            Self::LDI(LdiTarget::A) => f.write_str("ld a, [hl+]"),
+            Self::LDI(LdiTarget::HL) => f.write_str("ld [hl+], a"),
+            Self::LDD(LdiTarget::A) => f.write_str("ld a, [hl-]"),
+            Self::LDD(LdiTarget::HL) => f.write_str("ld [hl-], a"),
+            Self::LD16(target, value) => write!(f, "ld {target}, ${value:04x}"),
+            Self::LDSPHL => f.write_str("ld sp, hl"),
+            Self::LDHLSP(offset) => write!(f, "ld hl, sp{offset:+}"),
+            Self::LDNNSP(address) => write!(f, "ld [${address:04x}], sp"),
+            Self::PUSH(target) => write!(f, "push {target}"),
+            Self::POP(target) => write!(f, "pop {target}"),
+            Self::ADD(source) => write!(f, "add a, {source}"),
+            Self::ADC(source) => write!(f, "adc a, {source}"),
+            Self::SUB(source) => write!(f, "sub a, {source}"),
+            Self::SBC(source) => write!(f, "sbc a, {source}"),
+            Self::CP(source) => write!(f, "cp a, {source}"),
+            Self::INC(target) => write!(f, "inc {target}"),
+            Self::DEC(target) => write!(f, "dec {target}"),
+            Self::AND(source) => write!(f, "and a, {source}"),
+            Self::OR(source) => write!(f, "or a, {source}"),
+            Self::XOR(source) => write!(f, "xor a, {source}"),
+            Self::ADD16(source) => write!(f, "add hl, {source}"),
+            Self::ADDSP(offset) => write!(f, "add sp, {offset}"),
+            Self::INC16(target) => write!(f, "inc {target}"),
+            Self::DEC16(target) => write!(f, "dec {target}"),
+            Self::DAA => f.write_str("daa"),
+            Self::CPL => f.write_str("cpl"),
+            Self::SCF => f.write_str("scf"),
+            Self::CCF => f.write_str("ccf"),
+            Self::RLCA => f.write_str("rlca"),
+            Self::RLA => f.write_str("rla"),
+            Self::RRCA => f.write_str("rrca"),
+            Self::RRA => f.write_str("rra"),
+            Self::PREFIX(kind, target) => write!(f, "{kind} {target}"),
+            Self::BIT(bit, target) => write!(f, "bit {bit}, {target}"),
+            Self::RES(bit, target) => write!(f, "res {bit}, {target}"),
+            Self::SET(bit, target) => write!(f, "set {bit}, {target}"),
+            Self::JP(jump, address) => write!(f, "jp {}${address:04x}", condition(jump)),
+            Self::JPHL => f.write_str("jp hl"),
+            Self::JR(jump, offset) => {
+                // `$` is the address of the jr itself, which is two bytes before the base
+                // the offset is applied to.
+                let relative = i16::from(offset) + 2;
+                write!(f, "jr {}$", condition(jump))?;
+                if relative != 0 {
+                    write!(f, "{relative:+}")?;
+                }
+                Ok(())
+            }
+            Self::CALL(jump, address) => write!(f, "call {}${address:04x}", condition(jump)),
+            Self::RET(JumpCondition::Always) => f.write_str("ret"),
+            Self::RET(jump) => write!(f, "ret {}", condition(jump).trim_end_matches(", ")),
+            Self::RETI => f.write_str("reti"),
+            Self::RST(vector) => write!(f, "rst ${vector:02x}"),
+            Self::NOP => f.write_str("nop"),
+            Self::HALT => f.write_str("halt"),
+            Self::STOP => f.write_str("stop"),
+            Self::DI => f.write_str("di"),
+            Self::EI => f.write_str("ei"),
+            Self::ILLEGAL(opcode) => write!(f, "db ${opcode:02x}"),
+        }
+    }
+}
+
+/// Decodes the instruction at `address` without side effects, returning it with its length
+/// in bytes.
+#[must_use]
+pub fn decode_at<M: MemoryBus + ?Sized>(memory: &M, address: u16) -> (Instruction, u16) {
+    let mut length = 0u16;
+    let instruction = Instruction::decode(|| {
+        let byte = memory.peek(address.wrapping_add(length));
+        length += 1;
+        byte
+    });
+    (instruction, length)
+}
+
+/// Disassembles the instruction at `address` into RGBDS syntax, returning the text and the
+/// length in bytes.
+#[must_use]
+pub fn disassemble<M: MemoryBus + ?Sized>(memory: &M, address: u16) -> (String, u16) {
+    let (instruction, length) = decode_at(memory, address);
+    (instruction.to_string(), length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(bytes: &[u8]) -> (String, u16) {
+        let mut memory = [0; 4];
+        memory[..bytes.len()].copy_from_slice(bytes);
+        disassemble(&memory, 0)
+    }
+
+    #[test]
+    fn test_disassemble() {
+        assert_eq!(("ld a, [hl+]".to_string(), 1), text(&[0x2A]));
+        assert_eq!(("jr nz, $-5".to_string(), 2), text(&[0x20, 0xF9]));
+        assert_eq!(("jr $".to_string(), 2), text(&[0x18, 0xFE]));
+        assert_eq!(("ldh [$ff40], a".to_string(), 2), text(&[0xE0, 0x40]));
+        assert_eq!(("ld hl, $c000".to_string(), 3), text(&[0x21, 0x00, 0xC0]));
+        assert_eq!(("call nc, $0150".to_string(), 3), text(&[0xD4, 0x50, 0x01]));
+        assert_eq!(("ret z".to_string(), 1), text(&[0xC8]));
+        assert_eq!(("ld hl, sp-2".to_string(), 2), text(&[0xF8, 0xFE]));
+        assert_eq!(("bit 7, [hl]".to_string(), 2), text(&[0xCB, 0x7E]));
+        assert_eq!(("swap a".to_string(), 2), text(&[0xCB, 0x37]));
+        assert_eq!(("cp a, $90".to_string(), 2), text(&[0xFE, 0x90]));
+        assert_eq!(("push af".to_string(), 1), text(&[0xF5]));
+        assert_eq!(("db $d3".to_string(), 1), text(&[0xD3]));
+        assert_eq!(("stop".to_string(), 2), text(&[0x10, 0x00]));
+    }
+
+    #[test]
+    fn test_every_opcode_has_text() {
+        for opcode in 0..=u8::MAX {
+            let (text, length) = text(&[opcode, 0x12, 0x34]);
+            assert!(!text.is_empty());
+            assert!((1..=3).contains(&length), "{opcode:02X} is {length} bytes");
+        }
+    }
+}
@@ -0,0 +1,534 @@
+use crate::bus::{Bus, MemoryBus};
+use crate::cartridge::{Cartridge, CartridgeError};
+use crate::compatibility;
+use crate::cpu::{Cpu, CpuState};
+use crate::joypad::Button;
+use crate::link::LinkPort;
+use crate::model::Model;
+use crate::state::{StateError, StateReader, StateWriter, MAGIC, VERSION};
+
+pub const CYCLES_PER_FRAME: u32 = 70_224;
+const STOP_OPCODE: u8 = 0x10;
+
+#[derive(Debug, Clone)]
+pub struct GameBoy {
+    cpu: Cpu,
+    bus: Bus,
+    cycles: u64,
+}
+
+impl GameBoy {
+    /// Builds a machine in the state the boot ROM leaves it in, on the model the cartridge
+    /// asks for, see [`Model::detect`].
+    ///
+    /// # Errors
+    ///
+    /// Fails when the cartridge cannot be loaded, see [`Cartridge::from_rom`].
+    pub fn from_rom(rom: Vec<u8>) -> Result<Self, CartridgeError> {
+        let cartridge = Cartridge::from_rom(rom)?;
+        let model = Model::detect(cartridge.header());
+        Ok(Self::with_cartridge(cartridge, model))
+    }
+
+    /// Builds a machine like [`GameBoy::from_rom`], on `model`.
+    ///
+    /// # Errors
+    ///
+    /// Fails when the cartridge cannot be loaded, see [`Cartridge::from_rom`].
+    pub fn with_model(rom: Vec<u8>, model: Model) -> Result<Self, CartridgeError> {
+        Ok(Self::with_cartridge(Cartridge::from_rom(rom)?, model))
+    }
+
+    fn with_cartridge(cartridge: Cartridge, model: Model) -> Self {
+        let mut gameboy = Self {
+            cpu: Cpu::default(),
+            bus: Bus::new(cartridge, model),
+            cycles: 0,
+        };
+        gameboy.reset();
+        gameboy
+    }
+
+    /// Builds a machine that starts by executing `boot_rom` from address 0.
+    ///
+    /// # Errors
+    ///
+    /// Fails when the cartridge cannot be loaded, see [`Cartridge::from_rom`].
+    pub fn with_boot_rom(rom: Vec<u8>, boot_rom: Vec<u8>) -> Result<Self, CartridgeError> {
+        let mut gameboy = Self::from_rom(rom)?;
+        gameboy.bus.set_boot_rom(Some(boot_rom));
+        gameboy.reset();
+        Ok(gameboy)
+    }
+
+    /// Picks the colours of a DMG game on CGB hardware as if `held` were held while the boot
+    /// logo shows, see [`crate::compatibility`], and resets. Other combinations leave the
+    /// colours picked for the title.
+    pub fn hold_boot_buttons(&mut self, held: &[Button]) {
+        if let Some(palette) = compatibility::button_palette(held) {
+            self.bus.set_compatibility_palette(palette);
+        }
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.bus.reset();
+        if self.bus.boot_rom_mapped() {
+            self.cpu = Cpu::default();
+        } else {
+            self.cpu.reset_post_boot(self.bus.model(), self.bus.cgb());
+        }
+        self.cycles = 0;
+    }
+
+    /// Snapshots the whole machine, see [`crate::state`] for the format. The ROM and boot ROM
+    /// are not included; load the state into a machine built from the same ROM.
+    #[must_use]
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::default();
+        for byte in MAGIC {
+            state.u8(byte);
+        }
+        state.u16(VERSION);
+        state.u16(self.bus.cartridge().header().checksum);
+        self.save_machine(&mut state);
+        state.into_bytes()
+    }
+
+    fn save_machine(&self, state: &mut StateWriter) {
+        self.cpu.save_state(state);
+        self.bus.save_state(state);
+        state.u64(self.cycles);
+    }
+
+    fn load_machine(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.cpu.load_state(state)?;
+        self.bus.load_state(state)?;
+        self.cycles = state.u64()?;
+        if state.is_empty() {
+            Ok(())
+        } else {
+            Err(StateError::Invalid("trailing data"))
+        }
+    }
+
+    /// Restores a snapshot taken with [`GameBoy::save_state`]. The machine is left unchanged
+    /// when the state is rejected.
+    ///
+    /// # Errors
+    ///
+    /// Fails when `state` is not a save state, has a different format version, was taken with
+    /// another ROM, or is truncated or corrupt.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut reader = StateReader::new(state);
+        for byte in MAGIC {
+            if reader.u8().ok() != Some(byte) {
+                return Err(StateError::NotAState);
+            }
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let expected = self.bus.cartridge().header().checksum;
+        let found = reader.u16()?;
+        if found != expected {
+            return Err(StateError::RomMismatch { expected, found });
+        }
+
+        // Only the state itself is backed up, which is much smaller than the whole machine.
+        let mut backup = StateWriter::default();
+        self.save_machine(&mut backup);
+        let result = self.load_machine(&mut reader);
+        if result.is_err() {
+            let restored = self.load_machine(&mut StateReader::new(&backup.into_bytes()));
+            debug_assert!(restored.is_ok(), "a state saved by this machine loads back");
+        }
+        result
+    }
+
+    #[must_use]
+    pub const fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub const fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    #[must_use]
+    pub const fn bus(&self) -> &Bus {
+        &self.bus
+    }
+
+    pub const fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
+    #[must_use]
+    pub const fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    #[must_use]
+    pub fn framebuffer(&self) -> &[u8] {
+        self.bus.ppu().framebuffer()
+    }
+
+    /// The frame in 15-bit colour, see [`crate::ppu::Ppu::color_framebuffer`].
+    #[must_use]
+    pub fn color_framebuffer(&self) -> &[u16] {
+        self.bus.ppu().color_framebuffer()
+    }
+
+    /// The SGB screen with its border on SGB hardware, see [`crate::sgb::Sgb::framebuffer`].
+    #[must_use]
+    pub fn sgb_framebuffer(&self) -> Option<&[u16]> {
+        self.bus.sgb().map(crate::sgb::Sgb::framebuffer)
+    }
+
+    #[must_use]
+    pub fn audio_samples(&self) -> &[f32] {
+        self.bus.apu().samples()
+    }
+
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.bus.apu_mut().take_samples()
+    }
+
+    pub const fn set_button(&mut self, button: Button, pressed: bool) {
+        self.bus.joypad_mut().set_button(button, pressed);
+    }
+
+    /// Runs one instruction, or one idle step while halted or stopped. Returns the cycles
+    /// taken at the normal clock, which is half the CPU cycles in double speed mode, and
+    /// includes any VRAM DMA the CPU was halted for.
+    pub fn step(&mut self) -> u32 {
+        let switching_speed = self.bus.speed_switch_armed()
+            && self.cpu.state() == CpuState::Running
+            && self.bus.peek(self.cpu.registers().pc()) == STOP_OPCODE;
+        let mut cycles = u32::from(self.cpu.step(&mut self.bus));
+        self.bus.tick(cycles);
+        loop {
+            let stall = self.bus.take_dma_stall();
+            if stall == 0 {
+                break;
+            }
+            self.bus.tick(stall);
+            cycles += stall;
+        }
+        let cycles = if self.bus.double_speed() {
+            cycles / 2
+        } else {
+            cycles
+        };
+        if switching_speed && self.cpu.state() == CpuState::Stopped {
+            self.bus.switch_speed();
+            self.cpu.resume();
+        }
+        self.cycles += u64::from(cycles);
+        cycles
+    }
+
+    /// Steps like [`GameBoy::step`] with `port` at the other end of the link cable. A transfer
+    /// this machine clocks exchanges its byte with `port` when it completes.
+    pub fn step_linked(&mut self, port: &mut (impl LinkPort + ?Sized)) -> u32 {
+        let cycles = self.step();
+        let serial = self.bus.serial_mut();
+        if let Some(byte) = serial.take_sent() {
+            serial.receive(port.exchange(byte));
+        }
+        cycles
+    }
+
+    pub fn run_cycles(&mut self, cycles: u64) -> u64 {
+        let target = self.cycles + cycles;
+        let start = self.cycles;
+        while self.cycles < target {
+            self.step();
+        }
+        self.cycles - start
+    }
+
+    pub fn run_frame(&mut self) -> u64 {
+        self.run_frame_with(|_| {})
+    }
+
+    /// Runs a frame like [`GameBoy::run_frame`], calling `before_step` ahead of every step.
+    pub fn run_frame_with(&mut self, before_step: impl FnMut(&Self)) -> u64 {
+        self.run_frame_stepping(before_step, Self::step)
+    }
+
+    /// Runs a frame like [`GameBoy::run_frame_with`] with `port` at the other end of the link
+    /// cable, see [`GameBoy::step_linked`].
+    pub fn run_frame_linked(
+        &mut self,
+        port: &mut (impl LinkPort + ?Sized),
+        before_step: impl FnMut(&Self),
+    ) -> u64 {
+        self.run_frame_stepping(before_step, |gameboy| gameboy.step_linked(port))
+    }
+
+    fn run_frame_stepping(
+        &mut self,
+        mut before_step: impl FnMut(&Self),
+        mut step: impl FnMut(&mut Self) -> u32,
+    ) -> u64 {
+        self.bus.ppu_mut().take_frame_ready();
+        let start = self.cycles;
+        while !self.bus.ppu_mut().take_frame_ready() {
+            before_step(self);
+            step(self);
+            if self.cycles - start >= u64::from(CYCLES_PER_FRAME) {
+                break;
+            }
+        }
+        self.cycles - start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::MemoryBus;
+
+    fn rom(program: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x100 + program.len()].copy_from_slice(program);
+        rom
+    }
+
+    #[test]
+    fn test_starts_with_post_boot_state() {
+        let gameboy = GameBoy::from_rom(rom(&[])).unwrap();
+        assert_eq!(0x91, gameboy.bus().peek(0xFF40));
+        assert_eq!(0xFC, gameboy.bus().peek(0xFF47));
+    }
+
+    #[test]
+    fn test_boot_rom_leaves_the_header_mapped() {
+        let mut rom = rom(&[]);
+        rom[0x134] = b'H';
+        let gameboy = GameBoy::with_boot_rom(rom, vec![0xAA; 0x900]).unwrap();
+        let bus = gameboy.bus();
+        assert_eq!(0xAA, bus.peek(0x0000));
+        assert_eq!(0xAA, bus.peek(0x00FF));
+        assert_eq!(b'H', bus.peek(0x0134));
+        assert_eq!(0xAA, bus.peek(0x0200));
+        assert_eq!(0xAA, bus.peek(0x08FF));
+    }
+
+    #[test]
+    fn test_run_frame_stops_at_vblank() {
+        let mut gameboy = GameBoy::from_rom(rom(&[0x18, 0xFE])).unwrap();
+        gameboy.run_frame();
+        assert_eq!(144, gameboy.bus().ppu().ly());
+        let elapsed = gameboy.run_frame();
+        assert!(elapsed.abs_diff(u64::from(CYCLES_PER_FRAME)) < 12);
+    }
+
+    #[test]
+    fn test_program_writes_memory() {
+        let mut gameboy = GameBoy::from_rom(rom(&[0x3E, 0x42, 0xEA, 0x00, 0xC0, 0x76])).unwrap();
+        gameboy.run_cycles(64);
+        assert_eq!(0x42, gameboy.bus().peek(0xC000));
+    }
+
+    #[test]
+    fn test_cgb_speed_switch_and_wram_banks() {
+        // ld a, 1; ldh [KEY1], a; stop; jr -2
+        let mut rom = rom(&[0x3E, 0x01, 0xE0, 0x4D, 0x10, 0x00, 0x18, 0xFE]);
+        rom[0x143] = 0x80;
+        let mut gameboy = GameBoy::from_rom(rom).unwrap();
+        assert_eq!(0x11, gameboy.cpu().registers().a());
+        assert_eq!(0x7E, gameboy.bus().peek(0xFF4D));
+        gameboy.step();
+        gameboy.step();
+        assert_eq!(0x7F, gameboy.bus().peek(0xFF4D));
+        gameboy.step();
+        assert!(gameboy.bus().double_speed());
+        assert_eq!(0xFE, gameboy.bus().peek(0xFF4D));
+        assert_eq!(CpuState::Running, gameboy.cpu().state());
+        assert_eq!(6, gameboy.step());
+
+        let bus = gameboy.bus_mut();
+        for bank in 0..8 {
+            bus.write(0xFF70, bank);
+            bus.write(0xD000, 0x10 + bank);
+        }
+        bus.write(0xFF70, 0);
+        assert_eq!(0x11, bus.peek(0xD000));
+        assert_eq!(Some(1), bus.bank_at(0xD000));
+        bus.write(0xFF70, 7);
+        assert_eq!(0x17, bus.peek(0xF000));
+        assert_eq!(Some(7), bus.bank_at(0xD000));
+    }
+
+    #[test]
+    fn test_models_leave_their_registers() {
+        let program = rom(&[0x18, 0xFE]);
+        let identify = |model| {
+            let gameboy = GameBoy::with_model(program.clone(), model).unwrap();
+            let registers = gameboy.cpu().registers();
+            (registers.a(), registers.b(), gameboy.bus().cgb())
+        };
+        assert_eq!((0x01, 0xFF, false), identify(Model::Dmg0));
+        assert_eq!((0x01, 0x00, false), identify(Model::Dmg));
+        assert_eq!((0xFF, 0x00, false), identify(Model::Mgb));
+        assert_eq!((0x01, 0x00, false), identify(Model::Sgb));
+        assert_eq!((0x11, 0x00, false), identify(Model::Cgb));
+        assert_eq!((0x11, 0x01, false), identify(Model::Agb));
+
+        let mut cgb_program = program.clone();
+        cgb_program[0x143] = 0x80;
+        assert_eq!(
+            Model::Cgb,
+            GameBoy::from_rom(cgb_program.clone())
+                .unwrap()
+                .bus()
+                .model()
+        );
+        let dmg = GameBoy::with_model(cgb_program, Model::Dmg).unwrap();
+        assert!(!dmg.bus().cgb());
+        assert_eq!(0x01, dmg.cpu().registers().a());
+    }
+
+    #[test]
+    fn test_dmg_game_on_cgb() {
+        // ld a, $E4; ldh [BGP], a; jr -2
+        let rom = rom(&[0x3E, 0xE4, 0xE0, 0x47, 0x18, 0xFE]);
+        let mut gameboy = GameBoy::with_model(rom, Model::Cgb).unwrap();
+        gameboy.hold_boot_buttons(&[Button::Down, Button::B]);
+        assert_eq!(0x11, gameboy.cpu().registers().a());
+        assert_eq!(0x0008, gameboy.cpu().registers().de());
+        assert!(!gameboy.bus().cgb());
+        assert_eq!(
+            Some(&compatibility::DOWN_B),
+            gameboy.bus().compatibility_palette()
+        );
+
+        let vram = gameboy.bus_mut().ppu_mut();
+        for offset in 0..16 {
+            vram.write_vram(0x8010 + offset, 0xFF);
+        }
+        vram.write_vram(0x9800, 0x01);
+        gameboy.run_frame();
+        gameboy.run_frame();
+        let background = compatibility::DOWN_B.background;
+        assert_eq!(background[3], gameboy.color_framebuffer()[0]);
+        assert_eq!(background[0], gameboy.color_framebuffer()[8]);
+
+        let state = gameboy.save_state();
+        gameboy.reset();
+        assert!(gameboy.bus().ppu().compatibility());
+        let rom = gameboy.bus().cartridge().rom().to_vec();
+        let mut dmg = GameBoy::from_rom(rom).unwrap();
+        assert_eq!(
+            Err(StateError::Invalid("CGB compatibility mode")),
+            dmg.load_state(&state)
+        );
+    }
+
+    #[test]
+    fn test_cgb_vram_dma() {
+        // ld a, 1; ldh [HDMA5], a; ld a, $81; ldh [HDMA5], a; jr -2
+        let mut rom = rom(&[0x3E, 0x01, 0xE0, 0x55, 0x3E, 0x81, 0xE0, 0x55, 0x18, 0xFE]);
+        rom[0x143] = 0x80;
+        let mut gameboy = GameBoy::from_rom(rom).unwrap();
+        let bus = gameboy.bus_mut();
+        bus.write(0xFF51, 0x01);
+        bus.write(0xFF52, 0x00);
+        bus.write(0xFF53, 0x10);
+        bus.write(0xFF54, 0x00);
+        gameboy.step();
+        assert_eq!(12 + 2 * 32, gameboy.step());
+        assert_eq!(0xFF, gameboy.bus().peek(0xFF55));
+        for address in 0..0x20 {
+            let source = gameboy.bus().peek(0x0100 + address);
+            assert_eq!(source, gameboy.bus().ppu().read_vram(0x9000 + address));
+        }
+
+        gameboy.step();
+        gameboy.step();
+        assert_eq!(0x01, gameboy.bus().peek(0xFF55));
+        while gameboy.bus().ppu().mode() != crate::ppu::PpuMode::HBlank {
+            gameboy.step();
+        }
+        assert_eq!(0x00, gameboy.bus().peek(0xFF55));
+        gameboy.bus_mut().write(0xFF55, 0x00);
+        assert_eq!(0x80, gameboy.bus().peek(0xFF55));
+        assert_eq!(
+            gameboy.bus().peek(0x0120),
+            gameboy.bus().ppu().read_vram(0x9020)
+        );
+        assert_eq!(0, gameboy.bus().ppu().read_vram(0x9030));
+    }
+
+    #[test]
+    fn test_save_state_round_trip() {
+        let rom = crate::assembler::assemble_rom(
+            "
+            section \"main\", rom0[$150]
+                ld a, $80
+                ldh [$26], a
+                ld a, $f3
+                ldh [$12], a
+                ld a, $87
+                ldh [$14], a
+                ld hl, $8000
+            .loop:
+                inc a
+                ld [hl+], a
+                ldh [$43], a
+                res 5, h
+                jr .loop
+            ",
+        )
+        .unwrap();
+        let mut gameboy = GameBoy::from_rom(rom).unwrap();
+        gameboy.run_frame();
+        let state = gameboy.save_state();
+        gameboy.run_frame();
+        let expected = gameboy.save_state();
+
+        let mut restored = GameBoy::from_rom(gameboy.bus().cartridge().rom().to_vec()).unwrap();
+        restored.load_state(&state).unwrap();
+        assert_eq!(state, restored.save_state());
+        restored.run_frame();
+        assert_eq!(expected, restored.save_state());
+        assert_eq!(gameboy.framebuffer(), restored.framebuffer());
+    }
+
+    #[test]
+    fn test_load_state_errors() {
+        let mut gameboy = GameBoy::from_rom(rom(&[0x18, 0xFE])).unwrap();
+        let state = gameboy.save_state();
+        gameboy.run_frame();
+        let before = gameboy.save_state();
+
+        let mut newer = state.clone();
+        newer[8..10].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Err(StateError::UnsupportedVersion(VERSION + 1)),
+            gameboy.load_state(&newer)
+        );
+        assert_eq!(
+            Err(StateError::Truncated),
+            gameboy.load_state(&state[..state.len() - 1])
+        );
+        let mut trailing = state.clone();
+        trailing.push(0);
+        assert_eq!(
+            Err(StateError::Invalid("trailing data")),
+            gameboy.load_state(&trailing)
+        );
+        assert_eq!(Err(StateError::NotAState), gameboy.load_state(b"nonsense"));
+        let mut foreign = state.clone();
+        foreign[10] ^= 0xFF;
+        assert!(matches!(
+            gameboy.load_state(&foreign),
+            Err(StateError::RomMismatch { .. })
+        ));
+        assert_eq!(before, gameboy.save_state());
+    }
+}
@@ -0,0 +1,477 @@
+//! A GDB remote serial protocol stub, so GDB or an IDE front end can debug a running ROM.
+//!
+//! Registers are exposed as six 16-bit little-endian values: AF, BC, DE, HL, SP and PC, as
+//! described by the `target.xml` sent to the client. Source mapping is left to the client.
+
+use std::fmt::Write as _;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+use crate::bus::MemoryBus;
+use crate::debugger::{Breakpoint, Debugger, Register, StopReason};
+use crate::gameboy::CYCLES_PER_FRAME;
+use crate::watchpoint::{WatchKind, Watchpoint};
+
+const REGISTERS: [Register; 6] = [
+    Register::AF,
+    Register::BC,
+    Register::DE,
+    Register::HL,
+    Register::SP,
+    Register::PC,
+];
+const INTERRUPT: u8 = 0x03;
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.gnu.gdb.sm83.core">
+    <reg name="af" bitsize="16" type="int" regnum="0"/>
+    <reg name="bc" bitsize="16" type="int"/>
+    <reg name="de" bitsize="16" type="int"/>
+    <reg name="hl" bitsize="16" type="int"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+enum Reply {
+    Packet(String),
+    Resume,
+    Close,
+}
+
+fn checksum(payload: &str) -> u8 {
+    payload.bytes().fold(0, u8::wrapping_add)
+}
+
+fn hex_bytes(bytes: impl IntoIterator<Item = u8>) -> String {
+    bytes.into_iter().fold(String::new(), |mut text, byte| {
+        let _ = write!(text, "{byte:02x}");
+        text
+    })
+}
+
+fn parse_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+fn parse_bytes(text: &str) -> Option<Vec<u8>> {
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parses `addr,length` into the address and the number of bytes, clamped to the address space.
+fn parse_range(text: &str) -> Option<(u16, u16)> {
+    let (address, length) = text.split_once(',')?;
+    let address = u16::try_from(parse_hex(address)?).ok()?;
+    let length = parse_hex(length)?.min(0x1_0000 - u32::from(address));
+    Some((address, u16::try_from(length).ok()?))
+}
+
+fn read_byte(stream: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match stream.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+/// Reads the next packet payload, acknowledging it, or `None` when the client disconnects.
+fn read_packet(stream: &mut (impl Read + Write)) -> io::Result<Option<String>> {
+    loop {
+        match read_byte(stream)? {
+            None => return Ok(None),
+            Some(b'$') => {}
+            Some(_) => continue,
+        }
+        let mut payload = Vec::new();
+        loop {
+            match read_byte(stream)? {
+                None => return Ok(None),
+                Some(b'#') => break,
+                Some(byte) => payload.push(byte),
+            }
+        }
+        let mut sum = [0; 2];
+        stream.read_exact(&mut sum)?;
+        let payload = String::from_utf8_lossy(&payload).into_owned();
+        let expected = std::str::from_utf8(&sum)
+            .ok()
+            .and_then(|sum| u8::from_str_radix(sum, 16).ok());
+        if expected == Some(checksum(&payload)) {
+            stream.write_all(b"+")?;
+            return Ok(Some(payload));
+        }
+        stream.write_all(b"-")?;
+    }
+}
+
+fn write_packet(stream: &mut impl Write, payload: &str) -> io::Result<()> {
+    write!(stream, "${payload}#{:02x}", checksum(payload))?;
+    stream.flush()
+}
+
+#[derive(Debug, Clone)]
+pub struct GdbStub {
+    debugger: Debugger,
+    /// Breakpoint ids in the debugger, by the address GDB inserted them at.
+    breakpoints: Vec<(u16, usize)>,
+    /// Watchpoint ids on the bus, by the `type,addr,length` GDB inserted them with.
+    watchpoints: Vec<(String, usize)>,
+}
+
+impl GdbStub {
+    #[must_use]
+    pub const fn new(debugger: Debugger) -> Self {
+        Self {
+            debugger,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub const fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    #[must_use]
+    pub fn into_debugger(self) -> Debugger {
+        self.debugger
+    }
+
+    /// Waits for one client on `address` and serves it until it detaches.
+    ///
+    /// # Errors
+    ///
+    /// Returns the I/O error when listening or talking to the client fails.
+    pub fn listen(&mut self, address: impl ToSocketAddrs) -> io::Result<()> {
+        let (stream, _) = std::net::TcpListener::bind(address)?.accept()?;
+        self.serve(stream)
+    }
+
+    /// Serves a connected client until it detaches, kills the target or disconnects.
+    ///
+    /// # Errors
+    ///
+    /// Returns the I/O error when talking to the client fails.
+    pub fn serve(&mut self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        while let Some(packet) = read_packet(&mut stream)? {
+            let reply = match self.handle(&packet) {
+                Reply::Packet(reply) => reply,
+                Reply::Resume => match self.resume(&mut stream)? {
+                    Some(reply) => reply,
+                    None => return Ok(()),
+                },
+                Reply::Close => {
+                    write_packet(&mut stream, "OK")?;
+                    return Ok(());
+                }
+            };
+            write_packet(&mut stream, &reply)?;
+        }
+        Ok(())
+    }
+
+    /// Runs a frame at a time until the target stops or the client sends an interrupt.
+    fn resume(&mut self, stream: &mut TcpStream) -> io::Result<Option<String>> {
+        stream.set_nonblocking(true)?;
+        let reply = loop {
+            let reason = self.debugger.resume(Some(u64::from(CYCLES_PER_FRAME)));
+            if reason != StopReason::Limit {
+                break Some(self.stop_reply(reason));
+            }
+            match read_byte(stream) {
+                Ok(None) => break None,
+                Ok(Some(INTERRUPT)) => break Some(format!("S{SIGINT:02x}")),
+                Ok(Some(_)) => {}
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+                Err(error) => return Err(error),
+            }
+        };
+        stream.set_nonblocking(false)?;
+        Ok(reply)
+    }
+
+    fn stop_reply(&self, reason: StopReason) -> String {
+        let StopReason::Watchpoint { hit, .. } = reason else {
+            return format!("S{SIGTRAP:02x}");
+        };
+        let watchpoints = self.debugger.gameboy().bus().watchpoints();
+        let kind = match watchpoints.iter().find(|(id, _)| *id == hit.id) {
+            Some((_, watchpoint)) if watchpoint.kind == WatchKind::Read => "rwatch",
+            Some((_, watchpoint)) if watchpoint.kind == WatchKind::Access => "awatch",
+            _ => "watch",
+        };
+        format!("T{SIGTRAP:02x}{kind}:{:x};", hit.address)
+    }
+
+    fn handle(&mut self, packet: &str) -> Reply {
+        let (command, arguments) = packet.split_at_checked(1).unwrap_or((packet, ""));
+        let reply = match command {
+            "?" => format!("S{SIGTRAP:02x}"),
+            "g" => self.read_registers(),
+            "G" => self.write_registers(arguments),
+            "p" => self.read_register(arguments),
+            "P" => self.write_register(arguments),
+            "m" => self.read_memory(arguments),
+            "M" => self.write_memory(arguments),
+            "Z" => self.insert(arguments),
+            "z" => self.remove(arguments),
+            "s" | "c" => {
+                if let Some(address) = parse_hex(arguments).and_then(|a| u16::try_from(a).ok()) {
+                    Register::PC.set(
+                        self.debugger.gameboy_mut().cpu_mut().registers_mut(),
+                        address,
+                    );
+                }
+                if command == "c" {
+                    return Reply::Resume;
+                }
+                let reason = self.debugger.step();
+                self.stop_reply(reason)
+            }
+            "H" => "OK".to_string(),
+            "D" | "k" => return Reply::Close,
+            "q" => Self::query(arguments),
+            _ => String::new(),
+        };
+        Reply::Packet(reply)
+    }
+
+    fn query(query: &str) -> String {
+        if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let Some((offset, length)) = range.split_once(',') else {
+                return "E01".to_string();
+            };
+            let (Some(offset), Some(length)) = (parse_hex(offset), parse_hex(length)) else {
+                return "E01".to_string();
+            };
+            let start = (offset as usize).min(TARGET_XML.len());
+            let end = (start + length as usize).min(TARGET_XML.len());
+            let marker = if end == TARGET_XML.len() { 'l' } else { 'm' };
+            return format!("{marker}{}", &TARGET_XML[start..end]);
+        }
+        match query.split(':').next().unwrap_or_default() {
+            "Supported" => "PacketSize=4000;qXfer:features:read+".to_string(),
+            "Attached" => "1".to_string(),
+            "C" => "QC1".to_string(),
+            "fThreadInfo" => "m1".to_string(),
+            "sThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        let registers = self.debugger.gameboy().cpu().registers();
+        hex_bytes(
+            REGISTERS
+                .iter()
+                .flat_map(|register| register.get(registers).to_le_bytes()),
+        )
+    }
+
+    fn write_registers(&mut self, values: &str) -> String {
+        let Some(bytes) = parse_bytes(values).filter(|bytes| bytes.len() == REGISTERS.len() * 2)
+        else {
+            return "E01".to_string();
+        };
+        let registers = self.debugger.gameboy_mut().cpu_mut().registers_mut();
+        for (register, value) in REGISTERS.iter().zip(bytes.chunks(2)) {
+            register.set(registers, u16::from_le_bytes([value[0], value[1]]));
+        }
+        "OK".to_string()
+    }
+
+    fn read_register(&self, number: &str) -> String {
+        let registers = self.debugger.gameboy().cpu().registers();
+        parse_hex(number)
+            .and_then(|number| REGISTERS.get(number as usize))
+            .map_or_else(
+                || "E01".to_string(),
+                |register| hex_bytes(register.get(registers).to_le_bytes()),
+            )
+    }
+
+    fn write_register(&mut self, assignment: &str) -> String {
+        let Some((number, value)) = assignment.split_once('=') else {
+            return "E01".to_string();
+        };
+        let register = parse_hex(number).and_then(|number| REGISTERS.get(number as usize));
+        let value = parse_bytes(value).filter(|bytes| bytes.len() == 2);
+        let (Some(register), Some(value)) = (register, value) else {
+            return "E01".to_string();
+        };
+        register.set(
+            self.debugger.gameboy_mut().cpu_mut().registers_mut(),
+            u16::from_le_bytes([value[0], value[1]]),
+        );
+        "OK".to_string()
+    }
+
+    fn read_memory(&self, range: &str) -> String {
+        let Some((address, length)) = parse_range(range) else {
+            return "E01".to_string();
+        };
+        let bus = self.debugger.gameboy().bus();
+        hex_bytes((0..length).map(|offset| bus.peek(address + offset)))
+    }
+
+    fn write_memory(&mut self, arguments: &str) -> String {
+        let Some((range, data)) = arguments.split_once(':') else {
+            return "E01".to_string();
+        };
+        let (Some((address, _)), Some(bytes)) = (parse_range(range), parse_bytes(data)) else {
+            return "E01".to_string();
+        };
+        let bus = self.debugger.gameboy_mut().bus_mut();
+        for (offset, byte) in (0..).zip(bytes) {
+            bus.write(address.wrapping_add(offset), byte);
+        }
+        "OK".to_string()
+    }
+
+    /// Handles `Z`: software and hardware breakpoints, and write, read and access watchpoints.
+    fn insert(&mut self, arguments: &str) -> String {
+        let Some((kind, range)) = arguments.split_once(',') else {
+            return "E01".to_string();
+        };
+        let Some((address, length)) = parse_range(range) else {
+            return "E01".to_string();
+        };
+        let watch_kind = match kind {
+            "0" | "1" => {
+                if !self
+                    .breakpoints
+                    .iter()
+                    .any(|&(existing, _)| existing == address)
+                {
+                    let id = self.debugger.add_breakpoint(Breakpoint {
+                        bank: None,
+                        address,
+                        conditions: Vec::new(),
+                    });
+                    self.breakpoints.push((address, id));
+                }
+                return "OK".to_string();
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return String::new(),
+        };
+        let end = address.saturating_add(length.max(1) - 1);
+        let id = self
+            .debugger
+            .gameboy_mut()
+            .bus_mut()
+            .add_watchpoint(Watchpoint::new(watch_kind, address..=end));
+        self.watchpoints.push((arguments.to_string(), id));
+        "OK".to_string()
+    }
+
+    fn remove(&mut self, arguments: &str) -> String {
+        let Some((kind, range)) = arguments.split_once(',') else {
+            return "E01".to_string();
+        };
+        match kind {
+            "0" | "1" => {
+                let Some((address, _)) = parse_range(range) else {
+                    return "E01".to_string();
+                };
+                for (_, id) in self
+                    .breakpoints
+                    .extract_if(.., |(existing, _)| *existing == address)
+                {
+                    self.debugger.remove_breakpoint(id);
+                }
+            }
+            "2" | "3" | "4" => {
+                let bus = self.debugger.gameboy_mut().bus_mut();
+                for (_, id) in self.watchpoints.extract_if(.., |(key, _)| key == arguments) {
+                    bus.remove_watchpoint(id);
+                }
+            }
+            _ => return String::new(),
+        }
+        "OK".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble_rom;
+    use crate::gameboy::GameBoy;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn request(stream: &mut TcpStream, payload: &str) -> String {
+        write_packet(stream, payload).unwrap();
+        assert_eq!(Some(b'+'), read_byte(stream).unwrap());
+        read_packet(stream).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_loopback_session() {
+        let rom = assemble_rom(
+            "
+            section \"main\", rom0[$150]
+                ld hl, $c000
+            .loop:
+                inc a
+                ld [hl], a
+                jr .loop
+            ",
+        )
+        .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut stub = GdbStub::new(Debugger::new(GameBoy::from_rom(rom).unwrap()));
+            stub.serve(listener.accept().unwrap().0).unwrap();
+            stub
+        });
+
+        let mut client = TcpStream::connect(address).unwrap();
+        assert!(request(&mut client, "qSupported:swbreak+").contains("qXfer:features:read+"));
+        assert!(request(&mut client, "qXfer:features:read:target.xml:0,fff").starts_with("l<?xml"));
+        assert_eq!("S05", request(&mut client, "?"));
+        assert_eq!("b0011300d8004d01feff0001", request(&mut client, "g"));
+        assert_eq!("0001", request(&mut client, "p5"));
+        assert_eq!("2100c0", request(&mut client, "m150,3"));
+
+        assert_eq!("OK", request(&mut client, "Z0,154,1"));
+        assert_eq!("S05", request(&mut client, "c"));
+        assert_eq!("5401", request(&mut client, "p5"));
+        assert_eq!("OK", request(&mut client, "z0,154,1"));
+        assert_eq!("S05", request(&mut client, "s"));
+        assert_eq!("5501", request(&mut client, "p5"));
+
+        assert_eq!("OK", request(&mut client, "Z2,c000,1"));
+        assert_eq!("T05watch:c000;", request(&mut client, "c"));
+        assert_eq!("OK", request(&mut client, "z2,c000,1"));
+
+        assert_eq!("OK", request(&mut client, "Mc000,2:dead"));
+        assert_eq!("dead", request(&mut client, "mc000,2"));
+
+        write_packet(&mut client, "c").unwrap();
+        assert_eq!(Some(b'+'), read_byte(&mut client).unwrap());
+        client.write_all(&[INTERRUPT]).unwrap();
+        assert_eq!("S02", read_packet(&mut client).unwrap().unwrap());
+
+        assert_eq!("OK", request(&mut client, "P0=00ff"));
+        assert_eq!("", request(&mut client, "vMustReplyEmpty"));
+        assert_eq!("OK", request(&mut client, "D"));
+        let stub = server.join().unwrap();
+        let registers = stub.debugger().gameboy().cpu().registers();
+        assert_eq!(0xFF, registers.a());
+        assert_eq!(0xC000, registers.hl());
+    }
+}
@@ -0,0 +1,169 @@
+use crate::memory_map::{
+    HDMA_DESTINATION_HIGH_REGISTER_INDEX, HDMA_DESTINATION_LOW_REGISTER_INDEX,
+    HDMA_LENGTH_REGISTER_INDEX, HDMA_SOURCE_HIGH_REGISTER_INDEX, HDMA_SOURCE_LOW_REGISTER_INDEX,
+};
+use crate::state::{StateError, StateReader, StateWriter};
+
+pub const BLOCK_SIZE: u16 = 0x10;
+
+/// The CGB VRAM DMA registers, HDMA1 to HDMA5. The copying itself is done by the bus, one
+/// 16-byte block at a time, see [`Hdma::next_block`].
+#[derive(Debug, Clone)]
+pub struct Hdma {
+    source: u16,
+    destination: u16,
+    /// Blocks left to copy, less one, as HDMA5 reports them.
+    remaining: u8,
+    /// Whether an HBlank DMA is copying a block every HBlank.
+    hblank_active: bool,
+    /// Blocks of a general-purpose DMA still to be copied.
+    general_blocks: u16,
+}
+
+impl Default for Hdma {
+    fn default() -> Self {
+        Self {
+            source: 0,
+            destination: 0x8000,
+            remaining: 0x7F,
+            hblank_active: false,
+            general_blocks: 0,
+        }
+    }
+}
+
+impl Hdma {
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.source);
+        state.u16(self.destination);
+        state.u8(self.remaining);
+        state.bool(self.hblank_active);
+        state.u16(self.general_blocks);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.source = state.u16()? & 0xFFF0;
+        self.destination = 0x8000 | (state.u16()? & 0x1FF0);
+        self.remaining = state.u8()? & 0x7F;
+        self.hblank_active = state.bool()?;
+        self.general_blocks = state.u16()?;
+        if self.general_blocks > 0x80 {
+            return Err(StateError::Invalid("general-purpose DMA length"));
+        }
+        Ok(())
+    }
+
+    /// Whether an HBlank DMA is waiting for the next HBlank.
+    #[must_use]
+    pub const fn hblank_active(&self) -> bool {
+        self.hblank_active
+    }
+
+    #[must_use]
+    pub const fn read(&self, address: u16) -> u8 {
+        match address {
+            HDMA_LENGTH_REGISTER_INDEX if self.hblank_active => self.remaining,
+            HDMA_LENGTH_REGISTER_INDEX => 0x80 | self.remaining,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        let [source_high, source_low] = self.source.to_be_bytes();
+        let [destination_high, destination_low] = self.destination.to_be_bytes();
+        match address {
+            HDMA_SOURCE_HIGH_REGISTER_INDEX => {
+                self.source = u16::from_be_bytes([value, source_low]);
+            }
+            HDMA_SOURCE_LOW_REGISTER_INDEX => {
+                self.source = u16::from_be_bytes([source_high, value & 0xF0]);
+            }
+            HDMA_DESTINATION_HIGH_REGISTER_INDEX => {
+                self.destination = u16::from_be_bytes([0x80 | (value & 0x1F), destination_low]);
+            }
+            HDMA_DESTINATION_LOW_REGISTER_INDEX => {
+                self.destination = u16::from_be_bytes([destination_high, value & 0xF0]);
+            }
+            HDMA_LENGTH_REGISTER_INDEX => {
+                if value & 0x80 != 0 {
+                    self.remaining = value & 0x7F;
+                    self.hblank_active = true;
+                } else if self.hblank_active {
+                    // Clearing bit 7 during an HBlank DMA cancels it, leaving the length.
+                    self.hblank_active = false;
+                } else {
+                    self.remaining = value & 0x7F;
+                    self.general_blocks = u16::from(self.remaining) + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Counts down one block copied during HBlank, or of a general-purpose DMA when one is
+    /// pending.
+    const fn finish_block(&mut self) {
+        self.source = self.source.wrapping_add(BLOCK_SIZE);
+        self.destination = 0x8000 | (self.destination.wrapping_add(BLOCK_SIZE) & 0x1FF0);
+        if self.remaining == 0 {
+            self.remaining = 0x7F;
+            self.hblank_active = false;
+        } else {
+            self.remaining -= 1;
+        }
+    }
+
+    /// The source and destination of the next block to copy, if a general-purpose DMA is
+    /// pending or `hblank` starts an HBlank with an HBlank DMA active. The transfer advances
+    /// past the block.
+    pub const fn next_block(&mut self, hblank: bool) -> Option<(u16, u16)> {
+        let block = (self.source, self.destination);
+        if self.general_blocks > 0 {
+            self.general_blocks -= 1;
+        } else if !(hblank && self.hblank_active) {
+            return None;
+        }
+        self.finish_block();
+        Some(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hblank_dma_counts_down_and_cancels() {
+        let mut hdma = Hdma::default();
+        hdma.write(HDMA_SOURCE_HIGH_REGISTER_INDEX, 0xC1);
+        hdma.write(HDMA_SOURCE_LOW_REGISTER_INDEX, 0x2F);
+        hdma.write(HDMA_DESTINATION_HIGH_REGISTER_INDEX, 0xFF);
+        hdma.write(HDMA_DESTINATION_LOW_REGISTER_INDEX, 0x00);
+        hdma.write(HDMA_LENGTH_REGISTER_INDEX, 0x82);
+        assert_eq!(0x02, hdma.read(HDMA_LENGTH_REGISTER_INDEX));
+        assert_eq!(None, hdma.next_block(false));
+        assert_eq!(Some((0xC120, 0x9F00)), hdma.next_block(true));
+        assert_eq!(0x01, hdma.read(HDMA_LENGTH_REGISTER_INDEX));
+
+        hdma.write(HDMA_LENGTH_REGISTER_INDEX, 0x00);
+        assert_eq!(0x81, hdma.read(HDMA_LENGTH_REGISTER_INDEX));
+        assert_eq!(None, hdma.next_block(true));
+
+        hdma.write(HDMA_LENGTH_REGISTER_INDEX, 0x81);
+        assert_eq!(Some((0xC130, 0x9F10)), hdma.next_block(true));
+        assert_eq!(Some((0xC140, 0x9F20)), hdma.next_block(true));
+        assert_eq!(0xFF, hdma.read(HDMA_LENGTH_REGISTER_INDEX));
+        assert_eq!(None, hdma.next_block(true));
+    }
+
+    #[test]
+    fn test_general_dma_copies_every_block() {
+        let mut hdma = Hdma::default();
+        hdma.write(HDMA_SOURCE_HIGH_REGISTER_INDEX, 0x40);
+        hdma.write(HDMA_LENGTH_REGISTER_INDEX, 0x01);
+        assert_eq!(Some((0x4000, 0x8000)), hdma.next_block(false));
+        assert_eq!(Some((0x4010, 0x8010)), hdma.next_block(false));
+        assert_eq!(None, hdma.next_block(false));
+        assert_eq!(0xFF, hdma.read(HDMA_LENGTH_REGISTER_INDEX));
+    }
+}
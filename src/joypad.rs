@@ -0,0 +1,138 @@
+use crate::state::{StateError, StateReader, StateWriter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    pub const ALL: [Self; 8] = [
+        Self::Right,
+        Self::Left,
+        Self::Up,
+        Self::Down,
+        Self::A,
+        Self::B,
+        Self::Select,
+        Self::Start,
+    ];
+
+    #[must_use]
+    pub const fn mask(self) -> u8 {
+        match self {
+            Self::Right => 0x01,
+            Self::Left => 0x02,
+            Self::Up => 0x04,
+            Self::Down => 0x08,
+            Self::A => 0x10,
+            Self::B => 0x20,
+            Self::Select => 0x40,
+            Self::Start => 0x80,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Joypad {
+    pressed: u8,
+    select: u8,
+    interrupt: bool,
+}
+
+impl Joypad {
+    #[must_use]
+    pub const fn pressed(&self) -> u8 {
+        self.pressed
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.pressed);
+        state.u8(self.select);
+        state.bool(self.interrupt);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.pressed = state.u8()?;
+        self.select = state.u8()?;
+        self.interrupt = state.bool()?;
+        Ok(())
+    }
+
+    pub const fn set_pressed(&mut self, pressed: u8) {
+        let before = self.lines(self.pressed);
+        self.pressed = pressed;
+        if before & !self.lines(self.pressed) & 0x0F != 0 {
+            self.interrupt = true;
+        }
+    }
+
+    pub const fn set_button(&mut self, button: Button, pressed: bool) {
+        let state = if pressed {
+            self.pressed | button.mask()
+        } else {
+            self.pressed & !button.mask()
+        };
+        self.set_pressed(state);
+    }
+
+    const fn lines(&self, pressed: u8) -> u8 {
+        let mut lines = 0x0F;
+        if self.select & 0x10 == 0 {
+            lines &= !(pressed & 0x0F);
+        }
+        if self.select & 0x20 == 0 {
+            lines &= !(pressed >> 4);
+        }
+        lines
+    }
+
+    pub fn take_interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.interrupt)
+    }
+
+    #[must_use]
+    pub const fn read(&self) -> u8 {
+        self.read_pressed(self.pressed)
+    }
+
+    /// Reads P1 as if the buttons in `pressed` were held, for the other SGB controllers.
+    #[must_use]
+    pub(crate) const fn read_pressed(&self, pressed: u8) -> u8 {
+        0xC0 | self.select | self.lines(pressed)
+    }
+
+    pub const fn write(&mut self, value: u8) {
+        self.select = value & 0x30;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selected_group_is_reported() {
+        let mut joypad = Joypad::default();
+        joypad.set_button(Button::Start, true);
+        joypad.set_button(Button::Left, true);
+        joypad.write(0x10);
+        assert_eq!(0xD7, joypad.read());
+        joypad.write(0x20);
+        assert_eq!(0xED, joypad.read());
+    }
+
+    #[test]
+    fn test_press_raises_interrupt() {
+        let mut joypad = Joypad::default();
+        joypad.write(0x20);
+        joypad.set_button(Button::Down, true);
+        assert!(joypad.take_interrupt());
+    }
+}
@@ -0,0 +1,46 @@
+#![deny(clippy::all, clippy::nursery, clippy::pedantic)]
+
+pub mod apu;
+pub mod assembler;
+pub mod bus;
+pub mod cartridge;
+pub mod cheat;
+pub mod compatibility;
+pub mod cpu;
+pub mod debugger;
+pub mod disassembler;
+pub mod gameboy;
+pub mod gdb;
+pub mod hdma;
+pub mod joypad;
+pub mod link;
+pub mod memory_map;
+pub mod model;
+pub mod movie;
+pub mod net_link;
+pub mod ppu;
+pub mod printer;
+pub mod rewind;
+pub mod rom_disassembly;
+pub mod screenshot;
+pub mod serial;
+pub mod sgb;
+pub mod state;
+pub mod timer;
+pub mod trace;
+pub mod watchpoint;
+
+pub use crate::assembler::{assemble, assemble_rom, AssembleError};
+pub use crate::bus::{Bus, MemoryBus};
+pub use crate::cartridge::{Cartridge, CartridgeError, Header};
+pub use crate::cheat::{Cheat, CheatCode};
+pub use crate::cpu::{Cpu, CpuFlags, CpuState, Instruction, Registers};
+pub use crate::disassembler::disassemble;
+pub use crate::gameboy::{GameBoy, CYCLES_PER_FRAME};
+pub use crate::joypad::Button;
+pub use crate::link::{LinkPort, LinkedPair};
+pub use crate::model::Model;
+pub use crate::movie::{Movie, MovieError};
+pub use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+pub use crate::screenshot::Palette;
+pub use crate::state::StateError;
@@ -0,0 +1,147 @@
+//! The link cable.
+//!
+//! A transfer is clocked by one Game Boy, the master, which shifts a byte out while shifting
+//! the other end's byte in. The emulation exchanges the whole byte when the master's transfer
+//! completes, through the [`LinkPort`] at the other end. A Game Boy waiting for the external
+//! clock is itself a port, see [`crate::serial::Serial`].
+
+use crate::gameboy::GameBoy;
+
+/// The other end of the link cable, as seen by a Game Boy clocking a transfer.
+pub trait LinkPort {
+    /// Sends `byte`, shifted out by a completed transfer, and returns the byte shifted in.
+    fn exchange(&mut self, byte: u8) -> u8;
+}
+
+/// Two Game Boys connected by a link cable, stepped in lockstep.
+///
+/// Whichever machine is behind runs the next step, so the two never drift apart by more than
+/// one instruction. Each side can clock transfers, which complete on the other side at the
+/// same point in time. Stepping is deterministic.
+#[derive(Debug, Clone)]
+pub struct LinkedPair {
+    first: GameBoy,
+    second: GameBoy,
+    /// Normal-clock cycles each machine has run since they were connected.
+    first_cycles: u64,
+    second_cycles: u64,
+}
+
+impl LinkedPair {
+    #[must_use]
+    pub const fn new(first: GameBoy, second: GameBoy) -> Self {
+        Self {
+            first,
+            second,
+            first_cycles: 0,
+            second_cycles: 0,
+        }
+    }
+
+    #[must_use]
+    pub const fn first(&self) -> &GameBoy {
+        &self.first
+    }
+
+    pub const fn first_mut(&mut self) -> &mut GameBoy {
+        &mut self.first
+    }
+
+    #[must_use]
+    pub const fn second(&self) -> &GameBoy {
+        &self.second
+    }
+
+    pub const fn second_mut(&mut self) -> &mut GameBoy {
+        &mut self.second
+    }
+
+    /// Disconnects the cable.
+    #[must_use]
+    pub fn into_inner(self) -> (GameBoy, GameBoy) {
+        (self.first, self.second)
+    }
+
+    /// Normal-clock cycles both machines have run since they were connected.
+    #[must_use]
+    pub fn cycles(&self) -> u64 {
+        self.first_cycles.min(self.second_cycles)
+    }
+
+    /// Steps the machine that is behind, the first one when they are level.
+    pub fn step(&mut self) {
+        if self.first_cycles <= self.second_cycles {
+            let port = self.second.bus_mut().serial_mut();
+            self.first_cycles += u64::from(self.first.step_linked(port));
+        } else {
+            let port = self.first.bus_mut().serial_mut();
+            self.second_cycles += u64::from(self.second.step_linked(port));
+        }
+    }
+
+    /// Runs both machines for at least `cycles` normal-clock cycles. Returns the cycles run.
+    pub fn run_cycles(&mut self, cycles: u64) -> u64 {
+        let start = self.cycles();
+        while self.cycles() < start + cycles {
+            self.step();
+        }
+        self.cycles() - start
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::assembler::assemble_rom;
+    use crate::bus::MemoryBus;
+
+    /// Loads `data` into SB, starts a transfer with `control` and halts until it completes.
+    pub fn transfer(data: u8, control: u8) -> GameBoy {
+        let rom = assemble_rom(&format!(
+            "
+            section \"main\", rom0[$150]
+                ld a, $08
+                ldh [$ff], a
+                xor a
+                ldh [$0f], a
+                ld a, ${data:02x}
+                ldh [$01], a
+                ld a, ${control:02x}
+                ldh [$02], a
+            .wait:
+                halt
+                jr .wait
+            "
+        ))
+        .unwrap();
+        GameBoy::from_rom(rom).unwrap()
+    }
+
+    #[test]
+    fn test_bytes_swap_when_the_master_clocks() {
+        let mut pair = LinkedPair::new(transfer(0x42, 0x81), transfer(0x99, 0x80));
+        pair.run_cycles(2048);
+        assert_eq!(0x81, pair.first().bus().peek(0xFF02) & 0x81);
+        pair.run_cycles(4096);
+        for (gameboy, received) in [(pair.first(), 0x99), (pair.second(), 0x42)] {
+            let bus = gameboy.bus();
+            assert_eq!(received, bus.peek(0xFF01));
+            assert_eq!(0, bus.peek(0xFF02) & 0x80);
+            assert_eq!(0x08, bus.peek(0xFF0F) & 0x08);
+        }
+    }
+
+    #[test]
+    fn test_slave_without_a_master_waits() {
+        let mut pair = LinkedPair::new(transfer(0x42, 0x80), transfer(0x99, 0x80));
+        pair.run_cycles(8192);
+        for gameboy in [pair.first(), pair.second()] {
+            assert_eq!(0x80, gameboy.bus().peek(0xFF02) & 0x80);
+        }
+
+        let mut pair = LinkedPair::new(transfer(0x42, 0x81), transfer(0x99, 0x01));
+        pair.run_cycles(8192);
+        assert_eq!(0xFF, pair.first().bus().peek(0xFF01));
+        assert_eq!(0x99, pair.second().bus().peek(0xFF01));
+    }
+}
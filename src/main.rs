@@ -1,14 +1,762 @@
 #![deny(clippy::all, clippy::nursery, clippy::pedantic)]
 
-use crate::cpu::{Cpu, Instruction, Registers};
-mod cpu;
-mod memory_map;
-
-fn main() {
-    let mut memory = [0; 8192];
-    let mut cpu = Cpu::default();
-    cpu.execute(Instruction::LDA(cpu::LdaTarget::Value(5)), &mut memory);
-    cpu.execute(Instruction::LDFA(cpu::LdfaTarget::Addr(0xFFe)), &mut memory);
-    println!("{:#?}", cpu);
-    println!("{:#?}", &memory[0xFFE]);
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use dmg_01::cheat::parse_cheats;
+use dmg_01::debugger::Debugger;
+use dmg_01::gdb::GdbStub;
+use dmg_01::movie::{Movie, Playback};
+use dmg_01::net_link::NetLink;
+use dmg_01::printer::Printer;
+use dmg_01::rom_disassembly::RomDisassembly;
+use dmg_01::screenshot::{self, Palette};
+use dmg_01::trace::Tracer;
+use dmg_01::{Button, GameBoy, Model, CYCLES_PER_FRAME};
+
+const USAGE: &str = "usage: dmg-01 <rom> [options]
+       dmg-01 disasm <rom> [--output <dir>]
+       dmg-01 debug <rom> [--boot-rom <file>] [--gdb <port>]
+
+run options:
+  --frames <n>          frames to run, or the limit for --until-pc (default 60)
+  --until-pc <addr>     stop once PC reaches the hex address
+  --screenshot <file>   write the last frame as a PNG, with the border on SGB hardware
+  --palette <palette>   classic, grayscale, pocket or four RRGGBB colours (default grayscale),
+                        ignored for CGB games and on CGB hardware
+  --scale <n>           integer screenshot scale (default 1)
+  --dump-registers      print the CPU registers when done
+  --boot-rom <file>     start from a boot ROM instead of the post-boot state
+  --model <model>       dmg0, dmg, mgb, sgb, cgb or agb (default from the cartridge header)
+  --boot-buttons <b>    buttons held during the CGB boot logo to colour DMG games, like up+a
+  --serial-out <file>   write bytes sent over the serial port, '-' for stdout
+  --link-listen <port>  wait on localhost for another instance to connect a link cable
+  --link-connect <addr> connect a link cable to an instance listening on host:port
+  --printer <dir>       connect a Game Boy Printer, writing printed strips to print_NNN.png
+  --cheats <file>       apply the Game Genie and GameShark codes listed in the file
+  --load-state <file>   start from a save state taken with the same ROM
+  --save-state <file>   write a save state when done
+  --record-movie <file> record the joypad input of every frame to an input movie
+  --play-movie <file>   replay an input movie, running its length unless --frames is given
+  --trace <file>        log every instruction in Game Boy doctor format, '-' for stdout
+  --trace-pc <lo-hi>    only trace instructions with PC in the hex range
+  --trace-bank <n>      only trace instructions in this ROM bank
+  --trace-ring <n>      keep the last n traced instructions, written out on a crash
+
+disasm options:
+  --output <dir>        where to write bank_NNN.asm files and the .sym file (default .)
+
+debug options:
+  --boot-rom <file>     start from a boot ROM instead of the post-boot state
+  --gdb <port>          serve the GDB remote protocol on localhost instead of the prompt
+
+exit status: 0 on success, 1 on errors, 2 on bad usage, 3 if --until-pc was not reached";
+
+const EXIT_ERROR: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_PC_NOT_REACHED: u8 = 3;
+const DEFAULT_FRAMES: u64 = 60;
+const MOVIE_SYNC_INTERVAL: u32 = 60;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Run(Box<RunOptions>),
+    Disassemble {
+        rom: PathBuf,
+        output: PathBuf,
+    },
+    Debug {
+        rom: PathBuf,
+        boot_rom: Option<PathBuf>,
+        gdb_port: Option<u16>,
+    },
+}
+
+/// What to plug into the link port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Link {
+    /// A cable to another instance, see [`NetLink`].
+    Listen(u16),
+    Connect(String),
+    /// A printer, writing strips to the directory.
+    Printer(PathBuf),
+}
+
+/// The other end of the link cable while running.
+enum Cable {
+    Unplugged,
+    Net(NetLink),
+    Printer(Printer),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct RunOptions {
+    rom: PathBuf,
+    frames: Option<u64>,
+    until_pc: Option<u16>,
+    screenshot: Option<PathBuf>,
+    palette: Palette,
+    scale: usize,
+    dump_registers: bool,
+    boot_rom: Option<PathBuf>,
+    model: Option<Model>,
+    boot_buttons: Vec<Button>,
+    serial_out: Option<PathBuf>,
+    link: Option<Link>,
+    cheats: Option<PathBuf>,
+    load_state: Option<PathBuf>,
+    save_state: Option<PathBuf>,
+    record_movie: Option<PathBuf>,
+    play_movie: Option<PathBuf>,
+    trace: Option<PathBuf>,
+    trace_pc: Option<RangeInclusive<u16>>,
+    trace_bank: Option<usize>,
+    trace_ring: Option<usize>,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            rom: PathBuf::new(),
+            frames: None,
+            until_pc: None,
+            screenshot: None,
+            palette: Palette::default(),
+            scale: 1,
+            dump_registers: false,
+            boot_rom: None,
+            model: None,
+            boot_buttons: Vec::new(),
+            serial_out: None,
+            link: None,
+            cheats: None,
+            load_state: None,
+            save_state: None,
+            record_movie: None,
+            play_movie: None,
+            trace: None,
+            trace_pc: None,
+            trace_bank: None,
+            trace_ring: None,
+        }
+    }
+}
+
+fn parse_address(value: &str) -> Result<u16, String> {
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix('$'))
+        .unwrap_or(value);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address: {value}"))
+}
+
+fn parse_buttons(value: &str) -> Result<Vec<Button>, String> {
+    value
+        .split('+')
+        .map(|name| match name.to_ascii_lowercase().as_str() {
+            "right" => Ok(Button::Right),
+            "left" => Ok(Button::Left),
+            "up" => Ok(Button::Up),
+            "down" => Ok(Button::Down),
+            "a" => Ok(Button::A),
+            "b" => Ok(Button::B),
+            "select" => Ok(Button::Select),
+            "start" => Ok(Button::Start),
+            _ => Err(format!("invalid button: {name}")),
+        })
+        .collect()
+}
+
+fn parse_run_options(mut args: impl Iterator<Item = String>) -> Result<RunOptions, String> {
+    let mut options = RunOptions::default();
+    let mut rom = None;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} expects a value"));
+        match arg.as_str() {
+            "--frames" => {
+                let frames = value()?;
+                options.frames = Some(
+                    frames
+                        .parse()
+                        .map_err(|_| format!("invalid frame count: {frames}"))?,
+                );
+            }
+            "--until-pc" => options.until_pc = Some(parse_address(&value()?)?),
+            "--screenshot" => options.screenshot = Some(value()?.into()),
+            "--palette" => {
+                options.palette = value()?.parse().map_err(|error| format!("{error}"))?;
+            }
+            "--scale" => {
+                let scale = value()?;
+                options.scale = scale
+                    .parse()
+                    .ok()
+                    .filter(|&scale| scale > 0)
+                    .ok_or_else(|| format!("invalid scale: {scale}"))?;
+            }
+            "--dump-registers" => options.dump_registers = true,
+            "--boot-rom" => options.boot_rom = Some(value()?.into()),
+            "--model" => {
+                options.model = Some(value()?.parse().map_err(|error| format!("{error}"))?);
+            }
+            "--boot-buttons" => options.boot_buttons = parse_buttons(&value()?)?,
+            "--serial-out" => options.serial_out = Some(value()?.into()),
+            "--link-listen" | "--link-connect" | "--printer" => {
+                if options.link.is_some() {
+                    return Err("only one link cable can be connected".into());
+                }
+                let value = value()?;
+                options.link = Some(match arg.as_str() {
+                    "--link-listen" => Link::Listen(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid port: {value}"))?,
+                    ),
+                    "--link-connect" => Link::Connect(value),
+                    _ => Link::Printer(value.into()),
+                });
+            }
+            "--cheats" => options.cheats = Some(value()?.into()),
+            "--load-state" => options.load_state = Some(value()?.into()),
+            "--save-state" => options.save_state = Some(value()?.into()),
+            "--record-movie" => options.record_movie = Some(value()?.into()),
+            "--play-movie" => options.play_movie = Some(value()?.into()),
+            "--trace" => options.trace = Some(value()?.into()),
+            "--trace-pc" => {
+                let range = value()?;
+                let (start, end) = range
+                    .split_once('-')
+                    .ok_or_else(|| format!("invalid PC range: {range}"))?;
+                options.trace_pc = Some(parse_address(start)?..=parse_address(end)?);
+            }
+            "--trace-bank" => {
+                let bank = value()?;
+                options.trace_bank =
+                    Some(bank.parse().map_err(|_| format!("invalid bank: {bank}"))?);
+            }
+            "--trace-ring" => {
+                let length = value()?;
+                options.trace_ring = Some(
+                    length
+                        .parse()
+                        .map_err(|_| format!("invalid ring length: {length}"))?,
+                );
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option: {arg}")),
+            _ if rom.is_none() => rom = Some(arg.into()),
+            _ => return Err(format!("unexpected argument: {arg}")),
+        }
+    }
+    options.rom = rom.ok_or("missing ROM path")?;
+    if !options.boot_buttons.is_empty() && options.boot_rom.is_some() {
+        return Err(
+            "--boot-buttons stands in for a boot ROM and cannot be used with --boot-rom".into(),
+        );
+    }
+    let movie = options.record_movie.is_some() || options.play_movie.is_some();
+    if movie && options.until_pc.is_some() {
+        return Err("input movies run whole frames and cannot be used with --until-pc".into());
+    }
+    Ok(options)
+}
+
+fn parse_disassemble_options(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut rom = None;
+    let mut output = PathBuf::from(".");
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => output = args.next().ok_or("--output expects a value")?.into(),
+            _ if arg.starts_with("--") => return Err(format!("unknown option: {arg}")),
+            _ if rom.is_none() => rom = Some(arg.into()),
+            _ => return Err(format!("unexpected argument: {arg}")),
+        }
+    }
+    let rom = rom.ok_or("missing ROM path")?;
+    Ok(Command::Disassemble { rom, output })
+}
+
+fn parse_debug_options(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut rom = None;
+    let mut boot_rom = None;
+    let mut gdb_port = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--boot-rom" => {
+                boot_rom = Some(args.next().ok_or("--boot-rom expects a value")?.into());
+            }
+            "--gdb" => {
+                let port = args.next().ok_or("--gdb expects a value")?;
+                gdb_port = Some(port.parse().map_err(|_| format!("invalid port: {port}"))?);
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option: {arg}")),
+            _ if rom.is_none() => rom = Some(arg.into()),
+            _ => return Err(format!("unexpected argument: {arg}")),
+        }
+    }
+    let rom = rom.ok_or("missing ROM path")?;
+    Ok(Command::Debug {
+        rom,
+        boot_rom,
+        gdb_port,
+    })
+}
+
+fn parse_command(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.peekable();
+    if args.next_if(|arg| arg == "disasm").is_some() {
+        parse_disassemble_options(args)
+    } else if args.next_if(|arg| arg == "debug").is_some() {
+        parse_debug_options(args)
+    } else {
+        parse_run_options(args).map(|options| Command::Run(Box::new(options)))
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|error| format!("failed to read {}: {error}", path.display()))
+}
+
+fn load(rom_path: &Path, model: Option<Model>, boot_rom: Option<&Path>) -> Result<GameBoy, String> {
+    let rom = read(rom_path)?;
+    let loaded = match model {
+        Some(model) => GameBoy::with_model(rom, model),
+        None => GameBoy::from_rom(rom),
+    };
+    let mut gameboy =
+        loaded.map_err(|error| format!("failed to load {}: {error}", rom_path.display()))?;
+    if let Some(boot_rom) = boot_rom {
+        if gameboy.bus().compatibility_palette().is_some() {
+            return Err("DMG games cannot boot from a boot ROM on CGB hardware".into());
+        }
+        gameboy.bus_mut().set_boot_rom(Some(read(boot_rom)?));
+        gameboy.reset();
+    }
+    Ok(gameboy)
+}
+
+fn open_output(path: &Path) -> Result<Box<dyn Write>, String> {
+    if path.as_os_str() == "-" {
+        return Ok(Box::new(io::stdout()));
+    }
+    let file = std::fs::File::create(path)
+        .map_err(|error| format!("failed to create {}: {error}", path.display()))?;
+    Ok(Box::new(io::BufWriter::new(file)))
+}
+
+fn tracer(options: &RunOptions) -> Result<Option<Tracer>, String> {
+    let Some(path) = &options.trace else {
+        return Ok(None);
+    };
+    let output = open_output(path)?;
+    let mut tracer = match options.trace_ring {
+        Some(length) => Tracer::ring(output, length),
+        None => Tracer::new(output),
+    };
+    if let Some(range) = &options.trace_pc {
+        tracer = tracer.with_range(range.clone());
+    }
+    if let Some(bank) = options.trace_bank {
+        tracer = tracer.with_bank(bank);
+    }
+    Ok(Some(tracer))
+}
+
+fn load_movie(path: &Path) -> Result<Movie, String> {
+    Movie::from_bytes(&read(path)?)
+        .map_err(|error| format!("failed to load {}: {error}", path.display()))
+}
+
+impl Cable {
+    fn open(link: Option<&Link>) -> Result<Self, String> {
+        let opened = match link {
+            None => return Ok(Self::Unplugged),
+            Some(Link::Printer(_)) => return Ok(Self::Printer(Printer::default())),
+            Some(Link::Listen(port)) => {
+                eprintln!("waiting for a link cable on 127.0.0.1:{port}");
+                NetLink::listen(("127.0.0.1", *port))
+            }
+            Some(Link::Connect(address)) => NetLink::connect(address.as_str()),
+        };
+        opened
+            .map(Self::Net)
+            .map_err(|error| format!("failed to connect the link cable: {error}"))
+    }
+
+    fn step(&mut self, gameboy: &mut GameBoy) -> Result<u32, String> {
+        match self {
+            Self::Unplugged => Ok(gameboy.step()),
+            Self::Net(link) => link
+                .step(gameboy)
+                .map_err(|error| format!("link cable failed: {error}")),
+            Self::Printer(printer) => Ok(gameboy.step_linked(printer)),
+        }
+    }
+
+    fn run_frame(
+        &mut self,
+        gameboy: &mut GameBoy,
+        before_step: impl FnMut(&GameBoy),
+    ) -> Result<u64, String> {
+        match self {
+            Self::Unplugged => Ok(gameboy.run_frame_with(before_step)),
+            Self::Net(link) => link
+                .run_frame_with(gameboy, before_step)
+                .map_err(|error| format!("link cable failed: {error}")),
+            Self::Printer(printer) => Ok(gameboy.run_frame_linked(printer, before_step)),
+        }
+    }
+
+    /// Writes what the printer printed into `directory`.
+    fn save_prints(&mut self, directory: &Path, options: &RunOptions) -> Result<(), String> {
+        let Self::Printer(printer) = self else {
+            return Ok(());
+        };
+        for (index, strip) in printer.take_strips().iter().enumerate() {
+            let path = directory.join(format!("print_{:03}.png", index + 1));
+            strip
+                .save_png(&path, options.palette, options.scale)
+                .map_err(|error| format!("failed to write {}: {error}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+fn save_screenshot(path: &Path, gameboy: &GameBoy, options: &RunOptions) -> Result<(), String> {
+    let color = gameboy.bus().cgb() || gameboy.bus().ppu().compatibility();
+    let saved = match gameboy.sgb_framebuffer() {
+        Some(framebuffer) => screenshot::save_sgb_png(path, framebuffer, options.scale),
+        None if color => {
+            screenshot::save_color_png(path, gameboy.color_framebuffer(), options.scale)
+        }
+        None => screenshot::save_png(path, gameboy.framebuffer(), options.palette, options.scale),
+    };
+    saved.map_err(|error| format!("failed to write {}: {error}", path.display()))
+}
+
+fn run(options: &RunOptions) -> Result<bool, String> {
+    let mut gameboy = load(&options.rom, options.model, options.boot_rom.as_deref())?;
+    if !options.boot_buttons.is_empty() {
+        gameboy.hold_boot_buttons(&options.boot_buttons);
+    }
+    if let Some(path) = &options.load_state {
+        gameboy
+            .load_state(&read(path)?)
+            .map_err(|error| format!("failed to load {}: {error}", path.display()))?;
+    }
+    if let Some(path) = &options.cheats {
+        let text = String::from_utf8_lossy(&read(path)?).into_owned();
+        let cheats = parse_cheats(&text)
+            .map_err(|error| format!("failed to load {}: {error}", path.display()))?;
+        for cheat in cheats {
+            gameboy.bus_mut().add_cheat(cheat);
+        }
+    }
+    let mut tracer = tracer(options)?;
+    let mut trace_error = None;
+    let mut trace = |gameboy: &GameBoy| {
+        if let (Some(tracer), None) = (&mut tracer, &trace_error) {
+            trace_error = tracer.trace(gameboy).err();
+        }
+    };
+
+    let movie = options.play_movie.as_deref().map(load_movie).transpose()?;
+    let mut playback = movie
+        .as_ref()
+        .map(|movie| Playback::new(movie, &gameboy))
+        .transpose()
+        .map_err(|error| error.to_string())?;
+    let mut recording = options
+        .record_movie
+        .as_ref()
+        .map(|_| Movie::record(&gameboy, MOVIE_SYNC_INTERVAL));
+
+    let mut cable = Cable::open(options.link.as_ref())?;
+    let movie_frames = movie.as_ref().and_then(|movie| movie.len().try_into().ok());
+    let frames = options.frames.or(movie_frames).unwrap_or(DEFAULT_FRAMES);
+    let reached = if let Some(target) = options.until_pc {
+        let limit = frames * u64::from(CYCLES_PER_FRAME);
+        let start = gameboy.cycles();
+        while gameboy.cpu().registers().pc() != target && gameboy.cycles() - start < limit {
+            trace(&gameboy);
+            cable.step(&mut gameboy)?;
+        }
+        gameboy.cpu().registers().pc() == target
+    } else {
+        for _ in 0..frames {
+            if let Some(playback) = &mut playback {
+                playback
+                    .apply_frame(&mut gameboy)
+                    .map_err(|error| error.to_string())?;
+            }
+            if let Some(recording) = &mut recording {
+                recording.record_frame(&gameboy);
+            }
+            cable.run_frame(&mut gameboy, &mut trace)?;
+        }
+        true
+    };
+
+    if let Some(tracer) = &mut tracer {
+        let flushed = trace_error.map_or_else(|| tracer.flush(), Err);
+        flushed.map_err(|error| format!("failed to write the trace: {error}"))?;
+        if tracer.crashed() {
+            eprintln!("the CPU locked up on an illegal opcode");
+        }
+    }
+
+    if let Some(Link::Printer(directory)) = &options.link {
+        cable.save_prints(directory, options)?;
+    }
+    if let Some(path) = &options.serial_out {
+        let output = gameboy.bus().serial().output();
+        if path.as_os_str() == "-" {
+            print!("{}", String::from_utf8_lossy(output));
+        } else {
+            std::fs::write(path, output)
+                .map_err(|error| format!("failed to write {}: {error}", path.display()))?;
+        }
+    }
+    if let Some(path) = &options.screenshot {
+        save_screenshot(path, &gameboy, options)?;
+    }
+    if let Some(path) = &options.save_state {
+        std::fs::write(path, gameboy.save_state())
+            .map_err(|error| format!("failed to write {}: {error}", path.display()))?;
+    }
+    if let (Some(path), Some(recording)) = (&options.record_movie, &recording) {
+        std::fs::write(path, recording.to_bytes())
+            .map_err(|error| format!("failed to write {}: {error}", path.display()))?;
+    }
+    if options.dump_registers {
+        println!("{} CYCLES:{}", gameboy.cpu().registers(), gameboy.cycles());
+    }
+    Ok(reached)
+}
+
+fn disassemble(rom: &Path, output: &Path) -> Result<(), String> {
+    let disassembly = RomDisassembly::new(&read(rom)?);
+    let write = |name: String, contents: &str| {
+        let path = output.join(name);
+        std::fs::write(&path, contents)
+            .map_err(|error| format!("failed to write {}: {error}", path.display()))
+    };
+    std::fs::create_dir_all(output)
+        .map_err(|error| format!("failed to create {}: {error}", output.display()))?;
+    for (bank, source) in disassembly.banks().iter().enumerate() {
+        write(format!("bank_{bank:03}.asm"), source)?;
+    }
+    let stem = rom.file_stem().unwrap_or_default().to_string_lossy();
+    write(format!("{stem}.sym"), disassembly.symbols())
+}
+
+fn debug(rom: &Path, boot_rom: Option<&Path>, gdb_port: Option<u16>) -> Result<(), String> {
+    let mut debugger = Debugger::new(load(rom, None, boot_rom)?);
+    if let Some(port) = gdb_port {
+        eprintln!("waiting for GDB on 127.0.0.1:{port}");
+        return GdbStub::new(debugger)
+            .listen(("127.0.0.1", port))
+            .map_err(|error| format!("GDB session failed: {error}"));
+    }
+    let mut last = String::new();
+    println!("{}", debugger.current_instruction());
+    loop {
+        print!("(dmg) ");
+        io::stdout().flush().map_err(|error| error.to_string())?;
+        let mut line = String::new();
+        let read = io::stdin()
+            .read_line(&mut line)
+            .map_err(|error| format!("failed to read input: {error}"))?;
+        if read == 0 {
+            println!();
+            return Ok(());
+        }
+        let command = line.trim();
+        if !command.is_empty() {
+            last = command.to_string();
+        }
+        match last.as_str() {
+            "" => {}
+            "quit" | "q" => return Ok(()),
+            command => match debugger.execute(command) {
+                Ok(output) => print!("{output}"),
+                Err(message) => eprintln!("{message}"),
+            },
+        }
+    }
+}
+
+fn report(result: Result<(), String>) -> ExitCode {
+    result.map_or_else(
+        |message| {
+            eprintln!("{message}");
+            ExitCode::from(EXIT_ERROR)
+        },
+        |()| ExitCode::SUCCESS,
+    )
+}
+
+fn main() -> ExitCode {
+    let options = match parse_command(std::env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Debug {
+            rom,
+            boot_rom,
+            gdb_port,
+        }) => return report(debug(&rom, boot_rom.as_deref(), gdb_port)),
+        Ok(Command::Disassemble { rom, output }) => {
+            return report(disassemble(&rom, &output));
+        }
+        Err(message) => {
+            eprintln!("{message}\n\n{USAGE}");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    match run(&options) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => {
+            eprintln!(
+                "PC never reached {:04X}",
+                options.until_pc.unwrap_or_default()
+            );
+            ExitCode::from(EXIT_PC_NOT_REACHED)
+        }
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<RunOptions, String> {
+        parse_run_options(args.iter().map(ToString::to_string))
+    }
+
+    #[test]
+    fn test_parse_run_options() {
+        let options = parse(&[
+            "game.gb",
+            "--frames",
+            "10",
+            "--until-pc",
+            "$0150",
+            "--dump-registers",
+            "--screenshot",
+            "out.png",
+            "--palette",
+            "pocket",
+            "--scale",
+            "3",
+            "--trace",
+            "-",
+            "--trace-pc",
+            "150-$1ff",
+            "--trace-bank",
+            "10",
+            "--trace-ring",
+            "100",
+        ])
+        .unwrap();
+        assert_eq!(PathBuf::from("game.gb"), options.rom);
+        assert_eq!(Some(10), options.frames);
+        assert_eq!(Some(0x0150), options.until_pc);
+        assert_eq!(Some(PathBuf::from("out.png")), options.screenshot);
+        assert_eq!(Palette::Pocket, options.palette);
+        assert_eq!(3, options.scale);
+        assert!(options.dump_registers);
+        assert_eq!(Some(PathBuf::from("-")), options.trace);
+        assert_eq!(Some(0x0150..=0x01FF), options.trace_pc);
+        assert_eq!(Some(10), options.trace_bank);
+        assert_eq!(Some(100), options.trace_ring);
+
+        let options = parse(&[
+            "game.gb",
+            "--play-movie",
+            "in.mov",
+            "--record-movie",
+            "out.mov",
+            "--cheats",
+            "game.cht",
+            "--model",
+            "agb",
+            "--boot-buttons",
+            "Up+A",
+            "--link-connect",
+            "localhost:5000",
+        ])
+        .unwrap();
+        assert_eq!(Some(Model::Agb), options.model);
+        assert_eq!(vec![Button::Up, Button::A], options.boot_buttons);
+        assert_eq!(Some(PathBuf::from("game.cht")), options.cheats);
+        assert_eq!(Some(PathBuf::from("in.mov")), options.play_movie);
+        assert_eq!(Some(PathBuf::from("out.mov")), options.record_movie);
+        assert_eq!(Some(Link::Connect("localhost:5000".into())), options.link);
+        assert_eq!(
+            Some(Link::Listen(5000)),
+            parse(&["game.gb", "--link-listen", "5000"]).unwrap().link
+        );
+        assert_eq!(
+            Some(Link::Printer("prints".into())),
+            parse(&["game.gb", "--printer", "prints"]).unwrap().link
+        );
+    }
+
+    #[test]
+    fn test_parse_run_options_errors() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["game.gb", "--frames"]).is_err());
+        assert!(parse(&["game.gb", "--until-pc", "xyz"]).is_err());
+        assert!(parse(&["game.gb", "--bogus"]).is_err());
+        assert!(parse(&["game.gb", "--scale", "0"]).is_err());
+        assert!(parse(&["game.gb", "--palette", "sepia"]).is_err());
+        assert!(parse(&["game.gb", "other.gb"]).is_err());
+        assert!(parse(&["game.gb", "--trace-pc", "150"]).is_err());
+        assert!(parse(&["game.gb", "--play-movie", "a", "--until-pc", "150"]).is_err());
+        assert!(parse(&["game.gb", "--boot-buttons", "up+c"]).is_err());
+        assert!(parse(&["game.gb", "--boot-buttons", "up", "--boot-rom", "cgb.bin"]).is_err());
+        assert!(parse(&["game.gb", "--model", "gba"]).is_err());
+        assert!(parse(&["game.gb", "--link-listen", "port"]).is_err());
+        assert!(parse(&["game.gb", "--printer", "out", "--link-listen", "1"]).is_err());
+        assert!(parse(&["game.gb", "--link-listen", "1", "--link-connect", "a:1"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_disassemble_command() {
+        let command = parse_command(
+            ["disasm", "game.gb", "--output", "out"]
+                .map(String::from)
+                .into_iter(),
+        );
+        assert_eq!(
+            Ok(Command::Disassemble {
+                rom: PathBuf::from("game.gb"),
+                output: PathBuf::from("out"),
+            }),
+            command
+        );
+        assert!(parse_command(["disasm"].map(String::from).into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_parse_debug_command() {
+        let command = parse_command(
+            ["debug", "game.gb", "--boot-rom", "dmg.bin", "--gdb", "2345"]
+                .map(String::from)
+                .into_iter(),
+        );
+        assert_eq!(
+            Ok(Command::Debug {
+                rom: PathBuf::from("game.gb"),
+                boot_rom: Some(PathBuf::from("dmg.bin")),
+                gdb_port: Some(2345),
+            }),
+            command
+        );
+        assert!(parse_command(["debug", "--frames", "1"].map(String::from).into_iter()).is_err());
+    }
 }
@@ -1,44 +1,80 @@
 use std::ops::{Range, RangeInclusive};
 
-const ROM_BANK_RANGE: Range<u16> = 0x0000..0x4000;
-const SWITCHABLE_ROM_BANK_RANGE: Range<u16> = 0x4000..0x8000;
-const VIDEO_RAM_RANGE: Range<u16> = 0x8000..0xA000;
-const SWITCHABLE_RAM_BANK_RANGE: Range<u16> = 0xA000..0xC000;
-const K8_INTERNAL_RAM_RANGE: Range<u16> = 0xC000..0xE000;
-const ECHO_INTERNAL_RAM_RANGE: Range<u16> = 0xE000..0xFE00;
-const SPRITE_ATTRIB_RANGE: Range<u16> = 0xFE00..0xFEA0;
-const EMPTY_RANGE: Range<u16> = 0xFEA0..0xFF00;
-const IO_PORT_RANGE: Range<u16> = 0xFF00..0xFF4C;
-const EMPTY2_RANGE: Range<u16> = 0xFF4C..0xFF80;
-const INTERNAL_RAM_RANGE: Range<u16> = 0xFF80..0xFFFF;
-const INTERUPT_ENABLE_REGISTER_INDEX: u16 = 0xFFFF;
+pub const ROM_BANK_RANGE: Range<u16> = 0x0000..0x4000;
+pub const SWITCHABLE_ROM_BANK_RANGE: Range<u16> = 0x4000..0x8000;
+pub const VIDEO_RAM_RANGE: Range<u16> = 0x8000..0xA000;
+pub const SWITCHABLE_RAM_BANK_RANGE: Range<u16> = 0xA000..0xC000;
+pub const K8_INTERNAL_RAM_RANGE: Range<u16> = 0xC000..0xE000;
+pub const ECHO_INTERNAL_RAM_RANGE: Range<u16> = 0xE000..0xFE00;
+pub const SPRITE_ATTRIB_RANGE: Range<u16> = 0xFE00..0xFEA0;
+pub const EMPTY_RANGE: Range<u16> = 0xFEA0..0xFF00;
+pub const IO_PORT_RANGE: Range<u16> = 0xFF00..0xFF4C;
+pub const EMPTY2_RANGE: Range<u16> = 0xFF4C..0xFF80;
+pub const INTERNAL_RAM_RANGE: Range<u16> = 0xFF80..0xFFFF;
+pub const INTERUPT_ENABLE_REGISTER_INDEX: u16 = 0xFFFF;
 
-const RESTART_00_INDEX: u16 = 0x0000;
-const RESTART_08_INDEX: u16 = 0x0008;
-const RESTART_10_INDEX: u16 = 0x0010;
-const RESTART_18_INDEX: u16 = 0x0018;
-const RESTART_20_INDEX: u16 = 0x0020;
-const RESTART_28_INDEX: u16 = 0x0028;
-const RESTART_30_INDEX: u16 = 0x0030;
-const RESTART_38_INDEX: u16 = 0x0038;
-const VERTICAL_BLANK_INTERUPT_START_INDEX: u16 = 0x0040;
-const LCDC_STATUS_INTERUPT_START_INDEX: u16 = 0x0048;
-const TIMER_OVERFLOW_INTERUPT_START_INDEX: u16 = 0x0050;
-const SERIAL_TRANSFER_COMPLETION_INTERUPT_START_INDEX: u16 = 0x0058;
-const HIGH_TO_LOW_INTERUPT_START_INDEX: u16 = 0x0060;
+pub const RESTART_00_INDEX: u16 = 0x0000;
+pub const RESTART_08_INDEX: u16 = 0x0008;
+pub const RESTART_10_INDEX: u16 = 0x0010;
+pub const RESTART_18_INDEX: u16 = 0x0018;
+pub const RESTART_20_INDEX: u16 = 0x0020;
+pub const RESTART_28_INDEX: u16 = 0x0028;
+pub const RESTART_30_INDEX: u16 = 0x0030;
+pub const RESTART_38_INDEX: u16 = 0x0038;
+pub const VERTICAL_BLANK_INTERUPT_START_INDEX: u16 = 0x0040;
+pub const LCDC_STATUS_INTERUPT_START_INDEX: u16 = 0x0048;
+pub const TIMER_OVERFLOW_INTERUPT_START_INDEX: u16 = 0x0050;
+pub const SERIAL_TRANSFER_COMPLETION_INTERUPT_START_INDEX: u16 = 0x0058;
+pub const HIGH_TO_LOW_INTERUPT_START_INDEX: u16 = 0x0060;
 
-const EXECUTION_START_INDEX: RangeInclusive<u16> = 0x0100..=0x0103;
-const NINTENDO_SCROLL_INDEX: RangeInclusive<u16> = 0x0104..=0x0133;
-const GAME_TITLE_INDEX: RangeInclusive<u16> = 0x0134..=0x0142;
-const IS_CGB_INDEX: u16 = 0x0143;
-const HIGH_NIB_LICENCE_INDEX: u16 = 0x0144;
-const LOW_NIB_LICENCE_INDEX: u16 = 0x0145;
-const IS_SGB_INDEX: u16 = 0x0146;
-const CARTRIDGE_TYPE_INDEX: u16 = 0x0147;
-const ROM_SIZE_INDEX: u16 = 0x0148;
-const RAM_SIZE_INDEX: u16 = 0x0149;
-const DESTINATION_CODE_INDEX: u16 = 0x014a;
-const LICENCE_CODE_INDEX: u16 = 0x014b;
-const MASK_ROM_VERSION_INDEX: u16 = 0x014c;
-const COMPLEMENT_CHECK_INDEX: u16 = 0x014d;
-const CHECKSUM_INDEX: RangeInclusive<u16> = 0x014e..=0x014f;
+pub const EXECUTION_START_INDEX: RangeInclusive<u16> = 0x0100..=0x0103;
+pub const NINTENDO_SCROLL_INDEX: RangeInclusive<u16> = 0x0104..=0x0133;
+pub const GAME_TITLE_INDEX: RangeInclusive<u16> = 0x0134..=0x0142;
+pub const IS_CGB_INDEX: u16 = 0x0143;
+pub const HIGH_NIB_LICENCE_INDEX: u16 = 0x0144;
+pub const LOW_NIB_LICENCE_INDEX: u16 = 0x0145;
+pub const IS_SGB_INDEX: u16 = 0x0146;
+pub const CARTRIDGE_TYPE_INDEX: u16 = 0x0147;
+pub const ROM_SIZE_INDEX: u16 = 0x0148;
+pub const RAM_SIZE_INDEX: u16 = 0x0149;
+pub const DESTINATION_CODE_INDEX: u16 = 0x014a;
+pub const LICENCE_CODE_INDEX: u16 = 0x014b;
+pub const MASK_ROM_VERSION_INDEX: u16 = 0x014c;
+pub const COMPLEMENT_CHECK_INDEX: u16 = 0x014d;
+pub const CHECKSUM_INDEX: RangeInclusive<u16> = 0x014e..=0x014f;
+
+pub const JOYPAD_REGISTER_INDEX: u16 = 0xFF00;
+pub const SERIAL_DATA_REGISTER_INDEX: u16 = 0xFF01;
+pub const SERIAL_CONTROL_REGISTER_INDEX: u16 = 0xFF02;
+pub const DIVIDER_REGISTER_INDEX: u16 = 0xFF04;
+pub const TIMER_COUNTER_REGISTER_INDEX: u16 = 0xFF05;
+pub const TIMER_MODULO_REGISTER_INDEX: u16 = 0xFF06;
+pub const TIMER_CONTROL_REGISTER_INDEX: u16 = 0xFF07;
+pub const INTERUPT_FLAG_REGISTER_INDEX: u16 = 0xFF0F;
+pub const SOUND_REGISTER_RANGE: RangeInclusive<u16> = 0xFF10..=0xFF3F;
+pub const LCDC_REGISTER_INDEX: u16 = 0xFF40;
+pub const STAT_REGISTER_INDEX: u16 = 0xFF41;
+pub const SCROLL_Y_REGISTER_INDEX: u16 = 0xFF42;
+pub const SCROLL_X_REGISTER_INDEX: u16 = 0xFF43;
+pub const LY_REGISTER_INDEX: u16 = 0xFF44;
+pub const LYC_REGISTER_INDEX: u16 = 0xFF45;
+pub const DMA_REGISTER_INDEX: u16 = 0xFF46;
+pub const BG_PALETTE_REGISTER_INDEX: u16 = 0xFF47;
+pub const OBJ_PALETTE_0_REGISTER_INDEX: u16 = 0xFF48;
+pub const OBJ_PALETTE_1_REGISTER_INDEX: u16 = 0xFF49;
+pub const WINDOW_Y_REGISTER_INDEX: u16 = 0xFF4A;
+pub const WINDOW_X_REGISTER_INDEX: u16 = 0xFF4B;
+pub const KEY1_REGISTER_INDEX: u16 = 0xFF4D;
+pub const VRAM_BANK_REGISTER_INDEX: u16 = 0xFF4F;
+pub const BOOT_ROM_DISABLE_REGISTER_INDEX: u16 = 0xFF50;
+pub const HDMA_SOURCE_HIGH_REGISTER_INDEX: u16 = 0xFF51;
+pub const HDMA_SOURCE_LOW_REGISTER_INDEX: u16 = 0xFF52;
+pub const HDMA_DESTINATION_HIGH_REGISTER_INDEX: u16 = 0xFF53;
+pub const HDMA_DESTINATION_LOW_REGISTER_INDEX: u16 = 0xFF54;
+pub const HDMA_LENGTH_REGISTER_INDEX: u16 = 0xFF55;
+pub const BG_COLOR_PALETTE_INDEX_REGISTER_INDEX: u16 = 0xFF68;
+pub const BG_COLOR_PALETTE_DATA_REGISTER_INDEX: u16 = 0xFF69;
+pub const OBJ_COLOR_PALETTE_INDEX_REGISTER_INDEX: u16 = 0xFF6A;
+pub const OBJ_COLOR_PALETTE_DATA_REGISTER_INDEX: u16 = 0xFF6B;
+pub const OBJ_PRIORITY_MODE_REGISTER_INDEX: u16 = 0xFF6C;
+pub const WRAM_BANK_REGISTER_INDEX: u16 = 0xFF70;
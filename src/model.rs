@@ -0,0 +1,145 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::cartridge::Header;
+
+/// The hardware to emulate. Each model's boot ROM leaves different registers behind, which
+/// is how software tells them apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Model {
+    /// The early DMG boot ROM revision.
+    Dmg0,
+    #[default]
+    Dmg,
+    /// Game Boy Pocket.
+    Mgb,
+    /// Super Game Boy.
+    Sgb,
+    /// Game Boy Color.
+    Cgb,
+    /// Game Boy Advance running Game Boy Color software.
+    Agb,
+}
+
+impl Model {
+    pub const ALL: [Self; 6] = [
+        Self::Dmg0,
+        Self::Dmg,
+        Self::Mgb,
+        Self::Sgb,
+        Self::Cgb,
+        Self::Agb,
+    ];
+
+    /// The model a cartridge asks for: CGB when it supports CGB mode, SGB when it supports
+    /// SGB functions, the DMG otherwise.
+    #[must_use]
+    pub const fn detect(header: &Header) -> Self {
+        if header.cgb_flag & 0x80 != 0 {
+            Self::Cgb
+        } else if header.supports_sgb() {
+            Self::Sgb
+        } else {
+            Self::Dmg
+        }
+    }
+
+    /// Whether this is CGB hardware, which runs CGB cartridges in CGB mode.
+    #[must_use]
+    pub const fn is_cgb(self) -> bool {
+        matches!(self, Self::Cgb | Self::Agb)
+    }
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Dmg0 => "dmg0",
+            Self::Dmg => "dmg",
+            Self::Mgb => "mgb",
+            Self::Sgb => "sgb",
+            Self::Cgb => "cgb",
+            Self::Agb => "agb",
+        }
+    }
+
+    pub(crate) const fn bits(self) -> u8 {
+        match self {
+            Self::Dmg0 => 0,
+            Self::Dmg => 1,
+            Self::Mgb => 2,
+            Self::Sgb => 3,
+            Self::Cgb => 4,
+            Self::Agb => 5,
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseModelError(String);
+
+impl fmt::Display for ParseModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid model {:?}, expected dmg0, dmg, mgb, sgb, cgb or agb",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseModelError {}
+
+impl FromStr for Model {
+    type Err = ParseModelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|model| model.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseModelError(s.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble_rom;
+    use crate::cartridge::Cartridge;
+    use crate::memory_map::{IS_CGB_INDEX, IS_SGB_INDEX, LICENCE_CODE_INDEX};
+
+    #[test]
+    fn test_detect() {
+        let rom = assemble_rom("section \"main\", rom0[$150]\nhalt\n").unwrap();
+        let detect = |patches: &[(u16, u8)]| {
+            let mut rom = rom.clone();
+            for &(address, value) in patches {
+                rom[usize::from(address)] = value;
+            }
+            Model::detect(Cartridge::from_rom(rom).unwrap().header())
+        };
+        assert_eq!(Model::Dmg, detect(&[]));
+        assert_eq!(Model::Cgb, detect(&[(IS_CGB_INDEX, 0x80)]));
+        assert_eq!(
+            Model::Cgb,
+            detect(&[(IS_CGB_INDEX, 0xC0), (IS_SGB_INDEX, 0x03)])
+        );
+        let sgb = [(IS_SGB_INDEX, 0x03), (LICENCE_CODE_INDEX, 0x33)];
+        assert_eq!(Model::Sgb, detect(&sgb));
+        assert_eq!(Model::Dmg, detect(&sgb[..1]));
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        for model in Model::ALL {
+            assert_eq!(Ok(model), model.to_string().parse());
+        }
+        assert_eq!(Ok(Model::Agb), "AGB".parse());
+        assert!("gba".parse::<Model>().is_err());
+    }
+}
@@ -0,0 +1,396 @@
+//! Input movies: the joypad state of every frame, recorded from a known starting point so the
+//! run can be replayed bit-exactly.
+//!
+//! A movie starts with [`MAGIC`], the format [`VERSION`], the global checksum of the ROM from
+//! [`crate::memory_map::CHECKSUM_INDEX`], the hash of the boot ROM when one was used, and the
+//! hash of the machine state the recording started from. Playback refuses to start unless all
+//! three match. Every `sync_interval` frames the hash of the machine state is recorded as well,
+//! and playback stops with [`MovieError::Desync`] at the first one that differs.
+
+use std::fmt;
+
+use crate::gameboy::GameBoy;
+use crate::state::{StateError, StateReader, StateWriter};
+
+pub const MAGIC: [u8; 8] = *b"DMG01MOV";
+pub const VERSION: u16 = 1;
+
+/// The 64-bit FNV-1a hash of `bytes`, used for states and boot ROMs. Unlike the standard
+/// library's hashers it is stable across Rust versions, so movies stay valid.
+#[must_use]
+pub fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+fn state_hash(gameboy: &GameBoy) -> u64 {
+    hash(&gameboy.save_state())
+}
+
+fn boot_rom_hash(gameboy: &GameBoy) -> Option<u64> {
+    gameboy.bus().boot_rom().map(hash)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    NotAMovie,
+    UnsupportedVersion(u16),
+    /// The movie was recorded with a ROM whose global checksum differs from the loaded one.
+    RomMismatch {
+        expected: u16,
+        found: u16,
+    },
+    /// The movie was recorded with a different boot ROM, or with one where none is loaded or
+    /// the other way around.
+    BootRomMismatch,
+    /// The machine is not in the state the recording started from.
+    StartMismatch,
+    /// The machine state at the start of `frame` differs from the recording.
+    Desync {
+        frame: usize,
+        expected: u64,
+        found: u64,
+    },
+    Truncated,
+    Invalid(&'static str),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAMovie => write!(f, "not an input movie"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "movie version {version} is not supported, expected version {VERSION}"
+            ),
+            Self::RomMismatch { expected, found } => write!(
+                f,
+                "movie is for a ROM with checksum {found:04X}, the loaded ROM has {expected:04X}"
+            ),
+            Self::BootRomMismatch => write!(f, "movie was recorded with a different boot ROM"),
+            Self::StartMismatch => write!(f, "movie was recorded from a different starting state"),
+            Self::Desync {
+                frame,
+                expected,
+                found,
+            } => write!(
+                f,
+                "movie desynced at frame {frame}: state hash {found:016X}, expected {expected:016X}"
+            ),
+            Self::Truncated => write!(f, "movie is truncated"),
+            Self::Invalid(what) => write!(f, "movie has an invalid {what}"),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<StateError> for MovieError {
+    fn from(error: StateError) -> Self {
+        match error {
+            StateError::Invalid(what) => Self::Invalid(what),
+            _ => Self::Truncated,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    checksum: u16,
+    boot_rom: Option<u64>,
+    start: u64,
+    sync_interval: u32,
+    /// The joypad state at the start of each frame, see [`crate::joypad::Joypad::pressed`].
+    inputs: Vec<u8>,
+    /// The state hash at the start of every frame that is a nonzero multiple of
+    /// `sync_interval`, after its input was applied.
+    sync_hashes: Vec<u64>,
+}
+
+impl Movie {
+    /// Starts recording from the current state of `gameboy`, hashing the state every
+    /// `sync_interval` frames.
+    ///
+    /// # Panics
+    ///
+    /// Panics when `sync_interval` is zero.
+    #[must_use]
+    pub fn record(gameboy: &GameBoy, sync_interval: u32) -> Self {
+        assert!(
+            sync_interval > 0,
+            "the sync interval must be at least one frame"
+        );
+        Self {
+            checksum: gameboy.bus().cartridge().header().checksum,
+            boot_rom: boot_rom_hash(gameboy),
+            start: state_hash(gameboy),
+            sync_interval,
+            inputs: Vec::new(),
+            sync_hashes: Vec::new(),
+        }
+    }
+
+    /// Records the input for the frame `gameboy` is about to run. Call this once before every
+    /// frame, after setting the buttons.
+    pub fn record_frame(&mut self, gameboy: &GameBoy) {
+        if self.syncs_at(self.inputs.len()) {
+            self.sync_hashes.push(state_hash(gameboy));
+        }
+        self.inputs.push(gameboy.bus().joypad().pressed());
+    }
+
+    /// Records the current input and runs one frame.
+    pub fn run_frame(&mut self, gameboy: &mut GameBoy) -> u64 {
+        self.record_frame(gameboy);
+        gameboy.run_frame()
+    }
+
+    fn syncs_at(&self, frame: usize) -> bool {
+        frame > 0 && u32::try_from(frame).is_ok_and(|frame| frame % self.sync_interval == 0)
+    }
+
+    /// The number of recorded frames.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    #[must_use]
+    pub fn inputs(&self) -> &[u8] {
+        &self.inputs
+    }
+
+    /// Serializes the movie, see the [module documentation](self) for the contents.
+    ///
+    /// # Panics
+    ///
+    /// Panics when the movie is 4 Gi frames or longer.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut movie = StateWriter::default();
+        for byte in MAGIC {
+            movie.u8(byte);
+        }
+        movie.u16(VERSION);
+        movie.u16(self.checksum);
+        movie.bool(self.boot_rom.is_some());
+        movie.u64(self.boot_rom.unwrap_or_default());
+        movie.u64(self.start);
+        movie.u32(self.sync_interval);
+        movie.bytes(&self.inputs);
+        movie.u32(u32::try_from(self.sync_hashes.len()).expect("sync hashes fit in 32 bits"));
+        for &hash in &self.sync_hashes {
+            movie.u64(hash);
+        }
+        movie.into_bytes()
+    }
+
+    /// Parses a movie written by [`Movie::to_bytes`].
+    ///
+    /// # Errors
+    ///
+    /// Fails when `bytes` is not a movie, has a different format version, or is truncated or
+    /// corrupt.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MovieError> {
+        let mut reader = StateReader::new(bytes);
+        for byte in MAGIC {
+            if reader.u8().ok() != Some(byte) {
+                return Err(MovieError::NotAMovie);
+            }
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        let checksum = reader.u16()?;
+        let has_boot_rom = reader.bool()?;
+        let boot_rom = Some(reader.u64()?).filter(|_| has_boot_rom);
+        let start = reader.u64()?;
+        let sync_interval = reader.u32()?;
+        if sync_interval == 0 {
+            return Err(MovieError::Invalid("sync interval"));
+        }
+        let mut movie = Self {
+            checksum,
+            boot_rom,
+            start,
+            sync_interval,
+            inputs: reader.bytes()?.to_vec(),
+            sync_hashes: Vec::new(),
+        };
+        let count = reader.u32()?;
+        let expected = (1..movie.len())
+            .filter(|&frame| movie.syncs_at(frame))
+            .count();
+        if usize::try_from(count).ok() != Some(expected) {
+            return Err(MovieError::Invalid("sync hash count"));
+        }
+        movie.sync_hashes = (0..count).map(|_| reader.u64()).collect::<Result<_, _>>()?;
+        if !reader.is_empty() {
+            return Err(MovieError::Invalid("trailing data"));
+        }
+        Ok(movie)
+    }
+
+    /// Replays the whole movie on `gameboy`.
+    ///
+    /// # Errors
+    ///
+    /// Fails as [`Playback::new`] and [`Playback::run_frame`] do.
+    pub fn replay(&self, gameboy: &mut GameBoy) -> Result<(), MovieError> {
+        let mut playback = Playback::new(self, gameboy)?;
+        while playback.run_frame(gameboy)?.is_some() {}
+        Ok(())
+    }
+}
+
+/// A movie being played back one frame at a time.
+#[derive(Debug, Clone)]
+pub struct Playback<'a> {
+    movie: &'a Movie,
+    frame: usize,
+}
+
+impl<'a> Playback<'a> {
+    /// Starts playing `movie` on `gameboy`, which must be in the state the recording started
+    /// from.
+    ///
+    /// # Errors
+    ///
+    /// Fails when the ROM, the boot ROM or the machine state differ from the recording.
+    pub fn new(movie: &'a Movie, gameboy: &GameBoy) -> Result<Self, MovieError> {
+        let expected = gameboy.bus().cartridge().header().checksum;
+        if movie.checksum != expected {
+            return Err(MovieError::RomMismatch {
+                expected,
+                found: movie.checksum,
+            });
+        }
+        if movie.boot_rom != boot_rom_hash(gameboy) {
+            return Err(MovieError::BootRomMismatch);
+        }
+        if movie.start != state_hash(gameboy) {
+            return Err(MovieError::StartMismatch);
+        }
+        Ok(Self { movie, frame: 0 })
+    }
+
+    /// The number of frames played so far.
+    #[must_use]
+    pub const fn frame(&self) -> usize {
+        self.frame
+    }
+
+    #[must_use]
+    pub const fn is_finished(&self) -> bool {
+        self.frame >= self.movie.len()
+    }
+
+    /// Applies the recorded input for the frame `gameboy` is about to run, and checks the state
+    /// hash when one was recorded for it. Call this once before every frame. Returns false,
+    /// leaving `gameboy` alone, once the movie is over.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MovieError::Desync`] when the machine state differs from the recording.
+    pub fn apply_frame(&mut self, gameboy: &mut GameBoy) -> Result<bool, MovieError> {
+        let Some(&pressed) = self.movie.inputs.get(self.frame) else {
+            return Ok(false);
+        };
+        gameboy.bus_mut().joypad_mut().set_pressed(pressed);
+        if self.movie.syncs_at(self.frame) {
+            let index = self.frame / usize::try_from(self.movie.sync_interval).unwrap_or(1) - 1;
+            let expected = self.movie.sync_hashes[index];
+            let found = state_hash(gameboy);
+            if found != expected {
+                return Err(MovieError::Desync {
+                    frame: self.frame,
+                    expected,
+                    found,
+                });
+            }
+        }
+        self.frame += 1;
+        Ok(true)
+    }
+
+    /// Applies the recorded input and runs one frame, returning the cycles run, or `None` once
+    /// the movie is over.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MovieError::Desync`] when the machine state differs from the recording.
+    pub fn run_frame(&mut self, gameboy: &mut GameBoy) -> Result<Option<u64>, MovieError> {
+        if !self.apply_frame(gameboy)? {
+            return Ok(None);
+        }
+        Ok(Some(gameboy.run_frame()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::MemoryBus;
+    use crate::joypad::Button;
+    use crate::rewind::tests::joypad_echo;
+
+    fn record(frames: usize) -> (Movie, Vec<u8>) {
+        let mut gameboy = joypad_echo();
+        let mut movie = Movie::record(&gameboy, 4);
+        for frame in 0..frames {
+            gameboy.set_button(Button::A, frame % 3 == 0);
+            movie.run_frame(&mut gameboy);
+        }
+        (movie, gameboy.save_state())
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let (movie, end) = record(10);
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(10, movie.len());
+        assert_eq!(2, movie.sync_hashes.len());
+
+        let mut gameboy = joypad_echo();
+        movie.replay(&mut gameboy).unwrap();
+        assert!(end == gameboy.save_state());
+    }
+
+    #[test]
+    fn test_desync_and_mismatches() {
+        let (movie, _) = record(10);
+
+        let mut gameboy = joypad_echo();
+        gameboy.run_frame();
+        assert_eq!(
+            Err(MovieError::StartMismatch),
+            Playback::new(&movie, &gameboy).map(|_| ())
+        );
+
+        let mut gameboy = joypad_echo();
+        let mut playback = Playback::new(&movie, &gameboy).unwrap();
+        for _ in 0..4 {
+            playback.run_frame(&mut gameboy).unwrap();
+        }
+        gameboy.bus_mut().write(0xC800, 0x55);
+        assert!(matches!(
+            playback.run_frame(&mut gameboy),
+            Err(MovieError::Desync { frame: 4, .. })
+        ));
+
+        let mut bytes = movie.to_bytes();
+        assert_eq!(
+            Err(MovieError::Truncated),
+            Movie::from_bytes(&bytes[..bytes.len() - 1])
+        );
+        bytes[0] = b'X';
+        assert_eq!(Err(MovieError::NotAMovie), Movie::from_bytes(&bytes));
+    }
+}
@@ -0,0 +1,423 @@
+//! A link cable between two processes over TCP.
+//!
+//! Each side runs its own Game Boy and tells the other how far it has got. Neither may run more
+//! than the maximum lead ahead of the time the other last reported; a side that gets that far
+//! stalls until the other catches up. A transfer clocked by one side is sent with the time it
+//! completed, and that side stalls until the other has run to the same time, shifted the byte
+//! in and replied with its own byte. The timing of a transfer on the receiving side is off by at
+//! most the maximum lead.
+//!
+//! Messages are 10 bytes: a kind, a little-endian u64 time in normal-clock cycles since the
+//! connection was made, and a data byte. Both sides start by sending [`PROTOCOL_VERSION`] in a
+//! hello message. Closing the connection unplugs the cable, after which the remaining side runs
+//! on its own and reads 0xFF from transfers.
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::gameboy::{GameBoy, CYCLES_PER_FRAME};
+use crate::link::LinkPort;
+
+pub const PROTOCOL_VERSION: u8 = 1;
+/// How far, in normal-clock cycles, a side may run ahead of the other, see
+/// [`NetLink::with_max_lead`].
+pub const DEFAULT_MAX_LEAD: u64 = 2048;
+
+const MESSAGE_SIZE: usize = 10;
+const HELLO: u8 = b'H';
+const TIME: u8 = b'T';
+const TRANSFER: u8 = b'X';
+const REPLY: u8 = b'R';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Message {
+    Hello(u8),
+    Time(u64),
+    /// A byte shifted out by a transfer the sender clocked, completed at the time given.
+    Transfer(u64, u8),
+    /// The byte shifted out in return for a [`Message::Transfer`].
+    Reply(u64, u8),
+}
+
+impl Message {
+    fn encode(self) -> [u8; MESSAGE_SIZE] {
+        let (kind, time, byte) = match self {
+            Self::Hello(version) => (HELLO, 0, version),
+            Self::Time(time) => (TIME, time, 0),
+            Self::Transfer(time, byte) => (TRANSFER, time, byte),
+            Self::Reply(time, byte) => (REPLY, time, byte),
+        };
+        let mut bytes = [0; MESSAGE_SIZE];
+        bytes[0] = kind;
+        bytes[1..9].copy_from_slice(&time.to_le_bytes());
+        bytes[9] = byte;
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let mut time = [0; 8];
+        time.copy_from_slice(&bytes[1..9]);
+        let time = u64::from_le_bytes(time);
+        match bytes[0] {
+            HELLO => Ok(Self::Hello(bytes[9])),
+            TIME => Ok(Self::Time(time)),
+            TRANSFER => Ok(Self::Transfer(time, bytes[9])),
+            REPLY => Ok(Self::Reply(time, bytes[9])),
+            kind => Err(invalid(&format!("unknown link message kind 0x{kind:02X}"))),
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+fn is_disconnect(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe
+    )
+}
+
+/// The socket and the two clocks, which is the port a transfer clocked by this side goes to.
+#[derive(Debug)]
+struct Connection {
+    stream: TcpStream,
+    /// Bytes received that do not make up a whole message yet.
+    buffer: Vec<u8>,
+    connected: bool,
+    time: u64,
+    peer_time: u64,
+    /// An error hit while exchanging a byte, returned once the step is over.
+    error: Option<io::Error>,
+}
+
+impl Connection {
+    fn send(&mut self, message: Message) -> io::Result<()> {
+        if !self.connected {
+            return Ok(());
+        }
+        match self.stream.write_all(&message.encode()) {
+            Err(error) if is_disconnect(&error) => {
+                self.connected = false;
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// The next message, waiting for one when `block` is set. `None` when there is none yet or
+    /// the other side has disconnected.
+    fn receive(&mut self, block: bool) -> io::Result<Option<Message>> {
+        loop {
+            if self.buffer.len() >= MESSAGE_SIZE {
+                let message = Message::decode(&self.buffer[..MESSAGE_SIZE]);
+                self.buffer.drain(..MESSAGE_SIZE);
+                return message.map(Some);
+            }
+            if !self.connected {
+                return Ok(None);
+            }
+            self.stream.set_nonblocking(!block)?;
+            let mut chunk = [0; 256];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => self.connected = false,
+                Ok(length) => self.buffer.extend_from_slice(&chunk[..length]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(None),
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) if is_disconnect(&error) => self.connected = false,
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    fn wait_for_reply(&mut self, byte: u8) -> io::Result<u8> {
+        self.send(Message::Transfer(self.time, byte))?;
+        loop {
+            match self.receive(true)? {
+                None => return Ok(0xFF),
+                Some(Message::Time(time)) => self.peer_time = self.peer_time.max(time),
+                // Both sides clocked a transfer; neither is waiting for the other's clock.
+                Some(Message::Transfer(time, _)) => {
+                    self.peer_time = self.peer_time.max(time);
+                    self.send(Message::Reply(self.time, 0xFF))?;
+                }
+                Some(Message::Reply(time, reply)) => {
+                    self.peer_time = self.peer_time.max(time);
+                    return Ok(reply);
+                }
+                Some(Message::Hello(_)) => return Err(invalid("unexpected link hello")),
+            }
+        }
+    }
+}
+
+impl LinkPort for Connection {
+    /// Waits for the other side to reach the same time and reply. Reads 0xFF once disconnected.
+    fn exchange(&mut self, byte: u8) -> u8 {
+        self.wait_for_reply(byte).unwrap_or_else(|error| {
+            self.error = Some(error);
+            0xFF
+        })
+    }
+}
+
+/// This end of a link cable to a Game Boy in another process, see the [module
+/// documentation](self). Step the local machine through it to keep the two in step.
+#[derive(Debug)]
+pub struct NetLink {
+    connection: Connection,
+    /// A transfer the other side clocked, waiting for this side to reach its time.
+    pending: Option<(u64, u8)>,
+    max_lead: u64,
+    next_report: u64,
+}
+
+impl NetLink {
+    /// Waits for the other side to connect to `address`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the I/O error when listening fails, or fails like [`NetLink::new`].
+    pub fn listen(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let (stream, _) = TcpListener::bind(address)?.accept()?;
+        Self::new(stream)
+    }
+
+    /// Connects to the other side listening on `address`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the I/O error when connecting fails, or fails like [`NetLink::new`].
+    pub fn connect(address: impl ToSocketAddrs) -> io::Result<Self> {
+        Self::new(TcpStream::connect(address)?)
+    }
+
+    /// Starts a link over a connected `stream` by exchanging hello messages.
+    ///
+    /// # Errors
+    ///
+    /// Returns the I/O error when talking to the other side fails, or an
+    /// [`ErrorKind::InvalidData`] error when it does not speak the same protocol version.
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        let mut connection = Connection {
+            stream,
+            buffer: Vec::new(),
+            connected: true,
+            time: 0,
+            peer_time: 0,
+            error: None,
+        };
+        connection.send(Message::Hello(PROTOCOL_VERSION))?;
+        match connection.receive(true)? {
+            Some(Message::Hello(PROTOCOL_VERSION)) => {}
+            Some(Message::Hello(version)) => {
+                return Err(invalid(&format!(
+                    "link protocol version {version} is not supported, expected version \
+                     {PROTOCOL_VERSION}"
+                )));
+            }
+            Some(_) => return Err(invalid("expected a link hello")),
+            None => return Err(ErrorKind::UnexpectedEof.into()),
+        }
+        Ok(Self {
+            connection,
+            pending: None,
+            max_lead: DEFAULT_MAX_LEAD,
+            next_report: 0,
+        })
+    }
+
+    /// Sets how far, in normal-clock cycles, this side may run ahead of the other. A shorter
+    /// lead times transfers more closely, a longer one stalls less on a slow connection.
+    #[must_use]
+    pub fn with_max_lead(mut self, cycles: u64) -> Self {
+        self.max_lead = cycles.max(4);
+        self
+    }
+
+    /// Whether the other side is still connected.
+    #[must_use]
+    pub const fn connected(&self) -> bool {
+        self.connection.connected
+    }
+
+    /// Normal-clock cycles run through the link since it was connected.
+    #[must_use]
+    pub const fn cycles(&self) -> u64 {
+        self.connection.time
+    }
+
+    fn handle(&mut self, message: Message, gameboy: &mut GameBoy) -> io::Result<()> {
+        match message {
+            Message::Time(time) => {
+                self.connection.peer_time = self.connection.peer_time.max(time);
+            }
+            Message::Transfer(time, byte) => {
+                self.connection.peer_time = self.connection.peer_time.max(time);
+                self.pending = Some((time, byte));
+                self.apply_pending(gameboy)?;
+            }
+            Message::Reply(..) => return Err(invalid("unexpected link reply")),
+            Message::Hello(_) => return Err(invalid("unexpected link hello")),
+        }
+        Ok(())
+    }
+
+    /// Shifts in the byte of a transfer the other side clocked once this side has caught up.
+    fn apply_pending(&mut self, gameboy: &mut GameBoy) -> io::Result<()> {
+        let Some((time, byte)) = self.pending else {
+            return Ok(());
+        };
+        if self.connection.time < time {
+            return Ok(());
+        }
+        self.pending = None;
+        let reply = gameboy.bus_mut().serial_mut().exchange(byte);
+        self.connection
+            .send(Message::Reply(self.connection.time, reply))
+    }
+
+    /// Stalls while this side is too far ahead of the other.
+    fn wait_for_peer(&mut self, gameboy: &mut GameBoy) -> io::Result<()> {
+        let ahead = |connection: &Connection, max_lead| {
+            connection.connected && connection.time >= connection.peer_time + max_lead
+        };
+        if !ahead(&self.connection, self.max_lead) {
+            return Ok(());
+        }
+        self.connection.send(Message::Time(self.connection.time))?;
+        while ahead(&self.connection, self.max_lead) {
+            if let Some(message) = self.connection.receive(true)? {
+                self.handle(message, gameboy)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Steps `gameboy` like [`GameBoy::step`], first waiting for the other side when this one
+    /// is too far ahead. Returns the cycles taken.
+    ///
+    /// # Errors
+    ///
+    /// Returns the I/O error when talking to the other side fails, or an
+    /// [`ErrorKind::InvalidData`] error when it breaks the protocol. The other side
+    /// disconnecting is not an error.
+    pub fn step(&mut self, gameboy: &mut GameBoy) -> io::Result<u32> {
+        self.wait_for_peer(gameboy)?;
+        let cycles = gameboy.step_linked(&mut self.connection);
+        if let Some(error) = self.connection.error.take() {
+            return Err(error);
+        }
+        self.connection.time += u64::from(cycles);
+        self.apply_pending(gameboy)?;
+        if self.connection.time >= self.next_report {
+            self.next_report = self.connection.time + self.max_lead / 4;
+            self.connection.send(Message::Time(self.connection.time))?;
+            while let Some(message) = self.connection.receive(false)? {
+                self.handle(message, gameboy)?;
+            }
+        }
+        Ok(cycles)
+    }
+
+    /// Runs `gameboy` for at least `cycles` normal-clock cycles, see [`NetLink::step`].
+    ///
+    /// # Errors
+    ///
+    /// Fails like [`NetLink::step`].
+    pub fn run_cycles(&mut self, gameboy: &mut GameBoy, cycles: u64) -> io::Result<u64> {
+        let start = self.connection.time;
+        while self.connection.time < start + cycles {
+            self.step(gameboy)?;
+        }
+        Ok(self.connection.time - start)
+    }
+
+    /// Runs a frame like [`GameBoy::run_frame_with`], see [`NetLink::step`].
+    ///
+    /// # Errors
+    ///
+    /// Fails like [`NetLink::step`].
+    pub fn run_frame_with(
+        &mut self,
+        gameboy: &mut GameBoy,
+        mut before_step: impl FnMut(&GameBoy),
+    ) -> io::Result<u64> {
+        gameboy.bus_mut().ppu_mut().take_frame_ready();
+        let start = self.connection.time;
+        while !gameboy.bus_mut().ppu_mut().take_frame_ready() {
+            before_step(gameboy);
+            self.step(gameboy)?;
+            if self.connection.time - start >= u64::from(CYCLES_PER_FRAME) {
+                break;
+            }
+        }
+        Ok(self.connection.time - start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::MemoryBus;
+    use crate::link::tests::transfer;
+    use std::thread;
+
+    /// Runs `first` and `second` for `cycles` on either end of a loopback link.
+    fn run_linked(mut first: GameBoy, mut second: GameBoy, cycles: u64) -> (GameBoy, GameBoy) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let other = thread::spawn(move || {
+            let mut link = NetLink::new(listener.accept().unwrap().0).unwrap();
+            link.run_cycles(&mut second, cycles).unwrap();
+            second
+        });
+        let mut link = NetLink::connect(address).unwrap().with_max_lead(512);
+        link.run_cycles(&mut first, cycles).unwrap();
+        drop(link);
+        (first, other.join().unwrap())
+    }
+
+    #[test]
+    fn test_loopback_transfer() {
+        let (first, second) = run_linked(transfer(0x42, 0x81), transfer(0x99, 0x80), 16_384);
+        for (gameboy, received) in [(&first, 0x99), (&second, 0x42)] {
+            assert_eq!(received, gameboy.bus().peek(0xFF01));
+            assert_eq!(0, gameboy.bus().peek(0xFF02) & 0x80);
+        }
+
+        let (first, second) = run_linked(transfer(0x42, 0x81), transfer(0x99, 0x81), 16_384);
+        assert_eq!(0xFF, first.bus().peek(0xFF01));
+        assert_eq!(0xFF, second.bus().peek(0xFF01));
+    }
+
+    #[test]
+    fn test_runs_on_alone_after_disconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let other = thread::spawn(move || drop(NetLink::new(listener.accept().unwrap().0)));
+        let mut link = NetLink::connect(address).unwrap();
+        other.join().unwrap();
+        let mut gameboy = transfer(0x42, 0x81);
+        assert!(link.run_cycles(&mut gameboy, 16_384).unwrap() >= 16_384);
+        assert!(!link.connected());
+        assert_eq!(0xFF, gameboy.bus().peek(0xFF01));
+    }
+
+    #[test]
+    fn test_rejects_other_protocol_versions() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let other = thread::spawn(move || {
+            let mut stream = listener.accept().unwrap().0;
+            stream
+                .write_all(&Message::Hello(PROTOCOL_VERSION + 1).encode())
+                .unwrap();
+        });
+        let error = NetLink::connect(address).unwrap_err();
+        other.join().unwrap();
+        assert_eq!(ErrorKind::InvalidData, error.kind());
+    }
+}
@@ -0,0 +1,774 @@
+use crate::compatibility::CompatibilityPalette;
+use crate::memory_map::{
+    BG_COLOR_PALETTE_DATA_REGISTER_INDEX, BG_COLOR_PALETTE_INDEX_REGISTER_INDEX,
+    BG_PALETTE_REGISTER_INDEX, LCDC_REGISTER_INDEX, LYC_REGISTER_INDEX, LY_REGISTER_INDEX,
+    OBJ_COLOR_PALETTE_DATA_REGISTER_INDEX, OBJ_COLOR_PALETTE_INDEX_REGISTER_INDEX,
+    OBJ_PALETTE_0_REGISTER_INDEX, OBJ_PALETTE_1_REGISTER_INDEX, OBJ_PRIORITY_MODE_REGISTER_INDEX,
+    SCROLL_X_REGISTER_INDEX, SCROLL_Y_REGISTER_INDEX, SPRITE_ATTRIB_RANGE, STAT_REGISTER_INDEX,
+    VIDEO_RAM_RANGE, VRAM_BANK_REGISTER_INDEX, WINDOW_X_REGISTER_INDEX, WINDOW_Y_REGISTER_INDEX,
+};
+use crate::state::{StateError, StateReader, StateWriter};
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+const DOTS_PER_LINE: u32 = 456;
+const OAM_SCAN_DOTS: u32 = 80;
+const DRAWING_DOTS: u32 = 172;
+const VISIBLE_LINES: u8 = 144;
+const LINES_PER_FRAME: u8 = 154;
+const MAX_SPRITES_PER_LINE: usize = 10;
+const VRAM_BANK_SIZE: usize = 0x2000;
+/// Bytes of CGB palette RAM for each of the background and object palettes: eight palettes of
+/// four little-endian 15-bit colours.
+const PALETTE_RAM_SIZE: usize = 64;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PpuMode {
+    #[default]
+    HBlank,
+    VBlank,
+    OamScan,
+    Drawing,
+}
+
+impl PpuMode {
+    const fn bits(self) -> u8 {
+        match self {
+            Self::HBlank => 0,
+            Self::VBlank => 1,
+            Self::OamScan => 2,
+            Self::Drawing => 3,
+        }
+    }
+}
+
+/// A background or window pixel, before the palette is applied.
+#[derive(Debug, Clone, Copy, Default)]
+struct BackgroundPixel {
+    color: u8,
+    /// The CGB palette number from the tile attributes.
+    palette: u8,
+    /// Whether the tile attributes give the background priority over objects.
+    priority: bool,
+}
+
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone)]
+pub struct Ppu {
+    cgb: bool,
+    /// A DMG game on CGB hardware: the DMG palettes pick colours from palette RAM.
+    compatibility: bool,
+    /// Two banks in CGB mode, only the first is used otherwise.
+    vram: Vec<u8>,
+    vram_bank: u8,
+    oam: Vec<u8>,
+    lcdc: u8,
+    stat: u8,
+    scy: u8,
+    scx: u8,
+    ly: u8,
+    lyc: u8,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    wy: u8,
+    wx: u8,
+    window_line: u8,
+    bg_palettes: [u8; PALETTE_RAM_SIZE],
+    obj_palettes: [u8; PALETTE_RAM_SIZE],
+    bg_palette_index: u8,
+    obj_palette_index: u8,
+    obj_priority_mode: u8,
+    dots: u32,
+    mode: PpuMode,
+    stat_line: bool,
+    framebuffer: Vec<u8>,
+    color_framebuffer: Vec<u16>,
+    frame_ready: bool,
+    vblank_interrupt: bool,
+    stat_interrupt: bool,
+    hblank_started: bool,
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self {
+            cgb: false,
+            compatibility: false,
+            vram: vec![0; VRAM_BANK_SIZE * 2],
+            vram_bank: 0,
+            oam: vec![0; SPRITE_ATTRIB_RANGE.len()],
+            lcdc: 0,
+            stat: 0,
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0,
+            obp0: 0,
+            obp1: 0,
+            wy: 0,
+            wx: 0,
+            window_line: 0,
+            bg_palettes: [0; PALETTE_RAM_SIZE],
+            obj_palettes: [0; PALETTE_RAM_SIZE],
+            bg_palette_index: 0,
+            obj_palette_index: 0,
+            obj_priority_mode: 0,
+            dots: 0,
+            mode: PpuMode::HBlank,
+            stat_line: false,
+            framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            color_framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            frame_ready: false,
+            vblank_interrupt: false,
+            stat_interrupt: false,
+            hblank_started: false,
+        }
+    }
+}
+
+impl Ppu {
+    /// A PPU in CGB mode when `cgb` is set, with VRAM banking, tile attributes and colour
+    /// palettes.
+    #[must_use]
+    pub fn new(cgb: bool) -> Self {
+        Self {
+            cgb,
+            ..Self::default()
+        }
+    }
+
+    /// Resets to the state the boot ROM leaves, keeping the mode. The CGB boot ROM sets every
+    /// background colour to white.
+    pub fn reset_post_boot(&mut self) {
+        let bg_palettes = if self.cgb {
+            [0xFF; PALETTE_RAM_SIZE]
+        } else {
+            [0; PALETTE_RAM_SIZE]
+        };
+        *self = Self {
+            lcdc: 0x91,
+            bgp: 0xFC,
+            mode: PpuMode::OamScan,
+            bg_palettes,
+            ..Self::new(self.cgb)
+        };
+    }
+
+    #[must_use]
+    pub const fn cgb(&self) -> bool {
+        self.cgb
+    }
+
+    /// Whether a DMG game is coloured as on CGB hardware, see
+    /// [`Ppu::set_compatibility_palette`].
+    #[must_use]
+    pub const fn compatibility(&self) -> bool {
+        self.compatibility
+    }
+
+    /// Colours a DMG game the way the CGB boot ROM does: BGP picks from the first background
+    /// palette, OBP0 and OBP1 from the first two object palettes. Ignored in CGB mode.
+    pub fn set_compatibility_palette(&mut self, palette: &CompatibilityPalette) {
+        if self.cgb {
+            return;
+        }
+        self.compatibility = true;
+        let (obj0, obj1) = self.obj_palettes.split_at_mut(8);
+        let layers = [
+            (&mut self.bg_palettes[..8], palette.background),
+            (obj0, palette.obj0),
+            (&mut obj1[..8], palette.obj1),
+        ];
+        for (ram, colors) in layers {
+            for (bytes, color) in ram.chunks_exact_mut(2).zip(colors) {
+                bytes.copy_from_slice(&color.to_le_bytes());
+            }
+        }
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.vram);
+        state.bytes(&self.oam);
+        for value in [
+            self.lcdc,
+            self.stat,
+            self.scy,
+            self.scx,
+            self.ly,
+            self.lyc,
+            self.bgp,
+            self.obp0,
+            self.obp1,
+            self.wy,
+            self.wx,
+            self.window_line,
+        ] {
+            state.u8(value);
+        }
+        state.u32(self.dots);
+        state.u8(self.mode.bits());
+        state.bool(self.stat_line);
+        state.bytes(&self.framebuffer);
+        state.bool(self.frame_ready);
+        state.bool(self.vblank_interrupt);
+        state.bool(self.stat_interrupt);
+        state.bool(self.hblank_started);
+        state.bool(self.cgb);
+        state.bool(self.compatibility);
+        state.u8(self.vram_bank);
+        state.bytes(&self.bg_palettes);
+        state.bytes(&self.obj_palettes);
+        state.u8(self.bg_palette_index);
+        state.u8(self.obj_palette_index);
+        state.u8(self.obj_priority_mode);
+        for &color in &self.color_framebuffer {
+            state.u16(color);
+        }
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.bytes_into(&mut self.vram, "VRAM size")?;
+        state.bytes_into(&mut self.oam, "OAM size")?;
+        for value in [
+            &mut self.lcdc,
+            &mut self.stat,
+            &mut self.scy,
+            &mut self.scx,
+            &mut self.ly,
+            &mut self.lyc,
+            &mut self.bgp,
+            &mut self.obp0,
+            &mut self.obp1,
+            &mut self.wy,
+            &mut self.wx,
+            &mut self.window_line,
+        ] {
+            *value = state.u8()?;
+        }
+        self.dots = state.u32()?;
+        self.mode = match state.u8()? {
+            0 => PpuMode::HBlank,
+            1 => PpuMode::VBlank,
+            2 => PpuMode::OamScan,
+            3 => PpuMode::Drawing,
+            _ => return Err(StateError::Invalid("PPU mode")),
+        };
+        self.stat_line = state.bool()?;
+        state.bytes_into(&mut self.framebuffer, "framebuffer size")?;
+        self.frame_ready = state.bool()?;
+        self.vblank_interrupt = state.bool()?;
+        self.stat_interrupt = state.bool()?;
+        self.hblank_started = state.bool()?;
+        if state.bool()? != self.cgb {
+            return Err(StateError::Invalid("CGB mode"));
+        }
+        if state.bool()? != self.compatibility {
+            return Err(StateError::Invalid("CGB compatibility mode"));
+        }
+        self.vram_bank = state.u8()? & 0x01;
+        state.bytes_into(&mut self.bg_palettes, "palette RAM size")?;
+        state.bytes_into(&mut self.obj_palettes, "palette RAM size")?;
+        self.bg_palette_index = state.u8()? & 0xBF;
+        self.obj_palette_index = state.u8()? & 0xBF;
+        self.obj_priority_mode = state.u8()? & 0x01;
+        for color in &mut self.color_framebuffer {
+            *color = state.u16()? & 0x7FFF;
+        }
+        Ok(())
+    }
+
+    /// Shades 0 to 3 after the DMG palettes, or in CGB mode the colour numbers before the
+    /// colour palettes.
+    #[must_use]
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    /// 15-bit colours, red in the low bits, written in CGB mode and for DMG games coloured
+    /// on CGB hardware.
+    #[must_use]
+    pub fn color_framebuffer(&self) -> &[u16] {
+        &self.color_framebuffer
+    }
+
+    #[must_use]
+    pub const fn ly(&self) -> u8 {
+        self.ly
+    }
+
+    #[must_use]
+    pub const fn mode(&self) -> PpuMode {
+        self.mode
+    }
+
+    pub fn take_frame_ready(&mut self) -> bool {
+        std::mem::take(&mut self.frame_ready)
+    }
+
+    pub fn take_vblank_interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.vblank_interrupt)
+    }
+
+    pub fn take_stat_interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.stat_interrupt)
+    }
+
+    /// Whether a visible line entered HBlank since the last call, for HBlank DMA.
+    pub fn take_hblank_started(&mut self) -> bool {
+        std::mem::take(&mut self.hblank_started)
+    }
+
+    const fn lcd_enabled(&self) -> bool {
+        self.lcdc & 0x80 != 0
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        if !self.lcd_enabled() {
+            return;
+        }
+
+        for _ in 0..cycles {
+            self.dots += 1;
+            if self.ly < VISIBLE_LINES {
+                if self.dots == OAM_SCAN_DOTS {
+                    self.mode = PpuMode::Drawing;
+                } else if self.dots == OAM_SCAN_DOTS + DRAWING_DOTS {
+                    self.render_scanline();
+                    self.mode = PpuMode::HBlank;
+                    self.hblank_started = true;
+                }
+            }
+
+            if self.dots == DOTS_PER_LINE {
+                self.dots = 0;
+                self.next_line();
+            }
+
+            self.update_stat_line();
+        }
+    }
+
+    const fn next_line(&mut self) {
+        self.ly = (self.ly + 1) % LINES_PER_FRAME;
+        if self.ly == VISIBLE_LINES {
+            self.mode = PpuMode::VBlank;
+            self.vblank_interrupt = true;
+            self.frame_ready = true;
+        } else if self.ly == 0 {
+            self.window_line = 0;
+            self.mode = PpuMode::OamScan;
+        } else if self.ly < VISIBLE_LINES {
+            self.mode = PpuMode::OamScan;
+        }
+    }
+
+    fn update_stat_line(&mut self) {
+        let line = (self.ly == self.lyc && self.stat & 0x40 != 0)
+            || (self.mode == PpuMode::HBlank && self.stat & 0x08 != 0)
+            || (self.mode == PpuMode::VBlank && self.stat & 0x10 != 0)
+            || (self.mode == PpuMode::OamScan && self.stat & 0x20 != 0);
+        if line && !self.stat_line {
+            self.stat_interrupt = true;
+        }
+        self.stat_line = line;
+    }
+
+    fn tile_row(&self, bank: usize, tile_data_address: u16, row: u8) -> (u8, u8) {
+        let address = bank * VRAM_BANK_SIZE
+            + usize::from(tile_data_address + u16::from(row) * 2 - VIDEO_RAM_RANGE.start);
+        (self.vram[address], self.vram[address + 1])
+    }
+
+    fn background_tile_address(&self, tile_index: u8) -> u16 {
+        if self.lcdc & 0x10 != 0 {
+            0x8000 + u16::from(tile_index) * 16
+        } else {
+            0x9000u16.wrapping_add_signed(i16::from(tile_index.cast_signed()) * 16)
+        }
+    }
+
+    /// The offset into the first VRAM bank of the tile map entry covering `x`, `y`. In CGB
+    /// mode the same offset in the second bank holds the tile's attributes.
+    fn map_offset(map_base: u16, x: u8, y: u8) -> usize {
+        usize::from(map_base + u16::from(y / 8) * 32 + u16::from(x / 8) - VIDEO_RAM_RANGE.start)
+    }
+
+    const fn shade(palette: u8, color: u8) -> u8 {
+        (palette >> (color * 2)) & 0x03
+    }
+
+    fn palette_color(palettes: &[u8; PALETTE_RAM_SIZE], palette: u8, color: u8) -> u16 {
+        let index = usize::from(palette & 0x07) * 8 + usize::from(color) * 2;
+        u16::from_le_bytes([palettes[index], palettes[index + 1]]) & 0x7FFF
+    }
+
+    fn background_pixel(&self, map: u16, x: u8, y: u8) -> BackgroundPixel {
+        let offset = Self::map_offset(map, x, y);
+        let tile = self.vram[offset];
+        let attributes = if self.cgb {
+            self.vram[VRAM_BANK_SIZE + offset]
+        } else {
+            0
+        };
+        let row = if attributes & 0x40 != 0 {
+            7 - y % 8
+        } else {
+            y % 8
+        };
+        let bank = usize::from((attributes >> 3) & 0x01);
+        let (low, high) = self.tile_row(bank, self.background_tile_address(tile), row);
+        let bit = if attributes & 0x20 != 0 {
+            x % 8
+        } else {
+            7 - x % 8
+        };
+        BackgroundPixel {
+            color: ((low >> bit) & 1) | (((high >> bit) & 1) << 1),
+            palette: attributes & 0x07,
+            priority: attributes & 0x80 != 0,
+        }
+    }
+
+    fn render_scanline(&mut self) {
+        let mut background = [BackgroundPixel::default(); SCREEN_WIDTH];
+        let line = self.ly;
+        let row_start = usize::from(line) * SCREEN_WIDTH;
+
+        // In CGB mode LCDC bit 0 only takes priority away from the background, see
+        // `render_sprites`.
+        if self.cgb || self.lcdc & 0x01 != 0 {
+            let bg_map = if self.lcdc & 0x08 != 0 {
+                0x9C00
+            } else {
+                0x9800
+            };
+            let window_map = if self.lcdc & 0x40 != 0 {
+                0x9C00
+            } else {
+                0x9800
+            };
+            let window_visible = self.lcdc & 0x20 != 0 && self.wy <= line && self.wx <= 166;
+            let mut window_drawn = false;
+
+            for (x, pixel) in (0u8..).zip(background.iter_mut()) {
+                *pixel = if window_visible && x + 7 >= self.wx {
+                    window_drawn = true;
+                    self.background_pixel(window_map, x + 7 - self.wx, self.window_line)
+                } else {
+                    self.background_pixel(
+                        bg_map,
+                        x.wrapping_add(self.scx),
+                        line.wrapping_add(self.scy),
+                    )
+                };
+            }
+
+            if window_drawn {
+                self.window_line += 1;
+            }
+        }
+
+        for (x, pixel) in background.iter().enumerate() {
+            if self.cgb {
+                self.framebuffer[row_start + x] = pixel.color;
+                self.color_framebuffer[row_start + x] =
+                    Self::palette_color(&self.bg_palettes, pixel.palette, pixel.color);
+            } else {
+                let shade = Self::shade(self.bgp, pixel.color);
+                self.framebuffer[row_start + x] = shade;
+                if self.compatibility {
+                    self.color_framebuffer[row_start + x] =
+                        Self::palette_color(&self.bg_palettes, 0, shade);
+                }
+            }
+        }
+
+        if self.lcdc & 0x02 != 0 {
+            self.render_sprites(line, &background);
+        }
+    }
+
+    /// Draws the objects on `line`. On the DMG, and in CGB mode when OPRI bit 0 is set, the
+    /// object with the lowest X coordinate wins where objects overlap; otherwise the first in
+    /// OAM does.
+    fn render_sprites(&mut self, line: u8, background: &[BackgroundPixel; SCREEN_WIDTH]) {
+        let height: u8 = if self.lcdc & 0x04 != 0 { 16 } else { 8 };
+        let mut sprites: Vec<(usize, [u8; 4])> = self
+            .oam
+            .chunks_exact(4)
+            .enumerate()
+            .filter(|(_, sprite)| {
+                let top = i16::from(sprite[0]) - 16;
+                (top..top + i16::from(height)).contains(&i16::from(line))
+            })
+            .take(MAX_SPRITES_PER_LINE)
+            .map(|(index, sprite)| (index, [sprite[0], sprite[1], sprite[2], sprite[3]]))
+            .collect();
+        if !self.cgb || self.obj_priority_mode & 0x01 != 0 {
+            sprites.sort_by_key(|(index, sprite)| (sprite[1], *index));
+        }
+
+        let row_start = usize::from(line) * SCREEN_WIDTH;
+        let mut drawn = [false; SCREEN_WIDTH];
+        for (_, sprite) in sprites {
+            let attributes = sprite[3];
+            let mut row = line.wrapping_add(16).wrapping_sub(sprite[0]);
+            if attributes & 0x40 != 0 {
+                row = (height - 1) - row;
+            }
+            let tile = if height == 16 {
+                sprite[2] & 0xFE
+            } else {
+                sprite[2]
+            };
+            let bank = if self.cgb {
+                usize::from((attributes >> 3) & 0x01)
+            } else {
+                0
+            };
+            let (low, high) = self.tile_row(bank, 0x8000 + u16::from(tile) * 16, row);
+            let (palette, color_palette) = if attributes & 0x10 != 0 {
+                (self.obp1, 1)
+            } else {
+                (self.obp0, 0)
+            };
+
+            for pixel in 0..8u8 {
+                let x = i16::from(sprite[1]) - 8 + i16::from(pixel);
+                let Ok(x) = usize::try_from(x) else { continue };
+                if x >= SCREEN_WIDTH || drawn[x] {
+                    continue;
+                }
+                let bit = if attributes & 0x20 != 0 {
+                    pixel
+                } else {
+                    7 - pixel
+                };
+                let color = ((low >> bit) & 1) | (((high >> bit) & 1) << 1);
+                if color == 0 {
+                    continue;
+                }
+                drawn[x] = true;
+                let behind = background[x].color != 0
+                    && (attributes & 0x80 != 0 || (self.cgb && background[x].priority));
+                if behind && (!self.cgb || self.lcdc & 0x01 != 0) {
+                    continue;
+                }
+                if self.cgb {
+                    self.framebuffer[row_start + x] = color;
+                    self.color_framebuffer[row_start + x] =
+                        Self::palette_color(&self.obj_palettes, attributes & 0x07, color);
+                } else {
+                    let shade = Self::shade(palette, color);
+                    self.framebuffer[row_start + x] = shade;
+                    if self.compatibility {
+                        self.color_framebuffer[row_start + x] =
+                            Self::palette_color(&self.obj_palettes, color_palette, shade);
+                    }
+                }
+            }
+        }
+    }
+
+    fn vram_offset(&self, address: u16) -> usize {
+        usize::from(self.vram_bank) * VRAM_BANK_SIZE + usize::from(address - VIDEO_RAM_RANGE.start)
+    }
+
+    #[must_use]
+    pub fn read_vram(&self, address: u16) -> u8 {
+        self.vram[self.vram_offset(address)]
+    }
+
+    pub fn write_vram(&mut self, address: u16, value: u8) {
+        let offset = self.vram_offset(address);
+        self.vram[offset] = value;
+    }
+
+    #[must_use]
+    pub fn read_oam(&self, address: u16) -> u8 {
+        self.oam[(address - SPRITE_ATTRIB_RANGE.start) as usize]
+    }
+
+    pub fn write_oam(&mut self, address: u16, value: u8) {
+        self.oam[(address - SPRITE_ATTRIB_RANGE.start) as usize] = value;
+    }
+
+    #[must_use]
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            LCDC_REGISTER_INDEX => self.lcdc,
+            STAT_REGISTER_INDEX => {
+                let coincidence = if self.ly == self.lyc { 0x04 } else { 0 };
+                let mode = if self.lcd_enabled() {
+                    self.mode.bits()
+                } else {
+                    0
+                };
+                0x80 | self.stat | coincidence | mode
+            }
+            SCROLL_Y_REGISTER_INDEX => self.scy,
+            SCROLL_X_REGISTER_INDEX => self.scx,
+            LY_REGISTER_INDEX => self.ly,
+            LYC_REGISTER_INDEX => self.lyc,
+            BG_PALETTE_REGISTER_INDEX => self.bgp,
+            OBJ_PALETTE_0_REGISTER_INDEX => self.obp0,
+            OBJ_PALETTE_1_REGISTER_INDEX => self.obp1,
+            WINDOW_Y_REGISTER_INDEX => self.wy,
+            WINDOW_X_REGISTER_INDEX => self.wx,
+            _ if !self.cgb => 0xFF,
+            VRAM_BANK_REGISTER_INDEX => 0xFE | self.vram_bank,
+            BG_COLOR_PALETTE_INDEX_REGISTER_INDEX => 0x40 | self.bg_palette_index,
+            BG_COLOR_PALETTE_DATA_REGISTER_INDEX => {
+                self.bg_palettes[usize::from(self.bg_palette_index & 0x3F)]
+            }
+            OBJ_COLOR_PALETTE_INDEX_REGISTER_INDEX => 0x40 | self.obj_palette_index,
+            OBJ_COLOR_PALETTE_DATA_REGISTER_INDEX => {
+                self.obj_palettes[usize::from(self.obj_palette_index & 0x3F)]
+            }
+            OBJ_PRIORITY_MODE_REGISTER_INDEX => 0xFE | self.obj_priority_mode,
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes a byte of palette RAM and advances the index when its bit 7 asks for it.
+    fn write_palette(palettes: &mut [u8; PALETTE_RAM_SIZE], index: &mut u8, value: u8) {
+        palettes[usize::from(*index & 0x3F)] = value;
+        if *index & 0x80 != 0 {
+            *index = 0x80 | (index.wrapping_add(1) & 0x3F);
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            LCDC_REGISTER_INDEX => {
+                let was_enabled = self.lcd_enabled();
+                self.lcdc = value;
+                if was_enabled && !self.lcd_enabled() {
+                    self.ly = 0;
+                    self.dots = 0;
+                    self.window_line = 0;
+                    self.mode = PpuMode::HBlank;
+                } else if !was_enabled && self.lcd_enabled() {
+                    self.mode = PpuMode::OamScan;
+                }
+            }
+            STAT_REGISTER_INDEX => self.stat = value & 0x78,
+            SCROLL_Y_REGISTER_INDEX => self.scy = value,
+            SCROLL_X_REGISTER_INDEX => self.scx = value,
+            LYC_REGISTER_INDEX => self.lyc = value,
+            BG_PALETTE_REGISTER_INDEX => self.bgp = value,
+            OBJ_PALETTE_0_REGISTER_INDEX => self.obp0 = value,
+            OBJ_PALETTE_1_REGISTER_INDEX => self.obp1 = value,
+            WINDOW_Y_REGISTER_INDEX => self.wy = value,
+            WINDOW_X_REGISTER_INDEX => self.wx = value,
+            _ if !self.cgb => {}
+            VRAM_BANK_REGISTER_INDEX => self.vram_bank = value & 0x01,
+            BG_COLOR_PALETTE_INDEX_REGISTER_INDEX => self.bg_palette_index = value & 0xBF,
+            BG_COLOR_PALETTE_DATA_REGISTER_INDEX => {
+                Self::write_palette(&mut self.bg_palettes, &mut self.bg_palette_index, value);
+            }
+            OBJ_COLOR_PALETTE_INDEX_REGISTER_INDEX => self.obj_palette_index = value & 0xBF,
+            OBJ_COLOR_PALETTE_DATA_REGISTER_INDEX => {
+                Self::write_palette(&mut self.obj_palettes, &mut self.obj_palette_index, value);
+            }
+            OBJ_PRIORITY_MODE_REGISTER_INDEX => self.obj_priority_mode = value & 0x01,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_takes_70224_cycles() {
+        let mut ppu = Ppu::default();
+        ppu.reset_post_boot();
+        ppu.tick(DOTS_PER_LINE * u32::from(VISIBLE_LINES) - 1);
+        assert!(!ppu.take_frame_ready());
+        ppu.tick(1);
+        assert!(ppu.take_frame_ready());
+        assert!(ppu.take_vblank_interrupt());
+        ppu.tick(DOTS_PER_LINE * 10);
+        assert_eq!(0, ppu.ly());
+        assert_eq!(PpuMode::OamScan, ppu.mode());
+    }
+
+    #[test]
+    fn test_background_tile_is_rendered() {
+        let mut ppu = Ppu::default();
+        ppu.reset_post_boot();
+        ppu.write_vram(0x8010, 0xFF);
+        ppu.write_vram(0x8011, 0xFF);
+        ppu.write_vram(0x9800, 0x01);
+        ppu.tick(DOTS_PER_LINE);
+        assert_eq!(&[3; 8], &ppu.framebuffer()[..8]);
+        assert_eq!(0, ppu.framebuffer()[8]);
+    }
+
+    #[test]
+    fn test_cgb_attributes_and_palettes() {
+        let mut ppu = Ppu::new(true);
+        ppu.reset_post_boot();
+        // Tile 1 in bank 1: the leftmost pixel colour 1, the rest colour 3.
+        ppu.write(VRAM_BANK_REGISTER_INDEX, 1);
+        ppu.write_vram(0x8010, 0xFF);
+        ppu.write_vram(0x8011, 0x7F);
+        // Palette 2, tile bank 1, flipped horizontally.
+        ppu.write_vram(0x9800, 0x2A);
+        ppu.write(VRAM_BANK_REGISTER_INDEX, 0);
+        ppu.write_vram(0x9800, 0x01);
+        ppu.write(BG_COLOR_PALETTE_INDEX_REGISTER_INDEX, 0x80 | (2 * 8 + 2));
+        for byte in [0xE0, 0x03, 0xFF, 0x7F, 0x1F, 0x00] {
+            ppu.write(BG_COLOR_PALETTE_DATA_REGISTER_INDEX, byte);
+        }
+        assert_eq!(
+            0xC0 | (3 * 8),
+            ppu.read(BG_COLOR_PALETTE_INDEX_REGISTER_INDEX)
+        );
+
+        ppu.tick(DOTS_PER_LINE);
+        assert_eq!(&[3; 7], &ppu.framebuffer()[..7]);
+        assert_eq!(1, ppu.framebuffer()[7]);
+        assert_eq!(&[0x001F; 7], &ppu.color_framebuffer()[..7]);
+        assert_eq!(0x03E0, ppu.color_framebuffer()[7]);
+        assert_eq!(0x7FFF, ppu.color_framebuffer()[8]);
+    }
+
+    #[test]
+    fn test_cgb_object_priority() {
+        let mut ppu = Ppu::new(true);
+        ppu.reset_post_boot();
+        ppu.write(LCDC_REGISTER_INDEX, 0x93);
+        ppu.write_vram(0x8000, 0xFF);
+        ppu.write_vram(0x8010, 0xFF);
+        ppu.write_vram(0x8011, 0xFF);
+        // Two overlapping objects; the second is further left but later in OAM.
+        for (address, value) in [(0xFE00, 16), (0xFE01, 12), (0xFE02, 0), (0xFE03, 0x01)] {
+            ppu.write_oam(address, value);
+        }
+        for (address, value) in [(0xFE04, 16), (0xFE05, 8), (0xFE06, 1), (0xFE07, 0x02)] {
+            ppu.write_oam(address, value);
+        }
+        ppu.write(OBJ_COLOR_PALETTE_INDEX_REGISTER_INDEX, 0x80);
+        for _ in 0..3 {
+            for byte in [0, 0, 0x1F, 0x00, 0, 0, 0xE0, 0x03] {
+                ppu.write(OBJ_COLOR_PALETTE_DATA_REGISTER_INDEX, byte);
+            }
+        }
+
+        ppu.tick(DOTS_PER_LINE);
+        // OAM order wins in CGB mode, so object 0 covers object 1 from x = 4.
+        assert_eq!(0x03E0, ppu.color_framebuffer()[0]);
+        assert_eq!(0x001F, ppu.color_framebuffer()[4]);
+        assert_eq!(1, ppu.framebuffer()[4]);
+
+        ppu.write(OBJ_PRIORITY_MODE_REGISTER_INDEX, 1);
+        ppu.tick(DOTS_PER_LINE * u32::from(LINES_PER_FRAME));
+        assert_eq!(0x03E0, ppu.color_framebuffer()[4]);
+    }
+}
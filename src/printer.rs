@@ -0,0 +1,420 @@
+//! The Game Boy Printer, plugged into the serial port as a [`LinkPort`].
+//!
+//! The Game Boy clocks packets to the printer: the magic bytes 0x88 0x33, a command, a
+//! compression flag, a little-endian data length, the data and a little-endian checksum summing
+//! every byte from the command on. It then clocks two more bytes, to which the printer replies
+//! with 0x81 to say it is there and with its status. It replies 0x00 to everything else.
+//!
+//! Image data comes in bands of 20x2 tiles, optionally run-length compressed, and is printed as
+//! one [`Strip`] per print command.
+
+use std::io;
+use std::path::Path;
+
+use crate::link::LinkPort;
+use crate::screenshot::{self, Palette};
+
+/// Printed pixels per line.
+pub const STRIP_WIDTH: usize = 160;
+
+const MAGIC: [u8; 2] = [0x88, 0x33];
+const ALIVE: u8 = 0x81;
+const TILE_SIZE: usize = 16;
+const TILES_PER_LINE: usize = STRIP_WIDTH / 8;
+/// Bytes the printer can hold before printing, nine bands and a bit.
+const BUFFER_SIZE: usize = 0x2380;
+/// The buffer counts as full from this many bytes on, the eight bands of a Game Boy screen.
+const FULL_SIZE: usize = 0x2280;
+/// Status replies that report the printer busy after a print.
+const BUSY_REPLIES: u8 = 4;
+
+const INITIALIZE: u8 = 0x01;
+const PRINT: u8 = 0x02;
+const DATA: u8 = 0x04;
+const BREAK: u8 = 0x08;
+const STATUS: u8 = 0x0F;
+
+const CHECKSUM_ERROR: u8 = 0x01;
+const BUSY: u8 = 0x02;
+const IMAGE_DATA_FULL: u8 = 0x04;
+const UNPROCESSED_DATA: u8 = 0x08;
+const PACKET_ERROR: u8 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Receive {
+    Magic(usize),
+    Command,
+    Compression,
+    Length(usize),
+    Data,
+    Checksum(usize),
+    Alive,
+    Status,
+}
+
+/// An image printed by one print command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Strip {
+    /// Shades 0 (white) to 3 (black), [`STRIP_WIDTH`] pixels per line.
+    pub shades: Vec<u8>,
+    /// Lines of paper fed before the image, in units of the printer's margin setting.
+    pub margin_before: u8,
+    pub margin_after: u8,
+    /// Darkness from 0x00, 25% lighter, to 0x7F, 25% darker.
+    pub exposure: u8,
+}
+
+impl Strip {
+    #[must_use]
+    pub const fn height(&self) -> usize {
+        self.shades.len() / STRIP_WIDTH
+    }
+
+    /// Encodes the strip as a PNG, see [`screenshot::framebuffer_to_png`].
+    ///
+    /// # Panics
+    ///
+    /// Panics when `scale` is zero.
+    #[must_use]
+    pub fn to_png(&self, palette: Palette, scale: usize) -> Vec<u8> {
+        screenshot::framebuffer_to_png(&self.shades, palette, scale)
+    }
+
+    /// Writes the strip to `path` as a PNG, see [`Strip::to_png`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the underlying I/O error when the file cannot be written.
+    pub fn save_png(
+        &self,
+        path: impl AsRef<Path>,
+        palette: Palette,
+        scale: usize,
+    ) -> io::Result<()> {
+        std::fs::write(path, self.to_png(palette, scale))
+    }
+}
+
+/// Expands run-length compressed data: a control byte with bit 7 set repeats the next byte
+/// (control & 0x7F) + 2 times, one without copies the next control + 1 bytes.
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut bytes = data.iter();
+    while let Some(&control) = bytes.next() {
+        if control & 0x80 != 0 {
+            if let Some(&byte) = bytes.next() {
+                output.extend(std::iter::repeat_n(byte, usize::from(control & 0x7F) + 2));
+            }
+        } else {
+            output.extend(bytes.by_ref().take(usize::from(control) + 1));
+        }
+    }
+    output
+}
+
+#[derive(Debug, Clone)]
+pub struct Printer {
+    receive: Receive,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    status: u8,
+    busy_replies: u8,
+    /// Image data received since the last print.
+    image: Vec<u8>,
+    strips: Vec<Strip>,
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        Self {
+            receive: Receive::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            status: 0,
+            busy_replies: 0,
+            image: Vec::new(),
+            strips: Vec::new(),
+        }
+    }
+}
+
+impl Printer {
+    /// The strips printed so far.
+    #[must_use]
+    pub fn strips(&self) -> &[Strip] {
+        &self.strips
+    }
+
+    pub fn take_strips(&mut self) -> Vec<Strip> {
+        std::mem::take(&mut self.strips)
+    }
+
+    const fn status(&mut self) -> u8 {
+        if self.busy_replies > 0 {
+            self.busy_replies -= 1;
+            return self.status | BUSY;
+        }
+        self.status
+    }
+
+    fn receive(&mut self, byte: u8) {
+        self.receive = match self.receive {
+            Receive::Magic(index) if byte == MAGIC[index] => {
+                if index + 1 == MAGIC.len() {
+                    self.data.clear();
+                    Receive::Command
+                } else {
+                    Receive::Magic(index + 1)
+                }
+            }
+            Receive::Magic(_) if byte == MAGIC[0] => Receive::Magic(1),
+            Receive::Magic(_) | Receive::Status => Receive::Magic(0),
+            Receive::Command => {
+                self.command = byte;
+                self.checksum = u16::from(byte);
+                Receive::Compression
+            }
+            Receive::Compression => {
+                self.compressed = byte & 0x01 != 0;
+                self.checksum = self.checksum.wrapping_add(u16::from(byte));
+                Receive::Length(0)
+            }
+            Receive::Length(index) => {
+                let [low, high] = self.length.to_le_bytes();
+                self.length = if index == 0 {
+                    u16::from_le_bytes([byte, high])
+                } else {
+                    u16::from_le_bytes([low, byte])
+                };
+                self.checksum = self.checksum.wrapping_add(u16::from(byte));
+                match (index, self.length) {
+                    (0, _) => Receive::Length(1),
+                    (_, 0) => Receive::Checksum(0),
+                    _ => Receive::Data,
+                }
+            }
+            Receive::Data => {
+                self.data.push(byte);
+                self.checksum = self.checksum.wrapping_add(u16::from(byte));
+                if self.data.len() == usize::from(self.length) {
+                    Receive::Checksum(0)
+                } else {
+                    Receive::Data
+                }
+            }
+            Receive::Checksum(0) => {
+                self.checksum ^= u16::from(byte);
+                Receive::Checksum(1)
+            }
+            Receive::Checksum(_) => {
+                self.checksum ^= u16::from(byte) << 8;
+                self.finish_packet();
+                Receive::Alive
+            }
+            Receive::Alive => Receive::Status,
+        };
+    }
+
+    fn finish_packet(&mut self) {
+        if self.checksum != 0 {
+            self.status |= CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !CHECKSUM_ERROR;
+        match self.command {
+            INITIALIZE | BREAK => {
+                self.image.clear();
+                self.status = 0;
+                self.busy_replies = 0;
+            }
+            PRINT if self.data.len() >= 4 => self.print(),
+            DATA => {
+                let data = if self.compressed {
+                    decompress(&self.data)
+                } else {
+                    std::mem::take(&mut self.data)
+                };
+                let room = BUFFER_SIZE - self.image.len();
+                self.image.extend_from_slice(&data[..data.len().min(room)]);
+                if !self.image.is_empty() {
+                    self.status |= UNPROCESSED_DATA;
+                }
+                if self.image.len() >= FULL_SIZE {
+                    self.status |= IMAGE_DATA_FULL;
+                }
+            }
+            STATUS => {}
+            _ => self.status |= PACKET_ERROR,
+        }
+    }
+
+    fn print(&mut self) {
+        let [_, margins, palette, exposure] = [0, 1, 2, 3].map(|index| self.data[index]);
+        // A palette of 0 prints like the usual 0xE4.
+        let palette = if palette == 0 { 0xE4 } else { palette };
+        let lines = self.image.len() / (TILE_SIZE * TILES_PER_LINE) * 8;
+        let mut shades = vec![0; lines * STRIP_WIDTH];
+        for (y, line) in shades.chunks_exact_mut(STRIP_WIDTH).enumerate() {
+            for (x, shade) in line.iter_mut().enumerate() {
+                let tile = (y / 8 * TILES_PER_LINE + x / 8) * TILE_SIZE;
+                let row = tile + (y % 8) * 2;
+                let bit = 7 - x % 8;
+                let index =
+                    ((self.image[row] >> bit) & 1) | (((self.image[row + 1] >> bit) & 1) << 1);
+                *shade = (palette >> (index * 2)) & 0x03;
+            }
+        }
+        if !shades.is_empty() {
+            self.strips.push(Strip {
+                shades,
+                margin_before: margins >> 4,
+                margin_after: margins & 0x0F,
+                exposure: exposure & 0x7F,
+            });
+        }
+        self.image.clear();
+        self.status &= !(UNPROCESSED_DATA | IMAGE_DATA_FULL);
+        self.busy_replies = BUSY_REPLIES;
+    }
+}
+
+impl LinkPort for Printer {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        let reply = match self.receive {
+            Receive::Alive => ALIVE,
+            Receive::Status => self.status(),
+            _ => 0x00,
+        };
+        self.receive(byte);
+        reply
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble_rom;
+    use crate::bus::MemoryBus;
+    use crate::gameboy::GameBoy;
+
+    /// Sends a packet and returns the alive and status replies.
+    fn send(printer: &mut Printer, command: u8, compressed: bool, data: &[u8]) -> (u8, u8) {
+        let length = u16::try_from(data.len()).unwrap().to_le_bytes();
+        let mut packet = vec![command, u8::from(compressed), length[0], length[1]];
+        packet.extend_from_slice(data);
+        let checksum = packet
+            .iter()
+            .fold(0u16, |sum, &byte| sum.wrapping_add(u16::from(byte)));
+        packet.extend_from_slice(&checksum.to_le_bytes());
+        for byte in MAGIC.into_iter().chain(packet) {
+            assert_eq!(0x00, printer.exchange(byte));
+        }
+        (printer.exchange(0), printer.exchange(0))
+    }
+
+    #[test]
+    fn test_prints_a_band() {
+        let mut printer = Printer::default();
+        assert_eq!((ALIVE, 0x00), send(&mut printer, INITIALIZE, false, &[]));
+
+        // Colour 3 in the first line of the first tile, colour 1 everywhere else.
+        let mut band = [0xFF, 0x00].repeat(TILES_PER_LINE * 2 * 8);
+        band[1] = 0xFF;
+        assert_eq!(
+            (ALIVE, UNPROCESSED_DATA),
+            send(&mut printer, DATA, false, &band)
+        );
+        assert_eq!(
+            (ALIVE, UNPROCESSED_DATA),
+            send(&mut printer, DATA, false, &[])
+        );
+
+        assert_eq!(
+            (ALIVE, BUSY),
+            send(&mut printer, PRINT, false, &[1, 0x13, 0xE4, 0x40])
+        );
+        let strip = &printer.strips()[0];
+        assert_eq!(16, strip.height());
+        assert_eq!([3; 8], strip.shades[..8]);
+        assert_eq!(1, strip.shades[8]);
+        assert_eq!(1, strip.shades[STRIP_WIDTH]);
+        assert_eq!(
+            (1, 3, 0x40),
+            (strip.margin_before, strip.margin_after, strip.exposure)
+        );
+        for _ in 1..BUSY_REPLIES {
+            assert_eq!((ALIVE, BUSY), send(&mut printer, STATUS, false, &[]));
+        }
+        assert_eq!((ALIVE, 0x00), send(&mut printer, STATUS, false, &[]));
+        assert_eq!(1, printer.take_strips().len());
+    }
+
+    #[test]
+    fn test_game_boy_reaches_the_printer() {
+        let rom = assemble_rom(
+            "
+            section \"main\", rom0[$150]
+                ld hl, packet
+                ld de, $c000
+                ld b, 10
+            .next:
+                ld a, [hl+]
+                ldh [$01], a
+                ld a, $81
+                ldh [$02], a
+            .wait:
+                ldh a, [$02]
+                bit 7, a
+                jr nz, .wait
+                ldh a, [$01]
+                ld [de], a
+                inc de
+                dec b
+                jr nz, .next
+            .done:
+                halt
+                jr .done
+            packet:
+                db $88, $33, $0f, $00, $00, $00, $0f, $00, $00, $00
+            ",
+        )
+        .unwrap();
+        let mut gameboy = GameBoy::from_rom(rom).unwrap();
+        let mut printer = Printer::default();
+        gameboy.run_frame_linked(&mut printer, |_| {});
+        let replies: Vec<u8> = (0xC000..0xC00A)
+            .map(|address| gameboy.bus().peek(address))
+            .collect();
+        assert_eq!([0, 0, 0, 0, 0, 0, 0, 0, ALIVE, 0], replies[..]);
+    }
+
+    #[test]
+    fn test_compressed_data_and_bad_checksums() {
+        assert_eq!(vec![7, 7, 7, 1, 2], decompress(&[0x81, 7, 0x01, 1, 2]));
+
+        let mut printer = Printer::default();
+        let compressed = [0x80 | 0x7E, 0x55, 0x80 | 0x7E, 0x55, 0x80 | 0x7E, 0x55];
+        send(&mut printer, DATA, true, &compressed[..4]);
+        assert_eq!(0x100, printer.image.len());
+
+        for byte in MAGIC.into_iter().chain([STATUS, 0, 0, 0, 0x12, 0x34]) {
+            printer.exchange(byte);
+        }
+        assert_eq!(ALIVE, printer.exchange(0));
+        assert_eq!(CHECKSUM_ERROR | UNPROCESSED_DATA, printer.exchange(0));
+        assert_eq!(
+            (ALIVE, UNPROCESSED_DATA),
+            send(&mut printer, STATUS, false, &[])
+        );
+        assert_eq!(
+            (ALIVE, PACKET_ERROR | UNPROCESSED_DATA),
+            send(&mut printer, 0x03, false, &[])
+        );
+    }
+}
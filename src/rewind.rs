@@ -0,0 +1,319 @@
+//! Rewinding built on save states.
+//!
+//! Every `interval` frames the machine state is captured. The newest capture is kept whole and
+//! every older one as a delta against the capture after it: the XOR of the two states with runs
+//! of zero bytes collapsed, which is small because most memory does not change between frames.
+//! Evicting the oldest capture to stay within the memory budget therefore never invalidates the
+//! others. The joypad state at the start of every frame is recorded too, so stepping back to a
+//! frame between captures replays the frames after the capture with the original input.
+
+use std::collections::VecDeque;
+
+use crate::gameboy::GameBoy;
+
+const DELTA: u8 = 0;
+const FULL: u8 = 1;
+
+fn push_length(out: &mut Vec<u8>, mut length: usize) {
+    loop {
+        let [low, ..] = length.to_le_bytes();
+        length >>= 7;
+        if length == 0 {
+            out.push(low & 0x7F);
+            return;
+        }
+        out.push(0x80 | low);
+    }
+}
+
+fn read_length(bytes: &mut impl Iterator<Item = u8>) -> usize {
+    let mut length = 0;
+    for shift in (0..usize::BITS).step_by(7) {
+        let Some(byte) = bytes.next() else { break };
+        length |= usize::from(byte & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    length
+}
+
+/// Encodes `to` relative to `from` as alternating runs of unchanged bytes and XOR-ed literals.
+fn diff(from: &[u8], to: &[u8]) -> Vec<u8> {
+    if from.len() != to.len() {
+        let mut out = vec![FULL];
+        out.extend_from_slice(to);
+        return out;
+    }
+    let mut out = vec![DELTA];
+    let mut index = 0;
+    while index < to.len() {
+        let unchanged = from[index..]
+            .iter()
+            .zip(&to[index..])
+            .take_while(|(a, b)| a == b)
+            .count();
+        index += unchanged;
+        let changed = from[index..]
+            .iter()
+            .zip(&to[index..])
+            .take_while(|(a, b)| a != b)
+            .count();
+        push_length(&mut out, unchanged);
+        push_length(&mut out, changed);
+        out.extend((index..index + changed).map(|i| from[i] ^ to[i]));
+        index += changed;
+    }
+    out
+}
+
+fn patch(from: &[u8], delta: &[u8]) -> Vec<u8> {
+    let Some((&kind, delta)) = delta.split_first() else {
+        return from.to_vec();
+    };
+    if kind == FULL {
+        return delta.to_vec();
+    }
+    let mut state = from.to_vec();
+    let mut bytes = delta.iter().copied();
+    let mut index = 0;
+    while bytes.len() > 0 {
+        index += read_length(&mut bytes);
+        let changed = read_length(&mut bytes);
+        for (byte, mask) in state[index..index + changed].iter_mut().zip(&mut bytes) {
+            *byte ^= mask;
+        }
+        index += changed;
+    }
+    state
+}
+
+#[derive(Debug, Clone)]
+pub struct Rewind {
+    interval: u64,
+    budget: usize,
+    /// Frames run since the rewind buffer was created, counting replays and step backs.
+    frame: u64,
+    /// The newest capture and the frame it was taken at.
+    newest: Option<(u64, Vec<u8>)>,
+    /// Older captures, oldest first, each a delta against the capture after it.
+    older: VecDeque<(u64, Vec<u8>)>,
+    /// The joypad state at the start of each frame, from frame `first_input` on.
+    inputs: VecDeque<u8>,
+    first_input: u64,
+    used: usize,
+}
+
+impl Rewind {
+    /// Captures every `interval` frames, dropping the oldest captures when they use more than
+    /// `budget` bytes. The newest capture is always kept.
+    ///
+    /// # Panics
+    ///
+    /// Panics when `interval` is zero.
+    #[must_use]
+    pub fn new(interval: u32, budget: usize) -> Self {
+        assert!(
+            interval > 0,
+            "the capture interval must be at least one frame"
+        );
+        Self {
+            interval: interval.into(),
+            budget,
+            frame: 0,
+            newest: None,
+            older: VecDeque::new(),
+            inputs: VecDeque::new(),
+            first_input: 0,
+            used: 0,
+        }
+    }
+
+    /// The number of frames run through [`Rewind::run_frame`], less the frames stepped back.
+    #[must_use]
+    pub const fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Bytes used by captures and recorded input.
+    #[must_use]
+    pub const fn memory_used(&self) -> usize {
+        self.used
+    }
+
+    /// The earliest frame [`Rewind::step_back`] can return to.
+    #[must_use]
+    pub fn earliest_frame(&self) -> Option<u64> {
+        self.older
+            .front()
+            .or(self.newest.as_ref())
+            .map(|(frame, _)| *frame)
+    }
+
+    fn capture(&mut self, gameboy: &GameBoy) {
+        let state = gameboy.save_state();
+        self.used += state.len();
+        if let Some((frame, previous)) = self.newest.take() {
+            let delta = diff(&state, &previous);
+            self.used += delta.len();
+            self.used -= previous.len();
+            self.older.push_back((frame, delta));
+        }
+        self.newest = Some((self.frame, state));
+        while self.used > self.budget {
+            let Some((_, delta)) = self.older.pop_front() else {
+                break;
+            };
+            self.used -= delta.len();
+        }
+        let earliest = self.earliest_frame().unwrap_or(self.frame);
+        while self.first_input < earliest {
+            self.inputs.pop_front();
+            self.first_input += 1;
+            self.used -= 1;
+        }
+    }
+
+    /// Runs one frame, capturing the state beforehand when a capture is due.
+    pub fn run_frame(&mut self, gameboy: &mut GameBoy) -> u64 {
+        let due = self
+            .newest
+            .as_ref()
+            .is_none_or(|(frame, _)| self.frame - frame >= self.interval);
+        if due {
+            self.capture(gameboy);
+        }
+        self.inputs.push_back(gameboy.bus().joypad().pressed());
+        self.used += 1;
+        self.frame += 1;
+        gameboy.run_frame()
+    }
+
+    /// Goes back to the start of the previous frame. Returns false, leaving `gameboy` alone,
+    /// when that frame is older than every capture.
+    pub fn step_back(&mut self, gameboy: &mut GameBoy) -> bool {
+        let Some(target) = self.frame.checked_sub(1) else {
+            return false;
+        };
+        if self
+            .earliest_frame()
+            .is_none_or(|earliest| earliest > target)
+        {
+            return false;
+        }
+        while let Some((frame, state)) = self.newest.take() {
+            if frame <= target {
+                self.newest = Some((frame, state));
+                break;
+            }
+            self.used -= state.len();
+            if let Some((older_frame, delta)) = self.older.pop_back() {
+                let restored = patch(&state, &delta);
+                self.used += restored.len();
+                self.used -= delta.len();
+                self.newest = Some((older_frame, restored));
+            }
+        }
+        let Some((frame, state)) = &self.newest else {
+            return false;
+        };
+        if gameboy.load_state(state).is_err() {
+            return false;
+        }
+        let index = |frame: u64| usize::try_from(frame - self.first_input).unwrap_or(usize::MAX);
+        for replayed in *frame..=target {
+            if let Some(&pressed) = self.inputs.get(index(replayed)) {
+                gameboy.bus_mut().joypad_mut().set_pressed(pressed);
+            }
+            if replayed < target {
+                gameboy.run_frame();
+            }
+        }
+        let kept = index(target);
+        self.used -= self.inputs.len() - kept;
+        self.inputs.truncate(kept);
+        self.frame = target;
+        true
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::assembler::assemble_rom;
+    use crate::joypad::Button;
+
+    #[test]
+    fn test_diff_and_patch() {
+        let from = [0, 1, 2, 3, 4, 5, 6, 7];
+        let to = [0, 1, 9, 9, 4, 5, 6, 8];
+        let delta = diff(&from, &to);
+        assert_eq!(to.to_vec(), patch(&from, &delta));
+        assert_eq!(from.to_vec(), patch(&from, &diff(&from, &from)));
+        assert_eq!(vec![1, 2], patch(&from, &diff(&from, &[1, 2])));
+
+        let large = vec![0; 1000];
+        let mut changed = large.clone();
+        changed[500] = 1;
+        assert!(diff(&large, &changed).len() < 10);
+        assert_eq!(changed, patch(&large, &diff(&large, &changed)));
+    }
+
+    /// A machine that keeps copying the joypad register into WRAM, so input shows in its state.
+    pub fn joypad_echo() -> GameBoy {
+        let rom = assemble_rom(
+            "
+            section \"main\", rom0[$150]
+                ld a, $10
+                ldh [$00], a
+                ld hl, $c000
+            .loop:
+                ldh a, [$00]
+                ld [hl+], a
+                res 4, h
+                jr .loop
+            ",
+        )
+        .unwrap();
+        GameBoy::from_rom(rom).unwrap()
+    }
+
+    #[test]
+    fn test_step_back_replays_input() {
+        let mut gameboy = joypad_echo();
+        let mut rewind = Rewind::new(4, usize::MAX);
+        let mut states = Vec::new();
+        for frame in 0..20 {
+            gameboy.set_button(Button::A, frame % 3 == 0);
+            states.push(gameboy.save_state());
+            rewind.run_frame(&mut gameboy);
+        }
+        for (frame, state) in (0..20u32).zip(&states).rev() {
+            assert!(rewind.step_back(&mut gameboy));
+            assert_eq!(u64::from(frame), rewind.frame());
+            assert!(*state == gameboy.save_state());
+        }
+        assert!(!rewind.step_back(&mut gameboy));
+
+        gameboy.set_button(Button::B, true);
+        let pressed_b = gameboy.save_state();
+        rewind.run_frame(&mut gameboy);
+        assert!(rewind.step_back(&mut gameboy));
+        assert!(pressed_b == gameboy.save_state());
+    }
+
+    #[test]
+    fn test_budget_drops_oldest_captures() {
+        let mut gameboy = joypad_echo();
+        let budget = gameboy.save_state().len() + 1000;
+        let mut rewind = Rewind::new(1, budget);
+        for _ in 0..50 {
+            rewind.run_frame(&mut gameboy);
+        }
+        assert!(rewind.memory_used() <= budget);
+        let earliest = rewind.earliest_frame().unwrap();
+        assert!(earliest > 0);
+        while rewind.step_back(&mut gameboy) {}
+        assert_eq!(earliest, rewind.frame());
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::cpu::{Instruction, JumpCondition};
+use crate::disassembler::condition;
+use crate::memory_map::{
+    EXECUTION_START_INDEX, HIGH_TO_LOW_INTERUPT_START_INDEX, LCDC_STATUS_INTERUPT_START_INDEX,
+    RESTART_00_INDEX, RESTART_08_INDEX, RESTART_10_INDEX, RESTART_18_INDEX, RESTART_20_INDEX,
+    RESTART_28_INDEX, RESTART_30_INDEX, RESTART_38_INDEX, ROM_BANK_RANGE,
+    SERIAL_TRANSFER_COMPLETION_INTERUPT_START_INDEX, SWITCHABLE_ROM_BANK_RANGE,
+    TIMER_OVERFLOW_INTERUPT_START_INDEX, VERTICAL_BLANK_INTERUPT_START_INDEX,
+};
+
+const BANK_SIZE: usize = 0x4000;
+const BYTES_PER_DATA_LINE: usize = 8;
+
+const ENTRY_POINTS: [(u16, &str); 14] = [
+    (*EXECUTION_START_INDEX.start(), "Entry"),
+    (RESTART_00_INDEX, "Restart00"),
+    (RESTART_08_INDEX, "Restart08"),
+    (RESTART_10_INDEX, "Restart10"),
+    (RESTART_18_INDEX, "Restart18"),
+    (RESTART_20_INDEX, "Restart20"),
+    (RESTART_28_INDEX, "Restart28"),
+    (RESTART_30_INDEX, "Restart30"),
+    (RESTART_38_INDEX, "Restart38"),
+    (VERTICAL_BLANK_INTERUPT_START_INDEX, "VBlankInterrupt"),
+    (LCDC_STATUS_INTERUPT_START_INDEX, "LcdStatInterrupt"),
+    (TIMER_OVERFLOW_INTERUPT_START_INDEX, "TimerInterrupt"),
+    (
+        SERIAL_TRANSFER_COMPLETION_INTERUPT_START_INDEX,
+        "SerialInterrupt",
+    ),
+    (HIGH_TO_LOW_INTERUPT_START_INDEX, "JoypadInterrupt"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Location {
+    bank: usize,
+    address: u16,
+}
+
+impl Location {
+    const fn offset(self) -> usize {
+        self.bank * BANK_SIZE + (self.address as usize & (BANK_SIZE - 1))
+    }
+
+    const fn next(self, length: u16) -> Self {
+        Self {
+            bank: self.bank,
+            address: self.address.wrapping_add(length),
+        }
+    }
+}
+
+/// A ROM split into code and data by following control flow from the entry points and vectors.
+#[derive(Debug, Clone)]
+pub struct RomDisassembly {
+    banks: Vec<String>,
+    symbols: String,
+}
+
+struct Tracer<'a> {
+    rom: &'a [u8],
+    code: BTreeMap<Location, (Instruction, u16)>,
+    labels: BTreeMap<Location, String>,
+}
+
+impl Tracer<'_> {
+    const fn bank_count(&self) -> usize {
+        self.rom.len().div_ceil(BANK_SIZE)
+    }
+
+    /// Maps an address seen from `bank` to a ROM location, if the bank it refers to is known.
+    fn resolve(&self, bank: usize, address: u16) -> Option<Location> {
+        let bank = if ROM_BANK_RANGE.contains(&address) {
+            0
+        } else if !SWITCHABLE_ROM_BANK_RANGE.contains(&address) {
+            return None;
+        } else if bank != 0 {
+            bank
+        } else if self.bank_count() == 2 {
+            1
+        } else {
+            return None;
+        };
+        let location = Location { bank, address };
+        (location.offset() < self.rom.len()).then_some(location)
+    }
+
+    fn decode(&self, location: Location) -> Option<(Instruction, u16)> {
+        let end = ((location.bank + 1) * BANK_SIZE).min(self.rom.len());
+        let mut offset = location.offset();
+        let mut overrun = false;
+        let instruction = Instruction::decode(|| {
+            let byte = self.rom.get(offset).copied().filter(|_| offset < end);
+            overrun |= byte.is_none();
+            offset += 1;
+            byte.unwrap_or(0)
+        });
+        let length = u16::try_from(offset - location.offset()).ok()?;
+        (!overrun).then_some((instruction, length))
+    }
+
+    fn label(&mut self, location: Location, prefix: &str) {
+        self.labels
+            .entry(location)
+            .or_insert_with(|| format!("{prefix}_{:03x}_{:04x}", location.bank, location.address));
+    }
+
+    fn trace(&mut self, entry: Location) {
+        let mut pending = vec![entry];
+        while let Some(location) = pending.pop() {
+            if self.code.contains_key(&location) {
+                continue;
+            }
+            let Some((instruction, length)) = self.decode(location) else {
+                continue;
+            };
+            if matches!(instruction, Instruction::ILLEGAL(_)) {
+                continue;
+            }
+            self.code.insert(location, (instruction, length));
+
+            let next = location.next(length);
+            let (target, prefix, falls_through) = match instruction {
+                Instruction::JP(jump, address) => {
+                    (Some(address), "jump", jump != JumpCondition::Always)
+                }
+                Instruction::JR(jump, offset) => (
+                    Some(next.address.wrapping_add_signed(offset.into())),
+                    "jump",
+                    jump != JumpCondition::Always,
+                ),
+                Instruction::CALL(_, address) => (Some(address), "call", true),
+                Instruction::RST(vector) => (Some(vector.into()), "call", true),
+                Instruction::RET(JumpCondition::Always) | Instruction::RETI | Instruction::JPHL => {
+                    (None, "", false)
+                }
+                _ => (None, "", true),
+            };
+            if let Some(target) = target.and_then(|target| self.resolve(location.bank, target)) {
+                self.label(target, prefix);
+                pending.push(target);
+            }
+            if falls_through {
+                pending.push(next);
+            }
+        }
+    }
+
+    fn target_label(&self, bank: usize, address: u16) -> Option<&str> {
+        self.resolve(bank, address)
+            .and_then(|location| self.labels.get(&location))
+            .map(String::as_str)
+    }
+
+    fn render(&self, location: Location, instruction: Instruction, length: u16) -> String {
+        let next = location.next(length);
+        let (mnemonic, jump, target) = match instruction {
+            Instruction::JP(jump, address) => ("jp", jump, address),
+            Instruction::CALL(jump, address) => ("call", jump, address),
+            Instruction::JR(jump, offset) => {
+                ("jr", jump, next.address.wrapping_add_signed(offset.into()))
+            }
+            _ => return instruction.to_string(),
+        };
+        self.target_label(location.bank, target).map_or_else(
+            || instruction.to_string(),
+            |label| format!("{mnemonic} {}{label}", condition(jump)),
+        )
+    }
+
+    /// Whether the instruction can be emitted as code without hiding a label or changing bytes.
+    fn emittable(&self, location: Location, instruction: Instruction, length: u16) -> bool {
+        let hides_label = (1..length).any(|index| self.labels.contains_key(&location.next(index)));
+        let stop_padding = instruction == Instruction::STOP && self.rom[location.offset() + 1] != 0;
+        !hides_label && !stop_padding
+    }
+
+    fn write_bank(&self, bank: usize) -> String {
+        let base = if bank == 0 {
+            ROM_BANK_RANGE.start
+        } else {
+            SWITCHABLE_ROM_BANK_RANGE.start
+        };
+        let mut text = if bank == 0 {
+            "SECTION \"ROM Bank $000\", ROM0[$0000]\n".to_string()
+        } else {
+            format!("SECTION \"ROM Bank ${bank:03x}\", ROMX[$4000], BANK[${bank:x}]\n")
+        };
+
+        let mut data: Vec<u8> = Vec::new();
+        let flush = |text: &mut String, data: &mut Vec<u8>| {
+            if !data.is_empty() {
+                let bytes: Vec<String> = data.iter().map(|byte| format!("${byte:02x}")).collect();
+                let _ = writeln!(text, "    db {}", bytes.join(", "));
+                data.clear();
+            }
+        };
+
+        let start = bank * BANK_SIZE;
+        let end = (start + BANK_SIZE).min(self.rom.len());
+        let mut offset = start;
+        while offset < end {
+            let address = base + u16::try_from(offset - start).unwrap_or_default();
+            let location = Location { bank, address };
+            if let Some(label) = self.labels.get(&location) {
+                flush(&mut text, &mut data);
+                let _ = writeln!(text, "\n{label}:");
+            }
+            if let Some(&(instruction, length)) = self.code.get(&location) {
+                if self.emittable(location, instruction, length) {
+                    flush(&mut text, &mut data);
+                    let _ = writeln!(text, "    {}", self.render(location, instruction, length));
+                    offset += usize::from(length);
+                    continue;
+                }
+            }
+            data.push(self.rom[offset]);
+            if data.len() == BYTES_PER_DATA_LINE {
+                flush(&mut text, &mut data);
+            }
+            offset += 1;
+        }
+        flush(&mut text, &mut data);
+        text
+    }
+}
+
+impl RomDisassembly {
+    #[must_use]
+    pub fn new(rom: &[u8]) -> Self {
+        let mut tracer = Tracer {
+            rom,
+            code: BTreeMap::new(),
+            labels: BTreeMap::new(),
+        };
+        for (address, name) in ENTRY_POINTS {
+            if let Some(location) = tracer.resolve(0, address) {
+                tracer.labels.insert(location, name.to_string());
+                tracer.trace(location);
+            }
+        }
+
+        let banks = (0..tracer.bank_count())
+            .map(|bank| tracer.write_bank(bank))
+            .collect();
+        let mut symbols = String::new();
+        for (location, label) in &tracer.labels {
+            let _ = writeln!(
+                symbols,
+                "{:02x}:{:04x} {label}",
+                location.bank, location.address
+            );
+        }
+        Self { banks, symbols }
+    }
+
+    /// One RGBDS source file per ROM bank.
+    #[must_use]
+    pub fn banks(&self) -> &[String] {
+        &self.banks
+    }
+
+    /// The generated labels in the `bank:address name` symbol file format.
+    #[must_use]
+    pub fn symbols(&self) -> &str {
+        &self.symbols
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::{assemble, assemble_rom};
+
+    fn rom(program: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0xFF; 0x8000];
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+        rom[0x150..0x150 + program.len()].copy_from_slice(program);
+        rom
+    }
+
+    #[test]
+    fn test_follows_jumps_and_calls() {
+        let disassembly = RomDisassembly::new(&rom(&[
+            0xCD, 0x00, 0x40, 0x20, 0xFB, 0x18, 0xFE, 0x12, 0x34,
+        ]));
+        let bank0 = &disassembly.banks()[0];
+        assert!(bank0.contains("Entry:\n    nop\n    jp jump_000_0150\n"));
+        assert!(
+            bank0.contains("jump_000_0150:\n    call call_001_4000\n    jr nz, jump_000_0150\n")
+        );
+        assert!(bank0.contains("jump_000_0155:\n    jr jump_000_0155\n    db $12, $34, $ff"));
+        assert!(disassembly.banks()[1]
+            .starts_with("SECTION \"ROM Bank $001\", ROMX[$4000], BANK[$1]\n"));
+        assert!(disassembly.banks()[1].contains("call_001_4000:\n    rst $38\n"));
+        assert!(disassembly.symbols().contains("00:0100 Entry\n"));
+        assert!(disassembly.symbols().contains("01:4000 call_001_4000\n"));
+    }
+
+    #[test]
+    fn test_reassembles_to_the_same_rom() {
+        // The call lands on the operand of `ld a, $C9`, which must come out as data.
+        let rom = assemble_rom(
+            "
+            section \"main\", rom0[$150]
+            start:
+                call inner
+                db $3E
+            inner:
+                ret
+                jr start
+            ",
+        )
+        .unwrap();
+        let disassembly = RomDisassembly::new(&rom);
+        assert!(disassembly.banks()[0].contains("    db $3e\n\ncall_000_0154:\n    ret\n"));
+        // Each bank is one section covering the whole bank, header included, which
+        // `assemble_rom` keeps for itself, so the banks are assembled back to back instead.
+        assert_eq!(Ok(rom), assemble(&disassembly.banks().join("\n")));
+    }
+
+    #[test]
+    fn test_header_is_data() {
+        let disassembly = RomDisassembly::new(&rom(&[0x76]));
+        assert!(disassembly.banks()[0].contains("jp jump_000_0150\n    db $ff, $ff"));
+    }
+}
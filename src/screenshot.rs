@@ -0,0 +1,338 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::ppu::SCREEN_WIDTH;
+use crate::sgb::SGB_SCREEN_WIDTH;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Palette {
+    Classic,
+    #[default]
+    Grayscale,
+    Pocket,
+    /// RGB colours for shades 0 (lightest) to 3 (darkest).
+    Custom([[u8; 3]; 4]),
+}
+
+impl Palette {
+    #[must_use]
+    pub const fn colors(self) -> [[u8; 3]; 4] {
+        match self {
+            Self::Classic => [
+                [0x9B, 0xBC, 0x0F],
+                [0x8B, 0xAC, 0x0F],
+                [0x30, 0x62, 0x30],
+                [0x0F, 0x38, 0x0F],
+            ],
+            Self::Grayscale => [[0xFF; 3], [0xAA; 3], [0x55; 3], [0x00; 3]],
+            Self::Pocket => [
+                [0xC4, 0xCF, 0xA1],
+                [0x8B, 0x95, 0x6D],
+                [0x4D, 0x53, 0x3C],
+                [0x1F, 0x1F, 0x1F],
+            ],
+            Self::Custom(colors) => colors,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsePaletteError(String);
+
+impl fmt::Display for ParsePaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid palette {:?}, expected classic, grayscale, pocket or four RRGGBB colours",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParsePaletteError {}
+
+impl FromStr for Palette {
+    type Err = ParsePaletteError;
+
+    /// Parses a palette name or four comma-separated `RRGGBB` colours, lightest first.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParsePaletteError(s.to_string());
+        match s {
+            "classic" | "green" => return Ok(Self::Classic),
+            "grayscale" | "gray" => return Ok(Self::Grayscale),
+            "pocket" => return Ok(Self::Pocket),
+            _ => {}
+        }
+        let mut colors = [[0; 3]; 4];
+        let mut parts = s.split(',');
+        for color in &mut colors {
+            let part = parts.next().ok_or_else(error)?;
+            let hex = part.trim().trim_start_matches('#');
+            if hex.len() != 6 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+                return Err(error());
+            }
+            let value = u32::from_str_radix(hex, 16).map_err(|_| error())?;
+            color.copy_from_slice(&value.to_be_bytes()[1..]);
+        }
+        if parts.next().is_some() {
+            return Err(error());
+        }
+        Ok(Self::Custom(colors))
+    }
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 0 {
+                crc >> 1
+            } else {
+                (crc >> 1) ^ 0xEDB8_8320
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + u32::from(byte)) % 65_521;
+        b = (b + a) % 65_521;
+    }
+    (b << 16) | a
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let length = u16::try_from(block.len()).unwrap_or(u16::MAX);
+        out.push(u8::from(blocks.peek().is_none()));
+        out.extend_from_slice(&length.to_le_bytes());
+        out.extend_from_slice(&(!length).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn push_chunk(png: &mut Vec<u8>, kind: [u8; 4], data: &[u8]) {
+    let length = u32::try_from(data.len()).unwrap_or(u32::MAX);
+    png.extend_from_slice(&length.to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(&kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Encodes tightly packed 8-bit RGB pixels as a PNG.
+///
+/// The image data is stored uncompressed so the output only depends on the pixels.
+///
+/// # Panics
+///
+/// Panics when `rgb` does not hold exactly `width * height` pixels or a dimension
+/// does not fit in 32 bits.
+#[must_use]
+pub fn encode_png(rgb: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let row_bytes = width * 3;
+    assert_eq!(row_bytes * height, rgb.len());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&u32::try_from(width).unwrap().to_be_bytes());
+    header.extend_from_slice(&u32::try_from(height).unwrap().to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut raw = Vec::with_capacity((row_bytes + 1) * height);
+    for row in rgb.chunks(row_bytes.max(1)) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    push_chunk(&mut png, *b"IHDR", &header);
+    push_chunk(&mut png, *b"IDAT", &zlib_stored(&raw));
+    push_chunk(&mut png, *b"IEND", &[]);
+    png
+}
+
+fn scaled_png<T: Copy>(
+    framebuffer: &[T],
+    width: usize,
+    scale: usize,
+    color: impl Fn(T) -> [u8; 3],
+) -> Vec<u8> {
+    assert!(scale > 0, "scale must be at least 1");
+    let mut rgb = Vec::with_capacity(framebuffer.len() * scale * scale * 3);
+    for line in framebuffer.chunks(width) {
+        let row: Vec<u8> = line
+            .iter()
+            .flat_map(|&pixel| std::iter::repeat_n(color(pixel), scale))
+            .flatten()
+            .collect();
+        for _ in 0..scale {
+            rgb.extend_from_slice(&row);
+        }
+    }
+    encode_png(&rgb, width * scale, framebuffer.len() / width * scale)
+}
+
+/// Encodes a framebuffer of shades 160 pixels wide, like the 160x144 screen or a printed
+/// strip, with every pixel drawn as a `scale`x`scale` block.
+///
+/// # Panics
+///
+/// Panics when `scale` is zero or the framebuffer has the wrong size.
+#[must_use]
+pub fn framebuffer_to_png(framebuffer: &[u8], palette: Palette, scale: usize) -> Vec<u8> {
+    let colors = palette.colors();
+    scaled_png(framebuffer, SCREEN_WIDTH, scale, |shade| {
+        colors[usize::from(shade & 0x03)]
+    })
+}
+
+/// Expands a 15-bit colour, red in the low bits, to 8-bit RGB.
+#[must_use]
+pub fn rgb555_to_rgb(color: u16) -> [u8; 3] {
+    let channel = |shift: u16| {
+        let [value, _] = ((color >> shift) & 0x1F).to_le_bytes();
+        (value << 3) | (value >> 2)
+    };
+    [channel(0), channel(5), channel(10)]
+}
+
+/// Encodes a 160x144 framebuffer of 15-bit colours like [`framebuffer_to_png`].
+///
+/// # Panics
+///
+/// Panics when `scale` is zero or the framebuffer has the wrong size.
+#[must_use]
+pub fn color_framebuffer_to_png(framebuffer: &[u16], scale: usize) -> Vec<u8> {
+    scaled_png(framebuffer, SCREEN_WIDTH, scale, rgb555_to_rgb)
+}
+
+/// Encodes the 256x224 SGB screen and border like [`color_framebuffer_to_png`].
+///
+/// # Panics
+///
+/// Panics when `scale` is zero or the framebuffer has the wrong size.
+#[must_use]
+pub fn sgb_framebuffer_to_png(framebuffer: &[u16], scale: usize) -> Vec<u8> {
+    scaled_png(framebuffer, SGB_SCREEN_WIDTH, scale, rgb555_to_rgb)
+}
+
+/// Writes the framebuffer to `path` as a PNG, see [`framebuffer_to_png`].
+///
+/// # Errors
+///
+/// Returns the underlying I/O error when the file cannot be written.
+pub fn save_png(
+    path: impl AsRef<Path>,
+    framebuffer: &[u8],
+    palette: Palette,
+    scale: usize,
+) -> io::Result<()> {
+    std::fs::write(path, framebuffer_to_png(framebuffer, palette, scale))
+}
+
+/// Writes a framebuffer of 15-bit colours to `path` as a PNG, see
+/// [`color_framebuffer_to_png`].
+///
+/// # Errors
+///
+/// Returns the underlying I/O error when the file cannot be written.
+pub fn save_color_png(path: impl AsRef<Path>, framebuffer: &[u16], scale: usize) -> io::Result<()> {
+    std::fs::write(path, color_framebuffer_to_png(framebuffer, scale))
+}
+
+/// Writes the SGB screen and border to `path` as a PNG, see [`sgb_framebuffer_to_png`].
+///
+/// # Errors
+///
+/// Returns the underlying I/O error when the file cannot be written.
+pub fn save_sgb_png(path: impl AsRef<Path>, framebuffer: &[u16], scale: usize) -> io::Result<()> {
+    std::fs::write(path, sgb_framebuffer_to_png(framebuffer, scale))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::SCREEN_HEIGHT;
+    use crate::sgb::SGB_SCREEN_HEIGHT;
+
+    #[test]
+    fn test_crc32_matches_reference() {
+        assert_eq!(0xCBF4_3926, crc32(b"123456789"));
+    }
+
+    #[test]
+    fn test_png_layout() {
+        let png = encode_png(&[0xFF, 0x00, 0x00], 1, 1);
+        assert_eq!(&PNG_SIGNATURE, &png[..8]);
+        assert_eq!(b"IHDR", &png[12..16]);
+        assert_eq!(b"IEND", &png[png.len() - 8..png.len() - 4]);
+    }
+
+    #[test]
+    fn test_framebuffer_png_matches_pinned_crc() {
+        // Diagonal stripes of all four shades, checked once against an independent decoder.
+        let framebuffer: Vec<u8> = (0..SCREEN_HEIGHT)
+            .flat_map(|y| (0..SCREEN_WIDTH).map(move |x| [0, 1, 2, 3][(x + y) % 4]))
+            .collect();
+        let png = framebuffer_to_png(&framebuffer, Palette::Pocket, 2);
+        assert_eq!(0xC4C5_2208, crc32(&png));
+    }
+
+    #[test]
+    fn test_scale_sets_dimensions() {
+        let framebuffer = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        let png = framebuffer_to_png(&framebuffer, Palette::Classic, 3);
+        assert_eq!(480u32.to_be_bytes(), png[16..20]);
+        assert_eq!(432u32.to_be_bytes(), png[20..24]);
+    }
+
+    #[test]
+    fn test_sgb_png_includes_border() {
+        let framebuffer = vec![0; SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT];
+        let png = sgb_framebuffer_to_png(&framebuffer, 2);
+        assert_eq!(512u32.to_be_bytes(), png[16..20]);
+        assert_eq!(448u32.to_be_bytes(), png[20..24]);
+    }
+
+    #[test]
+    fn test_rgb555_expands_to_full_range() {
+        assert_eq!([0xFF, 0x00, 0x00], rgb555_to_rgb(0x001F));
+        assert_eq!([0x00, 0xFF, 0x00], rgb555_to_rgb(0x03E0));
+        assert_eq!([0x00, 0x00, 0xFF], rgb555_to_rgb(0x7C00));
+        assert_eq!([0x84, 0x84, 0x84], rgb555_to_rgb(0x4210));
+    }
+
+    #[test]
+    fn test_parse_palette() {
+        assert_eq!(Ok(Palette::Pocket), "pocket".parse());
+        assert_eq!(
+            Ok(Palette::Custom([
+                [0xFF, 0xFF, 0xFF],
+                [0xAA, 0x00, 0x00],
+                [0x00, 0x55, 0x00],
+                [0x00, 0x00, 0x01],
+            ])),
+            "#FFFFFF,aa0000,005500,000001".parse()
+        );
+        assert!("ffffff,000000".parse::<Palette>().is_err());
+        assert!("sepia".parse::<Palette>().is_err());
+        assert!("+FFFFF,aa0000,005500,000001".parse::<Palette>().is_err());
+    }
+}
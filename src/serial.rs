@@ -0,0 +1,171 @@
+use crate::link::LinkPort;
+use crate::memory_map::{SERIAL_CONTROL_REGISTER_INDEX, SERIAL_DATA_REGISTER_INDEX};
+use crate::state::{StateError, StateReader, StateWriter};
+
+const CYCLES_PER_BIT: u32 = 512;
+
+#[derive(Debug, Clone, Default)]
+pub struct Serial {
+    data: u8,
+    /// The byte the transfer in progress shifts out.
+    outgoing: u8,
+    /// The byte shifted out by the last transfer this side clocked, until taken to hand it to
+    /// the other end of the cable.
+    sent: Option<u8>,
+    control: u8,
+    bits_remaining: u8,
+    cycles: u32,
+    output: Vec<u8>,
+    interrupt: bool,
+}
+
+impl Serial {
+    #[must_use]
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Saves the transfer in progress; bytes already collected in the output are not included.
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.data);
+        state.u8(self.outgoing);
+        state.u8(self.control);
+        state.u8(self.bits_remaining);
+        state.u32(self.cycles);
+        state.bool(self.interrupt);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.data = state.u8()?;
+        self.outgoing = state.u8()?;
+        self.sent = None;
+        self.control = state.u8()?;
+        self.bits_remaining = state.u8()?;
+        self.cycles = state.u32()?;
+        self.interrupt = state.bool()?;
+        Ok(())
+    }
+
+    const fn transferring(&self) -> bool {
+        self.control & 0x80 != 0
+    }
+
+    const fn internal_clock(&self) -> bool {
+        self.control & 0x01 != 0
+    }
+
+    pub const fn tick(&mut self, cycles: u32) {
+        if !self.transferring() || !self.internal_clock() {
+            return;
+        }
+
+        self.cycles += cycles;
+        while self.cycles >= CYCLES_PER_BIT && self.bits_remaining > 0 {
+            self.cycles -= CYCLES_PER_BIT;
+            self.data = (self.data << 1) | 0x01;
+            self.bits_remaining -= 1;
+        }
+
+        if self.bits_remaining == 0 {
+            self.control &= 0x7F;
+            self.cycles = 0;
+            self.interrupt = true;
+            self.sent = Some(self.outgoing);
+        }
+    }
+
+    /// The byte shifted out by a transfer this side clocked that has just completed. With no
+    /// cable the line reads high and the byte shifted in is 0xFF; hand the byte to the other end
+    /// and store its reply with [`Serial::receive`].
+    pub const fn take_sent(&mut self) -> Option<u8> {
+        self.sent.take()
+    }
+
+    /// Replaces the byte shifted in by the transfer [`Serial::take_sent`] reported.
+    pub const fn receive(&mut self, byte: u8) {
+        self.data = byte;
+    }
+
+    pub fn take_interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.interrupt)
+    }
+
+    #[must_use]
+    pub const fn read(&self, address: u16) -> u8 {
+        match address {
+            SERIAL_DATA_REGISTER_INDEX => self.data,
+            SERIAL_CONTROL_REGISTER_INDEX => self.control | 0x7E,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            SERIAL_DATA_REGISTER_INDEX => self.data = value,
+            SERIAL_CONTROL_REGISTER_INDEX => {
+                self.control = value & 0x81;
+                if self.transferring() {
+                    self.output.push(self.data);
+                    self.outgoing = self.data;
+                    self.bits_remaining = 8;
+                    self.cycles = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The serial port is the far end of the cable for the Game Boy clocking the transfer.
+impl LinkPort for Serial {
+    /// Shifts `byte` in when a transfer waits for the external clock, completing it. Otherwise
+    /// nothing is shifted and the line reads high.
+    fn exchange(&mut self, byte: u8) -> u8 {
+        if !self.transferring() || self.internal_clock() {
+            return 0xFF;
+        }
+        let reply = self.data;
+        self.data = byte;
+        self.control &= 0x7F;
+        self.bits_remaining = 0;
+        self.interrupt = true;
+        reply
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer_captures_byte() {
+        let mut serial = Serial::default();
+        serial.write(SERIAL_DATA_REGISTER_INDEX, b'P');
+        serial.write(SERIAL_CONTROL_REGISTER_INDEX, 0x81);
+        serial.tick(8 * CYCLES_PER_BIT);
+        assert_eq!(b"P", serial.output());
+        assert_eq!(0xFF, serial.read(SERIAL_DATA_REGISTER_INDEX));
+        assert!(serial.take_interrupt());
+        assert_eq!(0x7F, serial.read(SERIAL_CONTROL_REGISTER_INDEX));
+        assert_eq!(Some(b'P'), serial.take_sent());
+        assert_eq!(None, serial.take_sent());
+    }
+
+    #[test]
+    fn test_external_clock_waits_for_the_other_end() {
+        let mut serial = Serial::default();
+        assert_eq!(0xFF, serial.exchange(0x12));
+        serial.write(SERIAL_DATA_REGISTER_INDEX, 0x34);
+        serial.write(SERIAL_CONTROL_REGISTER_INDEX, 0x80);
+        serial.tick(16 * CYCLES_PER_BIT);
+        assert!(!serial.take_interrupt());
+        assert_eq!(0x34, serial.exchange(0x12));
+        assert_eq!(0x12, serial.read(SERIAL_DATA_REGISTER_INDEX));
+        assert!(serial.take_interrupt());
+        assert_eq!(None, serial.take_sent());
+    }
+}
@@ -0,0 +1,777 @@
+//! Super Game Boy support.
+//!
+//! Games talk to the SGB by sending 16-byte packets over P1: a reset pulse with P14 and P15
+//! both low, then 128 bits least significant first, P14 low for a 0 and P15 low for a 1, each
+//! followed by both lines high, and a final 0 bit. The first byte of a command holds its code
+//! in bits 3-7 and the number of packets in bits 0-2.
+//!
+//! Larger data, like border tiles and palettes, goes through a VRAM transfer: the command is
+//! followed by a frame showing 4 KiB of tile data in the first 256 tiles of the background.
+//!
+//! The SGB colours each 8x8 cell of the screen with one of four palettes that share colour 0,
+//! and frames it in a 256x224 border drawn with 4-bit tiles and palettes 4 to 7.
+
+use crate::joypad::{Button, Joypad};
+use crate::memory_map::{LCDC_REGISTER_INDEX, VIDEO_RAM_RANGE};
+use crate::ppu::{Ppu, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::state::{StateError, StateReader, StateWriter};
+
+pub const SGB_SCREEN_WIDTH: usize = 256;
+pub const SGB_SCREEN_HEIGHT: usize = 224;
+/// Where the Game Boy screen sits inside the border.
+const SCREEN_LEFT: usize = 48;
+const SCREEN_TOP: usize = 40;
+
+const PACKET_SIZE: usize = 16;
+const PACKET_BITS: usize = PACKET_SIZE * 8;
+const TRANSFER_SIZE: usize = 0x1000;
+
+const COLUMNS: usize = SCREEN_WIDTH / 8;
+const ROWS: usize = SCREEN_HEIGHT / 8;
+const ATTRIBUTE_FILES: usize = 45;
+const ATTRIBUTE_FILE_SIZE: usize = COLUMNS * ROWS / 4;
+const SYSTEM_PALETTES: usize = 512;
+const BORDER_TILES: usize = 256;
+const BORDER_TILE_SIZE: usize = 32;
+const BORDER_MAP_SIZE: usize = 32 * 32;
+const BORDER_PALETTE_SIZE: usize = 16;
+
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const ATTR_BLK: u8 = 0x04;
+const ATTR_LIN: u8 = 0x05;
+const ATTR_DIV: u8 = 0x06;
+const ATTR_CHR: u8 = 0x07;
+const PAL_SET: u8 = 0x0A;
+const PAL_TRN: u8 = 0x0B;
+const MLT_REQ: u8 = 0x11;
+const CHR_TRN: u8 = 0x13;
+const PCT_TRN: u8 = 0x14;
+const ATTR_TRN: u8 = 0x15;
+const ATTR_SET: u8 = 0x16;
+const MASK_EN: u8 = 0x17;
+
+/// The palette the SGB starts with.
+const DEFAULT_PALETTE: [u16; 4] = [0x67BF, 0x265B, 0x10B5, 0x2866];
+
+/// What `MASK_EN` shows in place of the game screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mask {
+    #[default]
+    None,
+    /// Keeps showing the last frame.
+    Freeze,
+    Black,
+    /// Fills the screen with colour 0.
+    Color0,
+}
+
+impl Mask {
+    const fn from_bits(bits: u8) -> Self {
+        match bits & 0x03 {
+            0 => Self::None,
+            1 => Self::Freeze,
+            2 => Self::Black,
+            _ => Self::Color0,
+        }
+    }
+
+    const fn bits(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Freeze => 1,
+            Self::Black => 2,
+            Self::Color0 => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transfer {
+    Palettes,
+    /// Border tiles, the first or second half.
+    Tiles(usize),
+    Border,
+    AttributeFiles,
+}
+
+impl Transfer {
+    const fn bits(self) -> u8 {
+        match self {
+            Self::Palettes => 1,
+            Self::Tiles(0) => 2,
+            Self::Tiles(_) => 3,
+            Self::Border => 4,
+            Self::AttributeFiles => 5,
+        }
+    }
+
+    const fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            1 => Some(Self::Palettes),
+            2 => Some(Self::Tiles(0)),
+            3 => Some(Self::Tiles(1)),
+            4 => Some(Self::Border),
+            5 => Some(Self::AttributeFiles),
+            _ => None,
+        }
+    }
+}
+
+fn color(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]]) & 0x7FFF
+}
+
+#[derive(Debug, Clone)]
+pub struct Sgb {
+    /// Whether the cartridge enables SGB functions; packets are ignored otherwise.
+    enabled: bool,
+    select: u8,
+    receiving: bool,
+    packet: [u8; PACKET_SIZE],
+    bits: usize,
+    command: Vec<u8>,
+    transfer: Option<Transfer>,
+    palettes: [[u16; 4]; 4],
+    system_palettes: Vec<[u16; 4]>,
+    /// The palette of every 8x8 cell of the screen.
+    attributes: [u8; COLUMNS * ROWS],
+    attribute_files: Vec<u8>,
+    mask: Mask,
+    border_tiles: Vec<u8>,
+    border_map: Vec<u16>,
+    border_palettes: [[u16; BORDER_PALETTE_SIZE]; 4],
+    players: u8,
+    player: u8,
+    /// Buttons held on controllers 2 to 4, as [`Joypad::pressed`] masks.
+    other_pressed: [u8; 3],
+    framebuffer: Vec<u16>,
+}
+
+impl Sgb {
+    #[must_use]
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            select: 0x30,
+            receiving: false,
+            packet: [0; PACKET_SIZE],
+            bits: 0,
+            command: Vec::new(),
+            transfer: None,
+            palettes: [DEFAULT_PALETTE; 4],
+            system_palettes: vec![[0; 4]; SYSTEM_PALETTES],
+            attributes: [0; COLUMNS * ROWS],
+            attribute_files: vec![0; ATTRIBUTE_FILES * ATTRIBUTE_FILE_SIZE],
+            mask: Mask::None,
+            border_tiles: vec![0; BORDER_TILES * BORDER_TILE_SIZE],
+            border_map: vec![0; BORDER_MAP_SIZE],
+            border_palettes: [[0; BORDER_PALETTE_SIZE]; 4],
+            players: 1,
+            player: 0,
+            other_pressed: [0; 3],
+            framebuffer: vec![0; SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT],
+        }
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.select);
+        state.bool(self.receiving);
+        state.bytes(&self.packet);
+        state.u8(u8::try_from(self.bits).unwrap_or(u8::MAX));
+        state.bytes(&self.command);
+        state.u8(self.transfer.map_or(0, Transfer::bits));
+        for color in self.palettes.iter().chain(&self.system_palettes).flatten() {
+            state.u16(*color);
+        }
+        state.bytes(&self.attributes);
+        state.bytes(&self.attribute_files);
+        state.u8(self.mask.bits());
+        state.bytes(&self.border_tiles);
+        for &entry in &self.border_map {
+            state.u16(entry);
+        }
+        for &color in self.border_palettes.iter().flatten() {
+            state.u16(color);
+        }
+        state.u8(self.players);
+        state.u8(self.player);
+        state.bytes(&self.other_pressed);
+        for &color in &self.framebuffer {
+            state.u16(color);
+        }
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.select = state.u8()? & 0x30;
+        self.receiving = state.bool()?;
+        state.bytes_into(&mut self.packet, "SGB packet size")?;
+        self.bits = usize::from(state.u8()?);
+        self.command = state.bytes()?.to_vec();
+        if self.bits > PACKET_BITS || self.command.len() >= 7 * PACKET_SIZE {
+            return Err(StateError::Invalid("SGB packet"));
+        }
+        self.transfer = match state.u8()? {
+            0 => None,
+            bits => Some(Transfer::from_bits(bits).ok_or(StateError::Invalid("SGB transfer"))?),
+        };
+        for color in self
+            .palettes
+            .iter_mut()
+            .chain(&mut self.system_palettes)
+            .flatten()
+        {
+            *color = state.u16()? & 0x7FFF;
+        }
+        state.bytes_into(&mut self.attributes, "SGB attribute size")?;
+        state.bytes_into(&mut self.attribute_files, "SGB attribute file size")?;
+        self.mask = Mask::from_bits(state.u8()?);
+        state.bytes_into(&mut self.border_tiles, "SGB border tile size")?;
+        for entry in &mut self.border_map {
+            *entry = state.u16()?;
+        }
+        for color in self.border_palettes.iter_mut().flatten() {
+            *color = state.u16()? & 0x7FFF;
+        }
+        self.players = state.u8()?;
+        self.player = state.u8()?;
+        if ![1, 2, 4].contains(&self.players) || self.player >= self.players {
+            return Err(StateError::Invalid("SGB controller"));
+        }
+        state.bytes_into(&mut self.other_pressed, "SGB controller count")?;
+        for color in &mut self.framebuffer {
+            *color = state.u16()? & 0x7FFF;
+        }
+        if self.attributes.iter().any(|&palette| palette > 3) {
+            return Err(StateError::Invalid("SGB attribute"));
+        }
+        Ok(())
+    }
+
+    /// The screen and border in 15-bit colours, red in the low bits,
+    /// [`SGB_SCREEN_WIDTH`]x[`SGB_SCREEN_HEIGHT`].
+    #[must_use]
+    pub fn framebuffer(&self) -> &[u16] {
+        &self.framebuffer
+    }
+
+    /// Palettes 0 to 3, which colour the game screen.
+    #[must_use]
+    pub const fn palettes(&self) -> &[[u16; 4]; 4] {
+        &self.palettes
+    }
+
+    #[must_use]
+    pub const fn mask(&self) -> Mask {
+        self.mask
+    }
+
+    /// How many controllers `MLT_REQ` enabled: 1, 2 or 4.
+    #[must_use]
+    pub const fn players(&self) -> u8 {
+        self.players
+    }
+
+    /// Presses or releases `button` on controller `player`, 2 to 4. Controller 1 is the
+    /// joypad.
+    pub fn set_player_button(&mut self, player: usize, button: Button, pressed: bool) {
+        let Some(held) = player
+            .checked_sub(2)
+            .and_then(|index| self.other_pressed.get_mut(index))
+        else {
+            return;
+        };
+        if pressed {
+            *held |= button.mask();
+        } else {
+            *held &= !button.mask();
+        }
+    }
+
+    /// Reads P1, which reports the selected controller while multiplayer is on.
+    #[must_use]
+    pub fn read_joypad(&self, joypad: &Joypad) -> u8 {
+        if self.players > 1 && self.select == 0x30 {
+            return 0xF0 | (0x0F - self.player);
+        }
+        self.player.checked_sub(1).map_or_else(
+            || joypad.read(),
+            |index| joypad.read_pressed(self.other_pressed[usize::from(index)]),
+        )
+    }
+
+    /// Watches P1 writes for packets, and while multiplayer is on for the switch to the next
+    /// controller when P15 goes back high.
+    pub fn write_joypad(&mut self, value: u8) {
+        let select = value & 0x30;
+        match select {
+            0x00 => {
+                self.receiving = true;
+                self.bits = 0;
+                self.packet = [0; PACKET_SIZE];
+            }
+            0x10 | 0x20 if self.receiving && self.select == 0x30 => {
+                self.receive_bit(select == 0x10);
+            }
+            0x30 if !self.receiving && self.select == 0x10 && self.players > 1 => {
+                self.player = (self.player + 1) % self.players;
+            }
+            _ => {}
+        }
+        self.select = select;
+    }
+
+    fn receive_bit(&mut self, bit: bool) {
+        if self.bits < PACKET_BITS {
+            if bit {
+                self.packet[self.bits / 8] |= 1 << (self.bits % 8);
+            }
+            self.bits += 1;
+            return;
+        }
+        self.receiving = false;
+        if bit || !self.enabled {
+            return;
+        }
+        self.command.extend_from_slice(&self.packet);
+        let packets = usize::from(self.command[0] & 0x07).max(1);
+        if self.command.len() >= packets * PACKET_SIZE {
+            let command = std::mem::take(&mut self.command);
+            self.execute(&command);
+        }
+    }
+
+    fn execute(&mut self, data: &[u8]) {
+        match data[0] >> 3 {
+            PAL01 => self.set_palette_pair(data, 0, 1),
+            PAL23 => self.set_palette_pair(data, 2, 3),
+            PAL03 => self.set_palette_pair(data, 0, 3),
+            PAL12 => self.set_palette_pair(data, 1, 2),
+            ATTR_BLK => self.attribute_blocks(data),
+            ATTR_LIN => self.attribute_lines(data),
+            ATTR_DIV => self.attribute_division(data),
+            ATTR_CHR => self.attribute_cells(data),
+            PAL_SET => self.set_system_palettes(data),
+            PAL_TRN => self.transfer = Some(Transfer::Palettes),
+            MLT_REQ => {
+                self.players = match data[1] & 0x03 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1,
+                };
+                self.player = 0;
+            }
+            CHR_TRN => self.transfer = Some(Transfer::Tiles(usize::from(data[1] & 0x01))),
+            PCT_TRN => self.transfer = Some(Transfer::Border),
+            ATTR_TRN => self.transfer = Some(Transfer::AttributeFiles),
+            ATTR_SET => self.apply_attribute_file(data[1]),
+            MASK_EN => self.mask = Mask::from_bits(data[1]),
+            // Sound, SNES code and the remaining commands have no effect here.
+            _ => {}
+        }
+    }
+
+    fn set_palette_pair(&mut self, data: &[u8], first: usize, second: usize) {
+        let color0 = color(data, 1);
+        for palette in &mut self.palettes {
+            palette[0] = color0;
+        }
+        for (index, offset) in [(first, 3), (second, 9)] {
+            for shade in 1..4 {
+                self.palettes[index][shade] = color(data, offset + (shade - 1) * 2);
+            }
+        }
+    }
+
+    fn attribute_blocks(&mut self, data: &[u8]) {
+        let count = usize::from(data[1] & 0x1F);
+        for block in data[2..].chunks_exact(6).take(count) {
+            let control = block[0] & 0x07;
+            let [inside, border, outside] = [0, 2, 4].map(|shift| (block[1] >> shift) & 0x03);
+            // With only the inside or only the outside changed, the border goes with it.
+            let border = match control {
+                0x01 => Some(inside),
+                0x04 => Some(outside),
+                _ => (control & 0x02 != 0).then_some(border),
+            };
+            let [left, top, right, bottom] =
+                [block[2], block[3], block[4], block[5]].map(|value| usize::from(value & 0x1F));
+            for row in 0..ROWS {
+                for column in 0..COLUMNS {
+                    let within = (left..=right).contains(&column) && (top..=bottom).contains(&row);
+                    let strictly_within =
+                        left < column && column < right && top < row && row < bottom;
+                    let palette = if strictly_within {
+                        (control & 0x01 != 0).then_some(inside)
+                    } else if within {
+                        border
+                    } else {
+                        (control & 0x04 != 0).then_some(outside)
+                    };
+                    if let Some(palette) = palette {
+                        self.attributes[row * COLUMNS + column] = palette;
+                    }
+                }
+            }
+        }
+    }
+
+    fn attribute_lines(&mut self, data: &[u8]) {
+        let count = usize::from(data[1]);
+        for &line in data[2..].iter().take(count) {
+            let index = usize::from(line & 0x1F);
+            let palette = (line >> 5) & 0x03;
+            if line & 0x80 != 0 {
+                if index < ROWS {
+                    self.attributes[index * COLUMNS..(index + 1) * COLUMNS].fill(palette);
+                }
+            } else if index < COLUMNS {
+                for row in 0..ROWS {
+                    self.attributes[row * COLUMNS + index] = palette;
+                }
+            }
+        }
+    }
+
+    fn attribute_division(&mut self, data: &[u8]) {
+        let [after, before, on] = [0, 2, 4].map(|shift| (data[1] >> shift) & 0x03);
+        let horizontal = data[1] & 0x40 != 0;
+        let split = usize::from(data[2] & 0x1F);
+        for row in 0..ROWS {
+            for column in 0..COLUMNS {
+                let position = if horizontal { row } else { column };
+                self.attributes[row * COLUMNS + column] = match position.cmp(&split) {
+                    std::cmp::Ordering::Less => before,
+                    std::cmp::Ordering::Equal => on,
+                    std::cmp::Ordering::Greater => after,
+                };
+            }
+        }
+    }
+
+    fn attribute_cells(&mut self, data: &[u8]) {
+        let mut column = usize::from(data[1] & 0x1F);
+        let mut row = usize::from(data[2] & 0x1F);
+        let count = usize::from(u16::from_le_bytes([data[3], data[4]])).min(COLUMNS * ROWS);
+        let vertical = data[5] & 0x01 != 0;
+        let palettes = data[6..]
+            .iter()
+            .flat_map(|&byte| [6, 4, 2, 0].map(|shift| (byte >> shift) & 0x03));
+        for palette in palettes.take(count) {
+            if column >= COLUMNS || row >= ROWS {
+                break;
+            }
+            self.attributes[row * COLUMNS + column] = palette;
+            if vertical {
+                row += 1;
+                if row == ROWS {
+                    row = 0;
+                    column += 1;
+                }
+            } else {
+                column += 1;
+                if column == COLUMNS {
+                    column = 0;
+                    row += 1;
+                }
+            }
+        }
+    }
+
+    fn set_system_palettes(&mut self, data: &[u8]) {
+        for (index, palette) in self.palettes.iter_mut().enumerate() {
+            let id = usize::from(u16::from_le_bytes([
+                data[1 + index * 2],
+                data[2 + index * 2],
+            ]));
+            *palette = self.system_palettes[id % SYSTEM_PALETTES];
+        }
+        if data[9] & 0x80 != 0 {
+            self.apply_attribute_file(data[9]);
+        } else if data[9] & 0x40 != 0 {
+            self.mask = Mask::None;
+        }
+    }
+
+    /// Applies the attribute file in bits 0-5 of `value`, cancelling the mask when bit 6 is
+    /// set.
+    fn apply_attribute_file(&mut self, value: u8) {
+        let file = usize::from(value & 0x3F);
+        if file < ATTRIBUTE_FILES {
+            let start = file * ATTRIBUTE_FILE_SIZE;
+            let palettes = self.attribute_files[start..start + ATTRIBUTE_FILE_SIZE]
+                .iter()
+                .flat_map(|&byte| [6, 4, 2, 0].map(|shift| (byte >> shift) & 0x03));
+            for (attribute, palette) in self.attributes.iter_mut().zip(palettes) {
+                *attribute = palette;
+            }
+        }
+        if value & 0x40 != 0 {
+            self.mask = Mask::None;
+        }
+    }
+
+    /// The 4 KiB shown in the first 256 background tiles, in screen order.
+    fn transfer_data(ppu: &Ppu) -> Vec<u8> {
+        let lcdc = ppu.read(LCDC_REGISTER_INDEX);
+        let map = if lcdc & 0x08 != 0 { 0x9C00 } else { 0x9800 };
+        let mut data = Vec::with_capacity(TRANSFER_SIZE);
+        for index in 0..TRANSFER_SIZE / 16 {
+            let [row, column] = [index / COLUMNS, index % COLUMNS]
+                .map(|value| u16::try_from(value).unwrap_or_default());
+            let tile = ppu.read_vram(map + row * 32 + column);
+            let address = if lcdc & 0x10 != 0 {
+                VIDEO_RAM_RANGE.start + u16::from(tile) * 16
+            } else {
+                0x9000_u16.wrapping_add_signed(i16::from(tile.cast_signed()) * 16)
+            };
+            data.extend((address..address + 16).map(|address| ppu.read_vram(address)));
+        }
+        data
+    }
+
+    fn finish_transfer(&mut self, transfer: Transfer, data: &[u8]) {
+        match transfer {
+            Transfer::Palettes => {
+                for (palette, colors) in self.system_palettes.iter_mut().zip(data.chunks_exact(8)) {
+                    *palette = [0, 2, 4, 6].map(|offset| color(colors, offset));
+                }
+            }
+            Transfer::Tiles(half) => {
+                let start = half * TRANSFER_SIZE;
+                self.border_tiles[start..start + TRANSFER_SIZE].copy_from_slice(data);
+            }
+            Transfer::Border => {
+                for (entry, bytes) in self.border_map.iter_mut().zip(data.chunks_exact(2)) {
+                    *entry = u16::from_le_bytes([bytes[0], bytes[1]]);
+                }
+                let palettes = &data[BORDER_MAP_SIZE * 2..];
+                for (index, color) in self.border_palettes.iter_mut().flatten().enumerate() {
+                    *color = self::color(palettes, index * 2);
+                }
+            }
+            Transfer::AttributeFiles => {
+                let size = self.attribute_files.len();
+                self.attribute_files.copy_from_slice(&data[..size]);
+            }
+        }
+    }
+
+    /// Completes a pending VRAM transfer with the frame `ppu` just drew, and draws the frame
+    /// and border into [`Sgb::framebuffer`].
+    pub fn finish_frame(&mut self, ppu: &Ppu) {
+        if let Some(transfer) = self.transfer.take() {
+            self.finish_transfer(transfer, &Self::transfer_data(ppu));
+        }
+        let backdrop = self.palettes[0][0];
+        for (y, row) in self
+            .framebuffer
+            .chunks_exact_mut(SGB_SCREEN_WIDTH)
+            .enumerate()
+        {
+            let screen_row = y.checked_sub(SCREEN_TOP).filter(|&y| y < SCREEN_HEIGHT);
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let screen_x = x.checked_sub(SCREEN_LEFT).filter(|&x| x < SCREEN_WIDTH);
+                let (Some(screen_y), Some(screen_x)) = (screen_row, screen_x) else {
+                    *pixel = backdrop;
+                    continue;
+                };
+                let shade = ppu.framebuffer()[screen_y * SCREEN_WIDTH + screen_x];
+                let attribute = self.attributes[(screen_y / 8) * COLUMNS + screen_x / 8];
+                *pixel = match self.mask {
+                    Mask::Freeze => *pixel,
+                    Mask::Black => 0,
+                    Mask::Color0 => backdrop,
+                    Mask::None if shade == 0 => backdrop,
+                    Mask::None => self.palettes[usize::from(attribute)][usize::from(shade & 0x03)],
+                };
+            }
+        }
+        self.draw_border();
+    }
+
+    fn draw_border(&mut self) {
+        for (index, &entry) in self.border_map.iter().enumerate() {
+            let (tile_row, tile_column) = (index / 32, index % 32);
+            if tile_row * 8 >= SGB_SCREEN_HEIGHT {
+                break;
+            }
+            let tile = &self.border_tiles[usize::from(entry & 0xFF) * BORDER_TILE_SIZE..];
+            let palette = &self.border_palettes[usize::from((entry >> 10) & 0x03)];
+            for row in 0..8 {
+                let source_row = if entry & 0x8000 != 0 { 7 - row } else { row };
+                let planes = [
+                    tile[source_row * 2],
+                    tile[source_row * 2 + 1],
+                    tile[16 + source_row * 2],
+                    tile[16 + source_row * 2 + 1],
+                ];
+                for column in 0..8 {
+                    let bit = if entry & 0x4000 != 0 {
+                        column
+                    } else {
+                        7 - column
+                    };
+                    let index = planes.iter().enumerate().fold(0, |index, (plane, &byte)| {
+                        index | (((byte >> bit) & 1) << plane)
+                    });
+                    if index != 0 {
+                        let y = tile_row * 8 + row;
+                        let x = tile_column * 8 + column;
+                        self.framebuffer[y * SGB_SCREEN_WIDTH + x] = palette[usize::from(index)];
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(sgb: &mut Sgb, command: &[u8]) {
+        for packet in command.chunks(PACKET_SIZE) {
+            sgb.write_joypad(0x00);
+            sgb.write_joypad(0x30);
+            let bits = (0..PACKET_BITS).map(|bit| packet[bit / 8] >> (bit % 8) & 1 != 0);
+            for bit in bits.chain([false]) {
+                sgb.write_joypad(if bit { 0x10 } else { 0x20 });
+                sgb.write_joypad(0x30);
+            }
+        }
+    }
+
+    fn packet(bytes: &[u8]) -> [u8; PACKET_SIZE] {
+        let mut packet = [0; PACKET_SIZE];
+        packet[..bytes.len()].copy_from_slice(bytes);
+        packet
+    }
+
+    #[test]
+    fn test_palette_and_attribute_packets() {
+        let mut sgb = Sgb::new(true);
+        let mut pal01 = packet(&[(PAL01 << 3) | 1]);
+        for (index, byte) in pal01[1..15].iter_mut().enumerate() {
+            *byte = u8::try_from(index).unwrap() + 1;
+        }
+        send(&mut sgb, &pal01);
+        assert_eq!([0x0201, 0x0403, 0x0605, 0x0807], sgb.palettes()[0]);
+        assert_eq!([0x0201, 0x0A09, 0x0C0B, 0x0E0D], sgb.palettes()[1]);
+        assert_eq!(0x0201, sgb.palettes()[3][0]);
+
+        // Palette 1 inside and on the border of cells 2,3 to 4,5; palette 2 outside.
+        send(
+            &mut sgb,
+            &packet(&[(ATTR_BLK << 3) | 1, 1, 0x07, 0x25, 2, 3, 4, 5]),
+        );
+        assert_eq!(1, sgb.attributes[3 * COLUMNS + 2]);
+        assert_eq!(1, sgb.attributes[4 * COLUMNS + 3]);
+        assert_eq!(2, sgb.attributes[0]);
+
+        send(
+            &mut sgb,
+            &packet(&[(ATTR_LIN << 3) | 1, 1, 0x80 | 0x60 | 0x11]),
+        );
+        assert!(sgb.attributes[17 * COLUMNS..]
+            .iter()
+            .all(|&palette| palette == 3));
+
+        send(&mut sgb, &packet(&[(ATTR_DIV << 3) | 1, 0x40 | 0x24, 9]));
+        assert_eq!(
+            [1, 2, 0],
+            [8, 9, 10].map(|row| sgb.attributes[row * COLUMNS])
+        );
+
+        send(
+            &mut sgb,
+            &packet(&[(ATTR_CHR << 3) | 1, 19, 0, 2, 0, 0, 0b1110_0000]),
+        );
+        assert_eq!([3, 2], [19, COLUMNS].map(|index| sgb.attributes[index]));
+
+        send(&mut sgb, &packet(&[(MASK_EN << 3) | 1, 2]));
+        assert_eq!(Mask::Black, sgb.mask());
+    }
+
+    #[test]
+    fn test_packets_need_stop_bit_and_enabled_cartridge() {
+        let mut sgb = Sgb::new(false);
+        send(&mut sgb, &packet(&[(MASK_EN << 3) | 1, 2]));
+        assert_eq!(Mask::None, sgb.mask());
+
+        let mut sgb = Sgb::new(true);
+        sgb.write_joypad(0x00);
+        sgb.write_joypad(0x30);
+        for _ in 0..=PACKET_BITS {
+            sgb.write_joypad(0x10);
+            sgb.write_joypad(0x30);
+        }
+        assert_eq!(DEFAULT_PALETTE, sgb.palettes()[0]);
+    }
+
+    /// A PPU showing `data` through the first 256 background tiles.
+    fn showing(data: &[u8]) -> Ppu {
+        let mut ppu = Ppu::new(false);
+        ppu.write(LCDC_REGISTER_INDEX, 0x91);
+        for (offset, &byte) in (0x8000..).zip(data) {
+            ppu.write_vram(offset, byte);
+        }
+        for (tile, index) in (0..=u8::MAX).zip(0..) {
+            let (row, column) = (index / 20, index % 20);
+            ppu.write_vram(0x9800 + row * 32 + column, tile);
+        }
+        ppu
+    }
+
+    #[test]
+    fn test_border_transfers() {
+        let mut sgb = Sgb::new(true);
+        send(&mut sgb, &packet(&[(CHR_TRN << 3) | 1, 0]));
+        // Tile 1 uses colour 1 in its top row, flipped entries put it in the bottom row.
+        let mut tiles = vec![0; TRANSFER_SIZE];
+        tiles[BORDER_TILE_SIZE] = 0xFF;
+        sgb.finish_frame(&showing(&tiles));
+
+        send(&mut sgb, &packet(&[(PCT_TRN << 3) | 1]));
+        let mut border = vec![0; TRANSFER_SIZE];
+        border[..2].copy_from_slice(&0x0401_u16.to_le_bytes());
+        border[2..4].copy_from_slice(&0x8001_u16.to_le_bytes());
+        let palette_1 = BORDER_MAP_SIZE * 2 + BORDER_PALETTE_SIZE * 2;
+        border[palette_1 + 2..palette_1 + 4].copy_from_slice(&0x1234_u16.to_le_bytes());
+        border[BORDER_MAP_SIZE * 2 + 2..BORDER_MAP_SIZE * 2 + 4].copy_from_slice(&[0x21, 0x43]);
+        sgb.finish_frame(&showing(&border));
+
+        let framebuffer = sgb.framebuffer();
+        assert_eq!(0x1234, framebuffer[0]);
+        assert_eq!(DEFAULT_PALETTE[0], framebuffer[SGB_SCREEN_WIDTH]);
+        assert_eq!(DEFAULT_PALETTE[0], framebuffer[8]);
+        assert_eq!(0x4321, framebuffer[7 * SGB_SCREEN_WIDTH + 8]);
+        let screen = SCREEN_TOP * SGB_SCREEN_WIDTH + SCREEN_LEFT;
+        assert_eq!(DEFAULT_PALETTE[0], framebuffer[screen]);
+    }
+
+    #[test]
+    fn test_multiplayer_controller_ids() {
+        let mut sgb = Sgb::new(true);
+        let mut joypad = Joypad::default();
+        send(&mut sgb, &packet(&[(MLT_REQ << 3) | 1, 1]));
+        assert_eq!(2, sgb.players());
+        sgb.set_player_button(2, Button::A, true);
+
+        let mut poll = |sgb: &mut Sgb| {
+            sgb.write_joypad(0x30);
+            let id = sgb.read_joypad(&joypad) & 0x0F;
+            joypad.write(0x10);
+            sgb.write_joypad(0x10);
+            let buttons = sgb.read_joypad(&joypad) & 0x0F;
+            joypad.write(0x30);
+            sgb.write_joypad(0x30);
+            (id, buttons)
+        };
+        assert_eq!((0x0F, 0x0F), poll(&mut sgb));
+        assert_eq!((0x0E, 0x0E), poll(&mut sgb));
+        assert_eq!((0x0F, 0x0F), poll(&mut sgb));
+    }
+}
@@ -0,0 +1,237 @@
+//! The binary save state format.
+//!
+//! A state starts with [`MAGIC`], the format [`VERSION`] and the global checksum of the ROM it
+//! was taken from, followed by each component's fields in a fixed order, little-endian.
+//! Variable-length buffers are prefixed with their length. Any change to the layout must bump
+//! [`VERSION`].
+
+use std::fmt;
+
+pub const MAGIC: [u8; 8] = *b"DMG01SAV";
+pub const VERSION: u16 = 7;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    NotAState,
+    UnsupportedVersion(u16),
+    /// The state was saved with a ROM whose global checksum differs from the loaded one.
+    RomMismatch {
+        expected: u16,
+        found: u16,
+    },
+    Truncated,
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAState => write!(f, "not a save state"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "save state version {version} is not supported, expected version {VERSION}"
+            ),
+            Self::RomMismatch { expected, found } => write!(
+                f,
+                "save state is for a ROM with checksum {found:04X}, the loaded ROM has {expected:04X}"
+            ),
+            Self::Truncated => write!(f, "save state is truncated"),
+            Self::Invalid(what) => write!(f, "save state has an invalid {what}"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+#[derive(Debug, Default)]
+pub struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    #[must_use]
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(u8::from(value));
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn i32(&mut self, value: i32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes `bytes` prefixed with their length.
+    ///
+    /// # Panics
+    ///
+    /// Panics when `bytes` is 4 GiB or larger.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(u32::try_from(bytes.len()).expect("state buffers fit in 32 bits"));
+        self.bytes.extend_from_slice(bytes);
+    }
+}
+
+#[derive(Debug)]
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    #[must_use]
+    pub const fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let (taken, rest) = self
+            .bytes
+            .split_first_chunk()
+            .ok_or(StateError::Truncated)?;
+        self.bytes = rest;
+        Ok(*taken)
+    }
+
+    /// Whether every byte has been read.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// # Errors
+    ///
+    /// Returns [`StateError::Truncated`] at the end of the state.
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        self.take::<1>().map(|[value]| value)
+    }
+
+    /// # Errors
+    ///
+    /// Returns [`StateError::Truncated`] at the end of the state, or [`StateError::Invalid`]
+    /// for bytes other than 0 and 1.
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid("flag")),
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Returns [`StateError::Truncated`] at the end of the state.
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    /// # Errors
+    ///
+    /// Returns [`StateError::Truncated`] at the end of the state.
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    /// # Errors
+    ///
+    /// Returns [`StateError::Truncated`] at the end of the state.
+    pub fn i32(&mut self) -> Result<i32, StateError> {
+        self.take().map(i32::from_le_bytes)
+    }
+
+    /// # Errors
+    ///
+    /// Returns [`StateError::Truncated`] at the end of the state.
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    /// Reads a length-prefixed buffer of any length.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StateError::Truncated`] at the end of the state.
+    pub fn bytes(&mut self) -> Result<&'a [u8], StateError> {
+        let length = usize::try_from(self.u32()?).map_err(|_| StateError::Truncated)?;
+        let (bytes, rest) = self
+            .bytes
+            .split_at_checked(length)
+            .ok_or(StateError::Truncated)?;
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    /// Reads a length-prefixed buffer into `buffer`, which must have the same length.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StateError::Truncated`] at the end of the state, or
+    /// [`StateError::Invalid`] naming `what` when the length differs.
+    pub fn bytes_into(&mut self, buffer: &mut [u8], what: &'static str) -> Result<(), StateError> {
+        if usize::try_from(self.u32()?).ok() != Some(buffer.len()) {
+            return Err(StateError::Invalid(what));
+        }
+        let (bytes, rest) = self
+            .bytes
+            .split_at_checked(buffer.len())
+            .ok_or(StateError::Truncated)?;
+        buffer.copy_from_slice(bytes);
+        self.bytes = rest;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = StateWriter::default();
+        writer.u8(0x12);
+        writer.bool(true);
+        writer.u16(0x3456);
+        writer.i32(-7);
+        writer.u64(u64::MAX);
+        writer.bytes(&[1, 2, 3]);
+        let bytes = writer.into_bytes();
+
+        let mut reader = StateReader::new(&bytes);
+        assert_eq!(Ok(0x12), reader.u8());
+        assert_eq!(Ok(true), reader.bool());
+        assert_eq!(Ok(0x3456), reader.u16());
+        assert_eq!(Ok(-7), reader.i32());
+        assert_eq!(Ok(u64::MAX), reader.u64());
+        let mut buffer = [0; 3];
+        assert_eq!(Ok(()), reader.bytes_into(&mut buffer, "buffer"));
+        assert_eq!([1, 2, 3], buffer);
+        assert!(reader.is_empty());
+
+        let mut reader = StateReader::new(&bytes[bytes.len() - 7..]);
+        assert_eq!(Ok(&[1, 2, 3][..]), reader.bytes());
+        assert!(reader.is_empty());
+        assert_eq!(Err(StateError::Truncated), reader.u8());
+
+        let mut reader = StateReader::new(&bytes[bytes.len() - 7..]);
+        assert_eq!(
+            Err(StateError::Invalid("buffer")),
+            reader.bytes_into(&mut [0; 2], "buffer")
+        );
+    }
+}
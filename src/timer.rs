@@ -0,0 +1,140 @@
+use crate::memory_map::{
+    DIVIDER_REGISTER_INDEX, TIMER_CONTROL_REGISTER_INDEX, TIMER_COUNTER_REGISTER_INDEX,
+    TIMER_MODULO_REGISTER_INDEX,
+};
+use crate::state::{StateError, StateReader, StateWriter};
+
+#[derive(Debug, Clone, Default)]
+pub struct Timer {
+    divider: u16,
+    counter: u8,
+    modulo: u8,
+    control: u8,
+    interrupt: bool,
+}
+
+impl Timer {
+    pub fn reset_post_boot(&mut self) {
+        *self = Self {
+            divider: 0xABCC,
+            control: 0xF8,
+            ..Self::default()
+        };
+    }
+
+    #[must_use]
+    pub const fn divider(&self) -> u16 {
+        self.divider
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.divider);
+        state.u8(self.counter);
+        state.u8(self.modulo);
+        state.u8(self.control);
+        state.bool(self.interrupt);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.divider = state.u16()?;
+        self.counter = state.u8()?;
+        self.modulo = state.u8()?;
+        self.control = state.u8()?;
+        self.interrupt = state.bool()?;
+        Ok(())
+    }
+
+    const fn selected_bit(&self) -> u16 {
+        match self.control & 0x03 {
+            0 => 1 << 9,
+            1 => 1 << 3,
+            2 => 1 << 5,
+            _ => 1 << 7,
+        }
+    }
+
+    const fn signal(&self) -> bool {
+        self.control & 0x04 != 0 && self.divider & self.selected_bit() != 0
+    }
+
+    const fn increment_counter(&mut self) {
+        let (counter, overflow) = self.counter.overflowing_add(1);
+        if overflow {
+            self.counter = self.modulo;
+            self.interrupt = true;
+        } else {
+            self.counter = counter;
+        }
+    }
+
+    const fn set_divider(&mut self, divider: u16) {
+        let before = self.signal();
+        self.divider = divider;
+        if before && !self.signal() {
+            self.increment_counter();
+        }
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles / 4 {
+            self.set_divider(self.divider.wrapping_add(4));
+        }
+    }
+
+    pub fn take_interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.interrupt)
+    }
+
+    #[must_use]
+    pub const fn read(&self, address: u16) -> u8 {
+        match address {
+            DIVIDER_REGISTER_INDEX => self.divider.to_be_bytes()[0],
+            TIMER_COUNTER_REGISTER_INDEX => self.counter,
+            TIMER_MODULO_REGISTER_INDEX => self.modulo,
+            TIMER_CONTROL_REGISTER_INDEX => self.control | 0xF8,
+            _ => 0xFF,
+        }
+    }
+
+    pub const fn write(&mut self, address: u16, value: u8) {
+        match address {
+            DIVIDER_REGISTER_INDEX => self.set_divider(0),
+            TIMER_COUNTER_REGISTER_INDEX => self.counter = value,
+            TIMER_MODULO_REGISTER_INDEX => self.modulo = value,
+            TIMER_CONTROL_REGISTER_INDEX => {
+                let before = self.signal();
+                self.control = value & 0x07;
+                if before && !self.signal() {
+                    self.increment_counter();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counter_overflow_reloads_modulo() {
+        let mut timer = Timer::default();
+        timer.write(TIMER_MODULO_REGISTER_INDEX, 0x42);
+        timer.write(TIMER_COUNTER_REGISTER_INDEX, 0xFF);
+        timer.write(TIMER_CONTROL_REGISTER_INDEX, 0x05);
+        timer.tick(16);
+        assert_eq!(0x42, timer.read(TIMER_COUNTER_REGISTER_INDEX));
+        assert!(timer.take_interrupt());
+        assert!(!timer.take_interrupt());
+    }
+
+    #[test]
+    fn test_divider_write_resets() {
+        let mut timer = Timer::default();
+        timer.tick(1024);
+        assert_eq!(4, timer.read(DIVIDER_REGISTER_INDEX));
+        timer.write(DIVIDER_REGISTER_INDEX, 0x99);
+        assert_eq!(0, timer.read(DIVIDER_REGISTER_INDEX));
+    }
+}
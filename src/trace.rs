@@ -0,0 +1,230 @@
+//! Per-instruction execution traces in the format used by Game Boy doctor tools, e.g.
+//! `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02`.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use crate::bus::MemoryBus;
+use crate::cpu::Instruction;
+use crate::disassembler::decode_at;
+use crate::gameboy::GameBoy;
+
+/// The trace line for the instruction `gameboy` is about to execute.
+#[must_use]
+pub fn trace_line(gameboy: &GameBoy) -> String {
+    let registers = gameboy.cpu().registers();
+    let pc = registers.pc();
+    let memory = |offset| gameboy.bus().peek(pc.wrapping_add(offset));
+    format!(
+        "{registers} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+        memory(0),
+        memory(1),
+        memory(2),
+        memory(3)
+    )
+}
+
+pub struct Tracer {
+    output: Box<dyn Write>,
+    range: Option<RangeInclusive<u16>>,
+    bank: Option<usize>,
+    /// In ring mode, the last lines kept until a crash, at most `ring_capacity` of them.
+    ring: Option<VecDeque<String>>,
+    ring_capacity: usize,
+    crashed: bool,
+}
+
+impl Tracer {
+    /// Writes every traced instruction to `output` as it executes.
+    #[must_use]
+    pub fn new(output: Box<dyn Write>) -> Self {
+        Self {
+            output,
+            range: None,
+            bank: None,
+            ring: None,
+            ring_capacity: 0,
+            crashed: false,
+        }
+    }
+
+    /// Keeps only the last `capacity` instructions and writes them to `output` on a crash, or
+    /// when [`Tracer::dump`] is called.
+    #[must_use]
+    pub fn ring(output: Box<dyn Write>, capacity: usize) -> Self {
+        Self {
+            ring: Some(VecDeque::with_capacity(capacity)),
+            ring_capacity: capacity,
+            ..Self::new(output)
+        }
+    }
+
+    /// Only traces instructions with PC inside `range`.
+    #[must_use]
+    pub const fn with_range(mut self, range: RangeInclusive<u16>) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    /// Only traces instructions in this cartridge bank, see [`crate::Bus::bank_at`].
+    #[must_use]
+    pub const fn with_bank(mut self, bank: usize) -> Self {
+        self.bank = Some(bank);
+        self
+    }
+
+    /// Whether an illegal opcode has been reached, which locks up the CPU.
+    #[must_use]
+    pub const fn crashed(&self) -> bool {
+        self.crashed
+    }
+
+    /// Records the instruction `gameboy` is about to execute, if it passes the filters. Call
+    /// this before every [`GameBoy::step`]; steps that only idle or dispatch an interrupt are
+    /// skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns the I/O error when writing to the output fails.
+    pub fn trace(&mut self, gameboy: &GameBoy) -> io::Result<()> {
+        if self.crashed || !gameboy.cpu().executes_next(gameboy.bus()) {
+            return Ok(());
+        }
+        let pc = gameboy.cpu().registers().pc();
+        let included = self.range.as_ref().is_none_or(|range| range.contains(&pc))
+            && self
+                .bank
+                .is_none_or(|bank| gameboy.bus().bank_at(pc) == Some(bank));
+        if included {
+            let line = trace_line(gameboy);
+            match &mut self.ring {
+                Some(ring) => {
+                    if ring.len() == self.ring_capacity {
+                        ring.pop_front();
+                    }
+                    if self.ring_capacity > 0 {
+                        ring.push_back(line);
+                    }
+                }
+                None => writeln!(self.output, "{line}")?,
+            }
+        }
+        if matches!(decode_at(gameboy.bus(), pc).0, Instruction::ILLEGAL(_)) {
+            self.crashed = true;
+            self.dump()?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the output without dumping the ring buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns the I/O error when writing to the output fails.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+
+    /// Writes out and clears the ring buffer, and flushes the output.
+    ///
+    /// # Errors
+    ///
+    /// Returns the I/O error when writing to the output fails.
+    pub fn dump(&mut self) -> io::Result<()> {
+        if let Some(ring) = &mut self.ring {
+            for line in ring.drain(..) {
+                writeln!(self.output, "{line}")?;
+            }
+        }
+        self.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble_rom;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(bytes)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8_lossy(&self.0.borrow())
+                .lines()
+                .map(String::from)
+                .collect()
+        }
+    }
+
+    fn run(mut tracer: Tracer, steps: usize) -> Tracer {
+        let rom = assemble_rom(
+            "
+            section \"main\", rom0[$150]
+                ld a, 3
+            .loop:
+                dec a
+                jr nz, .loop
+                db $d3
+            ",
+        )
+        .unwrap();
+        let mut gameboy = GameBoy::from_rom(rom).unwrap();
+        for _ in 0..steps {
+            tracer.trace(&gameboy).unwrap();
+            gameboy.step();
+        }
+        tracer
+    }
+
+    #[test]
+    fn test_doctor_format() {
+        let output = Shared::default();
+        run(Tracer::new(Box::new(output.clone())), 3);
+        assert_eq!(
+            vec![
+                "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,50,01",
+                "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:C3,50,01,CE",
+                "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0150 PCMEM:3E,03,3D,20",
+            ],
+            output.lines()
+        );
+    }
+
+    #[test]
+    fn test_filters_and_ring() {
+        let output = Shared::default();
+        run(
+            Tracer::new(Box::new(output.clone()))
+                .with_range(0x0152..=0x0153)
+                .with_bank(0),
+            20,
+        );
+        assert_eq!(6, output.lines().len());
+
+        let output = Shared::default();
+        let tracer = run(Tracer::ring(Box::new(output.clone()), 2), 5);
+        assert!(!tracer.crashed());
+        assert!(output.lines().is_empty());
+
+        let tracer = run(Tracer::ring(Box::new(output.clone()), 2), 20);
+        assert!(tracer.crashed());
+        let lines = output.lines();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].contains("PC:0153"));
+        assert!(lines[1].ends_with("PC:0155 PCMEM:D3,00,00,00"));
+    }
+}
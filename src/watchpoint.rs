@@ -0,0 +1,169 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// Either a read or a write.
+    Access,
+    /// A write that changes the stored value, optionally only from or to the given values.
+    Change {
+        from: Option<u8>,
+        to: Option<u8>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WatchAction {
+    /// Stop the debugger after the instruction that triggered the watchpoint.
+    #[default]
+    Stop,
+    /// Record the hit and keep running.
+    Log,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    /// Only trigger when this bank is mapped at the address, see [`crate::Bus::bank_at`].
+    pub bank: Option<usize>,
+    pub kind: WatchKind,
+    pub action: WatchAction,
+}
+
+impl Watchpoint {
+    #[must_use]
+    pub const fn new(kind: WatchKind, range: RangeInclusive<u16>) -> Self {
+        Self {
+            range,
+            bank: None,
+            kind,
+            action: WatchAction::Stop,
+        }
+    }
+
+    /// Whether an access to `address` with `bank` mapped triggers this watchpoint. `old` and
+    /// `new` are the values stored before and after the access.
+    #[must_use]
+    pub fn matches(
+        &self,
+        access: Access,
+        address: u16,
+        bank: Option<usize>,
+        old: u8,
+        new: u8,
+    ) -> bool {
+        if !self.range.contains(&address) || self.bank.is_some_and(|watched| bank != Some(watched))
+        {
+            return false;
+        }
+        match (self.kind, access) {
+            (WatchKind::Read, Access::Read)
+            | (WatchKind::Write, Access::Write)
+            | (WatchKind::Access, _) => true,
+            (WatchKind::Change { from, to }, Access::Write) => {
+                old != new && from.is_none_or(|from| from == old) && to.is_none_or(|to| to == new)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            WatchKind::Read => "read",
+            WatchKind::Write => "write",
+            WatchKind::Access => "access",
+            WatchKind::Change { .. } => "change",
+        };
+        write!(f, "{kind} ")?;
+        if let Some(bank) = self.bank {
+            write!(f, "{bank:02X}:")?;
+        }
+        write!(f, "{:04X}", self.range.start())?;
+        if self.range.end() != self.range.start() {
+            write!(f, "-{:04X}", self.range.end())?;
+        }
+        if let WatchKind::Change { from, to } = self.kind {
+            if let Some(from) = from {
+                write!(f, " from {from:02X}")?;
+            }
+            if let Some(to) = to {
+                write!(f, " to {to:02X}")?;
+            }
+        }
+        if self.action == WatchAction::Log {
+            write!(f, " log")?;
+        }
+        Ok(())
+    }
+}
+
+/// A triggered watchpoint, recorded by the bus until taken with [`crate::Bus::take_watch_hits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub id: usize,
+    pub action: WatchAction,
+    pub access: Access,
+    pub address: u16,
+    pub bank: Option<usize>,
+    /// The value stored before the access.
+    pub old: u8,
+    /// The value read or written.
+    pub value: u8,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "watchpoint {}: ", self.id)?;
+        match self.access {
+            Access::Read => write!(f, "read ")?,
+            Access::Write => write!(f, "write ")?,
+        }
+        if let Some(bank) = self.bank {
+            write!(f, "{bank:02X}:")?;
+        }
+        write!(f, "{:04X} ", self.address)?;
+        match self.access {
+            Access::Read => write!(f, "= {:02X}", self.value),
+            Access::Write => write!(f, "{:02X} -> {:02X}", self.old, self.value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        let mut watchpoint = Watchpoint::new(WatchKind::Write, 0xC000..=0xC0FF);
+        assert!(watchpoint.matches(Access::Write, 0xC010, None, 0, 0));
+        assert!(!watchpoint.matches(Access::Read, 0xC010, None, 0, 0));
+        assert!(!watchpoint.matches(Access::Write, 0xC100, None, 0, 0));
+
+        watchpoint.bank = Some(1);
+        assert!(!watchpoint.matches(Access::Write, 0xC010, None, 0, 0));
+        assert!(watchpoint.matches(Access::Write, 0xC010, Some(1), 0, 0));
+
+        let watchpoint = Watchpoint::new(
+            WatchKind::Change {
+                from: None,
+                to: Some(5),
+            },
+            0xFF80..=0xFF80,
+        );
+        assert!(watchpoint.matches(Access::Write, 0xFF80, None, 4, 5));
+        assert!(!watchpoint.matches(Access::Write, 0xFF80, None, 5, 5));
+        assert!(!watchpoint.matches(Access::Write, 0xFF80, None, 4, 6));
+        assert_eq!("change FF80 to 05", watchpoint.to_string());
+    }
+}
@@ -0,0 +1,171 @@
+//! Runs third-party test ROMs.
+//!
+//! ROMs are looked up in `$DMG01_TEST_ROMS`, or `tests/roms` by default, under a `blargg`
+//! and a `mooneye` subdirectory. Suites whose directory is missing are skipped.
+
+use std::path::{Path, PathBuf};
+
+use dmg_01::{GameBoy, MemoryBus, CYCLES_PER_FRAME};
+
+const CYCLES_PER_SECOND: u64 = 4_194_304;
+const BLARGG_TIMEOUT: u64 = 120 * CYCLES_PER_SECOND;
+const MOONEYE_TIMEOUT: u64 = 30 * CYCLES_PER_SECOND;
+const LD_B_B: u8 = 0x40;
+const FIBONACCI: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    Passed,
+    Failed(String),
+    Timeout(String),
+}
+
+/// Runs until the serial output reports "Passed" or "Failed", Blargg style.
+fn run_serial(gameboy: &mut GameBoy, timeout: u64) -> Outcome {
+    while gameboy.cycles() < timeout {
+        gameboy.run_cycles(u64::from(CYCLES_PER_FRAME));
+        let output = String::from_utf8_lossy(gameboy.bus().serial().output()).into_owned();
+        if output.contains("Passed") {
+            return Outcome::Passed;
+        }
+        if output.contains("Failed") {
+            return Outcome::Failed(output);
+        }
+    }
+    Outcome::Timeout(String::from_utf8_lossy(gameboy.bus().serial().output()).into_owned())
+}
+
+/// Runs until `LD B,B` and checks for the Fibonacci numbers Mooneye tests leave on success.
+fn run_mooneye(gameboy: &mut GameBoy, timeout: u64) -> Outcome {
+    while gameboy.cycles() < timeout {
+        let pc = gameboy.cpu().registers().pc();
+        if gameboy.bus().peek(pc) == LD_B_B {
+            let registers = gameboy.cpu().registers();
+            let values = [
+                registers.b(),
+                registers.c(),
+                registers.d(),
+                registers.e(),
+                registers.h(),
+                registers.l(),
+            ];
+            return if values == FIBONACCI {
+                Outcome::Passed
+            } else {
+                Outcome::Failed(format!("registers B/C/D/E/H/L were {values:02X?}"))
+            };
+        }
+        gameboy.step();
+    }
+    Outcome::Timeout(String::new())
+}
+
+fn rom_directory() -> PathBuf {
+    std::env::var_os("DMG01_TEST_ROMS").map_or_else(
+        || PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/roms"),
+        PathBuf::from,
+    )
+}
+
+fn collect_roms(directory: &Path, roms: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return;
+    };
+    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        if path.is_dir() {
+            collect_roms(&path, roms);
+        } else if path.extension().is_some_and(|extension| extension == "gb") {
+            roms.push(path);
+        }
+    }
+}
+
+fn run_suite(name: &str, run: fn(&mut GameBoy, u64) -> Outcome, timeout: u64) {
+    let directory = rom_directory().join(name);
+    let mut roms = Vec::new();
+    collect_roms(&directory, &mut roms);
+    if roms.is_empty() {
+        eprintln!("skipping {name}: no ROMs in {}", directory.display());
+        return;
+    }
+    roms.sort();
+
+    let mut failures = Vec::new();
+    for path in &roms {
+        let outcome = std::fs::read(path)
+            .map_err(|error| error.to_string())
+            .and_then(|rom| GameBoy::from_rom(rom).map_err(|error| error.to_string()))
+            .map_or_else(Outcome::Failed, |mut gameboy| run(&mut gameboy, timeout));
+        if outcome != Outcome::Passed {
+            failures.push(format!("{}: {outcome:?}", path.display()));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} {name} ROMs failed:\n{}",
+        failures.len(),
+        roms.len(),
+        failures.join("\n")
+    );
+}
+
+fn rom(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x150..0x150 + program.len()].copy_from_slice(program);
+    rom
+}
+
+#[test]
+fn test_blargg_roms() {
+    run_suite("blargg", run_serial, BLARGG_TIMEOUT);
+}
+
+#[test]
+fn test_mooneye_roms() {
+    run_suite("mooneye", run_mooneye, MOONEYE_TIMEOUT);
+}
+
+#[test]
+fn test_serial_harness_reads_result() {
+    let mut program = vec![
+        0x21, 0x60, 0x01, 0x2A, 0xB7, 0x28, 0xFE, 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, 0x18, 0xF4,
+        0x00,
+    ];
+    program.extend_from_slice(b"Passed\n\0");
+    let mut gameboy = GameBoy::from_rom(rom(&program)).unwrap();
+    assert_eq!(Outcome::Passed, run_serial(&mut gameboy, CYCLES_PER_SECOND));
+
+    program[0x10..].copy_from_slice(b"Failed\n\0");
+    let mut gameboy = GameBoy::from_rom(rom(&program)).unwrap();
+    assert_eq!(
+        Outcome::Failed("Failed\n".to_string()),
+        run_serial(&mut gameboy, CYCLES_PER_SECOND)
+    );
+
+    program[0x10] = 0;
+    let mut gameboy = GameBoy::from_rom(rom(&program)).unwrap();
+    assert_eq!(
+        Outcome::Timeout(String::new()),
+        run_serial(&mut gameboy, CYCLES_PER_SECOND)
+    );
+}
+
+#[test]
+fn test_mooneye_harness_checks_registers() {
+    let mut program = vec![
+        0x06, 0x03, 0x0E, 0x05, 0x16, 0x08, 0x1E, 0x0D, 0x26, 0x15, 0x2E, 0x22, 0x40, 0x18, 0xFE,
+    ];
+    let mut gameboy = GameBoy::from_rom(rom(&program)).unwrap();
+    assert_eq!(
+        Outcome::Passed,
+        run_mooneye(&mut gameboy, CYCLES_PER_SECOND)
+    );
+
+    program[11] = 0x42;
+    let mut gameboy = GameBoy::from_rom(rom(&program)).unwrap();
+    assert!(matches!(
+        run_mooneye(&mut gameboy, CYCLES_PER_SECOND),
+        Outcome::Failed(_)
+    ));
+}
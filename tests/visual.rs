@@ -0,0 +1,169 @@
+//! Visual regression tests.
+//!
+//! Each scenario runs a ROM with scripted input and compares the final frame against
+//! `tests/reference/<name>.png`. Set `DMG01_BLESS=1` to (re)write the references.
+
+use std::path::PathBuf;
+
+use dmg_01::screenshot::{self, Palette};
+use dmg_01::{Button, GameBoy, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// Draws striped tiles, then sets BGP to $1B once A is pressed and SCX to 4 once Right is
+/// pressed. Neither is undone on release.
+const PATTERN_PROGRAM: &[u8] = &[
+    0xAF, 0xE0, 0x40, 0x21, 0x10, 0x80, 0x06, 0x10, 0x78, 0x22, 0x05, 0x20, 0xFB, 0x21, 0x00, 0x98,
+    0x7D, 0xE6, 0x01, 0x22, 0x7C, 0xFE, 0x9C, 0x20, 0xF7, 0x3E, 0x91, 0xE0, 0x40, 0x3E, 0x10, 0xE0,
+    0x00, 0xF0, 0x00, 0xE6, 0x01, 0x20, 0x04, 0x3E, 0x1B, 0xE0, 0x47, 0x3E, 0x20, 0xE0, 0x00, 0xF0,
+    0x00, 0xE6, 0x01, 0x20, 0x04, 0x3E, 0x04, 0xE0, 0x43, 0x18, 0xE2,
+];
+
+struct Input {
+    frame: u64,
+    button: Button,
+    pressed: bool,
+}
+
+const fn press(frame: u64, button: Button) -> Input {
+    Input {
+        frame,
+        button,
+        pressed: true,
+    }
+}
+
+const fn release(frame: u64, button: Button) -> Input {
+    Input {
+        frame,
+        button,
+        pressed: false,
+    }
+}
+
+fn rom(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x150..0x150 + program.len()].copy_from_slice(program);
+    rom
+}
+
+fn run(program: &[u8], frames: u64, script: &[Input]) -> Vec<u8> {
+    let mut gameboy = GameBoy::from_rom(rom(program)).unwrap();
+    for frame in 0..frames {
+        for input in script.iter().filter(|input| input.frame == frame) {
+            gameboy.set_button(input.button, input.pressed);
+        }
+        gameboy.run_frame();
+    }
+    gameboy.framebuffer().to_vec()
+}
+
+/// Reads back the pixels of a PNG written by [`screenshot::encode_png`].
+fn decode_png(png: &[u8]) -> Option<(Vec<u8>, usize, usize)> {
+    let mut offset = 8;
+    let (mut width, mut height, mut zlib) = (0, 0, Vec::new());
+    while offset + 8 <= png.len() {
+        let length = u32::from_be_bytes(png[offset..offset + 4].try_into().ok()?) as usize;
+        let data = png.get(offset + 8..offset + 8 + length)?;
+        match &png[offset + 4..offset + 8] {
+            b"IHDR" => {
+                width = u32::from_be_bytes(data[0..4].try_into().ok()?) as usize;
+                height = u32::from_be_bytes(data[4..8].try_into().ok()?) as usize;
+                if data[8..13] != [8, 2, 0, 0, 0] {
+                    return None;
+                }
+            }
+            b"IDAT" => zlib.extend_from_slice(data),
+            _ => {}
+        }
+        offset += length + 12;
+    }
+
+    let mut raw = Vec::new();
+    let mut position = 2;
+    loop {
+        let last = *zlib.get(position)? & 0x01 != 0;
+        if zlib[position] & 0x06 != 0 {
+            return None;
+        }
+        let length = usize::from(u16::from_le_bytes(
+            zlib.get(position + 1..position + 3)?.try_into().ok()?,
+        ));
+        raw.extend_from_slice(zlib.get(position + 5..position + 5 + length)?);
+        position += 5 + length;
+        if last {
+            break;
+        }
+    }
+
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for row in raw.chunks(width * 3 + 1) {
+        if row[0] != 0 {
+            return None;
+        }
+        rgb.extend_from_slice(&row[1..]);
+    }
+    (rgb.len() == width * height * 3).then_some((rgb, width, height))
+}
+
+fn check(name: &str, framebuffer: &[u8]) {
+    let reference = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/reference")
+        .join(format!("{name}.png"));
+    let actual = screenshot::framebuffer_to_png(framebuffer, Palette::Grayscale, 1);
+    if std::env::var_os("DMG01_BLESS").is_some() {
+        std::fs::write(&reference, actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read(&reference).unwrap_or_else(|error| {
+        panic!(
+            "cannot read {}: {error}, run with DMG01_BLESS=1 to create it",
+            reference.display()
+        )
+    });
+    if expected == actual {
+        return;
+    }
+
+    let (expected, width, height) = decode_png(&expected)
+        .unwrap_or_else(|| panic!("{} is not a blessed reference", reference.display()));
+    let (actual, ..) = decode_png(&actual).unwrap();
+    assert_eq!((SCREEN_WIDTH, SCREEN_HEIGHT), (width, height));
+
+    let mut mismatches = 0;
+    let mut diff = Vec::with_capacity(actual.len());
+    for (expected, actual) in expected.chunks(3).zip(actual.chunks(3)) {
+        if expected == actual {
+            diff.extend(expected.iter().map(|channel| channel / 4 + 0x80));
+        } else {
+            mismatches += 1;
+            diff.extend_from_slice(&[0xFF, 0x00, 0x00]);
+        }
+    }
+    let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("visual-diffs");
+    std::fs::create_dir_all(&directory).unwrap();
+    let diff_path = directory.join(format!("{name}.png"));
+    std::fs::write(&diff_path, screenshot::encode_png(&diff, width, height)).unwrap();
+    panic!(
+        "{name}: {mismatches} pixels differ from {}, diff written to {}",
+        reference.display(),
+        diff_path.display()
+    );
+}
+
+#[test]
+fn test_pattern_idle() {
+    check("pattern_idle", &run(PATTERN_PROGRAM, 10, &[]));
+}
+
+#[test]
+fn test_pattern_press_a() {
+    let script = [press(3, Button::A)];
+    check("pattern_press_a", &run(PATTERN_PROGRAM, 10, &script));
+}
+
+#[test]
+fn test_pattern_scroll_right() {
+    let script = [press(2, Button::Right), release(6, Button::Right)];
+    check("pattern_scroll_right", &run(PATTERN_PROGRAM, 10, &script));
+}